@@ -0,0 +1,37 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "grpc")]
+    grpc::generate();
+}
+
+/// Service code for `proto/eink.proto`. The messages are written out in `src/grpc.rs`, so no
+/// `protoc` is needed to build.
+#[cfg(feature = "grpc")]
+mod grpc {
+    use tonic_build::manual::{Builder, Method, Service};
+
+    pub fn generate() {
+        println!("cargo:rerun-if-changed=proto/eink.proto");
+        let method = |name: &str, route: &str, input: &str, output: &str| {
+            Method::builder()
+                .name(name)
+                .route_name(route)
+                .input_type(format!("crate::grpc::{input}"))
+                .output_type(format!("crate::grpc::{output}"))
+                .codec_path("tonic_prost::ProstCodec")
+        };
+        let service = Service::builder()
+            .name("Display")
+            .package("eink.v1")
+            .method(method("display_text", "DisplayText", "TextRequest", "DisplayReply").build())
+            .method(method("display_image", "DisplayImage", "ImageRequest", "DisplayReply").build())
+            .method(method("clear", "Clear", "ClearRequest", "DisplayReply").build())
+            .method(
+                method("subscribe", "Subscribe", "SubscribeRequest", "DisplayEvent")
+                    .server_streaming()
+                    .build(),
+            )
+            .build();
+        Builder::new().build_client(false).compile(&[service]);
+    }
+}
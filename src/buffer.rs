@@ -1,6 +1,103 @@
+use barcoders::sym::{code128::Code128, ean13::EAN13, ean8::EAN8};
 use embedded_graphics::{
-    draw_target::DrawTarget, geometry::OriginDimensions, pixelcolor::BinaryColor, prelude::*,
+    draw_target::DrawTarget,
+    geometry::OriginDimensions,
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
 };
+use qrcode::{types::QrError, Color, QrCode};
+use std::{fmt, str::FromStr};
+use thiserror::Error;
+
+/// How [`MonoImage::compose`] combines two images. Operations act on ink: a pixel is
+/// "set" when it is `On` (black).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendMode {
+    /// Keep ink from either image.
+    Or,
+    /// Keep ink only where both images have it.
+    And,
+    /// Toggle ink wherever the other image has it.
+    Xor,
+}
+
+/// Clockwise rotation applied when a drawing is transferred to the panel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Rotation {
+    #[default]
+    Deg0,
+    Deg90,
+    Deg180,
+    Deg270,
+}
+
+impl Rotation {
+    pub fn degrees(self) -> u32 {
+        match self {
+            Rotation::Deg0 => 0,
+            Rotation::Deg90 => 90,
+            Rotation::Deg180 => 180,
+            Rotation::Deg270 => 270,
+        }
+    }
+
+    /// The rotation that undoes this one.
+    pub fn inverse(self) -> Self {
+        match self {
+            Rotation::Deg90 => Rotation::Deg270,
+            Rotation::Deg270 => Rotation::Deg90,
+            other => other,
+        }
+    }
+
+    /// Size of the canvas to draw on so that, once rotated, it covers `panel`.
+    pub fn canvas_size(self, panel: Size) -> Size {
+        match self {
+            Rotation::Deg0 | Rotation::Deg180 => panel,
+            Rotation::Deg90 | Rotation::Deg270 => Size::new(panel.height, panel.width),
+        }
+    }
+}
+
+impl fmt::Display for Rotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.degrees())
+    }
+}
+
+impl FromStr for Rotation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "0" => Ok(Rotation::Deg0),
+            "90" => Ok(Rotation::Deg90),
+            "180" => Ok(Rotation::Deg180),
+            "270" => Ok(Rotation::Deg270),
+            _ => Err(format!("unknown rotation '{s}' (expected 0, 90, 180 or 270)")),
+        }
+    }
+}
+
+/// Magic bytes at the start of a serialized frame.
+const FRAME_MAGIC: &[u8; 4] = b"EPF1";
+/// Serialized header: magic, then width, height and stride as little-endian `u32`s.
+const FRAME_HEADER_LEN: usize = 16;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum FrameError {
+    #[error("not a serialized frame (bad magic)")]
+    BadMagic,
+    #[error("frame header truncated")]
+    Truncated,
+    #[error("stride {stride} does not match width {width}")]
+    Stride { width: u32, stride: u32 },
+    #[error("frame data length mismatch: expected {expected} bytes, got {actual}")]
+    Length { expected: usize, actual: usize },
+    #[error("malformed run-length encoded data")]
+    Rle,
+}
 
 /// Simple 1-bit framebuffer laid out in the format expected by the Waveshare panel.
 pub struct MonoImage {
@@ -12,7 +109,7 @@ pub struct MonoImage {
 
 impl MonoImage {
     pub fn new(width: u32, height: u32) -> Self {
-        let bytes_per_row = ((width + 7) / 8) as usize;
+        let bytes_per_row = width.div_ceil(8) as usize;
         let len = bytes_per_row * height as usize;
         Self {
             width,
@@ -45,7 +142,159 @@ impl MonoImage {
         &self.data
     }
 
-    fn set_pixel(&mut self, x: u32, y: u32, color: BinaryColor) {
+    /// Serialize the image with a small header (magic, width, height, stride) so it can be
+    /// stored on disk and restored later with [`MonoImage::from_bytes_with_header`].
+    pub fn to_bytes_with_header(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(FRAME_HEADER_LEN + self.data.len());
+        out.extend_from_slice(FRAME_MAGIC);
+        out.extend_from_slice(&self.width.to_le_bytes());
+        out.extend_from_slice(&self.height.to_le_bytes());
+        out.extend_from_slice(&(self.bytes_per_row as u32).to_le_bytes());
+        out.extend_from_slice(&self.data);
+        out
+    }
+
+    /// Rebuild an image from the output of [`MonoImage::to_bytes_with_header`].
+    pub fn from_bytes_with_header(bytes: &[u8]) -> Result<Self, FrameError> {
+        if bytes.len() < FRAME_HEADER_LEN {
+            return Err(FrameError::Truncated);
+        }
+        if &bytes[..4] != FRAME_MAGIC {
+            return Err(FrameError::BadMagic);
+        }
+        let field = |idx: usize| u32::from_le_bytes(bytes[idx..idx + 4].try_into().unwrap());
+        let (width, height, stride) = (field(4), field(8), field(12));
+
+        if stride != width.div_ceil(8) {
+            return Err(FrameError::Stride { width, stride });
+        }
+        Self::from_data(width, height, &bytes[FRAME_HEADER_LEN..])
+    }
+
+    /// Wrap raw panel-format bytes (as returned by [`MonoImage::data`]) in an image.
+    pub fn from_data(width: u32, height: u32, data: &[u8]) -> Result<Self, FrameError> {
+        let bytes_per_row = width.div_ceil(8) as usize;
+        let expected = bytes_per_row * height as usize;
+        if data.len() != expected {
+            return Err(FrameError::Length {
+                expected,
+                actual: data.len(),
+            });
+        }
+        Ok(Self {
+            width,
+            height,
+            bytes_per_row,
+            data: data.to_vec(),
+        })
+    }
+
+    /// Compress the raw panel bytes with [`rle_encode`]. A mostly white frame shrinks from
+    /// ~4 KB to well under a hundred bytes.
+    pub fn to_rle(&self) -> Vec<u8> {
+        rle_encode(&self.data)
+    }
+
+    /// Rebuild a `width` x `height` image from [`MonoImage::to_rle`] output.
+    pub fn from_rle(width: u32, height: u32, bytes: &[u8]) -> Result<Self, FrameError> {
+        let expected = width.div_ceil(8) as usize * height as usize;
+        Self::from_data(width, height, &rle_decode(bytes, expected)?)
+    }
+
+    /// Read back a single pixel. Out-of-bounds coordinates read as `Off` (white).
+    pub fn pixel(&self, x: u32, y: u32) -> BinaryColor {
+        if x >= self.width || y >= self.height {
+            return BinaryColor::Off;
+        }
+        let idx = (y as usize) * self.bytes_per_row + (x as usize / 8);
+        let mask = 0x80 >> (x & 0x07);
+        if self.data[idx] & mask == 0 {
+            BinaryColor::On
+        } else {
+            BinaryColor::Off
+        }
+    }
+
+    /// Shift the whole image up by `pixels` rows, filling the exposed rows at the bottom
+    /// with `fill`. Useful for append-only log output where new lines push old ones off.
+    pub fn scroll_up(&mut self, pixels: u32, fill: BinaryColor) {
+        let rows = pixels.min(self.height) as usize;
+        let shift = rows * self.bytes_per_row;
+        self.data.copy_within(shift.., 0);
+
+        let byte = if fill == BinaryColor::Off { 0xFF } else { 0x00 };
+        let len = self.data.len();
+        self.data[len - shift..].fill(byte);
+    }
+
+    /// Shift every row left by `pixels` columns, filling the exposed strip on the right
+    /// with `fill`.
+    pub fn scroll_left(&mut self, pixels: u32, fill: BinaryColor) {
+        let shift = pixels.min(self.width);
+        for y in 0..self.height {
+            for x in 0..self.width - shift {
+                let color = self.pixel(x + shift, y);
+                self.set_pixel(x, y, color);
+            }
+            for x in self.width - shift..self.width {
+                self.set_pixel(x, y, fill);
+            }
+        }
+    }
+
+    /// Blend `other` onto this image, anchored at the top-left corner. Only the overlapping
+    /// area is touched when the sizes differ.
+    pub fn compose(&mut self, other: &MonoImage, mode: BlendMode) {
+        if self.width == other.width && self.height == other.height {
+            // Bits are inverted (1 = white), so ink OR/AND map to bitwise AND/OR.
+            for (dst, src) in self.data.iter_mut().zip(other.data.iter()) {
+                *dst = match mode {
+                    BlendMode::Or => *dst & *src,
+                    BlendMode::And => *dst | *src,
+                    BlendMode::Xor => !(*dst ^ *src),
+                };
+            }
+            return;
+        }
+
+        for y in 0..self.height.min(other.height) {
+            for x in 0..self.width.min(other.width) {
+                let a = self.pixel(x, y) == BinaryColor::On;
+                let b = other.pixel(x, y) == BinaryColor::On;
+                let ink = match mode {
+                    BlendMode::Or => a | b,
+                    BlendMode::And => a & b,
+                    BlendMode::Xor => a ^ b,
+                };
+                self.set_pixel(x, y, BinaryColor::from(ink));
+            }
+        }
+    }
+
+    /// A copy turned clockwise by `rotation`; quarter turns swap width and height.
+    pub fn rotated(&self, rotation: Rotation) -> MonoImage {
+        let size = rotation.canvas_size(Size::new(self.width, self.height));
+        let mut out = MonoImage::new(size.width, size.height);
+        if rotation == Rotation::Deg0 {
+            out.data.copy_from_slice(&self.data);
+            return out;
+        }
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let (nx, ny) = match rotation {
+                    Rotation::Deg0 => (x, y),
+                    Rotation::Deg90 => (self.height - 1 - y, x),
+                    Rotation::Deg180 => (self.width - 1 - x, self.height - 1 - y),
+                    Rotation::Deg270 => (y, self.width - 1 - x),
+                };
+                out.set_pixel(nx, ny, self.pixel(x, y));
+            }
+        }
+        out
+    }
+
+    /// Set a single pixel; out-of-bounds coordinates are ignored.
+    pub fn set_pixel(&mut self, x: u32, y: u32, color: BinaryColor) {
         if x >= self.width || y >= self.height {
             return;
         }
@@ -87,3 +336,190 @@ impl DrawTarget for MonoImage {
         Ok(())
     }
 }
+
+/// Longest run a single PackBits control byte can describe.
+const RLE_MAX_RUN: usize = 128;
+
+/// PackBits run-length encoding. Each chunk starts with a control byte `n`: `0..=127` means
+/// `n + 1` literal bytes follow, `129..=255` means the next byte repeats `257 - n` times.
+pub fn rle_encode(data: &[u8]) -> Vec<u8> {
+    let run_at = |i: usize| {
+        data[i..]
+            .iter()
+            .take(RLE_MAX_RUN)
+            .take_while(|&&b| b == data[i])
+            .count()
+    };
+
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        let run = run_at(i);
+        if run >= 2 {
+            out.push((257 - run) as u8);
+            out.push(data[i]);
+            i += run;
+            continue;
+        }
+
+        let start = i;
+        while i < data.len() && i - start < RLE_MAX_RUN && run_at(i) < 3 {
+            i += 1;
+        }
+        out.push((i - start - 1) as u8);
+        out.extend_from_slice(&data[start..i]);
+    }
+    out
+}
+
+/// Decode [`rle_encode`] output, refusing to produce more than `max_len` bytes.
+pub fn rle_decode(data: &[u8], max_len: usize) -> Result<Vec<u8>, FrameError> {
+    let mut out = Vec::with_capacity(max_len);
+    let mut i = 0;
+    while i < data.len() {
+        let control = data[i];
+        i += 1;
+        match control {
+            0..=127 => {
+                let len = control as usize + 1;
+                let literal = data.get(i..i + len).ok_or(FrameError::Rle)?;
+                out.extend_from_slice(literal);
+                i += len;
+            }
+            128 => {}
+            _ => {
+                let byte = *data.get(i).ok_or(FrameError::Rle)?;
+                out.resize(out.len() + 257 - control as usize, byte);
+                i += 1;
+            }
+        }
+        if out.len() > max_len {
+            return Err(FrameError::Rle);
+        }
+    }
+    Ok(out)
+}
+
+/// Quiet zone (in modules) kept around QR codes so scanners can lock on.
+const QR_QUIET_ZONE: u32 = 2;
+
+/// Draw `data` as a QR code centered inside `area`, using the largest integer module
+/// size that fits. The area is first cleared to white so the code scans on any background.
+/// A code that does not fit with its quiet zone even at one pixel per module is an error
+/// ([`QrError::DataTooLong`]) rather than being drawn clipped.
+pub fn draw_qr(image: &mut MonoImage, data: &str, area: Rectangle) -> Result<(), QrError> {
+    let code = QrCode::new(data.as_bytes())?;
+    let modules = code.width() as u32;
+    let span = modules + QR_QUIET_ZONE * 2;
+    let scale = area.size.width.min(area.size.height) / span;
+    if scale == 0 {
+        return Err(QrError::DataTooLong);
+    }
+    let side = modules * scale;
+    let origin = area.top_left
+        + Point::new(
+            (area.size.width.saturating_sub(side) / 2) as i32,
+            (area.size.height.saturating_sub(side) / 2) as i32,
+        );
+
+    area.into_styled(PrimitiveStyle::with_fill(BinaryColor::Off))
+        .draw(image)
+        .ok();
+
+    for (idx, color) in code.to_colors().into_iter().enumerate() {
+        if color != Color::Dark {
+            continue;
+        }
+        let x = (idx as u32 % modules) * scale;
+        let y = (idx as u32 / modules) * scale;
+        Rectangle::new(
+            origin + Point::new(x as i32, y as i32),
+            Size::new(scale, scale),
+        )
+        .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+        .draw(image)
+        .ok();
+    }
+    Ok(())
+}
+
+/// Barcode symbologies supported by [`draw_barcode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BarcodeKind {
+    Code128,
+    Ean13,
+    Ean8,
+}
+
+impl BarcodeKind {
+    /// Parse a symbology name such as `code128`, `ean13` or `ean-8` (case-insensitive).
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().replace('-', "").as_str() {
+            "code128" => Some(Self::Code128),
+            "ean13" => Some(Self::Ean13),
+            "ean8" => Some(Self::Ean8),
+            _ => None,
+        }
+    }
+}
+
+/// Quiet zone (in modules) kept on both ends of a barcode.
+const BARCODE_QUIET_ZONE: u32 = 6;
+
+/// Draw `data` as a 1D barcode filling `area`. Bars run along the longer side of the area,
+/// so on the portrait panel long Code128 payloads are drawn rotated rather than clipped.
+/// Code128 data is encoded with character set B unless it already starts with a set
+/// selector (`À`, `Ɓ`, `Ć`). A barcode longer than the area at one pixel per module is an
+/// `Error::Length` rather than being drawn clipped.
+pub fn draw_barcode(
+    image: &mut MonoImage,
+    kind: BarcodeKind,
+    data: &str,
+    area: Rectangle,
+) -> Result<(), barcoders::error::Error> {
+    let modules = match kind {
+        BarcodeKind::Code128 => {
+            if data.starts_with(['\u{00C0}', '\u{0181}', '\u{0106}']) {
+                Code128::new(data)?.encode()
+            } else {
+                Code128::new(format!("\u{0181}{data}"))?.encode()
+            }
+        }
+        BarcodeKind::Ean13 => EAN13::new(data)?.encode(),
+        BarcodeKind::Ean8 => EAN8::new(data)?.encode(),
+    };
+
+    let vertical = area.size.height > area.size.width;
+    let (length, depth) = if vertical {
+        (area.size.height, area.size.width)
+    } else {
+        (area.size.width, area.size.height)
+    };
+    let span = modules.len() as u32 + BARCODE_QUIET_ZONE * 2;
+    let scale = length / span;
+    if scale == 0 {
+        return Err(barcoders::error::Error::Length);
+    }
+    let offset = length.saturating_sub(modules.len() as u32 * scale) / 2;
+
+    area.into_styled(PrimitiveStyle::with_fill(BinaryColor::Off))
+        .draw(image)
+        .ok();
+
+    for (idx, bar) in modules.iter().enumerate() {
+        if *bar == 0 {
+            continue;
+        }
+        let pos = (offset + idx as u32 * scale) as i32;
+        let bar_rect = if vertical {
+            Rectangle::new(area.top_left + Point::new(0, pos), Size::new(depth, scale))
+        } else {
+            Rectangle::new(area.top_left + Point::new(pos, 0), Size::new(scale, depth))
+        };
+        bar_rect
+            .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+            .draw(image)
+            .ok();
+    }
+    Ok(())
+}
@@ -0,0 +1,77 @@
+//! Push buttons on GPIO pins, as fitted to many 2.13" HAT cases. Each button is taken to
+//! connect its pin to ground, so the internal pull-up is enabled and a low level means
+//! pressed. The pins are polled from a thread of their own and debounced there.
+
+use std::{
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use rppal::gpio::{Gpio, InputPin};
+
+/// How long a button has to stay pressed or released for the change to count.
+pub const DEBOUNCE: Duration = Duration::from_millis(30);
+
+/// How often the pins are read.
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Debounces one input: a new level counts once it has held for the debounce time, so
+/// contact bounce and short glitches are ignored.
+#[derive(Debug, Clone)]
+pub struct Debouncer {
+    debounce: Duration,
+    /// The level that counts.
+    stable: bool,
+    /// A different level seen since, and when it was first seen.
+    changing: Option<Instant>,
+}
+
+impl Debouncer {
+    /// An input that is `pressed` to begin with.
+    pub fn new(pressed: bool, debounce: Duration) -> Self {
+        Self {
+            debounce,
+            stable: pressed,
+            changing: None,
+        }
+    }
+
+    /// Feed the level read at `now`; returns the new state when it changes for good.
+    pub fn update(&mut self, pressed: bool, now: Instant) -> Option<bool> {
+        if pressed == self.stable {
+            self.changing = None;
+            return None;
+        }
+        let since = *self.changing.get_or_insert(now);
+        if now.duration_since(since) < self.debounce {
+            return None;
+        }
+        self.stable = pressed;
+        self.changing = None;
+        Some(pressed)
+    }
+}
+
+/// Watch the buttons on `pins` (BCM numbers) and send the pin of each press to `presses`.
+/// The thread ends once the receiver is gone.
+pub fn watch(pins: &[u8], presses: mpsc::Sender<u8>) -> Result<(), rppal::gpio::Error> {
+    let gpio = Gpio::new()?;
+    let mut buttons: Vec<(u8, InputPin, Debouncer)> = Vec::new();
+    for &pin in pins {
+        let input = gpio.get(pin)?.into_input_pullup();
+        let debouncer = Debouncer::new(input.is_low(), DEBOUNCE);
+        buttons.push((pin, input, debouncer));
+    }
+    thread::spawn(move || loop {
+        let now = Instant::now();
+        for (pin, input, debouncer) in &mut buttons {
+            let pressed = debouncer.update(input.is_low(), now) == Some(true);
+            if pressed && presses.send(*pin).is_err() {
+                return;
+            }
+        }
+        thread::sleep(POLL_INTERVAL);
+    });
+    Ok(())
+}
@@ -0,0 +1,42 @@
+//! An active buzzer on a GPIO pin, one that sounds while the pin is driven high. Beeps are
+//! played from a thread of their own so that the caller is not held up while they sound.
+
+use std::{sync::mpsc, thread, time::Duration};
+
+use rppal::gpio::Gpio;
+
+/// How long each beep sounds.
+const BEEP: Duration = Duration::from_millis(150);
+/// The silence between the beeps of one signal.
+const PAUSE: Duration = Duration::from_millis(120);
+
+/// A buzzer waiting for signals to play.
+pub struct Buzzer {
+    signals: mpsc::Sender<u32>,
+}
+
+impl Buzzer {
+    /// Drive the buzzer on `pin` (BCM number), silent to begin with.
+    pub fn open(pin: u8) -> Result<Self, rppal::gpio::Error> {
+        let mut output = Gpio::new()?.get(pin)?.into_output_low();
+        let (signals, received) = mpsc::channel::<u32>();
+        thread::spawn(move || {
+            for count in received {
+                for beep in 0..count {
+                    if beep > 0 {
+                        thread::sleep(PAUSE);
+                    }
+                    output.set_high();
+                    thread::sleep(BEEP);
+                    output.set_low();
+                }
+            }
+        });
+        Ok(Self { signals })
+    }
+
+    /// Sound `count` short beeps in a row.
+    pub fn beep(&self, count: u32) {
+        self.signals.send(count).ok();
+    }
+}
@@ -0,0 +1,115 @@
+//! Month-grid calendar rendering.
+
+use chrono::{Datelike, NaiveDate};
+use embedded_graphics::{
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
+};
+
+use crate::markup::BOLD_MARKER;
+use crate::text::{Alignment, TextLayout};
+
+const WEEKDAYS: [&str; 7] = ["Mo", "Tu", "We", "Th", "Fr", "Sa", "Su"];
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+/// Most event dots drawn under a day; busier days still show this many.
+const MAX_DOTS: usize = 3;
+
+/// Draw the month containing `month` (weeks start on Monday) inside `area`: a title row,
+/// weekday initials and up to six weeks. `today` is drawn inverted when it falls in the
+/// month, and `events(day)` adds up to three dots under each day number.
+pub fn draw_month<D>(
+    target: &mut D,
+    area: Rectangle,
+    month: NaiveDate,
+    today: Option<NaiveDate>,
+    events: impl Fn(u32) -> usize,
+    layout: &TextLayout,
+) where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    let first = month.with_day(1).unwrap_or(month);
+    let days = days_in_month(first);
+    let offset = first.weekday().num_days_from_monday();
+    let weeks = (offset + days).div_ceil(7);
+
+    let cell_layout = TextLayout {
+        alignment: "center,top".parse().unwrap_or_default(),
+        auto_fit: false,
+        ..*layout
+    };
+    let rows = weeks + 2;
+    let row_height = area.size.height / rows;
+    let col_width = area.size.width / 7;
+    let grid_left = area.top_left.x + (area.size.width - col_width * 7) as i32 / 2;
+    let cell = |col: u32, row: u32| {
+        Rectangle::new(
+            Point::new(
+                grid_left + (col * col_width) as i32,
+                area.top_left.y + (row * row_height) as i32,
+            ),
+            Size::new(col_width, row_height),
+        )
+    };
+
+    let title = format!("{} {}", MONTHS[first.month0() as usize], first.year());
+    let title_area = Rectangle::new(area.top_left, Size::new(area.size.width, row_height));
+    TextLayout {
+        alignment: Alignment::default(),
+        ..cell_layout
+    }
+    .draw(target, title_area, &format!("{BOLD_MARKER}{title}{BOLD_MARKER}"));
+
+    for (col, name) in WEEKDAYS.iter().enumerate() {
+        cell_layout.draw(target, cell(col as u32, 1), name);
+    }
+
+    let char_height = layout.font.char_size().height;
+    for day in 1..=days {
+        let index = offset + day - 1;
+        let area = cell(index % 7, index / 7 + 2);
+        let is_today = today.is_some_and(|t| t == first.with_day(day).unwrap_or(first));
+        let mut style = cell_layout;
+        if is_today {
+            area.into_styled(PrimitiveStyle::with_fill(layout.foreground))
+                .draw(target)
+                .ok();
+            style.foreground = layout.background;
+            style.background = layout.foreground;
+        }
+        style.draw(target, area, &day.to_string());
+
+        let dots = events(day).min(MAX_DOTS) as i32;
+        let dots_width = dots * 3 - 1;
+        let x = area.center().x - dots_width / 2;
+        let y = area.top_left.y + char_height as i32 + 1;
+        for i in 0..dots {
+            Rectangle::new(Point::new(x + i * 3, y), Size::new(2, 2))
+                .into_styled(PrimitiveStyle::with_fill(style.foreground))
+                .draw(target)
+                .ok();
+        }
+    }
+}
+
+fn days_in_month(first: NaiveDate) -> u32 {
+    let next = if first.month() == 12 {
+        NaiveDate::from_ymd_opt(first.year() + 1, 1, 1)
+    } else {
+        NaiveDate::from_ymd_opt(first.year(), first.month() + 1, 1)
+    };
+    next.map_or(31, |next| (next - first).num_days() as u32)
+}
@@ -0,0 +1,102 @@
+//! A client for the Unix socket protocol of `serve`, so other programs can drive the panel
+//! through a running server.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ClientError {
+    #[error("cannot talk to the server: {0}")]
+    Io(#[from] io::Error),
+    #[error("the server closed the connection")]
+    Closed,
+    /// An `ERR <status> <CODE> [message]` reply.
+    #[error("the server refused the command: {code} ({status})")]
+    Refused {
+        status: u16,
+        code: String,
+        message: Option<String>,
+    },
+    #[error("unexpected reply from the server: {0}")]
+    Unexpected(String),
+}
+
+/// A connection to the server. Commands are sent one at a time and each waits for its reply.
+pub struct EinkClient {
+    stream: UnixStream,
+    reader: BufReader<UnixStream>,
+}
+
+impl EinkClient {
+    /// Connect to the socket `serve` listens on, e.g. `/tmp/eink.sock`.
+    pub fn connect(path: impl AsRef<Path>) -> Result<Self, ClientError> {
+        let stream = UnixStream::connect(path)?;
+        let reader = BufReader::new(stream.try_clone()?);
+        Ok(Self { stream, reader })
+    }
+
+    /// Show wrapped text with the server's current font and alignment. Line breaks are kept,
+    /// and so are `\n` escapes, which the server turns into line breaks too. Returns the
+    /// server's reply, like all commands.
+    pub fn send_text(&mut self, text: &str) -> Result<String, ClientError> {
+        let text = text.replace("\r\n", "\n").replace('\n', "\\n");
+        self.command(&format!("TEXT {text}"))
+    }
+
+    /// Show an image file (PNG, JPEG, GIF, BMP), dithered by the server.
+    pub fn send_image(&mut self, image: &[u8]) -> Result<String, ClientError> {
+        self.command_with_data(&format!("IMAGE BYTES {}", image.len()), image)
+    }
+
+    /// Clear the panel.
+    pub fn clear(&mut self) -> Result<String, ClientError> {
+        self.command("CLEAR")
+    }
+
+    /// Send any single-line command, e.g. `QR https://example.com` or `STATUS`, and return
+    /// the server's reply without its trailing newline. `ERR` replies become
+    /// [`ClientError::Refused`].
+    pub fn command(&mut self, line: &str) -> Result<String, ClientError> {
+        self.command_with_data(line, &[])
+    }
+
+    /// Send a command line followed by the payload it announces, as `FRAME RAW <len>` does.
+    pub fn command_with_data(&mut self, line: &str, data: &[u8]) -> Result<String, ClientError> {
+        if line.contains('\n') {
+            return Err(ClientError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "a command must fit on one line",
+            )));
+        }
+        self.stream.write_all(line.as_bytes())?;
+        self.stream.write_all(b"\n")?;
+        self.stream.write_all(data)?;
+        self.stream.flush()?;
+
+        let mut reply = String::new();
+        if self.reader.read_line(&mut reply)? == 0 {
+            return Err(ClientError::Closed);
+        }
+        let reply = reply.trim_end_matches(['\r', '\n']).to_string();
+        match reply.strip_prefix("ERR ") {
+            Some(rest) => Err(parse_refusal(rest).unwrap_or(ClientError::Unexpected(reply))),
+            None => Ok(reply),
+        }
+    }
+}
+
+/// `<status> <CODE> [message]` from an error reply.
+fn parse_refusal(rest: &str) -> Option<ClientError> {
+    let mut parts = rest.splitn(3, ' ');
+    let status = parts.next()?.parse().ok()?;
+    let code = parts.next()?.to_string();
+    let message = parts.next().map(str::to_string);
+    Some(ClientError::Refused {
+        status,
+        code,
+        message,
+    })
+}
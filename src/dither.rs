@@ -0,0 +1,307 @@
+//! Grayscale scratch buffers and dithering down to the panel's 1-bit format.
+
+use embedded_graphics::pixelcolor::BinaryColor;
+use std::{fmt, path::Path, str::FromStr};
+
+use crate::buffer::MonoImage;
+
+/// 8-bit grayscale image used as an intermediate step before dithering. `0` is black and
+/// `255` is white.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GrayImage {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+impl GrayImage {
+    /// An image filled with `level`.
+    pub fn new(width: u32, height: u32, level: u8) -> Self {
+        Self {
+            width,
+            height,
+            data: vec![level; width as usize * height as usize],
+        }
+    }
+
+    /// Wrap row-major luminance bytes; returns `None` if the length does not match.
+    pub fn from_raw(width: u32, height: u32, data: Vec<u8>) -> Option<Self> {
+        (data.len() == width as usize * height as usize).then_some(Self {
+            width,
+            height,
+            data,
+        })
+    }
+
+    /// Decode a PNG, JPEG, GIF or BMP file and convert it to luminance. Transparent pixels
+    /// are composited over white.
+    pub fn open(path: &Path) -> Result<Self, image::ImageError> {
+        Ok(Self::from_dynamic(image::open(path)?))
+    }
+
+    /// Like [`GrayImage::open`], for an encoded image already in memory.
+    pub fn decode(bytes: &[u8]) -> Result<Self, image::ImageError> {
+        Ok(Self::from_dynamic(image::load_from_memory(bytes)?))
+    }
+
+    fn from_dynamic(image: image::DynamicImage) -> Self {
+        let rgba = image.into_rgba8();
+        let (width, height) = rgba.dimensions();
+        let data = rgba
+            .pixels()
+            .map(|p| {
+                let [r, g, b, a] = p.0;
+                let luma = (r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000;
+                let a = a as u32;
+                ((luma * a + 255 * (255 - a)) / 255) as u8
+            })
+            .collect();
+        Self {
+            width,
+            height,
+            data,
+        }
+    }
+
+    /// Convert a 1-bit image: `On` pixels become black, `Off` pixels white.
+    pub fn from_mono(image: &MonoImage) -> Self {
+        let mut gray = Self::new(image.width(), image.height(), 255);
+        for y in 0..image.height() {
+            for x in 0..image.width() {
+                if image.pixel(x, y) == BinaryColor::On {
+                    gray.set(x, y, 0);
+                }
+            }
+        }
+        gray
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Luminance at `(x, y)`; out-of-bounds reads are clamped to the nearest edge.
+    pub fn get(&self, x: u32, y: u32) -> u8 {
+        let x = x.min(self.width.saturating_sub(1));
+        let y = y.min(self.height.saturating_sub(1));
+        self.data[y as usize * self.width as usize + x as usize]
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, level: u8) {
+        if x < self.width && y < self.height {
+            self.data[y as usize * self.width as usize + x as usize] = level;
+        }
+    }
+
+    /// Swap black and white.
+    pub fn invert(&mut self) {
+        for level in &mut self.data {
+            *level = 255 - *level;
+        }
+    }
+
+    /// Bilinear resize to `width` x `height`.
+    pub fn resize(&self, width: u32, height: u32) -> Self {
+        let mut out = Self::new(width, height, 255);
+        if self.width == 0 || self.height == 0 {
+            return out;
+        }
+        let sx = self.width as f32 / width as f32;
+        let sy = self.height as f32 / height as f32;
+        for y in 0..height {
+            let fy = ((y as f32 + 0.5) * sy - 0.5).max(0.0);
+            let (y0, ty) = (fy as u32, fy.fract());
+            for x in 0..width {
+                let fx = ((x as f32 + 0.5) * sx - 0.5).max(0.0);
+                let (x0, tx) = (fx as u32, fx.fract());
+                let top = lerp(self.get(x0, y0), self.get(x0 + 1, y0), tx);
+                let bottom = lerp(self.get(x0, y0 + 1), self.get(x0 + 1, y0 + 1), tx);
+                out.set(x, y, (top + (bottom - top) * ty).round() as u8);
+            }
+        }
+        out
+    }
+
+    /// Scale into a `width` x `height` canvas according to `mode`; uncovered areas are white.
+    pub fn fit(&self, width: u32, height: u32, mode: FitMode) -> Self {
+        if mode == FitMode::Stretch || self.width == 0 || self.height == 0 {
+            return self.resize(width, height);
+        }
+        let scale_x = width as f32 / self.width as f32;
+        let scale_y = height as f32 / self.height as f32;
+        let scale = match mode {
+            FitMode::Cover => scale_x.max(scale_y),
+            _ => scale_x.min(scale_y),
+        };
+        let scaled_w = ((self.width as f32 * scale).round() as u32).max(1);
+        let scaled_h = ((self.height as f32 * scale).round() as u32).max(1);
+        let scaled = self.resize(scaled_w, scaled_h);
+
+        // Center the scaled image; offsets are negative when it overflows (cover).
+        let off_x = (width as i64 - scaled_w as i64) / 2;
+        let off_y = (height as i64 - scaled_h as i64) / 2;
+        let mut out = Self::new(width, height, 255);
+        for y in 0..height {
+            let sy = y as i64 - off_y;
+            if sy < 0 || sy >= scaled_h as i64 {
+                continue;
+            }
+            for x in 0..width {
+                let sx = x as i64 - off_x;
+                if sx >= 0 && sx < scaled_w as i64 {
+                    out.set(x, y, scaled.get(sx as u32, sy as u32));
+                }
+            }
+        }
+        out
+    }
+
+    /// Shrink by an integer `factor`, averaging each `factor` x `factor` block. This is
+    /// the box filter used for supersampled rendering.
+    pub fn downsample(&self, factor: u32) -> Self {
+        let factor = factor.max(1);
+        let (width, height) = (self.width / factor, self.height / factor);
+        let mut out = Self::new(width, height, 255);
+        let area = factor * factor;
+        for y in 0..height {
+            for x in 0..width {
+                let sum: u32 = (0..factor)
+                    .flat_map(|dy| (0..factor).map(move |dx| (dx, dy)))
+                    .map(|(dx, dy)| self.get(x * factor + dx, y * factor + dy) as u32)
+                    .sum();
+                out.set(x, y, (sum / area) as u8);
+            }
+        }
+        out
+    }
+}
+
+fn lerp(a: u8, b: u8, t: f32) -> f32 {
+    a as f32 + (b as f32 - a as f32) * t
+}
+
+/// How an image is scaled onto a canvas of a different aspect ratio.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FitMode {
+    /// Scale to fit entirely inside, padding the rest with white.
+    #[default]
+    Contain,
+    /// Scale to fill the canvas, cropping whatever overflows.
+    Cover,
+    /// Scale each axis independently, distorting the aspect ratio.
+    Stretch,
+}
+
+impl fmt::Display for FitMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FitMode::Contain => "contain",
+            FitMode::Cover => "cover",
+            FitMode::Stretch => "stretch",
+        })
+    }
+}
+
+impl FromStr for FitMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "contain" => Ok(FitMode::Contain),
+            "cover" => Ok(FitMode::Cover),
+            "stretch" => Ok(FitMode::Stretch),
+            _ => Err(format!(
+                "unknown fit mode '{s}' (expected contain, cover or stretch)"
+            )),
+        }
+    }
+}
+
+/// How grays are turned into black and white pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DitherMethod {
+    /// Plain 50% threshold; crisp but loses all gray levels.
+    Threshold,
+    /// 4x4 Bayer matrix; a regular pattern that stays stable across partial refreshes.
+    Ordered,
+    /// Floyd–Steinberg error diffusion.
+    #[default]
+    FloydSteinberg,
+}
+
+impl fmt::Display for DitherMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DitherMethod::Threshold => "threshold",
+            DitherMethod::Ordered => "ordered",
+            DitherMethod::FloydSteinberg => "floyd",
+        })
+    }
+}
+
+impl FromStr for DitherMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "threshold" | "none" => Ok(DitherMethod::Threshold),
+            "ordered" | "bayer" => Ok(DitherMethod::Ordered),
+            "floyd" | "floyd-steinberg" => Ok(DitherMethod::FloydSteinberg),
+            _ => Err(format!(
+                "unknown dither method '{s}' (expected threshold, ordered or floyd)"
+            )),
+        }
+    }
+}
+
+/// Bayer thresholds for [`DitherMethod::Ordered`], scaled to 0..=255.
+const BAYER_4X4: [[i16; 4]; 4] = [
+    [8, 136, 40, 168],
+    [200, 72, 232, 104],
+    [56, 184, 24, 152],
+    [248, 120, 216, 88],
+];
+
+/// Dither `gray` into a 1-bit image of the same size (`On` = black).
+pub fn dither(gray: &GrayImage, method: DitherMethod) -> MonoImage {
+    let (width, height) = (gray.width, gray.height);
+    let mut out = MonoImage::new(width, height);
+    let mut levels: Vec<i16> = gray.data.iter().map(|&v| v as i16).collect();
+    let idx = |x: u32, y: u32| y as usize * width as usize + x as usize;
+
+    for y in 0..height {
+        for x in 0..width {
+            let old = levels[idx(x, y)];
+            let threshold = match method {
+                DitherMethod::Ordered => BAYER_4X4[y as usize % 4][x as usize % 4],
+                _ => 128,
+            };
+            let new = if old < threshold { 0 } else { 255 };
+            if new == 0 {
+                out.set_pixel(x, y, BinaryColor::On);
+            }
+            if method != DitherMethod::FloydSteinberg {
+                continue;
+            }
+
+            let err = old - new;
+            let mut spread = |dx: i32, dy: u32, weight: i16| {
+                let nx = x as i32 + dx;
+                let ny = y + dy;
+                if nx >= 0 && (nx as u32) < width && ny < height {
+                    let i = idx(nx as u32, ny);
+                    levels[i] = (levels[i] + err * weight / 16).clamp(-255, 510);
+                }
+            };
+            spread(1, 0, 7);
+            spread(-1, 1, 3);
+            spread(0, 1, 5);
+            spread(1, 1, 1);
+        }
+    }
+    out
+}
@@ -0,0 +1,145 @@
+//! Container status from the local Docker Engine API, spoken as plain HTTP/1.0 over its Unix
+//! socket, and its event stream to hear when containers start, stop or change health.
+
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    os::unix::net::UnixStream,
+    path::Path,
+    time::Duration,
+};
+
+use serde::Deserialize;
+use thiserror::Error;
+
+/// Where the Docker daemon listens by default.
+pub const DEFAULT_SOCKET: &str = "/var/run/docker.sock";
+
+#[derive(Debug, Error)]
+pub enum DockerError {
+    #[error("cannot talk to Docker: {0}")]
+    Io(#[from] io::Error),
+    #[error("Docker replied with invalid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Docker replied {0}")]
+    Status(String),
+    #[error("Docker closed the event stream")]
+    Closed,
+}
+
+/// How a container is doing, as shown by its indicator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Health {
+    /// Not running: exited, created or dead.
+    Down,
+    /// Running but unhealthy, restarting or paused.
+    Degraded,
+    Up,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Container {
+    #[serde(rename = "Names", deserialize_with = "first_name")]
+    pub name: String,
+    /// `running`, `exited`, `restarting`, `paused`, `created` or `dead`.
+    #[serde(rename = "State")]
+    pub state: String,
+    /// Docker's summary, such as `Up 3 hours (healthy)` or `Exited (0) 2 days ago`.
+    #[serde(rename = "Status")]
+    pub status: String,
+}
+
+impl Container {
+    pub fn health(&self) -> Health {
+        match self.state.as_str() {
+            "running" if self.status.contains("(unhealthy)") => Health::Degraded,
+            "running" => Health::Up,
+            "restarting" | "paused" => Health::Degraded,
+            _ => Health::Down,
+        }
+    }
+}
+
+/// The first of a container's names, without the leading `/`.
+fn first_name<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    let names: Vec<String> = Vec::deserialize(deserializer)?;
+    let name = names.into_iter().next().unwrap_or_default();
+    Ok(name.trim_start_matches('/').to_string())
+}
+
+/// All containers, stopped ones included, the ones not up first and then by name.
+pub fn containers(socket: &Path) -> Result<Vec<Container>, DockerError> {
+    let mut reader = request(socket, "/containers/json?all=1")?;
+    let mut body = String::new();
+    reader.read_to_string(&mut body)?;
+    let mut containers: Vec<Container> = serde_json::from_str(&body)?;
+    containers.sort_by(|a, b| (a.health(), &a.name).cmp(&(b.health(), &b.name)));
+    Ok(containers)
+}
+
+/// Send a GET for `path` and return the body, once the status line says it is there. An
+/// HTTP/1.0 request makes the daemon send the body plainly and close the connection at its
+/// end, so no chunked decoding is needed.
+fn request(socket: &Path, path: &str) -> Result<BufReader<UnixStream>, DockerError> {
+    let mut stream = UnixStream::connect(socket)?;
+    stream.write_all(format!("GET {path} HTTP/1.0\r\nHost: docker\r\n\r\n").as_bytes())?;
+    let mut reader = BufReader::new(stream);
+    let mut status = String::new();
+    reader.read_line(&mut status)?;
+    let status = status.trim_end();
+    if status.split_whitespace().nth(1) != Some("200") {
+        return Err(DockerError::Status(status.to_string()));
+    }
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim_end().is_empty() {
+            return Ok(reader);
+        }
+    }
+}
+
+/// How long to keep reading after an event, so that a burst of them, as from
+/// `docker compose up`, counts once.
+const SETTLE: Duration = Duration::from_secs(1);
+
+/// The daemon's stream of container events.
+pub struct DockerEvents {
+    reader: BufReader<UnixStream>,
+}
+
+impl DockerEvents {
+    pub fn open(socket: &Path) -> Result<Self, DockerError> {
+        let filters = "%7B%22type%22%3A%5B%22container%22%5D%7D"; // {"type":["container"]}
+        let reader = request(socket, &format!("/events?filters={filters}"))?;
+        Ok(Self { reader })
+    }
+
+    /// Wait up to `timeout` for a container event; returns whether there was one.
+    pub fn wait(&mut self, timeout: Duration) -> Result<bool, DockerError> {
+        if !self.next(timeout)? {
+            return Ok(false);
+        }
+        while self.next(SETTLE)? {}
+        Ok(true)
+    }
+
+    /// Read one event within `timeout`.
+    fn next(&mut self, timeout: Duration) -> Result<bool, DockerError> {
+        self.reader
+            .get_ref()
+            .set_read_timeout(Some(timeout.max(Duration::from_millis(1))))?;
+        let mut line = String::new();
+        match self.reader.read_line(&mut line) {
+            Ok(0) => Err(DockerError::Closed),
+            Ok(_) => Ok(true),
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                Ok(false)
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+}
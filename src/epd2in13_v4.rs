@@ -1,12 +1,12 @@
-/// This file is a driver for the Waveshare 2.13" V4 e-paper display module.
-/// It uses the rppal crate for SPI and GPIO access on Raspberry Pi.
-/// It supports full, fast, and partial updates, as well as clearing the display
-/// and putting the display to sleep.
-/// 
-/// Copyright (c) 2025 Santiago Saavedra - Initial Rust version
-/// Copyright (c) 2023 Waveshare Team - Original specifications
-/// 
-/// Original copyright notice from Waveshare:
+//! This file is a driver for the Waveshare 2.13" V4 e-paper display module.
+//! It uses the rppal crate for SPI and GPIO access on Raspberry Pi.
+//! It supports full, fast, and partial updates, as well as clearing the display
+//! and putting the display to sleep.
+//!
+//! Copyright (c) 2025 Santiago Saavedra - Initial Rust version
+//! Copyright (c) 2023 Waveshare Team - Original specifications
+//!
+//! Original copyright notice from Waveshare:
 // # *****************************************************************************
 // # * | File        :	  epd2in13_V4.py
 // # * | Author      :   Waveshare team
@@ -36,14 +36,21 @@
 // # THE SOFTWARE.
 
 
-use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::{pixelcolor::BinaryColor, primitives::Rectangle};
 use rppal::{
     gpio::{Gpio, InputPin, OutputPin},
     spi::{Bus, Mode, SlaveSelect, Spi},
 };
-use std::{thread::sleep, time::Duration};
+use std::{
+    thread::sleep,
+    time::{Duration, Instant},
+};
 use thiserror::Error;
 
+/// Longest wait for the panel to finish a reset or refresh; full refreshes take a few
+/// seconds, longer in the cold.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(10);
+
 /// Pin assignments for the panel, using BCM numbering.
 #[derive(Debug, Clone, Copy)]
 pub struct EpdPins {
@@ -53,6 +60,18 @@ pub struct EpdPins {
     pub rst: u8,
 }
 
+impl Default for EpdPins {
+    /// Default Waveshare HAT wiring: BUSY=24, RST=17, DC=25, CS=8.
+    fn default() -> Self {
+        Self {
+            busy: 24,
+            dc: 25,
+            cs: 8,
+            rst: 17,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum UpdateMode {
     Normal,
@@ -68,6 +87,19 @@ pub enum EpdError {
     Gpio(#[from] rppal::gpio::Error),
     #[error("buffer length mismatch: expected {expected} bytes, got {actual}")]
     BufferSize { expected: usize, actual: usize },
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("panel still busy after {0:?}")]
+    BusyTimeout(Duration),
+}
+
+/// Time spent in one [`Epd2in13V4::display_timed`] call.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RefreshTiming {
+    /// Command setup and pushing the image over SPI.
+    pub transfer: Duration,
+    /// Waiting for the panel to finish the waveform (BUSY low again).
+    pub refresh: Duration,
 }
 
 pub struct Epd2in13V4 {
@@ -76,16 +108,24 @@ pub struct Epd2in13V4 {
     dc: OutputPin,
     cs: OutputPin,
     rst: OutputPin,
+    _pwr: Option<OutputPin>,
     bytes_per_row: usize,
 }
 
 impl Epd2in13V4 {
     pub const WIDTH: u16 = 122;
     pub const HEIGHT: u16 = 250;
+    /// SPI clock used by [`Epd2in13V4::new`].
+    pub const SPI_CLOCK_HZ: u32 = 4_000_000;
 
     /// Create a driver with the default SPI bus (SPI0, CE0) at 4 MHz.
     pub fn new(pins: EpdPins) -> Result<Self, EpdError> {
-        let spi = Spi::new(Bus::Spi0, SlaveSelect::Ss0, 4_000_000, Mode::Mode0)?;
+        Self::on_bus(Bus::Spi0, SlaveSelect::Ss0, pins)
+    }
+
+    /// Create a driver on another SPI bus or chip select line, at 4 MHz, for a second panel.
+    pub fn on_bus(bus: Bus, slave_select: SlaveSelect, pins: EpdPins) -> Result<Self, EpdError> {
+        let spi = Spi::new(bus, slave_select, Self::SPI_CLOCK_HZ, Mode::Mode0)?;
         Self::with_spi(spi, pins)
     }
 
@@ -96,22 +136,30 @@ impl Epd2in13V4 {
         let dc = gpio.get(pins.dc)?.into_output();
         let rst = gpio.get(pins.rst)?.into_output();
         let cs = gpio.get(pins.cs)?.into_output();
-        let bytes_per_row = ((Self::WIDTH as usize) + 7) / 8;
+        let bytes_per_row = (Self::WIDTH as usize).div_ceil(8);
         Ok(Self {
             spi,
             busy,
             dc,
             cs,
             rst,
+            _pwr: None,
             bytes_per_row,
         })
     }
 
+    /// Drive the panel power switch found on newer HAT revisions high for as long as the
+    /// driver lives. Call it before [`Epd2in13V4::init`].
+    pub fn with_power_pin(mut self, pin: u8) -> Result<Self, EpdError> {
+        self._pwr = Some(Gpio::new()?.get(pin)?.into_output_high());
+        Ok(self)
+    }
+
     pub fn init(&mut self) -> Result<(), EpdError> {
         self.reset()?;
-        self.wait_until_idle();
+        self.wait_until_idle()?;
         self.command(0x12)?; // SWRESET
-        self.wait_until_idle();
+        self.wait_until_idle()?;
 
         self.command_data(0x01, &[0xF9, 0x00, 0x00])?; // driver output control
         self.command_data(0x11, &[0x03])?; // data entry mode
@@ -123,7 +171,7 @@ impl Epd2in13V4 {
         self.command_data(0x21, &[0x00, 0x80])?; // display update control
 
         self.command_data(0x18, &[0x80])?; // enable internal temp sensor
-        self.wait_until_idle();
+        self.wait_until_idle()?;
 
         Ok(())
     }
@@ -131,7 +179,7 @@ impl Epd2in13V4 {
     pub fn init_fast(&mut self) -> Result<(), EpdError> {
         self.reset()?;
         self.command(0x12)?;
-        self.wait_until_idle();
+        self.wait_until_idle()?;
 
         self.command_data(0x18, &[0x80])?;
         self.command_data(0x11, &[0x03])?;
@@ -140,12 +188,12 @@ impl Epd2in13V4 {
 
         self.command_data(0x22, &[0xB1])?;
         self.command(0x20)?;
-        self.wait_until_idle();
+        self.wait_until_idle()?;
 
         self.command_data(0x1A, &[0x64, 0x00])?;
         self.command_data(0x22, &[0x91])?;
         self.command(0x20)?;
-        self.wait_until_idle();
+        self.wait_until_idle()?;
         Ok(())
     }
 
@@ -160,13 +208,11 @@ impl Epd2in13V4 {
     }
 
     pub fn display(&mut self, image: &[u8]) -> Result<(), EpdError> {
-        self.write_image(0x24, image)?;
-        self.turn_on_display(UpdateMode::Normal)
+        self.display_timed(image, UpdateMode::Normal).map(|_| ())
     }
 
     pub fn display_fast(&mut self, image: &[u8]) -> Result<(), EpdError> {
-        self.write_image(0x24, image)?;
-        self.turn_on_display(UpdateMode::Fast)
+        self.display_timed(image, UpdateMode::Fast).map(|_| ())
     }
 
     pub fn display_base(&mut self, image: &[u8]) -> Result<(), EpdError> {
@@ -176,15 +222,110 @@ impl Epd2in13V4 {
     }
 
     pub fn display_partial(&mut self, image: &[u8]) -> Result<(), EpdError> {
+        self.display_timed(image, UpdateMode::Partial).map(|_| ())
+    }
+
+    /// Show `image` with the given refresh mode, timing the SPI transfer and the panel
+    /// refresh separately. `Fast` expects [`Epd2in13V4::init_fast`] and `Partial` a prior
+    /// [`Epd2in13V4::display_base`].
+    pub fn display_timed(
+        &mut self,
+        image: &[u8],
+        mode: UpdateMode,
+    ) -> Result<RefreshTiming, EpdError> {
+        let start = Instant::now();
+        if let UpdateMode::Partial = mode {
+            self.prepare_partial()?;
+        }
+        self.write_image(0x24, image)?;
+        let transfer = start.elapsed();
+
+        let start = Instant::now();
+        self.turn_on_display(mode)?;
+        Ok(RefreshTiming {
+            transfer,
+            refresh: start.elapsed(),
+        })
+    }
+
+    /// Partial refresh for a controller that was reset or put to sleep since the panel
+    /// last changed, e.g. in a new process. No init or base refresh is needed: `previous`,
+    /// which must be what the panel currently shows, is loaded as the reference image first.
+    pub fn display_partial_over(
+        &mut self,
+        previous: &[u8],
+        image: &[u8],
+    ) -> Result<RefreshTiming, EpdError> {
+        let start = Instant::now();
+        self.prepare_partial()?;
+        self.write_image(0x26, previous)?;
+        self.write_image(0x24, image)?;
+        let transfer = start.elapsed();
+
+        let start = Instant::now();
+        self.turn_on_display(UpdateMode::Partial)?;
+        Ok(RefreshTiming {
+            transfer,
+            refresh: start.elapsed(),
+        })
+    }
+
+    /// Partial refresh that sends only the part of `image` inside `window` (widened to whole
+    /// bytes horizontally). The rest of the controller RAM keeps the previous frame, so
+    /// `image` should match it outside the window. Like `Partial`, this needs a prior
+    /// [`Epd2in13V4::display_base`].
+    pub fn display_partial_window(
+        &mut self,
+        image: &[u8],
+        window: Rectangle,
+    ) -> Result<RefreshTiming, EpdError> {
+        let expected = self.bytes_per_row * Self::HEIGHT as usize;
+        if image.len() != expected {
+            return Err(EpdError::BufferSize {
+                expected,
+                actual: image.len(),
+            });
+        }
+        let Some(bottom_right) = window.bottom_right() else {
+            return Ok(RefreshTiming::default());
+        };
+        let x_start = window.top_left.x.clamp(0, Self::WIDTH as i32 - 1) as u16;
+        let y_start = window.top_left.y.clamp(0, Self::HEIGHT as i32 - 1) as u16;
+        let x_end = bottom_right.x.clamp(x_start as i32, Self::WIDTH as i32 - 1) as u16;
+        let y_end = bottom_right.y.clamp(y_start as i32, Self::HEIGHT as i32 - 1) as u16;
+
+        let start = Instant::now();
+        self.prepare_partial()?;
+        self.set_window(x_start, y_start, x_end, y_end)?;
+        self.set_cursor(x_start, y_start)?;
+        let columns = x_start as usize / 8..=x_end as usize / 8;
+        let mut data = Vec::new();
+        for row in y_start as usize..=y_end as usize {
+            let offset = row * self.bytes_per_row;
+            data.extend_from_slice(&image[offset + columns.start()..=offset + columns.end()]);
+        }
+        self.command(0x24)?;
+        self.data(&data)?;
+        // Later full-frame writes expect the whole RAM as the window.
+        self.set_window(0, 0, Self::WIDTH - 1, Self::HEIGHT - 1)?;
+        self.set_cursor(0, 0)?;
+        let transfer = start.elapsed();
+
+        let start = Instant::now();
+        self.turn_on_display(UpdateMode::Partial)?;
+        Ok(RefreshTiming {
+            transfer,
+            refresh: start.elapsed(),
+        })
+    }
+
+    fn prepare_partial(&mut self) -> Result<(), EpdError> {
         self.fast_reset()?; // partial updates need a short reset
         self.command_data(0x3C, &[0x80])?;
         self.command_data(0x01, &[0xF9, 0x00, 0x00])?;
         self.command_data(0x11, &[0x03])?;
         self.set_window(0, 0, Self::WIDTH - 1, Self::HEIGHT - 1)?;
-        self.set_cursor(0, 0)?;
-
-        self.write_image(0x24, image)?;
-        self.turn_on_display(UpdateMode::Partial)
+        self.set_cursor(0, 0)
     }
 
     pub fn sleep(&mut self) -> Result<(), EpdError> {
@@ -193,6 +334,11 @@ impl Epd2in13V4 {
         Ok(())
     }
 
+    /// Whether the controller is currently driving BUSY high (refresh or reset in progress).
+    pub fn is_busy(&self) -> bool {
+        self.busy.is_high()
+    }
+
     fn write_image(&mut self, command: u8, image: &[u8]) -> Result<(), EpdError> {
         let expected = self.bytes_per_row * Self::HEIGHT as usize;
         if image.len() != expected {
@@ -223,11 +369,18 @@ impl Epd2in13V4 {
         Ok(())
     }
 
-    fn wait_until_idle(&mut self) {
+    /// Wait for BUSY to go low; a panel that stays busy longer than [`BUSY_TIMEOUT`] is
+    /// probably disconnected or hung.
+    fn wait_until_idle(&mut self) -> Result<(), EpdError> {
+        let start = Instant::now();
         while self.busy.is_high() {
+            if start.elapsed() > BUSY_TIMEOUT {
+                return Err(EpdError::BusyTimeout(BUSY_TIMEOUT));
+            }
             sleep(Duration::from_millis(10));
         }
         sleep(Duration::from_millis(10));
+        Ok(())
     }
 
     fn set_window(
@@ -264,7 +417,7 @@ impl Epd2in13V4 {
         };
         self.command_data(0x22, &[control])?;
         self.command(0x20)?;
-        self.wait_until_idle();
+        self.wait_until_idle()?;
         Ok(())
     }
 
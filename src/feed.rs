@@ -0,0 +1,114 @@
+//! Headlines from RSS 2.0 and Atom feeds.
+
+use quick_xml::{events::Event, Reader};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum FeedError {
+    #[error("malformed feed XML: {0}")]
+    Xml(#[from] quick_xml::Error),
+    #[error("no RSS items or Atom entries found")]
+    Empty,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Headline {
+    pub title: String,
+    /// RSS `description` or Atom `summary`, with HTML tags stripped.
+    pub summary: Option<String>,
+}
+
+/// A parsed feed: its own title and the items in document order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Feed {
+    pub title: Option<String>,
+    pub headlines: Vec<Headline>,
+}
+
+impl Feed {
+    /// Parse RSS (`<item>`) or Atom (`<entry>`) XML. Namespaces are ignored, so prefixed
+    /// elements such as `<atom:title>` are read like plain ones.
+    pub fn parse(xml: &str) -> Result<Self, FeedError> {
+        let mut reader = Reader::from_str(xml);
+        reader.config_mut().trim_text(true);
+
+        let mut feed = Feed::default();
+        let mut item: Option<Headline> = None;
+        // Local name of the innermost open element and the text collected inside it.
+        let mut path: Vec<Vec<u8>> = Vec::new();
+        let mut text = String::new();
+
+        loop {
+            match reader.read_event()? {
+                Event::Start(start) => {
+                    let name = start.local_name().as_ref().to_vec();
+                    if matches!(name.as_slice(), b"item" | b"entry") {
+                        item = Some(Headline::default());
+                    }
+                    path.push(name);
+                    text.clear();
+                }
+                Event::Text(chunk) => match chunk.unescape() {
+                    Ok(chunk) => text.push_str(&chunk),
+                    Err(_) => text.push_str(&String::from_utf8_lossy(&chunk)),
+                },
+                Event::CData(chunk) => text.push_str(&String::from_utf8_lossy(&chunk)),
+                Event::End(_) => {
+                    let name = path.pop().unwrap_or_default();
+                    let value = collapse_whitespace(&text);
+                    match (name.as_slice(), item.as_mut()) {
+                        (b"item" | b"entry", Some(_)) => {
+                            if let Some(done) = item.take().filter(|h| !h.title.is_empty()) {
+                                feed.headlines.push(done);
+                            }
+                        }
+                        (b"title", Some(headline)) => headline.title = value,
+                        (b"description" | b"summary", Some(headline)) => {
+                            let plain = collapse_whitespace(&strip_tags(&value));
+                            headline.summary = Some(plain).filter(|s| !s.is_empty());
+                        }
+                        (b"title", None) if feed.title.is_none() && !value.is_empty() => {
+                            feed.title = Some(value);
+                        }
+                        _ => {}
+                    }
+                    text.clear();
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+
+        if feed.headlines.is_empty() {
+            return Err(FeedError::Empty);
+        }
+        Ok(feed)
+    }
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Drop `<...>` tags from escaped HTML content and decode the common entities left behind.
+fn strip_tags(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for ch in html.chars() {
+        match ch {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                out.push(' ');
+            }
+            _ if !in_tag => out.push(ch),
+            _ => {}
+        }
+    }
+    out.replace("&nbsp;", " ")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
@@ -0,0 +1,176 @@
+//! gRPC front end for the display (the `grpc` feature), implementing the `Display` service
+//! of `proto/eink.proto`. The server runs on its own Tokio runtime and passes each request
+//! to the thread that drives the panel as a [`GrpcJob`].
+
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::mpsc;
+
+use tokio::sync::{broadcast, oneshot};
+use tokio_stream::{wrappers::BroadcastStream, Stream, StreamExt};
+use tonic::{Request, Response, Status};
+
+mod service {
+    include!(concat!(env!("OUT_DIR"), "/eink.v1.Display.rs"));
+}
+
+pub use service::display_server::{Display, DisplayServer};
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TextRequest {
+    #[prost(string, tag = "1")]
+    pub text: String,
+    #[prost(string, tag = "2")]
+    pub font: String,
+    #[prost(string, tag = "3")]
+    pub align: String,
+    #[prost(string, tag = "4")]
+    pub mode: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ImageRequest {
+    #[prost(bytes = "vec", tag = "1")]
+    pub image: Vec<u8>,
+    #[prost(string, tag = "2")]
+    pub dither: String,
+    #[prost(string, tag = "3")]
+    pub fit: String,
+    #[prost(string, tag = "4")]
+    pub mode: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ClearRequest {}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct DisplayReply {
+    #[prost(string, tag = "1")]
+    pub mode: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SubscribeRequest {}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct DisplayEvent {
+    #[prost(string, tag = "1")]
+    pub event: String,
+    #[prost(string, tag = "2")]
+    pub command: String,
+    #[prost(string, tag = "3")]
+    pub mode: String,
+    #[prost(string, tag = "4")]
+    pub source: String,
+}
+
+/// What a request asks of the panel.
+#[derive(Debug, Clone)]
+pub enum GrpcCommand {
+    Text(TextRequest),
+    Image(ImageRequest),
+    Clear,
+}
+
+/// A request for the panel thread, and where its outcome goes.
+pub struct GrpcJob {
+    pub command: GrpcCommand,
+    pub reply: oneshot::Sender<Result<DisplayReply, Status>>,
+}
+
+/// Events kept for subscribers that fall behind; older ones are dropped for them.
+const EVENT_BACKLOG: usize = 64;
+
+/// The `Display` service: requests go to `jobs`, and `events` feeds `Subscribe`.
+pub struct DisplayService {
+    jobs: mpsc::SyncSender<GrpcJob>,
+    events: broadcast::Sender<DisplayEvent>,
+}
+
+impl DisplayService {
+    /// A service passing requests to `jobs`; send display events to the returned sender.
+    pub fn new(jobs: mpsc::SyncSender<GrpcJob>) -> (Self, broadcast::Sender<DisplayEvent>) {
+        let (events, _) = broadcast::channel(EVENT_BACKLOG);
+        let service = Self {
+            jobs,
+            events: events.clone(),
+        };
+        (service, events)
+    }
+
+    async fn submit(&self, command: GrpcCommand) -> Result<Response<DisplayReply>, Status> {
+        let (reply, outcome) = oneshot::channel();
+        self.jobs
+            .try_send(GrpcJob { command, reply })
+            .map_err(|err| match err {
+                mpsc::TrySendError::Full(_) => {
+                    Status::resource_exhausted("the display queue is full; retry later")
+                }
+                mpsc::TrySendError::Disconnected(_) => Status::unavailable("shutting down"),
+            })?;
+        match outcome.await {
+            Ok(result) => result.map(Response::new),
+            Err(_) => Err(Status::unavailable("shutting down")),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Display for DisplayService {
+    async fn display_text(
+        &self,
+        request: Request<TextRequest>,
+    ) -> Result<Response<DisplayReply>, Status> {
+        self.submit(GrpcCommand::Text(request.into_inner())).await
+    }
+
+    async fn display_image(
+        &self,
+        request: Request<ImageRequest>,
+    ) -> Result<Response<DisplayReply>, Status> {
+        self.submit(GrpcCommand::Image(request.into_inner())).await
+    }
+
+    async fn clear(
+        &self,
+        _request: Request<ClearRequest>,
+    ) -> Result<Response<DisplayReply>, Status> {
+        self.submit(GrpcCommand::Clear).await
+    }
+
+    type SubscribeStream = Pin<Box<dyn Stream<Item = Result<DisplayEvent, Status>> + Send>>;
+
+    async fn subscribe(
+        &self,
+        _request: Request<SubscribeRequest>,
+    ) -> Result<Response<Self::SubscribeStream>, Status> {
+        // A subscriber that lags behind misses the events it had no room for.
+        let events = BroadcastStream::new(self.events.subscribe()).filter_map(|event| event.ok());
+        Ok(Response::new(Box::pin(events.map(Ok))))
+    }
+}
+
+/// Serve `service` on `addr` until the process exits, on a runtime of its own. Binding
+/// happens before this returns, so address errors are reported to the caller.
+pub fn spawn_server(
+    addr: SocketAddr,
+    service: DisplayService,
+) -> Result<std::thread::JoinHandle<()>, Box<dyn std::error::Error + Send + Sync>> {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(2)
+        .enable_io()
+        .enable_time()
+        .build()?;
+    let listener = runtime.block_on(tokio::net::TcpListener::bind(addr))?;
+    Ok(std::thread::spawn(move || {
+        let incoming = tokio_stream::wrappers::TcpListenerStream::new(listener);
+        let served = runtime.block_on(
+            tonic::transport::Server::builder()
+                .add_service(DisplayServer::new(service))
+                .serve_with_incoming(incoming),
+        );
+        if let Err(err) = served {
+            tracing::error!("gRPC server stopped: {err}");
+        }
+    }))
+}
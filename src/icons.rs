@@ -0,0 +1,604 @@
+//! A small set of 16x16 1bpp icons, addressable by name: weather conditions, Wi-Fi signal
+//! and battery levels, arrows, check and cross marks, and a warning sign. Each is drawn
+//! from the strings below, `#` for ink, at any whole-number scale.
+
+use embedded_graphics::{pixelcolor::BinaryColor, prelude::*, primitives::Rectangle};
+
+/// Width and height of every icon, in pixels at scale 1.
+pub const ICON_SIZE: u32 = 16;
+
+/// One icon: a name and its rows, top to bottom.
+#[derive(Debug, Clone, Copy)]
+pub struct Icon {
+    pub name: &'static str,
+    rows: [&'static str; ICON_SIZE as usize],
+}
+
+impl Icon {
+    /// Draw the ink pixels with `color` from `top_left`, each as a `scale`×`scale` block;
+    /// the rest of the icon's square is left alone.
+    pub fn draw<D: DrawTarget<Color = BinaryColor>>(
+        &self,
+        target: &mut D,
+        top_left: Point,
+        scale: u32,
+        color: BinaryColor,
+    ) {
+        let scale = scale.max(1);
+        for (y, row) in self.rows.iter().enumerate() {
+            for (x, ink) in row.bytes().enumerate() {
+                if ink != b'#' {
+                    continue;
+                }
+                let offset = Point::new(x as i32, y as i32) * scale as i32;
+                target
+                    .fill_solid(
+                        &Rectangle::new(top_left + offset, Size::new_equal(scale)),
+                        color,
+                    )
+                    .ok();
+            }
+        }
+    }
+}
+
+/// The icon called `name`, such as `wifi_3` or `battery_charging`.
+pub fn icon(name: &str) -> Option<&'static Icon> {
+    ICONS
+        .iter()
+        .find(|candidate| candidate.name.eq_ignore_ascii_case(name))
+}
+
+/// Every icon, in a stable order.
+pub fn all() -> &'static [Icon] {
+    ICONS
+}
+
+static ICONS: &[Icon] = &[
+    Icon {
+        name: "sun",
+        rows: [
+            ".......#........",
+            ".......#........",
+            "..#....#....#...",
+            "...#.......#....",
+            "......###.......",
+            ".....#####......",
+            "....#######.....",
+            "###.#######.###.",
+            "....#######.....",
+            ".....#####......",
+            "......###.......",
+            "...#.......#....",
+            "..#....#....#...",
+            ".......#........",
+            ".......#........",
+            "................",
+        ],
+    },
+    Icon {
+        name: "moon",
+        rows: [
+            "................",
+            ".....##.........",
+            "...###..........",
+            "..###...........",
+            "..###...........",
+            ".####...........",
+            ".####...........",
+            ".####...........",
+            ".#####..........",
+            ".######.........",
+            "..######........",
+            "..###########...",
+            "...#########....",
+            ".....#####......",
+            "................",
+            "................",
+        ],
+    },
+    Icon {
+        name: "cloud",
+        rows: [
+            "................",
+            "................",
+            "................",
+            "................",
+            "......####......",
+            ".....#....#.....",
+            "...###.....#....",
+            "..#.........##..",
+            "..#...........#.",
+            ".#............#.",
+            "#..............#",
+            "#..............#",
+            ".##############.",
+            "................",
+            "................",
+            "................",
+        ],
+    },
+    Icon {
+        name: "partly_cloudy",
+        rows: [
+            "................",
+            "......#.........",
+            "..#...#...#.....",
+            "...#.....#......",
+            ".....###........",
+            "....#####.......",
+            ".##.#####.##....",
+            "....########....",
+            ".....###....#...",
+            "...#.###.....##.",
+            "..#.#..........#",
+            "....#..........#",
+            ".....##########.",
+            "................",
+            "................",
+            "................",
+        ],
+    },
+    Icon {
+        name: "rain",
+        rows: [
+            "......####......",
+            ".....#....#.....",
+            "...###.....#....",
+            "..#.........##..",
+            "..#...........#.",
+            ".#............#.",
+            "#..............#",
+            "#..............#",
+            ".##############.",
+            "................",
+            "...#....#....#..",
+            "..#....#....#...",
+            "................",
+            ".....#....#.....",
+            "....#....#......",
+            "................",
+        ],
+    },
+    Icon {
+        name: "snow",
+        rows: [
+            "......####......",
+            ".....#....#.....",
+            "...###.....#....",
+            "..#.........##..",
+            "..#...........#.",
+            ".#............#.",
+            "#..............#",
+            "#..............#",
+            ".##############.",
+            "................",
+            "....#......#....",
+            "...###....###...",
+            "....#......#....",
+            ".......#........",
+            "......###.......",
+            ".......#........",
+        ],
+    },
+    Icon {
+        name: "storm",
+        rows: [
+            "......####......",
+            ".....#....#.....",
+            "...###.....#....",
+            "..#.........##..",
+            "..#...........#.",
+            ".#............#.",
+            "#..............#",
+            "#..............#",
+            ".##############.",
+            ".......###......",
+            "......###.......",
+            ".....######.....",
+            ".......###......",
+            "......##........",
+            ".....#..........",
+            "................",
+        ],
+    },
+    Icon {
+        name: "fog",
+        rows: [
+            "................",
+            "................",
+            "................",
+            "..############..",
+            "................",
+            "................",
+            "....############",
+            "................",
+            "................",
+            "############....",
+            "................",
+            "................",
+            "...############.",
+            "................",
+            "................",
+            "................",
+        ],
+    },
+    Icon {
+        name: "wifi_0",
+        rows: [
+            "................",
+            "................",
+            "................",
+            "................",
+            "................",
+            "................",
+            "................",
+            "................",
+            "................",
+            "................",
+            "................",
+            "................",
+            ".......##.......",
+            ".......##.......",
+            ".......##.......",
+            "................",
+        ],
+    },
+    Icon {
+        name: "wifi_1",
+        rows: [
+            "................",
+            "................",
+            "................",
+            "................",
+            "................",
+            "................",
+            "................",
+            "................",
+            "................",
+            "................",
+            ".....######.....",
+            ".....#....#.....",
+            ".......##.......",
+            ".......##.......",
+            ".......##.......",
+            "................",
+        ],
+    },
+    Icon {
+        name: "wifi_2",
+        rows: [
+            "................",
+            "................",
+            "................",
+            "................",
+            "................",
+            "................",
+            ".....######.....",
+            "...###....###...",
+            "..##........##..",
+            "................",
+            ".....######.....",
+            ".....#....#.....",
+            ".......##.......",
+            ".......##.......",
+            ".......##.......",
+            "................",
+        ],
+    },
+    Icon {
+        name: "wifi_3",
+        rows: [
+            "................",
+            "................",
+            "................",
+            "...##########...",
+            "..##........##..",
+            "##............##",
+            "#....######....#",
+            "...###....###...",
+            "..##........##..",
+            "................",
+            ".....######.....",
+            ".....#....#.....",
+            ".......##.......",
+            ".......##.......",
+            ".......##.......",
+            "................",
+        ],
+    },
+    Icon {
+        name: "battery_0",
+        rows: [
+            "................",
+            "................",
+            "................",
+            "................",
+            "##############..",
+            "#............#..",
+            "#............###",
+            "#............###",
+            "#............###",
+            "#............###",
+            "#............#..",
+            "##############..",
+            "................",
+            "................",
+            "................",
+            "................",
+        ],
+    },
+    Icon {
+        name: "battery_1",
+        rows: [
+            "................",
+            "................",
+            "................",
+            "................",
+            "##############..",
+            "#............#..",
+            "#.##.........###",
+            "#.##.........###",
+            "#.##.........###",
+            "#.##.........###",
+            "#............#..",
+            "##############..",
+            "................",
+            "................",
+            "................",
+            "................",
+        ],
+    },
+    Icon {
+        name: "battery_2",
+        rows: [
+            "................",
+            "................",
+            "................",
+            "................",
+            "##############..",
+            "#............#..",
+            "#.##.##......###",
+            "#.##.##......###",
+            "#.##.##......###",
+            "#.##.##......###",
+            "#............#..",
+            "##############..",
+            "................",
+            "................",
+            "................",
+            "................",
+        ],
+    },
+    Icon {
+        name: "battery_3",
+        rows: [
+            "................",
+            "................",
+            "................",
+            "................",
+            "##############..",
+            "#............#..",
+            "#.##.##.##...###",
+            "#.##.##.##...###",
+            "#.##.##.##...###",
+            "#.##.##.##...###",
+            "#............#..",
+            "##############..",
+            "................",
+            "................",
+            "................",
+            "................",
+        ],
+    },
+    Icon {
+        name: "battery_4",
+        rows: [
+            "................",
+            "................",
+            "................",
+            "................",
+            "##############..",
+            "#............#..",
+            "#.##.##.##.#####",
+            "#.##.##.##.#####",
+            "#.##.##.##.#####",
+            "#.##.##.##.#####",
+            "#............#..",
+            "##############..",
+            "................",
+            "................",
+            "................",
+            "................",
+        ],
+    },
+    Icon {
+        name: "battery_charging",
+        rows: [
+            "................",
+            "................",
+            "................",
+            "................",
+            "##############..",
+            "#......#.....#..",
+            "#.....#......###",
+            "#....#####...###",
+            "#......##....###",
+            "#......#.....###",
+            "#.....#......#..",
+            "##############..",
+            "................",
+            "................",
+            "................",
+            "................",
+        ],
+    },
+    Icon {
+        name: "arrow_up",
+        rows: [
+            "................",
+            "................",
+            ".......##.......",
+            "......####......",
+            ".....######.....",
+            "....########....",
+            "...##########...",
+            "..############..",
+            "......####......",
+            "......####......",
+            "......####......",
+            "......####......",
+            "......####......",
+            "......####......",
+            "......####......",
+            "................",
+        ],
+    },
+    Icon {
+        name: "arrow_down",
+        rows: [
+            "................",
+            "......####......",
+            "......####......",
+            "......####......",
+            "......####......",
+            "......####......",
+            "......####......",
+            "......####......",
+            "..############..",
+            "...##########...",
+            "....########....",
+            ".....######.....",
+            "......####......",
+            ".......##.......",
+            "................",
+            "................",
+        ],
+    },
+    Icon {
+        name: "arrow_left",
+        rows: [
+            "................",
+            "................",
+            ".......#........",
+            "......##........",
+            ".....###........",
+            "....####........",
+            "...############.",
+            "..#############.",
+            "..#############.",
+            "...############.",
+            "....####........",
+            ".....###........",
+            "......##........",
+            ".......#........",
+            "................",
+            "................",
+        ],
+    },
+    Icon {
+        name: "arrow_right",
+        rows: [
+            "................",
+            "................",
+            "........#.......",
+            "........##......",
+            "........###.....",
+            "........####....",
+            ".############...",
+            ".#############..",
+            ".#############..",
+            ".############...",
+            "........####....",
+            "........###.....",
+            "........##......",
+            "........#.......",
+            "................",
+            "................",
+        ],
+    },
+    Icon {
+        name: "check",
+        rows: [
+            "................",
+            "................",
+            "................",
+            ".............##.",
+            "............###.",
+            "...........###..",
+            "..........####..",
+            "..........###...",
+            "..##.....###....",
+            "..###...###.....",
+            "...###.####.....",
+            "....######......",
+            ".....####.......",
+            "......##........",
+            "................",
+            "................",
+        ],
+    },
+    Icon {
+        name: "cross",
+        rows: [
+            "................",
+            "................",
+            "................",
+            "...##.......##..",
+            "...###.....###..",
+            "....###...###...",
+            ".....###.###....",
+            "......#####.....",
+            ".......###......",
+            "......#####.....",
+            ".....###.###....",
+            "....###...###...",
+            "...###.....###..",
+            "...##.......##..",
+            "................",
+            "................",
+        ],
+    },
+    Icon {
+        name: "warning",
+        rows: [
+            "................",
+            ".......##.......",
+            "......#..#......",
+            "......#..#......",
+            ".....#....#.....",
+            ".....#.##.#.....",
+            "....#..##..#....",
+            "....#..##..#....",
+            "...#...##...#...",
+            "...#...##...#...",
+            "..#..........#..",
+            "..#....##....#..",
+            ".#.....##.....#.",
+            ".#............#.",
+            "################",
+            "................",
+        ],
+    },
+    Icon {
+        name: "thermometer",
+        rows: [
+            ".......##.......",
+            "......#..#......",
+            "......#..#......",
+            "......####......",
+            "......####......",
+            "......####......",
+            "......####......",
+            "......####......",
+            "......####......",
+            "......####......",
+            ".....######.....",
+            ".....######.....",
+            ".....######.....",
+            ".....######.....",
+            ".....######.....",
+            ".......##.......",
+        ],
+    },
+];
@@ -0,0 +1,435 @@
+//! Just enough iCalendar (RFC 5545) parsing to list events: `VEVENT` start and end times
+//! and summaries, in the zone of their `TZID=`. Simple recurrence rules (`FREQ=DAILY`,
+//! `WEEKLY`, `MONTHLY` or `YEARLY` with `INTERVAL`, `COUNT`, `UNTIL` and, for daily and
+//! weekly rules, `BYDAY`) are expanded by [`expand`], minus `EXDATE`s and instances moved
+//! by a `RECURRENCE-ID`; other rules only show their first occurrence.
+
+use chrono::{
+    DateTime, Datelike, Days, Local, Months, NaiveDate, NaiveDateTime, TimeDelta, TimeZone, Utc,
+    Weekday,
+};
+use chrono_tz::Tz;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    /// Start in local time; all-day events start at midnight.
+    pub start: NaiveDateTime,
+    pub all_day: bool,
+    /// End in local time, from `DTEND`; all-day events without one last the day.
+    pub end: Option<NaiveDateTime>,
+    pub summary: String,
+    /// How the event repeats, from its `RRULE`; `None` for single events and for the
+    /// occurrences [`expand`] returns.
+    pub recurrence: Option<Recurrence>,
+}
+
+/// A supported `RRULE`, with what it needs to place each occurrence.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recurrence {
+    freq: Frequency,
+    interval: u32,
+    count: Option<u32>,
+    /// The last start allowed, in local time.
+    until: Option<NaiveDateTime>,
+    /// Weekdays of `BYDAY`; empty for the weekday of the first occurrence.
+    by_day: Vec<Weekday>,
+    /// Occurrences are placed in the event's own zone, so a weekly 9:00 meeting stays at
+    /// 9:00 there across daylight saving changes.
+    zone: Zone,
+    /// The first start, in `zone`.
+    start: NaiveDateTime,
+    duration: Option<TimeDelta>,
+    /// Local starts of instances removed by `EXDATE` or moved by a `RECURRENCE-ID`.
+    skip: Vec<NaiveDateTime>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// The zone an iCalendar time is given in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Zone {
+    /// Floating times, and `TZID=`s that are not IANA names, are taken as local.
+    Local,
+    Utc,
+    Named(Tz),
+}
+
+impl Zone {
+    fn from_params(params: &str) -> Self {
+        let tzid = params.split(';').find_map(|param| {
+            let (key, value) = param.split_once('=')?;
+            key.eq_ignore_ascii_case("TZID").then(|| value.trim_matches('"'))
+        });
+        match tzid {
+            Some(name) => name.parse().map_or(Zone::Local, Zone::Named),
+            None => Zone::Local,
+        }
+    }
+
+    /// `naive`, a time in this zone, as local time.
+    fn to_local(self, naive: NaiveDateTime) -> NaiveDateTime {
+        let local = |time: DateTime<Local>| time.naive_local();
+        match self {
+            Zone::Local => naive,
+            Zone::Utc => local(Utc.from_utc_datetime(&naive).with_timezone(&Local)),
+            Zone::Named(tz) => tz
+                .from_local_datetime(&naive)
+                .earliest()
+                .map_or(naive, |time| local(time.with_timezone(&Local))),
+        }
+    }
+}
+
+/// A `DTSTART`, `DTEND`, `EXDATE` or `RECURRENCE-ID` value in its own zone.
+#[derive(Debug, Clone, Copy)]
+struct Time {
+    naive: NaiveDateTime,
+    all_day: bool,
+    zone: Zone,
+}
+
+impl Time {
+    fn local(self) -> NaiveDateTime {
+        if self.all_day {
+            self.naive
+        } else {
+            self.zone.to_local(self.naive)
+        }
+    }
+}
+
+/// An event being read.
+#[derive(Default)]
+struct Partial {
+    start: Option<Time>,
+    end: Option<Time>,
+    summary: String,
+    uid: String,
+    rrule: Option<String>,
+    exdates: Vec<NaiveDateTime>,
+    recurrence_id: Option<NaiveDateTime>,
+}
+
+/// Most occurrences [`expand`] steps through per event, so a rule that never reaches the
+/// window cannot stall it.
+const MAX_STEPS: u32 = 100_000;
+
+/// Events found in `text`, sorted by start. Events without a parseable `DTSTART` are skipped.
+pub fn parse_events(text: &str) -> Vec<Event> {
+    let mut events = Vec::new();
+    // Series (by UID) with the instances that were moved out of them.
+    let mut series: Vec<(String, usize)> = Vec::new();
+    let mut moved: Vec<(String, NaiveDateTime)> = Vec::new();
+    let mut current: Option<Partial> = None;
+
+    for line in unfold(text) {
+        let (name, value) = match line.split_once(':') {
+            Some(pair) => pair,
+            None => continue,
+        };
+        // Property parameters follow the name after `;`, e.g. `DTSTART;VALUE=DATE`.
+        let (name, params) = name.split_once(';').unwrap_or((name, ""));
+        match (name.to_ascii_uppercase().as_str(), current.as_mut()) {
+            ("BEGIN", None) if value.eq_ignore_ascii_case("VEVENT") => {
+                current = Some(Partial::default());
+            }
+            ("END", Some(_)) if value.eq_ignore_ascii_case("VEVENT") => {
+                let Some(partial) = current.take() else {
+                    continue;
+                };
+                let Some(start) = partial.start else {
+                    continue;
+                };
+                let local_start = start.local();
+                let end = partial
+                    .end
+                    .map(Time::local)
+                    .or(start.all_day.then(|| local_start + TimeDelta::days(1)));
+                if let Some(id) = partial.recurrence_id {
+                    moved.push((partial.uid.clone(), id));
+                }
+                let recurrence = partial
+                    .rrule
+                    .as_deref()
+                    .filter(|_| partial.recurrence_id.is_none())
+                    .and_then(|rule| {
+                        let duration = partial.end.map(|end| end.naive - start.naive);
+                        parse_rule(rule, start, duration, partial.exdates)
+                    });
+                if recurrence.is_some() && !partial.uid.is_empty() {
+                    series.push((partial.uid, events.len()));
+                }
+                events.push(Event {
+                    start: local_start,
+                    all_day: start.all_day,
+                    end,
+                    summary: partial.summary,
+                    recurrence,
+                });
+            }
+            ("DTSTART", Some(event)) => event.start = parse_time(value, params),
+            ("DTEND", Some(event)) => event.end = parse_time(value, params),
+            ("SUMMARY", Some(event)) => event.summary = unescape(value),
+            ("UID", Some(event)) => event.uid = value.trim().to_string(),
+            ("RRULE", Some(event)) => event.rrule = Some(value.trim().to_string()),
+            ("EXDATE", Some(event)) => event.exdates.extend(
+                value
+                    .split(',')
+                    .filter_map(|date| parse_time(date, params))
+                    .map(Time::local),
+            ),
+            ("RECURRENCE-ID", Some(event)) => {
+                event.recurrence_id = parse_time(value, params).map(Time::local);
+            }
+            _ => {}
+        }
+    }
+    for (uid, id) in moved {
+        let index = series.iter().find(|(series, _)| *series == uid);
+        if let Some(recurrence) = index.and_then(|&(_, index)| events[index].recurrence.as_mut()) {
+            recurrence.skip.push(id);
+        }
+    }
+    events.sort_by_key(|event| event.start);
+    events
+}
+
+/// `events` with each recurring event replaced by its occurrences that have not ended by
+/// `from` and start before `until`, sorted by start. Single events are kept as they are.
+pub fn expand(events: &[Event], from: NaiveDateTime, until: NaiveDateTime) -> Vec<Event> {
+    let mut expanded = Vec::new();
+    for event in events {
+        let Some(rule) = &event.recurrence else {
+            expanded.push(event.clone());
+            continue;
+        };
+        for start in rule.starts(until) {
+            let occurrence = |naive: NaiveDateTime| {
+                if event.all_day {
+                    naive
+                } else {
+                    rule.zone.to_local(naive)
+                }
+            };
+            let local_start = occurrence(start);
+            let end = match rule.duration {
+                Some(duration) => Some(occurrence(start + duration)),
+                None => event.all_day.then(|| local_start + TimeDelta::days(1)),
+            };
+            let over = end.map_or(local_start < from, |end| end <= from);
+            if over || rule.skip.contains(&local_start) {
+                continue;
+            }
+            expanded.push(Event {
+                start: local_start,
+                all_day: event.all_day,
+                end,
+                summary: event.summary.clone(),
+                recurrence: None,
+            });
+        }
+    }
+    expanded.sort_by_key(|event| event.start);
+    expanded
+}
+
+impl Recurrence {
+    /// Starts in the event's zone, in order, from the first one up to `COUNT`, `UNTIL` or
+    /// the last one before local time `before`.
+    fn starts(&self, before: NaiveDateTime) -> Vec<NaiveDateTime> {
+        let mut starts = Vec::new();
+        let time = self.start.time();
+        let first = self.start.date();
+        let days: Vec<Weekday> = if self.by_day.is_empty() {
+            vec![first.weekday()]
+        } else {
+            self.by_day.clone()
+        };
+        let interval = self.interval.max(1);
+        let mut generated = 0;
+        for step in 0..MAX_STEPS {
+            // Past the range of dates, the rule has nothing more to give.
+            let Some(dates) = step
+                .checked_mul(interval)
+                .and_then(|step| self.dates(first, step, &days))
+            else {
+                break;
+            };
+            for date in dates {
+                let start = date.and_time(time);
+                let local = self.zone.to_local(start);
+                let done = self.count.is_some_and(|count| generated >= count)
+                    || self.until.is_some_and(|until| local > until)
+                    || local >= before;
+                if done {
+                    return starts;
+                }
+                generated += 1;
+                starts.push(start);
+            }
+        }
+        starts
+    }
+
+    /// Dates of the period `step` periods after the one of `first`, from `first` on.
+    fn dates(&self, first: NaiveDate, step: u32, days: &[Weekday]) -> Option<Vec<NaiveDate>> {
+        let same_day = |date: &NaiveDate| date.day() == first.day();
+        Some(match self.freq {
+            Frequency::Daily => {
+                let date = first.checked_add_days(Days::new(step.into()))?;
+                let listed = self.by_day.is_empty() || days.contains(&date.weekday());
+                listed.then_some(date).into_iter().collect()
+            }
+            Frequency::Weekly => {
+                let monday = first.week(Weekday::Mon).first_day();
+                let week = monday.checked_add_days(Days::new(u64::from(step) * 7))?;
+                let mut dates = days
+                    .iter()
+                    .map(|day| week.checked_add_days(Days::new(day.num_days_from_monday().into())))
+                    .collect::<Option<Vec<_>>>()?;
+                dates.retain(|date| *date >= first);
+                dates.sort();
+                dates
+            }
+            // Months without the day of `first` are skipped, as RFC 5545 has it.
+            Frequency::Monthly => {
+                let date = first.checked_add_months(Months::new(step))?;
+                same_day(&date).then_some(date).into_iter().collect()
+            }
+            Frequency::Yearly => {
+                let date = first.checked_add_months(Months::new(step.checked_mul(12)?))?;
+                same_day(&date).then_some(date).into_iter().collect()
+            }
+        })
+    }
+}
+
+/// A `FREQ=...` rule this module can expand, or `None`.
+fn parse_rule(
+    rule: &str,
+    start: Time,
+    duration: Option<TimeDelta>,
+    exdates: Vec<NaiveDateTime>,
+) -> Option<Recurrence> {
+    let mut recurrence = Recurrence {
+        freq: Frequency::Daily,
+        interval: 1,
+        count: None,
+        until: None,
+        by_day: Vec::new(),
+        zone: if start.all_day { Zone::Local } else { start.zone },
+        start: start.naive,
+        duration,
+        skip: exdates,
+    };
+    let mut freq = None;
+    for part in rule.split(';').filter(|part| !part.is_empty()) {
+        let (key, value) = part.split_once('=')?;
+        match key.to_ascii_uppercase().as_str() {
+            "FREQ" => {
+                freq = Some(match value.to_ascii_uppercase().as_str() {
+                    "DAILY" => Frequency::Daily,
+                    "WEEKLY" => Frequency::Weekly,
+                    "MONTHLY" => Frequency::Monthly,
+                    "YEARLY" => Frequency::Yearly,
+                    _ => return None,
+                })
+            }
+            "INTERVAL" => recurrence.interval = value.parse().ok().filter(|n| *n > 0)?,
+            "COUNT" => recurrence.count = Some(value.parse().ok()?),
+            "UNTIL" => {
+                let until = parse_time(value, "")?;
+                // A date-only UNTIL includes that whole day.
+                recurrence.until = Some(if until.all_day {
+                    until.naive + TimeDelta::days(1) - TimeDelta::seconds(1)
+                } else {
+                    Time {
+                        zone: if value.ends_with('Z') { Zone::Utc } else { start.zone },
+                        ..until
+                    }
+                    .local()
+                });
+            }
+            "BYDAY" => {
+                recurrence.by_day = value
+                    .split(',')
+                    .map(|day| weekday(day.trim()))
+                    .collect::<Option<_>>()?;
+            }
+            "WKST" => {}
+            _ => return None,
+        }
+    }
+    recurrence.freq = freq?;
+    let by_day_allowed = matches!(recurrence.freq, Frequency::Daily | Frequency::Weekly);
+    if !recurrence.by_day.is_empty() && !by_day_allowed {
+        return None;
+    }
+    Some(recurrence)
+}
+
+/// A plain two-letter `BYDAY` weekday; ones with an ordinal such as `2MO` are not supported.
+fn weekday(name: &str) -> Option<Weekday> {
+    match name.to_ascii_uppercase().as_str() {
+        "MO" => Some(Weekday::Mon),
+        "TU" => Some(Weekday::Tue),
+        "WE" => Some(Weekday::Wed),
+        "TH" => Some(Weekday::Thu),
+        "FR" => Some(Weekday::Fri),
+        "SA" => Some(Weekday::Sat),
+        "SU" => Some(Weekday::Sun),
+        _ => None,
+    }
+}
+
+/// Join continuation lines (starting with a space or tab) onto the previous line.
+fn unfold(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for raw in text.lines() {
+        let raw = raw.trim_end_matches('\r');
+        match (raw.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(raw.to_string()),
+        }
+    }
+    lines
+}
+
+/// A date-time value: `20251231`, `20251231T235900` (floating, or in the zone of a
+/// `TZID=` parameter) or `20251231T235900Z` (UTC).
+fn parse_time(value: &str, params: &str) -> Option<Time> {
+    let value = value.trim();
+    if params.to_ascii_uppercase().contains("VALUE=DATE") || value.len() == 8 {
+        let date = NaiveDate::parse_from_str(value, "%Y%m%d").ok()?;
+        return Some(Time {
+            naive: date.and_hms_opt(0, 0, 0)?,
+            all_day: true,
+            zone: Zone::Local,
+        });
+    }
+    let (value, zone) = match value.strip_suffix('Z') {
+        Some(utc) => (utc, Zone::Utc),
+        None => (value, Zone::from_params(params)),
+    };
+    let naive = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()?;
+    Some(Time {
+        naive,
+        all_day: false,
+        zone,
+    })
+}
+
+fn unescape(value: &str) -> String {
+    value
+        .replace("\\n", " ")
+        .replace("\\N", " ")
+        .replace("\\,", ",")
+        .replace("\\;", ";")
+        .replace("\\\\", "\\")
+}
@@ -0,0 +1,451 @@
+//! Helpers for splitting a frame into independent text regions, such as side-by-side
+//! columns or a key/value table.
+
+use embedded_graphics::{
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::{Circle, Line, Polyline, PrimitiveStyle, Rectangle},
+};
+
+use crate::{
+    buffer::MonoImage,
+    icons::{self, ICON_SIZE},
+    text::{display_width, Alignment, FontChoice, HAlign, TextLayout, VAlign},
+};
+
+/// Space left between neighbouring regions; a 1px divider is drawn in its middle.
+pub const REGION_GAP: u32 = 4;
+
+/// Most lines of a progress bar's label; the rest is dropped.
+const PROGRESS_LABEL_LINES: u32 = 3;
+
+/// Font of the percentage under a progress bar.
+const PROGRESS_FONT: FontChoice = FontChoice::Font10x20;
+
+/// Scale of the icon of a battery widget.
+const BATTERY_ICON_SCALE: u32 = 3;
+
+/// Font of the minimum and maximum next to a sparkline.
+const SCALE_FONT: FontChoice = FontChoice::Font6x10;
+
+/// Split `area` into `count` equally wide columns separated by `gap` pixels. Leftover
+/// pixels from the division go to the last column.
+pub fn split_columns(area: Rectangle, count: usize, gap: u32) -> Vec<Rectangle> {
+    split(area.size.width, count, gap)
+        .into_iter()
+        .map(|(offset, width)| {
+            Rectangle::new(
+                area.top_left + Point::new(offset as i32, 0),
+                Size::new(width, area.size.height),
+            )
+        })
+        .collect()
+}
+
+/// Split `area` into `count` equally tall rows separated by `gap` pixels.
+pub fn split_rows(area: Rectangle, count: usize, gap: u32) -> Vec<Rectangle> {
+    split(area.size.height, count, gap)
+        .into_iter()
+        .map(|(offset, height)| {
+            Rectangle::new(
+                area.top_left + Point::new(0, offset as i32),
+                Size::new(area.size.width, height),
+            )
+        })
+        .collect()
+}
+
+fn split(length: u32, count: usize, gap: u32) -> Vec<(u32, u32)> {
+    if count == 0 {
+        return Vec::new();
+    }
+    let count = count as u32;
+    let usable = length.saturating_sub(gap * (count - 1));
+    let size = usable / count;
+    (0..count)
+        .map(|i| {
+            let offset = i * (size + gap);
+            let extent = if i == count - 1 {
+                length.saturating_sub(offset)
+            } else {
+                size
+            };
+            (offset, extent)
+        })
+        .collect()
+}
+
+/// Draw each entry of `columns` as independently wrapped text in its own column, with a
+/// vertical divider between columns.
+pub fn draw_columns<D: DrawTarget<Color = BinaryColor>>(
+    target: &mut D,
+    area: Rectangle,
+    columns: &[&str],
+    layout: &TextLayout,
+) {
+    let regions = split_columns(area, columns.len(), REGION_GAP);
+    for (region, text) in regions.iter().zip(columns) {
+        layout.draw(target, *region, text);
+    }
+    for region in regions.iter().skip(1) {
+        let x = region.top_left.x - (REGION_GAP / 2) as i32;
+        divider(
+            target,
+            layout,
+            Point::new(x, area.top_left.y),
+            Point::new(x, area.top_left.y + area.size.height as i32 - 1),
+        );
+    }
+}
+
+/// Draw `rows` as a table with equally wide columns. Each row is as tall as its tallest
+/// wrapped cell and rows are separated by horizontal dividers; rows that no longer fit in
+/// `area` are dropped.
+pub fn draw_table<D: DrawTarget<Color = BinaryColor>>(
+    target: &mut D,
+    area: Rectangle,
+    rows: &[Vec<&str>],
+    layout: &TextLayout,
+) {
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    if columns == 0 {
+        return;
+    }
+
+    let cell_layout = TextLayout {
+        auto_fit: false,
+        ..*layout
+    };
+    let line_height = layout.font.char_size().height + 2;
+    let bottom = area.top_left.y + area.size.height as i32;
+    let mut y = area.top_left.y;
+
+    for (idx, row) in rows.iter().enumerate() {
+        let slots = split_columns(
+            Rectangle::new(
+                Point::new(area.top_left.x, y),
+                Size::new(area.size.width, 0),
+            ),
+            columns,
+            REGION_GAP,
+        );
+        let lines = row
+            .iter()
+            .zip(&slots)
+            .map(|(text, slot)| {
+                let (max_chars, _) = cell_layout.capacity(slot.size);
+                cell_layout.wrap(text, max_chars).len().max(1) as u32
+            })
+            .max()
+            .unwrap_or(1);
+        let height = (lines * line_height).saturating_sub(2);
+        if y + height as i32 > bottom {
+            break;
+        }
+
+        if idx > 0 {
+            let rule = y - (REGION_GAP / 2) as i32;
+            divider(
+                target,
+                layout,
+                Point::new(area.top_left.x, rule),
+                Point::new(area.top_left.x + area.size.width as i32 - 1, rule),
+            );
+        }
+        for (text, slot) in row.iter().zip(&slots) {
+            let cell = Rectangle::new(slot.top_left, Size::new(slot.size.width, height));
+            cell_layout.draw(target, cell, text);
+        }
+        y += (height + REGION_GAP) as i32;
+    }
+}
+
+/// Draw a bar filled to `percent` (0 to 100) across `area`, with `label` wrapped and
+/// centered above it and the percentage in a large font below, the whole block centered
+/// vertically.
+pub fn draw_progress<D: DrawTarget<Color = BinaryColor>>(
+    target: &mut D,
+    area: Rectangle,
+    percent: f32,
+    label: &str,
+    layout: &TextLayout,
+) {
+    let label_layout = TextLayout {
+        alignment: Alignment {
+            horizontal: HAlign::Center,
+            vertical: VAlign::Bottom,
+        },
+        auto_fit: false,
+        ..*layout
+    };
+    let line_height = layout.font.char_size().height + 2;
+    let (max_chars, _) = label_layout.capacity(area.size);
+    let lines = match label.trim() {
+        "" => 0,
+        label => (label_layout.wrap(label, max_chars).len() as u32).min(PROGRESS_LABEL_LINES),
+    };
+    let label_height = lines * line_height;
+    let bar_height = (area.size.height / 5).clamp(12, 40);
+    let number_height = PROGRESS_FONT.char_size().height;
+    let total = label_height + bar_height + REGION_GAP + number_height;
+    let mut y = area.top_left.y + (area.size.height.saturating_sub(total) / 2) as i32;
+
+    if lines > 0 {
+        let label_area = Rectangle::new(
+            Point::new(area.top_left.x, y),
+            Size::new(area.size.width, label_height - 2),
+        );
+        label_layout.draw(target, label_area, label.trim());
+        y += label_height as i32;
+    }
+
+    let bar = Rectangle::new(
+        Point::new(area.top_left.x, y),
+        Size::new(area.size.width, bar_height),
+    );
+    bar.into_styled(PrimitiveStyle::with_stroke(layout.foreground, 2))
+        .draw(target)
+        .ok();
+    let inner = bar.offset(-4);
+    let filled = (inner.size.width as f32 * percent.clamp(0.0, 100.0) / 100.0).round() as u32;
+    Rectangle::new(inner.top_left, Size::new(filled, inner.size.height))
+        .into_styled(PrimitiveStyle::with_fill(layout.foreground))
+        .draw(target)
+        .ok();
+    y += (bar_height + REGION_GAP) as i32;
+
+    let number = if percent.fract() == 0.0 {
+        format!("{percent:.0}%")
+    } else {
+        format!("{percent:.1}%")
+    };
+    let width = number.len() as u32 * PROGRESS_FONT.char_size().width;
+    let x = area.top_left.x + (area.size.width.saturating_sub(width) / 2) as i32;
+    PROGRESS_FONT
+        .draw_text(target, &number, Point::new(x, y), layout.foreground)
+        .ok();
+}
+
+/// Draw a battery widget centered in `area`: a battery icon filled in quarters (or with a
+/// bolt while `charging`), the charge in a large font beside it, and the lines of `detail`,
+/// such as the voltage, under the charge.
+pub fn draw_battery<D: DrawTarget<Color = BinaryColor>>(
+    target: &mut D,
+    area: Rectangle,
+    percent: f32,
+    charging: bool,
+    detail: &str,
+    layout: &TextLayout,
+) {
+    let percent = percent.clamp(0.0, 100.0);
+    let name = if charging {
+        "battery_charging".to_string()
+    } else {
+        format!("battery_{}", (percent / 25.0).round() as u32)
+    };
+    let icon_size = ICON_SIZE * BATTERY_ICON_SCALE;
+    let number = format!("{percent:.0}%");
+    let lines: Vec<&str> = detail.lines().map(str::trim).collect();
+    let cell = layout.font.char_size();
+    let text_width = lines
+        .iter()
+        .map(|line| line.chars().count() as u32 * cell.width)
+        .chain([number.len() as u32 * PROGRESS_FONT.char_size().width])
+        .max()
+        .unwrap_or_default();
+    let text_height = PROGRESS_FONT.char_size().height + lines.len() as u32 * (cell.height + 2);
+    let width = icon_size + REGION_GAP * 2 + text_width;
+    let x = area.top_left.x + (area.size.width.saturating_sub(width) / 2) as i32;
+    let middle = area.top_left.y + area.size.height as i32 / 2;
+
+    if let Some(icon) = icons::icon(&name) {
+        let top = middle - icon_size as i32 / 2;
+        icon.draw(
+            target,
+            Point::new(x, top),
+            BATTERY_ICON_SCALE,
+            layout.foreground,
+        );
+    }
+    let x = x + (icon_size + REGION_GAP * 2) as i32;
+    let mut y = middle - text_height as i32 / 2;
+    PROGRESS_FONT
+        .draw_text(target, &number, Point::new(x, y), layout.foreground)
+        .ok();
+    y += PROGRESS_FONT.char_size().height as i32 + 2;
+    for line in lines {
+        layout
+            .font
+            .draw_text(target, line, Point::new(x, y), layout.foreground)
+            .ok();
+        y += cell.height as i32 + 2;
+    }
+}
+
+/// Draw `text`, one line, in the large font enlarged by the biggest whole factor that fits
+/// `area`, and centered in it; for a clock or a countdown read from across the room.
+pub fn draw_big_text<D: DrawTarget<Color = BinaryColor>>(
+    target: &mut D,
+    area: Rectangle,
+    text: &str,
+    color: BinaryColor,
+) {
+    let cell = PROGRESS_FONT.char_size();
+    let size = Size::new(display_width(text) as u32 * cell.width, cell.height);
+    if size.width == 0 {
+        return;
+    }
+    let scale = (area.size.width / size.width)
+        .min(area.size.height / size.height)
+        .max(1);
+    let mut glyphs = MonoImage::new(size.width, size.height);
+    glyphs.clear(BinaryColor::Off);
+    PROGRESS_FONT
+        .draw_text(&mut glyphs, text, Point::zero(), BinaryColor::On)
+        .ok();
+    let offset = Point::new(
+        area.size.width.saturating_sub(size.width * scale) as i32 / 2,
+        area.size.height.saturating_sub(size.height * scale) as i32 / 2,
+    );
+    for y in 0..size.height {
+        for x in 0..size.width {
+            if glyphs.pixel(x, y) != BinaryColor::On {
+                continue;
+            }
+            let pixel = Point::new(x as i32, y as i32) * scale as i32;
+            target
+                .fill_solid(
+                    &Rectangle::new(area.top_left + offset + pixel, Size::new_equal(scale)),
+                    color,
+                )
+                .ok();
+        }
+    }
+}
+
+/// Draw `values` as a line chart filling `area`: `label` and the latest value on a line
+/// above it, and the maximum and minimum at the top and bottom of a scale on its left. The
+/// values are spread evenly across the width and scaled to the height; a flat series runs
+/// through the middle.
+pub fn draw_sparkline<D: DrawTarget<Color = BinaryColor>>(
+    target: &mut D,
+    area: Rectangle,
+    values: &[f32],
+    label: &str,
+    layout: &TextLayout,
+) {
+    let Some(&last) = values.last() else {
+        return;
+    };
+    let cell = layout.font.char_size();
+    let latest = format_number(last);
+    let columns = (area.size.width / cell.width) as usize;
+    let room = columns.saturating_sub(latest.len() + 1);
+    let label: String = label.trim().chars().take(room).collect();
+    let top = area.top_left;
+    layout
+        .font
+        .draw_text(target, &label, top, layout.foreground)
+        .ok();
+    let x = top.x
+        + area
+            .size
+            .width
+            .saturating_sub(latest.len() as u32 * cell.width) as i32;
+    layout
+        .font
+        .draw_text(target, &latest, Point::new(x, top.y), layout.foreground)
+        .ok();
+
+    let (min, max) = values
+        .iter()
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), &value| {
+            (min.min(value), max.max(value))
+        });
+    let (min_label, max_label) = (format_number(min), format_number(max));
+    let scale = SCALE_FONT.char_size();
+    let gutter = min_label.len().max(max_label.len()) as u32 * scale.width + REGION_GAP;
+    let chart = Rectangle::new(
+        top + Point::new(gutter as i32, (cell.height + REGION_GAP) as i32),
+        Size::new(
+            area.size.width.saturating_sub(gutter),
+            area.size.height.saturating_sub(cell.height + REGION_GAP),
+        ),
+    );
+    if chart.size.width < 2 || chart.size.height < scale.height * 2 {
+        return;
+    }
+    let bottom = chart.top_left.y + chart.size.height as i32 - 1;
+    SCALE_FONT
+        .draw_text(
+            target,
+            &max_label,
+            Point::new(top.x, chart.top_left.y),
+            layout.foreground,
+        )
+        .ok();
+    let min_y = bottom + 1 - scale.height as i32;
+    SCALE_FONT
+        .draw_text(
+            target,
+            &min_label,
+            Point::new(top.x, min_y),
+            layout.foreground,
+        )
+        .ok();
+    let axis_x = chart.top_left.x - (REGION_GAP / 2) as i32;
+    divider(
+        target,
+        layout,
+        Point::new(axis_x, chart.top_left.y),
+        Point::new(axis_x, bottom),
+    );
+
+    let height = (chart.size.height - 1) as f32;
+    let step = (chart.size.width - 1) as f32 / (values.len().max(2) - 1) as f32;
+    let points: Vec<Point> = values
+        .iter()
+        .enumerate()
+        .map(|(index, &value)| {
+            let fraction = if max > min {
+                (value - min) / (max - min)
+            } else {
+                0.5
+            };
+            Point::new(
+                chart.top_left.x + (index as f32 * step).round() as i32,
+                bottom - (fraction * height).round() as i32,
+            )
+        })
+        .collect();
+    let style = PrimitiveStyle::with_stroke(layout.foreground, 1);
+    Polyline::new(&points).into_styled(style).draw(target).ok();
+    if let Some(&end) = points.last() {
+        Circle::with_center(end, 5)
+            .into_styled(PrimitiveStyle::with_fill(layout.foreground))
+            .draw(target)
+            .ok();
+    }
+}
+
+/// A chart value with at most two decimals and without trailing zeros: `21.5`, `1000`.
+fn format_number(value: f32) -> String {
+    let text = format!("{value:.2}");
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    match text {
+        "-0" => "0".to_string(),
+        text => text.to_string(),
+    }
+}
+
+fn divider<D: DrawTarget<Color = BinaryColor>>(
+    target: &mut D,
+    layout: &TextLayout,
+    start: Point,
+    end: Point,
+) {
+    Line::new(start, end)
+        .into_styled(PrimitiveStyle::with_stroke(layout.foreground, 1))
+        .draw(target)
+        .ok();
+}
@@ -0,0 +1,337 @@
+//! Declarative layouts: a JSON or TOML list of positioned elements rendered to a
+//! [`MonoImage`].
+//!
+//! ```json
+//! {
+//!   "elements": [
+//!     { "type": "text", "x": 0, "y": 0, "height": 24, "text": "Office", "font": "10x20",
+//!       "align": "center,middle" },
+//!     { "type": "line", "x1": 0, "y1": 24, "x2": 121, "y2": 24 },
+//!     { "type": "qr", "x": 21, "y": 40, "width": 80, "height": 80, "data": "https://…" }
+//!   ]
+//! }
+//! ```
+//!
+//! Coordinates are canvas pixels; a missing `width`/`height` extends the element to the
+//! right/bottom edge.
+
+use embedded_graphics::{
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::{Line, PrimitiveStyle, Rectangle},
+};
+use qrcode::types::QrError;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+use crate::{
+    buffer::{draw_qr, MonoImage},
+    dither::{dither, DitherMethod, FitMode, GrayImage},
+    icons,
+    text::{Alignment, FontChoice, TextLayout},
+};
+
+#[derive(Debug, Error)]
+pub enum LayoutError {
+    #[error("invalid JSON layout: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("invalid TOML layout: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("element {index}: {message}")]
+    Element { index: usize, message: String },
+    #[error("element {index}: cannot load {}: {source}", path.display())]
+    Image {
+        index: usize,
+        path: PathBuf,
+        source: image::ImageError,
+    },
+    #[error("element {index}: {source}")]
+    Qr { index: usize, source: QrError },
+}
+
+/// Ink color of an element.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Ink {
+    #[default]
+    Black,
+    White,
+}
+
+impl From<Ink> for BinaryColor {
+    fn from(ink: Ink) -> Self {
+        match ink {
+            Ink::Black => BinaryColor::On,
+            Ink::White => BinaryColor::Off,
+        }
+    }
+}
+
+/// Position and size shared by box-shaped elements.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(default)]
+pub struct Bounds {
+    pub x: i32,
+    pub y: i32,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+impl Bounds {
+    fn rect(&self, canvas: Size) -> Rectangle {
+        let remaining = |origin: i32, extent: u32| (extent as i32 - origin).max(0) as u32;
+        Rectangle::new(
+            Point::new(self.x, self.y),
+            Size::new(
+                self.width.unwrap_or(remaining(self.x, canvas.width)),
+                self.height.unwrap_or(remaining(self.y, canvas.height)),
+            ),
+        )
+    }
+}
+
+fn one() -> u32 {
+    1
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Element {
+    /// Wrapped text, drawn like the `write` command inside its bounds.
+    Text {
+        #[serde(flatten)]
+        bounds: Bounds,
+        text: String,
+        /// Font name such as `9x15`, or `auto` for the largest that fits.
+        font: Option<String>,
+        /// Alignment such as `center,middle`.
+        align: Option<String>,
+        #[serde(default)]
+        color: Ink,
+        #[serde(default)]
+        markup: bool,
+    },
+    /// Rectangle outline, or a filled box when `fill` is set.
+    Rect {
+        #[serde(flatten)]
+        bounds: Bounds,
+        #[serde(default)]
+        fill: bool,
+        #[serde(default = "one")]
+        stroke: u32,
+        #[serde(default)]
+        color: Ink,
+    },
+    Line {
+        x1: i32,
+        y1: i32,
+        x2: i32,
+        y2: i32,
+        #[serde(default = "one")]
+        stroke: u32,
+        #[serde(default)]
+        color: Ink,
+    },
+    /// Image file scaled into its bounds and dithered; relative paths are resolved
+    /// against the layout file's directory.
+    Image {
+        #[serde(flatten)]
+        bounds: Bounds,
+        path: PathBuf,
+        /// `contain`, `cover` or `stretch`.
+        fit: Option<String>,
+        /// `threshold`, `ordered` or `floyd`.
+        dither: Option<String>,
+    },
+    /// QR code centered in its bounds.
+    Qr {
+        #[serde(flatten)]
+        bounds: Bounds,
+        data: String,
+    },
+    /// Built-in icon (see [`crate::icons`]) with its top-left corner at `x`, `y`, enlarged
+    /// `scale` times.
+    Icon {
+        #[serde(default)]
+        x: i32,
+        #[serde(default)]
+        y: i32,
+        name: String,
+        #[serde(default = "one")]
+        scale: u32,
+        #[serde(default)]
+        color: Ink,
+    },
+}
+
+/// A parsed layout description.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LayoutSpec {
+    #[serde(default = "white")]
+    pub background: Ink,
+    #[serde(default)]
+    pub elements: Vec<Element>,
+}
+
+fn white() -> Ink {
+    Ink::White
+}
+
+impl LayoutSpec {
+    pub fn from_json(source: &str) -> Result<Self, LayoutError> {
+        Ok(serde_json::from_str(source)?)
+    }
+
+    pub fn from_toml(source: &str) -> Result<Self, LayoutError> {
+        Ok(toml::from_str(source)?)
+    }
+
+    /// Parse TOML if `path` ends in `.toml`, JSON otherwise.
+    pub fn parse_for_path(source: &str, path: &Path) -> Result<Self, LayoutError> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("toml") => Self::from_toml(source),
+            _ => Self::from_json(source),
+        }
+    }
+
+    /// Draw every element, in order, onto a fresh canvas of `size`. Relative image paths
+    /// are looked up in `base_dir`.
+    pub fn render(&self, size: Size, base_dir: &Path) -> Result<MonoImage, LayoutError> {
+        let mut fb = MonoImage::new(size.width, size.height);
+        fb.clear(self.background.into());
+        for (index, element) in self.elements.iter().enumerate() {
+            draw_element(&mut fb, element, base_dir).map_err(|err| err.at(index))?;
+        }
+        Ok(fb)
+    }
+}
+
+/// An element failure before the element index is known.
+enum ElementError {
+    Invalid(String),
+    Image(PathBuf, image::ImageError),
+    Qr(QrError),
+}
+
+impl ElementError {
+    fn at(self, index: usize) -> LayoutError {
+        match self {
+            ElementError::Invalid(message) => LayoutError::Element { index, message },
+            ElementError::Image(path, source) => LayoutError::Image {
+                index,
+                path,
+                source,
+            },
+            ElementError::Qr(source) => LayoutError::Qr { index, source },
+        }
+    }
+}
+
+fn parse_opt<T>(value: Option<&str>) -> Result<Option<T>, ElementError>
+where
+    T: std::str::FromStr<Err = String>,
+{
+    value
+        .map(|v| v.parse().map_err(ElementError::Invalid))
+        .transpose()
+}
+
+fn draw_element(
+    fb: &mut MonoImage,
+    element: &Element,
+    base_dir: &Path,
+) -> Result<(), ElementError> {
+    let canvas = Size::new(fb.width(), fb.height());
+    match element {
+        Element::Text {
+            bounds,
+            text,
+            font,
+            align,
+            color,
+            markup,
+        } => {
+            let auto_fit = font
+                .as_deref()
+                .is_some_and(|f| f.eq_ignore_ascii_case("auto"));
+            let font = match font.as_deref() {
+                Some(_) if auto_fit => FontChoice::default(),
+                other => parse_opt::<FontChoice>(other)?.unwrap_or_default(),
+            };
+            let foreground = BinaryColor::from(*color);
+            let layout = TextLayout {
+                alignment: parse_opt::<Alignment>(align.as_deref())?.unwrap_or_default(),
+                margin: 0,
+                border: false,
+                auto_fit,
+                markup: *markup,
+                ..TextLayout::new(font, foreground, foreground.invert())
+            };
+            layout.draw(fb, bounds.rect(canvas), text);
+        }
+        Element::Rect {
+            bounds,
+            fill,
+            stroke,
+            color,
+        } => {
+            let style = if *fill {
+                PrimitiveStyle::with_fill((*color).into())
+            } else {
+                PrimitiveStyle::with_stroke((*color).into(), *stroke)
+            };
+            bounds.rect(canvas).into_styled(style).draw(fb).ok();
+        }
+        Element::Line {
+            x1,
+            y1,
+            x2,
+            y2,
+            stroke,
+            color,
+        } => {
+            Line::new(Point::new(*x1, *y1), Point::new(*x2, *y2))
+                .into_styled(PrimitiveStyle::with_stroke((*color).into(), *stroke))
+                .draw(fb)
+                .ok();
+        }
+        Element::Image {
+            bounds,
+            path,
+            fit,
+            dither: method,
+        } => {
+            let fit = parse_opt::<FitMode>(fit.as_deref())?.unwrap_or_default();
+            let method = parse_opt::<DitherMethod>(method.as_deref())?.unwrap_or_default();
+            let path = base_dir.join(path);
+            let gray = GrayImage::open(&path).map_err(|err| ElementError::Image(path, err))?;
+            let area = bounds.rect(canvas);
+            let image = dither(&gray.fit(area.size.width, area.size.height, fit), method);
+            for y in 0..image.height() {
+                for x in 0..image.width() {
+                    let point = area.top_left + Point::new(x as i32, y as i32);
+                    if point.x >= 0 && point.y >= 0 {
+                        fb.set_pixel(point.x as u32, point.y as u32, image.pixel(x, y));
+                    }
+                }
+            }
+        }
+        Element::Qr { bounds, data } => {
+            draw_qr(fb, data, bounds.rect(canvas)).map_err(ElementError::Qr)?;
+        }
+        Element::Icon {
+            x,
+            y,
+            name,
+            scale,
+            color,
+        } => {
+            let icon = icons::icon(name)
+                .ok_or_else(|| ElementError::Invalid(format!("unknown icon '{name}'")))?;
+            icon.draw(fb, Point::new(*x, *y), *scale, (*color).into());
+        }
+    }
+    Ok(())
+}
@@ -1,5 +1,41 @@
 pub mod buffer;
+pub mod buttons;
+pub mod buzzer;
+pub mod calendar;
+pub mod client;
+pub mod dither;
+pub mod docker;
 pub mod epd2in13_v4;
+pub mod feed;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod icons;
+pub mod ics;
+pub mod layout;
+pub mod layout_spec;
+pub mod markdown;
+pub mod markup;
+pub mod menu;
+pub mod mpd;
+pub mod netinfo;
+pub mod panel;
+pub mod patterns;
+pub mod pihole;
+pub mod preview;
+pub mod quotes;
+pub mod state;
+pub mod sysinfo;
+pub mod template;
+pub mod terminal;
+pub mod text;
+pub mod ticker;
+pub mod todo;
+#[cfg(feature = "ups")]
+pub mod ups;
+pub mod webhook;
 
-pub use buffer::MonoImage;
-pub use epd2in13_v4::{Epd2in13V4, EpdPins, UpdateMode};
+pub use buffer::{BlendMode, MonoImage, Rotation};
+pub use client::EinkClient;
+pub use epd2in13_v4::{Epd2in13V4, EpdPins, RefreshTiming, UpdateMode};
+pub use panel::Panel;
+pub use text::{Alignment, FontChoice, TextLayout, TextQuality};
@@ -1,17 +1,83 @@
-use clap::{Parser, Subcommand};
+mod server;
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Datelike, Local, Months, NaiveDate, NaiveDateTime, TimeDelta};
+use clap::{ArgAction, Args, CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
 use embedded_graphics::{
-    mono_font::{ascii::FONT_6X10, MonoTextStyle},
     pixelcolor::BinaryColor,
     prelude::*,
-    primitives::{PrimitiveStyle, Rectangle},
-    text::Text,
+    primitives::{Line, PrimitiveStyle, Rectangle},
+};
+use inotify::{Inotify, WatchMask};
+use rpi_einkserver_rs::{
+    buffer::{draw_barcode, draw_qr, BarcodeKind, FrameError, Rotation},
+    buttons,
+    buzzer::Buzzer,
+    calendar::draw_month,
+    dither::{dither, DitherMethod, FitMode, GrayImage},
+    docker::{self, Container, DockerEvents},
+    epd2in13_v4::{EpdError, RefreshTiming},
+    feed::{Feed, FeedError},
+    icons::{self, ICON_SIZE},
+    ics,
+    layout::{
+        draw_big_text, draw_columns, draw_progress, draw_sparkline, draw_table, split_columns,
+        REGION_GAP,
+    },
+    layout_spec::{LayoutError, LayoutSpec},
+    markdown::draw_markdown,
+    menu::Menu,
+    mpd::{MpdClient, NowPlaying, PlayState},
+    netinfo::{NetlinkWatcher, NetworkSnapshot},
+    panel::CountingPanel,
+    patterns::TestPattern,
+    pihole::{PiholeApi, PiholeClient},
+    preview::PreviewPanel,
+    quotes::{self, Quote, QuoteSource},
+    state::{FrameStore, StateError, DEFAULT_FRAME_PATH},
+    sysinfo::{self, SystemSnapshot, Throttled},
+    template::{self, TemplateError},
+    text::{display_width, fit_columns, Alignment, HAlign, TextLayout, TextQuality, VAlign},
+    ticker::Ticker,
+    todo::TodoList,
+    EinkClient, Epd2in13V4, EpdPins, FontChoice, MonoImage, Panel, UpdateMode,
+};
+use rppal::gpio::Gpio;
+use rppal::spi::{Bus, SlaveSelect};
+use sd_notify::NotifyState;
+use server::http::run_http_server;
+use server::socket::{
+    blit, create_fifo, decode_frame, failure_reply, handle_buttons, handle_connection,
+    handle_packet, last_canvas, read_fifo, status_json, Chart, DisplayMode, FrameEncoding,
+    PanelInfo, ServerState, Session, SocketJob, SocketPacket, Subscribers, Templates,
+    DEFAULT_CHART_WINDOW, MAX_CHART_POINTS,
 };
-use rpi_einkserver_rs::{Epd2in13V4, EpdPins, MonoImage};
-use std::io::{self, BufRead, BufReader, Write};
-use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(feature = "grpc")]
+use server::socket::{failure_code, mode_name, parse_update_mode, set_font, show_planned};
+use signal_hook::consts::{SIGINT, SIGTERM};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::ffi::CString;
+use std::io::{self, BufRead, Read};
+#[cfg(feature = "grpc")]
+use std::net::SocketAddr;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::io::FromRawFd;
+use std::os::unix::process::CommandExt;
+use std::os::unix::net::UnixListener;
 use std::path::{Path, PathBuf};
+use std::process::{self, ExitCode};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tracing::{debug, error, info, warn};
+use tracing_subscriber::EnvFilter;
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 #[command(
     name = "rpi-einkserver-rs",
     author,
@@ -31,6 +97,79 @@ struct Cli {
     #[arg(long)]
     reverse_color: bool,
 
+    /// Font used for text: 6x10, 7x13, 9x15 or 10x20, and with the `cjk` feature also cjk-zh,
+    /// cjk-ja or cjk-ko (16px Unifont for Chinese, Japanese and Korean).
+    #[arg(long, default_value_t = FontChoice::default())]
+    font: FontChoice,
+
+    /// Text placement, e.g. `center`, `right,bottom` or `center,middle`.
+    #[arg(long, default_value_t = Alignment::default())]
+    align: Alignment,
+
+    /// Pick the largest font that fits the whole message (overrides --font).
+    #[arg(long)]
+    auto_fit: bool,
+
+    /// Never break words at soft hyphens (U+00AD) when wrapping.
+    #[arg(long)]
+    no_hyphenate: bool,
+
+    /// Interpret `*bold*`, `_underline_` and `~inverse~` spans in text.
+    #[arg(long)]
+    markup: bool,
+
+    /// BCM pin wired to the panel's BUSY line.
+    #[arg(long, default_value_t = 24)]
+    pin_busy: u8,
+
+    /// BCM pin wired to DC (data/command select).
+    #[arg(long, default_value_t = 25)]
+    pin_dc: u8,
+
+    /// BCM pin wired to RST.
+    #[arg(long, default_value_t = 17)]
+    pin_rst: u8,
+
+    /// BCM pin wired to CS.
+    #[arg(long, default_value_t = 8)]
+    pin_cs: u8,
+
+    /// BCM pin switching panel power on HAT revisions that have one (e.g. 18).
+    #[arg(long)]
+    pin_pwr: Option<u8>,
+
+    /// Rotate everything shown clockwise by 0, 90, 180 or 270 degrees (90/270 = landscape).
+    #[arg(long, default_value_t = Rotation::Deg0)]
+    rotate: Rotation,
+
+    /// Where the last frame sent to the panel is kept for `status` and later restores.
+    #[arg(long, default_value = DEFAULT_FRAME_PATH)]
+    persist_frame: PathBuf,
+
+    /// On `repl`, `serve` and `serve-http` startup, pick up the last frame from
+    /// --persist-frame: `keep` (the default) trusts the panel still shows it and only loads
+    /// it as the partial-update base; `redraw` shows it again with a full refresh. Partial
+    /// mode resumes if the frame came from a partial refresh.
+    #[arg(long, value_name = "HOW", num_args = 0..=1, require_equals = true, default_missing_value = "keep", value_parser = parse_restore)]
+    restore: Option<Restore>,
+
+    /// Render as usual but write each frame to this PNG instead of driving the panel. The
+    /// frames otherwise kept in --persist-frame go next to it (`out.epf` for `out.png`).
+    #[arg(long, value_name = "PNG")]
+    preview: Option<PathBuf>,
+
+    /// Smooth text edges: glyphs are upscaled 2x, filtered back down and dithered.
+    #[arg(long)]
+    smooth: bool,
+
+    /// More log output (-v debug, -vv trace). `RUST_LOG` overrides both flags.
+    #[arg(short, long, action = ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+
+    /// Less log output (-q warnings only, -qq errors only).
+    #[arg(short, long, action = ArgAction::Count)]
+    quiet: u8,
+
     #[command(subcommand)]
     command: Option<Command>,
 }
@@ -41,382 +180,4621 @@ enum Command {
     Clear,
     /// Initialize and write a wrapped message to the display.
     Write {
-        /// Text to render (wrapped to fit the display).
+        /// Text to render (wrapped to fit the display); `-` reads it from stdin.
         #[arg(long)]
         text: Option<String>,
+        /// Read the text from a file instead.
+        #[arg(long, conflicts_with = "text")]
+        file: Option<PathBuf>,
+        /// When the text needs more than one screen, flip to the next page this often
+        /// (e.g. `10s`) and keep cycling. Without it only the first page is shown.
+        #[arg(long, value_parser = parse_nonzero_interval)]
+        page_interval: Option<Duration>,
+        /// Partial refresh over the frame stored by an earlier run (see --persist-frame),
+        /// skipping initialization and the base refresh.
+        #[arg(long)]
+        partial: bool,
+    },
+    /// Initialize and show a QR code for the given payload.
+    Qr {
+        /// Data to encode (URL, WiFi credentials, ...).
+        #[arg(long)]
+        data: String,
+        /// Text shown below the code, e.g. "Guest WiFi".
+        #[arg(long)]
+        caption: Option<String>,
+    },
+    /// Initialize, show an image file (PNG, JPEG, GIF, BMP) dithered to black and white.
+    DrawImage {
+        /// Image to display.
+        path: PathBuf,
+        /// Dithering: threshold, ordered or floyd.
+        #[arg(long, default_value_t = DitherMethod::FloydSteinberg)]
+        dither: DitherMethod,
+        /// Scaling: contain (letterbox), cover (crop) or stretch.
+        #[arg(long, default_value_t = FitMode::Contain)]
+        fit: FitMode,
+    },
+    /// Cycle through checkerboard, stripes, black, white and a label frame to check wiring
+    /// and ghosting.
+    TestPattern {
+        /// Seconds to hold each pattern.
+        #[arg(long, default_value_t = 3)]
+        delay: u64,
+        /// Checkerboard square and stripe width in pixels.
+        #[arg(long, default_value_t = 8)]
+        cell: u32,
+    },
+    /// Time full, fast and partial refreshes, reporting SPI transfer and refresh separately.
+    Bench {
+        /// Refreshes per mode.
+        #[arg(long, short = 'n', default_value_t = 5)]
+        iterations: u32,
+    },
+    /// Dashboard with hostname, IP, load, memory, disk and CPU temperature, refreshed
+    /// periodically with partial updates.
+    Sysinfo {
+        /// Time between refreshes, e.g. `30s`, `5m`.
+        #[arg(long, default_value = "60s", value_parser = parse_nonzero_interval)]
+        interval: Duration,
+        /// Render a single frame and exit.
+        #[arg(long)]
+        once: bool,
+    },
+    /// Containers of the local Docker daemon with up/down indicators, redrawn as soon as one
+    /// starts, stops or changes health.
+    Docker {
+        /// The Docker daemon's socket.
+        #[arg(long, default_value = docker::DEFAULT_SOCKET)]
+        socket: PathBuf,
+        /// Time between refreshes when no events arrive, e.g. `5m`.
+        #[arg(long, default_value = "5m", value_parser = parse_nonzero_interval)]
+        interval: Duration,
+        /// Render a single frame and exit.
+        #[arg(long)]
+        once: bool,
+    },
+    /// Queries blocked today, the share blocked and the busiest client, from a Pi-hole's API.
+    Pihole {
+        /// Base URL of the Pi-hole's web interface.
+        #[arg(long, default_value = "http://pi.hole")]
+        url: String,
+        /// API to use: 6 for the REST API of Pi-hole 6, 5 for the `api.php` of Pi-hole 5.
+        #[arg(long, default_value_t = 6, value_parser = clap::value_parser!(u8).range(5..=6))]
+        api: u8,
+        /// Web password (Pi-hole 6), or the app password if two-factor login is enabled.
+        #[arg(long, conflicts_with = "token")]
+        password: Option<String>,
+        /// API token from Settings > API (Pi-hole 5).
+        #[arg(long)]
+        token: Option<String>,
+        /// Time between refreshes, e.g. `5m`.
+        #[arg(long, default_value = "5m", value_parser = parse_nonzero_interval)]
+        interval: Duration,
+        /// Render a single frame and exit.
+        #[arg(long)]
+        once: bool,
+    },
+    /// Artist, title and album of the song an MPD server is playing, with a progress bar
+    /// moved along with partial updates.
+    NowPlaying {
+        /// MPD server as `host:port`.
+        #[arg(long, default_value = "localhost:6600")]
+        mpd: String,
+        /// MPD password, if the server asks for one.
+        #[arg(long)]
+        password: Option<String>,
+        /// Time between progress bar updates while playing; song changes show at once.
+        #[arg(long, default_value = "10s", value_parser = parse_nonzero_interval)]
+        interval: Duration,
+        /// Render a single frame and exit.
+        #[arg(long)]
+        once: bool,
+    },
+    /// Temperature history of the first thermal zone as a sparkline, with the other zones
+    /// and a warning icon while the Pi is throttled or under-powered.
+    Thermal {
+        /// Time between readings, e.g. `10s`, `1m`.
+        #[arg(long, default_value = "10s", value_parser = parse_nonzero_interval)]
+        interval: Duration,
+        /// Readings kept in the chart.
+        #[arg(long, default_value_t = DEFAULT_CHART_WINDOW)]
+        window: usize,
+        /// Render a single frame and exit.
+        #[arg(long)]
+        once: bool,
+    },
+    /// Hostname, IPv4/IPv6 addresses, Wi-Fi network and signal strength, redrawn with
+    /// partial updates as soon as an interface or address changes.
+    Network {
+        /// Time between refreshes when nothing changes, which keeps the signal current.
+        #[arg(long, default_value = "60s", value_parser = parse_nonzero_interval)]
+        interval: Duration,
+        /// Render a single frame and exit.
+        #[arg(long)]
+        once: bool,
+    },
+    /// Charge, voltage and current of a UPS HAT's battery read over I2C, refreshed
+    /// periodically with partial updates.
+    #[cfg(feature = "ups")]
+    Battery {
+        /// Fuel gauge on the HAT: `ina219` (Waveshare UPS HATs) or `max17040`.
+        #[arg(long, default_value = "ina219")]
+        chip: rpi_einkserver_rs::ups::Gauge,
+        /// I2C address of the gauge, e.g. `0x43`; defaults to the chip's usual one.
+        #[arg(long, value_parser = parse_i2c_address)]
+        address: Option<u16>,
+        /// Li-ion cells in series, for estimating the charge from an INA219's voltage.
+        #[arg(long, default_value_t = 1)]
+        cells: u32,
+        /// Time between refreshes, e.g. `30s`, `5m`.
+        #[arg(long, default_value = "60s", value_parser = parse_nonzero_interval)]
+        interval: Duration,
+        /// Render a single frame and exit.
+        #[arg(long)]
+        once: bool,
+    },
+    /// Count down to a local date/time, updating every minute with partial refreshes.
+    Countdown {
+        /// Target as `YYYY-MM-DDTHH:MM[:SS]` (local time) or RFC 3339.
+        #[arg(long, value_parser = parse_local_time)]
+        until: DateTime<Local>,
+        /// Caption shown above the remaining time.
+        #[arg(long)]
+        label: Option<String>,
+    },
+    /// Pomodoro timer: work sessions and breaks in turn, with a large countdown updated
+    /// every 30 seconds by partial refreshes.
+    Pomodoro {
+        /// Length of a work session, e.g. `25m`.
+        #[arg(long, default_value = "25m", value_parser = parse_nonzero_interval)]
+        work: Duration,
+        /// Length of the break after a work session.
+        #[arg(long = "break", default_value = "5m", value_parser = parse_nonzero_interval)]
+        short_break: Duration,
+        /// Length of the break that ends a set of work sessions.
+        #[arg(long, default_value = "15m", value_parser = parse_nonzero_interval)]
+        long_break: Duration,
+        /// Work sessions in a set, before the long break.
+        #[arg(long, default_value_t = 4, value_parser = clap::value_parser!(u32).range(1..))]
+        sessions: u32,
+        /// GPIO pin (BCM) of an active buzzer that beeps as each phase begins: once for
+        /// work, twice for a break and three times for a long break.
+        #[arg(long, value_name = "PIN")]
+        buzzer: Option<u8>,
+        /// Render a single frame and exit.
+        #[arg(long)]
+        once: bool,
+    },
+    /// A to-do list from a Markdown file of `- [ ]` items, redrawn whenever the file
+    /// changes. With buttons, items can be selected and toggled, and the file is written
+    /// back.
+    Todo {
+        /// Markdown file with the items.
+        #[arg(long)]
+        file: PathBuf,
+        /// A push button between a GPIO pin (BCM) and ground, as `PIN:ACTION` with `up`,
+        /// `down` or `toggle` as the action; repeat for more.
+        #[arg(long = "button", value_name = "PIN:ACTION", value_parser = parse_todo_button)]
+        buttons: Vec<(u8, TodoButton)>,
+    },
+    /// Re-render a text file whenever it changes (watched with inotify).
+    Watch {
+        /// File whose contents are displayed; it may be replaced or recreated freely.
+        #[arg(long)]
+        file: PathBuf,
+    },
+    /// Run a shell command periodically and display its output.
+    Exec {
+        /// Time between runs, e.g. `30s`, `5m`.
+        #[arg(long, default_value = "60s", value_parser = parse_nonzero_interval)]
+        every: Duration,
+        /// Run once, display the output and exit.
+        #[arg(long)]
+        once: bool,
+        /// Command line passed to `sh -c`, e.g. `-- "df -h /"`.
+        #[arg(required = true, trailing_var_arg = true)]
+        command: Vec<String>,
+    },
+    /// Fill `{{name}}` placeholders (and `{{#if name}}` blocks) in a template file and show
+    /// the result like `write`.
+    Template {
+        /// Template file; `-` reads stdin.
+        #[arg(long)]
+        file: PathBuf,
+        /// Variable as `NAME=VALUE`; repeat for more.
+        #[arg(long = "var", value_name = "NAME=VALUE", value_parser = template::parse_var)]
+        vars: Vec<(String, String)>,
+    },
+    /// Render a JSON or TOML layout file of positioned text, rect, line, image and qr
+    /// elements.
+    RenderLayout {
+        /// Layout file; TOML when it ends in `.toml`, JSON otherwise.
+        file: PathBuf,
+    },
+    /// Scroll a line of text from right to left using partial refreshes.
+    Ticker {
+        /// Text to scroll; `-` reads it from stdin. Newlines become spaces.
+        #[arg(long)]
+        text: String,
+        /// Characters scrolled per second. Each refresh jumps by however far the text
+        /// moved while the previous one ran.
+        #[arg(long, default_value_t = 2.0)]
+        speed: f32,
+        /// Stop after this many passes (default: scroll forever).
+        #[arg(long)]
+        loops: Option<u32>,
+    },
+    /// Prices of stocks or cryptocurrencies with the day's change and an arrow, from a
+    /// JSON endpoint (Yahoo Finance unless configured otherwise).
+    TickerQuotes {
+        /// Comma-separated symbols as the endpoint knows them, e.g. `BTC-USD,AAPL`.
+        #[arg(long, value_delimiter = ',', required = true)]
+        symbols: Vec<String>,
+        /// Time between refreshes, e.g. `5m`.
+        #[arg(long, default_value = "5m", value_parser = parse_nonzero_interval)]
+        interval: Duration,
+        /// Endpoint, with `{symbol}` where the symbol goes.
+        #[arg(long, default_value = quotes::DEFAULT_URL)]
+        url: String,
+        /// JSON pointer to the price in the endpoint's reply.
+        #[arg(long, default_value = quotes::DEFAULT_PRICE)]
+        price: String,
+        /// JSON pointer to the day's change in percent, if the reply has it.
+        #[arg(long)]
+        change: Option<String>,
+        /// JSON pointer to the previous close, to work out the change when `--change` is
+        /// not given.
+        #[arg(long, default_value = quotes::DEFAULT_PREVIOUS)]
+        previous: String,
+        /// Render a single frame and exit.
+        #[arg(long)]
+        once: bool,
+    },
+    /// Fetch an RSS or Atom feed and cycle through its headlines.
+    Rss {
+        /// Feed URL (http or https).
+        #[arg(long)]
+        url: String,
+        /// How long each headline stays up, e.g. `30s` or `5m`.
+        #[arg(long, default_value = "5m", value_parser = parse_nonzero_interval)]
+        rotate: Duration,
+        /// How often the feed is fetched again.
+        #[arg(long, default_value = "30m", value_parser = parse_nonzero_interval)]
+        refresh: Duration,
+        /// Show each item's summary below its title.
+        #[arg(long)]
+        summary: bool,
+    },
+    /// Render a Markdown note: headings, bullet lists, horizontal rules and paragraphs.
+    Markdown {
+        /// Markdown file to show; `-` reads stdin.
+        #[arg(long)]
+        file: PathBuf,
+    },
+    /// Show a month grid with today highlighted, optionally marking days with events.
+    Calendar {
+        /// iCalendar file whose events are shown as dots under each day.
+        #[arg(long)]
+        ics: Option<PathBuf>,
+        /// Month to show as `YYYY-MM` (default: the current one).
+        #[arg(long, value_parser = parse_month)]
+        month: Option<NaiveDate>,
+    },
+    /// The next events of an iCalendar feed with their times, grouped by day, the ones under
+    /// way highlighted: a meeting-room sign.
+    Agenda {
+        /// Feed URL (`http`, `https` or `webcal`) or a local `.ics` file.
+        #[arg(long)]
+        ics: String,
+        /// Days shown, starting with today.
+        #[arg(long, default_value_t = 2, value_parser = clap::value_parser!(u32).range(1..))]
+        days: u32,
+        /// Time between fetches of the feed.
+        #[arg(long, default_value = "1h", value_parser = parse_nonzero_interval)]
+        refresh: Duration,
+        /// Render a single frame and exit.
+        #[arg(long)]
+        once: bool,
+    },
+    /// Deghost the panel with alternating black/white full refreshes, then restore the last
+    /// stored frame. Suitable for a nightly cron job.
+    Refresh {
+        /// Black/white cycles to run.
+        #[arg(long, default_value_t = 2)]
+        cycles: u32,
     },
+    /// Put the panel into deep sleep without changing what it shows.
+    Sleep,
+    /// Reset and initialize the panel, then redisplay the last stored frame (also as the
+    /// partial-update base).
+    Wake,
+    /// Print the panel configuration, BUSY level and last stored frame, without touching
+    /// the display.
+    Status,
+    /// Print a shell completion script for bash, zsh, fish, elvish or PowerShell.
+    Completions {
+        /// Shell to generate for.
+        shell: Shell,
+    },
+    /// Print the man page in roff format.
+    #[command(hide = true)]
+    Mangen,
     /// Interactive stdin REPL for issuing commands or text.
     Repl,
+    /// Serve a small HTTP API (`POST /text`, `/image`, `/clear`; `GET /status`,
+    /// `/screenshot.png`) for other machines on the network.
+    ServeHttp(HttpArgs),
+    /// Connect to an MQTT broker and show what is published to `<topic>/text`,
+    /// `<topic>/image` and `<topic>/clear`; status goes to `<topic>/status` and
+    /// `<topic>/availability`.
+    ServeMqtt {
+        /// Broker URL, `mqtt://host[:port]` (port 1883 by default).
+        #[arg(long, value_parser = parse_broker)]
+        broker: Broker,
+        /// Topic prefix.
+        #[arg(long, default_value = "eink/display")]
+        topic: String,
+        /// MQTT client id; must be unique on the broker.
+        #[arg(long, default_value = "rpi-einkserver-rs")]
+        client_id: String,
+        #[arg(long, requires = "password")]
+        username: Option<String>,
+        #[arg(
+            long,
+            requires = "username",
+            env = "EINK_MQTT_PASSWORD",
+            hide_env_values = true
+        )]
+        password: Option<String>,
+        /// Publish Home Assistant discovery configs under this prefix (`homeassistant` when
+        /// given without a value), so the display shows up as a notify entity and a clear
+        /// button.
+        #[arg(long, value_name = "PREFIX", num_args = 0..=1, default_missing_value = "homeassistant")]
+        ha_discovery: Option<String>,
+        /// Publish presses of a push button between this GPIO pin (BCM) and ground to
+        /// `<topic>/button`; repeat for more.
+        #[arg(long = "button", value_name = "PIN")]
+        buttons: Vec<u8>,
+    },
     /// Serve REPL-like commands over a Unix socket for scripting.
-    Serve {
-        /// Path to the Unix socket to bind, e.g. /tmp/eink.sock.
+    Serve(ServeArgs),
+    /// Serve the gRPC `Display` service of proto/eink.proto (DisplayText, DisplayImage,
+    /// Clear, Subscribe).
+    #[cfg(feature = "grpc")]
+    ServeGrpc {
+        /// Address and port to listen on.
+        #[arg(long, short = 'l', default_value = "0.0.0.0:50051")]
+        listen: SocketAddr,
+    },
+    /// Send a command to a running `serve` and print its reply, without touching the panel.
+    Client {
+        /// Socket the server listens on.
         #[arg(long, short = 's', default_value = "/tmp/eink.sock")]
         socket: PathBuf,
+        #[command(subcommand)]
+        request: ClientRequest,
     },
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let cli = Cli::parse();
-
-    // Default Waveshare HAT pins (BCM numbering): BUSY=24, RST=17, DC=25, CS=8.
-    let pins = EpdPins {
-        busy: 24,
-        dc: 25,
-        cs: 8,
-        rst: 17,
-    };
-
-    let mut epd = Epd2in13V4::new(pins)?;
-
-    let fg_color = if cli.reverse_color {
-        BinaryColor::Off
-    } else {
-        BinaryColor::On
-    };
-    let bg_color = if cli.reverse_color {
-        BinaryColor::On
-    } else {
-        BinaryColor::Off
-    };
+/// What `client` asks the server to do.
+#[derive(Subcommand, Debug, Clone)]
+enum ClientRequest {
+    /// Show wrapped text; `-` reads it from stdin.
+    Text { text: String },
+    /// Show an image file (PNG, JPEG, GIF, BMP), dithered by the server.
+    Image { path: PathBuf },
+    /// Clear the panel.
+    Clear,
+    /// Send any protocol line as is, e.g. `client send "QR https://example.com"`.
+    Send { line: String },
+}
 
-    let command = cli
-        .command
-        .clone()
-        .unwrap_or(Command::Write { text: None });
+#[derive(Debug, Clone, Args)]
+struct HttpArgs {
+    /// Address and port to listen on.
+    #[arg(long, short = 'l', default_value = "0.0.0.0:8080")]
+    listen: String,
+    /// Require `Authorization: Bearer <TOKEN>` on every request (WebSocket clients may pass
+    /// `?token=<TOKEN>` instead).
+    #[arg(long, env = "EINK_AUTH_TOKEN", hide_env_values = true)]
+    auth_token: Option<String>,
+    /// Read the token for `--auth-token` from the first line of a file.
+    #[arg(long, value_name = "PATH", conflicts_with = "auth_token")]
+    auth_token_file: Option<PathBuf>,
+    /// The secret set on a GitHub webhook: `POST /webhook/github` then needs a valid
+    /// `X-Hub-Signature-256` instead of the bearer token.
+    #[arg(long, env = "EINK_WEBHOOK_SECRET", hide_env_values = true)]
+    webhook_secret: Option<String>,
+    /// Serve HTTPS (and WSS) with this PEM certificate chain; needs `--tls-key`.
+    #[arg(long, value_name = "PATH", requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+    /// PEM private key for `--tls-cert`.
+    #[arg(long, value_name = "PATH", requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+    /// Put the panel into deep sleep after this long without updates (e.g. 120s); the next
+    /// update wakes it.
+    #[arg(long, value_name = "DURATION", value_parser = parse_nonzero_interval)]
+    idle_sleep: Option<Duration>,
+    /// Act on the content once no client has updated it for this long (e.g. 10m), so
+    /// outdated readings are not taken for current ones.
+    #[arg(long, value_name = "DURATION", value_parser = parse_nonzero_interval)]
+    stale_after: Option<Duration>,
+    /// What `--stale-after` does: `clear` the panel or overlay a `banner` saying since when
+    /// the content is stale.
+    #[arg(long, value_name = "ACTION", default_value = "banner", value_parser = parse_stale_action)]
+    stale_action: StaleAction,
+}
 
-    match command {
-        Command::Clear => {
-            maybe_init(&mut epd, &cli)?;
-            epd.clear(bg_color)?;
-            epd.sleep()?;
-        }
-        Command::Write { text } => {
-            maybe_init(&mut epd, &cli)?;
-            let message = text
-                .map(|t| decode_newlines(&t))
-                .unwrap_or_else(|| {
-                    "Hello from Rust! Pass --write --text \"your message\" to set custom text."
-                        .to_string()
-                });
-            render_text(&mut epd, &message, fg_color, bg_color, cli.fast)?;
-            epd.sleep()?;
+impl HttpArgs {
+    fn token(&self) -> io::Result<Option<String>> {
+        let token = match &self.auth_token_file {
+            Some(path) => {
+                let contents = std::fs::read_to_string(path)?;
+                Some(
+                    contents
+                        .lines()
+                        .next()
+                        .unwrap_or_default()
+                        .trim()
+                        .to_string(),
+                )
+            }
+            None => self.auth_token.clone(),
+        };
+        match token {
+            Some(token) if token.is_empty() => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the auth token is empty",
+            )),
+            token => Ok(token),
         }
-        Command::Repl => run_repl(epd, &cli, fg_color, bg_color)?,
-        Command::Serve { socket } => run_server(epd, &cli, fg_color, bg_color, &socket)?,
     }
 
-    Ok(())
-}
-
-fn maybe_init(epd: &mut Epd2in13V4, cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
-    if cli.noinit {
-        println!("Skipping panel initialization as requested.");
-        return Ok(());
-    }
+    fn tls_config(&self) -> Result<Option<Arc<rustls::ServerConfig>>, CliError> {
+        use rustls::pki_types::pem::PemObject;
+        use rustls::pki_types::{CertificateDer, PrivateKeyDer};
 
-    if cli.fast {
-        epd.init_fast()?;
-    } else {
-        epd.init()?;
+        let (Some(cert), Some(key)) = (&self.tls_cert, &self.tls_key) else {
+            return Ok(None);
+        };
+        let load = || -> Result<rustls::ServerConfig, Box<dyn std::error::Error + Send + Sync>> {
+            let certs = CertificateDer::pem_file_iter(cert)?.collect::<Result<Vec<_>, _>>()?;
+            let key = PrivateKeyDer::from_pem_file(key)?;
+            let provider = Arc::new(rustls::crypto::ring::default_provider());
+            Ok(rustls::ServerConfig::builder_with_provider(provider)
+                .with_safe_default_protocol_versions()?
+                .with_no_client_auth()
+                .with_single_cert(certs, key)?)
+        };
+        load()
+            .map(|config| Some(Arc::new(config)))
+            .map_err(|source| CliError::Tls {
+                cert: cert.clone(),
+                key: key.clone(),
+                source,
+            })
     }
-    Ok(())
 }
 
-fn render_text(
-    epd: &mut Epd2in13V4,
-    message: &str,
-    fg: BinaryColor,
-    bg: BinaryColor,
-    fast: bool,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let fb = build_framebuffer(message, fg, bg);
-    if fast {
-        epd.display_fast(fb.data())?;
-    } else {
-        epd.display(fb.data())?;
-    }
-    Ok(())
+#[derive(Debug, Clone, Args)]
+struct ServeArgs {
+    /// Path to the Unix socket to bind, e.g. /tmp/eink.sock.
+    #[arg(long, short = 's', default_value = "/tmp/eink.sock")]
+    socket: PathBuf,
+    /// Commands from all clients that may wait for the display; further ones are
+    /// answered with `ERR BUSY`.
+    #[arg(long, default_value_t = 8, value_parser = clap::value_parser!(u32).range(1..))]
+    queue: u32,
+    /// Permissions for the socket file (and the --fifo) in octal, e.g. 0660 (default: from
+    /// the umask).
+    #[arg(long, value_name = "MODE", value_parser = parse_socket_mode)]
+    socket_mode: Option<u32>,
+    /// Group (name or id) to own the socket file (and the --fifo), so its members can use
+    /// the display.
+    #[arg(long, value_name = "GROUP", value_parser = parse_group)]
+    socket_group: Option<u32>,
+    /// Put the panel into deep sleep after this long without updates (e.g. 120s); the next
+    /// update wakes it.
+    #[arg(long, value_name = "DURATION", value_parser = parse_nonzero_interval)]
+    idle_sleep: Option<Duration>,
+    /// Act on the content once no client has updated it for this long (e.g. 10m), so
+    /// outdated readings are not taken for current ones.
+    #[arg(long, value_name = "DURATION", value_parser = parse_nonzero_interval)]
+    stale_after: Option<Duration>,
+    /// What `--stale-after` does: `clear` the panel or overlay a `banner` saying since when
+    /// the content is stale.
+    #[arg(long, value_name = "ACTION", default_value = "banner", value_parser = parse_stale_action)]
+    stale_action: StaleAction,
+    /// Shortest time between refreshes for `TEXT` (e.g. 2s). A message arriving sooner is
+    /// held back, replaced by any later one, and shown once the time is up.
+    #[arg(long, value_name = "DURATION", value_parser = parse_interval)]
+    min_interval: Option<Duration>,
+    /// Also read commands from this named pipe, created if missing, e.g. `echo hello >
+    /// /run/eink.fifo`. Replies only go to the log.
+    #[arg(long, value_name = "PATH")]
+    fifo: Option<PathBuf>,
+    /// Drive another panel, addressed with `@NAME <command>`, as
+    /// `NAME:busy=5,dc=6,rst=13,cs=7[,pwr=N][,spi=BUS.CS]` (SPI 0.0 by default); repeat for
+    /// more.
+    #[arg(long = "panel", value_name = "NAME:PINS", value_parser = parse_panel_spec)]
+    panels: Vec<PanelSpec>,
+    /// Keep `TEMPLATE SAVE` templates in this directory, as `<name>.json`, so they outlive
+    /// the server; it is created when the first one is saved.
+    #[arg(long, value_name = "DIR")]
+    templates: Option<PathBuf>,
+    /// A menu for `MENU`, as JSON (TOML for `.toml` files): entries that open submenus, run
+    /// protocol commands (`command`) or shell commands (`run`), optionally after a yes/no
+    /// confirmation (`confirm`).
+    #[arg(long, value_name = "FILE")]
+    menu: Option<PathBuf>,
+    /// A push button between a GPIO pin (BCM) and ground, as `PIN[:ACTION]`: `next` or
+    /// `prev` page, `clear`, `menu`, `up`, `down`, `select` or `back` for the `--menu`,
+    /// `cmd=<command>` to run any protocol command, or `event` (the default) to only tell
+    /// `SUBSCRIBE` clients; repeat for more.
+    #[arg(long = "button", value_name = "PIN:ACTION", value_parser = parse_button)]
+    buttons: Vec<ButtonSpec>,
 }
 
-fn build_framebuffer(message: &str, fg: BinaryColor, bg: BinaryColor) -> MonoImage {
-    let mut fb = MonoImage::new(Epd2in13V4::WIDTH as u32, Epd2in13V4::HEIGHT as u32);
-    fb.clear(bg);
-
-    Rectangle::new(
-        Point::new(0, 0),
-        Size::new(Epd2in13V4::WIDTH as u32, Epd2in13V4::HEIGHT as u32),
-    )
-    .into_styled(PrimitiveStyle::with_stroke(fg, 1))
-    .draw(&mut fb)
-    .ok();
-
-    let margin = 6i32;
-    let font = FONT_6X10;
-    let char_width = font.character_size.width as usize;
-    let line_height = font.character_size.height as i32 + 2;
-    let max_chars = ((Epd2in13V4::WIDTH as usize).saturating_sub((margin as usize) * 2)
-        / char_width)
-        .max(1);
-    let max_lines = (Epd2in13V4::HEIGHT as usize).saturating_sub((margin as usize) * 2)
-        / line_height as usize;
-    let lines = wrap_text(message, max_chars);
-
-    let style = MonoTextStyle::new(&font, fg);
-    let mut y = margin + font.character_size.height as i32;
-    for line in lines.into_iter().take(max_lines) {
-        Text::new(&line, Point::new(margin, y), style)
-            .draw(&mut fb)
-            .ok();
-        y += line_height;
-    }
-
-    fb
+/// A button for `serve --button`.
+#[derive(Debug, Clone)]
+struct ButtonSpec {
+    pin: u8,
+    /// The protocol command a press runs, if any.
+    command: Option<String>,
 }
 
-fn wrap_text(text: &str, max_chars: usize) -> Vec<String> {
-    let mut lines = Vec::new();
-    for paragraph in text.split('\n') {
-        if paragraph.is_empty() {
-            lines.push(String::new());
-            continue;
-        }
-
-        let mut current = String::new();
-        for word in paragraph.split_whitespace() {
-            let word_len = word.chars().count();
-            let current_len = current.chars().count();
-
-            if current_len == 0 && word_len > max_chars {
-                for chunk in word.chars().collect::<Vec<_>>().chunks(max_chars) {
-                    lines.push(chunk.iter().collect());
-                }
-                continue;
+fn parse_button(input: &str) -> Result<ButtonSpec, String> {
+    let (pin, action) = input.split_once(':').unwrap_or((input, "event"));
+    let pin = pin
+        .parse()
+        .map_err(|_| format!("invalid button pin '{pin}'"))?;
+    let command = match action {
+        "next" => Some("PAGE NEXT".to_string()),
+        "prev" => Some("PAGE PREV".to_string()),
+        "clear" => Some("CLEAR".to_string()),
+        "menu" => Some("MENU".to_string()),
+        "up" | "down" | "select" | "back" => Some(format!("MENU {}", action.to_uppercase())),
+        "event" => None,
+        action => match action.strip_prefix("cmd=") {
+            Some(command) if !command.trim().is_empty() => Some(command.to_string()),
+            _ => {
+                return Err(format!(
+                    "unknown button action '{action}' (next, prev, clear, menu, up, down, select, \
+                 back, event or cmd=<command>)"
+                ))
             }
+        },
+    };
+    Ok(ButtonSpec { pin, command })
+}
 
-            if current_len == 0 {
-                current.push_str(word);
-                continue;
-            }
+/// An extra panel for `serve --panel`.
+#[derive(Debug, Clone)]
+struct PanelSpec {
+    name: String,
+    pins: EpdPins,
+    /// Panel power switch, if the HAT has one.
+    pwr: Option<u8>,
+    bus: Bus,
+    slave_select: SlaveSelect,
+}
 
-            if current_len + 1 + word_len <= max_chars {
-                current.push(' ');
-                current.push_str(word);
-            } else {
-                lines.push(current);
-                current = String::new();
-                if word_len > max_chars {
-                    for chunk in word.chars().collect::<Vec<_>>().chunks(max_chars) {
-                        lines.push(chunk.iter().collect());
+fn parse_panel_spec(input: &str) -> Result<PanelSpec, String> {
+    let (name, settings) = input
+        .split_once(':')
+        .ok_or_else(|| format!("expected NAME:busy=..,dc=..,rst=..,cs=.., got '{input}'"))?;
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(format!(
+            "invalid panel name '{name}' (use letters, digits, - and _)"
+        ));
+    }
+    let (mut busy, mut dc, mut rst, mut cs, mut pwr) = (None, None, None, None, None);
+    let (mut bus, mut slave_select) = (Bus::Spi0, SlaveSelect::Ss0);
+    for setting in settings.split(',') {
+        let (key, value) = setting
+            .split_once('=')
+            .ok_or_else(|| format!("expected KEY=VALUE, got '{setting}'"))?;
+        let pin = || {
+            value
+                .parse::<u8>()
+                .map_err(|_| format!("invalid pin '{value}' for {key}"))
+        };
+        match key {
+            "busy" => busy = Some(pin()?),
+            "dc" => dc = Some(pin()?),
+            "rst" => rst = Some(pin()?),
+            "cs" => cs = Some(pin()?),
+            "pwr" => pwr = Some(pin()?),
+            "spi" => {
+                (bus, slave_select) = match value.split_once('.') {
+                    Some(("0", "0")) => (Bus::Spi0, SlaveSelect::Ss0),
+                    Some(("0", "1")) => (Bus::Spi0, SlaveSelect::Ss1),
+                    Some(("1", "0")) => (Bus::Spi1, SlaveSelect::Ss0),
+                    Some(("1", "1")) => (Bus::Spi1, SlaveSelect::Ss1),
+                    Some(("1", "2")) => (Bus::Spi1, SlaveSelect::Ss2),
+                    _ => {
+                        return Err(format!(
+                            "unsupported SPI device '{value}' (0.0, 0.1, 1.0, 1.1 or 1.2)"
+                        ))
                     }
-                } else {
-                    current.push_str(word);
                 }
             }
-        }
-
-        if !current.is_empty() {
-            lines.push(current);
+            _ => return Err(format!("unknown panel setting '{key}'")),
         }
     }
-    lines
+    match (busy, dc, rst, cs) {
+        (Some(busy), Some(dc), Some(rst), Some(cs)) => Ok(PanelSpec {
+            name: name.to_string(),
+            pins: EpdPins { busy, dc, cs, rst },
+            pwr,
+            bus,
+            slave_select,
+        }),
+        _ => Err(format!("panel '{name}' needs busy, dc, rst and cs pins")),
+    }
 }
 
-fn blank_framebuffer(bg: BinaryColor) -> MonoImage {
-    let mut fb = MonoImage::new(Epd2in13V4::WIDTH as u32, Epd2in13V4::HEIGHT as u32);
-    fb.clear(bg);
-    fb
+/// Error classes that get their own exit code and a hint on how to fix them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Failure {
+    Other,
+    BadInput,
+    Gpio,
+    Spi,
+    SocketBind,
 }
 
-fn run_repl(
-    mut epd: Epd2in13V4,
-    cli: &Cli,
-    fg: BinaryColor,
-    bg: BinaryColor,
-) -> Result<(), Box<dyn std::error::Error>> {
-    maybe_init(&mut epd, cli)?;
-
-    println!(
-        "REPL ready. Commands: /clear, /partial, /nopartial. Type text to display. Ctrl-D to exit."
-    );
-
-    let stdin = io::stdin();
-    let mut partial = false;
-
-    for line in stdin.lock().lines() {
-        let line = line?;
-
-        if line.starts_with('/') {
-            match line.as_str() {
-                "/clear" => {
-                    epd.clear(bg)?;
-                }
-                "/partial" => {
-                    let blank = blank_framebuffer(bg);
-                    epd.display_base(blank.data())?;
-                    partial = true;
-                    println!("Partial updates enabled.");
-                }
-                "/nopartial" => {
-                    partial = false;
-                    println!("Partial updates disabled.");
-                }
-                other => {
-                    println!("Unknown command: {other}");
-                }
-            }
-            continue;
+impl Failure {
+    fn classify(err: &(dyn std::error::Error + 'static)) -> Self {
+        if let Some(err) = err.downcast_ref::<EpdError>() {
+            return match err {
+                EpdError::Gpio(_) => Failure::Gpio,
+                EpdError::Spi(_) => Failure::Spi,
+                EpdError::BufferSize { .. } => Failure::BadInput,
+                EpdError::Io(_) | EpdError::BusyTimeout(_) => Failure::Other,
+            };
         }
-
-        if line.trim().is_empty() {
-            continue;
+        if let Some(err) = err.downcast_ref::<CliError>() {
+            return match err {
+                CliError::SocketPermissions { .. } | CliError::Fifo { .. } => Failure::Other,
+                CliError::Tls { .. } => Failure::BadInput,
+                _ => Failure::SocketBind,
+            };
         }
-
-        let text = decode_newlines(&line);
-        let fb = build_framebuffer(&text, fg, bg);
-        if partial {
-            epd.display_partial(fb.data())?;
-        } else if cli.fast {
-            epd.display_fast(fb.data())?;
-        } else {
-            epd.display(fb.data())?;
+        if err.is::<image::ImageError>()
+            || err.is::<FrameError>()
+            || err.is::<StateError>()
+            || err.is::<TemplateError>()
+            || err.is::<LayoutError>()
+            || err.is::<FeedError>()
+            || err.is::<qrcode::types::QrError>()
+            || err.is::<barcoders::error::Error>()
+        {
+            return Failure::BadInput;
+        }
+        match err.downcast_ref::<io::Error>().map(io::Error::kind) {
+            Some(io::ErrorKind::NotFound | io::ErrorKind::InvalidData) => Failure::BadInput,
+            _ => Failure::Other,
         }
     }
 
-    epd.sleep()?;
-    Ok(())
+    /// Exit status; 2 matches clap's status for invalid command-line arguments.
+    fn exit_code(self) -> ExitCode {
+        ExitCode::from(match self {
+            Failure::Other => 1,
+            Failure::BadInput => 2,
+            Failure::Gpio => 3,
+            Failure::Spi => 4,
+            Failure::SocketBind => 5,
+        })
+    }
+
+    fn hint(self) -> Option<&'static str> {
+        match self {
+            Failure::Gpio => Some(
+                "GPIO is unavailable: run on a Raspberry Pi, make sure the user can access \
+                 /dev/gpiomem (e.g. `sudo usermod -aG gpio $USER`) and that no other process \
+                 holds the panel pins (see --pin-* to change them)",
+            ),
+            Failure::Spi => Some(
+                "SPI is unavailable: enable it with `sudo raspi-config` (Interface Options > \
+                 SPI) or `dtparam=spi=on` in /boot/firmware/config.txt, reboot, and check \
+                 that /dev/spidev0.0 exists and is accessible (group `spi`)",
+            ),
+            Failure::SocketBind => Some(
+                "is another server already running? Stop it, or pick another --socket path \
+                 in a directory this user can write to (or another --listen port)",
+            ),
+            Failure::BadInput | Failure::Other => None,
+        }
+    }
 }
 
-fn decode_newlines(input: &str) -> String {
-    input.replace("\\n", "\n")
+/// How `--restore` brings back the last frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Restore {
+    Keep,
+    Redraw,
 }
 
-fn run_server(
-    mut epd: Epd2in13V4,
-    cli: &Cli,
-    fg: BinaryColor,
-    bg: BinaryColor,
-    socket: &Path,
-) -> Result<(), Box<dyn std::error::Error>> {
-    if socket.exists() {
-        std::fs::remove_file(socket)?;
+fn parse_restore(input: &str) -> Result<Restore, String> {
+    match input.to_ascii_lowercase().as_str() {
+        "keep" => Ok(Restore::Keep),
+        "redraw" => Ok(Restore::Redraw),
+        _ => Err(format!("expected `keep` or `redraw`, got '{input}'")),
     }
+}
 
-    maybe_init(&mut epd, cli)?;
+/// Failures of the CLI itself, as opposed to the panel or the input.
+#[derive(Debug, Error)]
+enum CliError {
+    #[error("cannot bind socket {}: {source}", path.display())]
+    SocketBind { path: PathBuf, source: io::Error },
+    #[error("cannot set permissions of socket {}: {source}", path.display())]
+    SocketPermissions { path: PathBuf, source: io::Error },
+    #[error("cannot create FIFO {}: {source}", path.display())]
+    Fifo { path: PathBuf, source: io::Error },
+    #[error("cannot load TLS certificate {} and key {}: {source}", cert.display(), key.display())]
+    Tls {
+        cert: PathBuf,
+        key: PathBuf,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    #[error("cannot listen on {addr}: {source}")]
+    HttpBind {
+        addr: String,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+}
 
-    let listener = UnixListener::bind(socket)?;
-    println!(
-        "Unix socket server listening on {}",
-        socket.to_string_lossy()
-    );
-    println!("Protocol: newline-delimited packets. Commands: TEXT <msg> (default), CLEAR, PARTIAL_ON, PARTIAL_OFF, PING.");
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    init_logging(&cli);
 
-    for conn in listener.incoming() {
-        match conn {
-            Ok(stream) => {
-                if let Err(err) = handle_connection(stream, &mut epd, cli, fg, bg) {
-                    eprintln!("Connection error: {err}");
-                }
+    match run(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            let failure = Failure::classify(err.as_ref());
+            eprintln!("error: {err}");
+            if let Some(hint) = failure.hint() {
+                eprintln!("hint: {hint}");
             }
-            Err(err) => eprintln!("Accept error: {err}"),
+            failure.exit_code()
         }
     }
-
-    Ok(())
 }
 
-fn handle_connection(
-    stream: UnixStream,
-    epd: &mut Epd2in13V4,
-    cli: &Cli,
-    fg: BinaryColor,
-    bg: BinaryColor,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let mut writer = stream;
-    let reader_stream = writer.try_clone()?;
-    let mut reader = BufReader::new(reader_stream);
-
-    let mut line = String::new();
-    let mut partial = false;
+fn run(mut cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
+    // A dry run keeps its frames next to the PNG so the panel's stored frame stays true.
+    if let Some(path) = &cli.preview {
+        cli.persist_frame = path.with_extension("epf");
+    }
+    // Defaults match the Waveshare HAT (BCM numbering): BUSY=24, RST=17, DC=25, CS=8.
+    let pins = EpdPins {
+        busy: cli.pin_busy,
+        dc: cli.pin_dc,
+        cs: cli.pin_cs,
+        rst: cli.pin_rst,
+    };
 
-    loop {
-        line.clear();
-        let read = reader.read_line(&mut line)?;
-        if read == 0 {
-            break;
+    // Commands that never touch the panel.
+    match cli.command {
+        Some(Command::Status) => {
+            print_status(&cli, pins);
+            return Ok(());
         }
-
-        let trimmed = line.trim_end_matches(&['\r', '\n'][..]);
-        if trimmed.is_empty() {
-            continue;
+        Some(Command::Completions { shell }) => {
+            let mut command = Cli::command();
+            let name = command.get_name().to_string();
+            clap_complete::generate(shell, &mut command, name, &mut io::stdout());
+            return Ok(());
         }
-
-        let (cmd, payload) = parse_packet(trimmed);
-        let response = match cmd {
-            PacketCommand::Clear => {
-                epd.clear(bg)?;
-                "OK CLEAR"
-            }
-            PacketCommand::PartialOn => {
-                let blank = blank_framebuffer(bg);
-                epd.display_base(blank.data())?;
-                partial = true;
-                "OK PARTIAL_ON"
-            }
-            PacketCommand::PartialOff => {
-                partial = false;
-                "OK PARTIAL_OFF"
-            }
-            PacketCommand::Ping => "PONG",
-            PacketCommand::Text => {
-                let text = decode_newlines(payload.unwrap_or_default());
-                if text.trim().is_empty() {
-                    "IGNORED EMPTY"
-                } else {
-                    let fb = build_framebuffer(&text, fg, bg);
-                    if partial {
-                        epd.display_partial(fb.data())?;
-                    } else if cli.fast {
-                        epd.display_fast(fb.data())?;
-                    } else {
-                        epd.display(fb.data())?;
-                    }
-                    "OK TEXT"
+        Some(Command::Mangen) => {
+            clap_mangen::Man::new(Cli::command()).render(&mut io::stdout())?;
+            return Ok(());
+        }
+        Some(Command::Client { socket, request }) => {
+            let mut client = EinkClient::connect(socket)?;
+            let reply = match request {
+                ClientRequest::Text { text } if text == "-" => {
+                    client.send_text(io::read_to_string(io::stdin())?.trim_end())?
                 }
-            }
-        };
-
-        respond(&mut writer, response)?;
+                ClientRequest::Text { text } => client.send_text(&text)?,
+                ClientRequest::Image { path } => client.send_image(&std::fs::read(path)?)?,
+                ClientRequest::Clear => client.clear()?,
+                ClientRequest::Send { line } => client.command(&line)?,
+            };
+            println!("{reply}");
+            return Ok(());
+        }
+        _ => {}
     }
 
-    Ok(())
-}
-
-#[derive(Debug, Clone, Copy)]
-enum PacketCommand {
-    Text,
-    Clear,
-    PartialOn,
-    PartialOff,
-    Ping,
-}
-
-fn parse_packet(input: &str) -> (PacketCommand, Option<&str>) {
-    let mut parts = input.splitn(2, char::is_whitespace);
-    let head = parts.next().unwrap_or("");
-    let payload = parts.next();
-
-    match head.to_ascii_uppercase().as_str() {
-        "CLEAR" => (PacketCommand::Clear, None),
-        "PARTIAL_ON" => (PacketCommand::PartialOn, None),
-        "PARTIAL_OFF" => (PacketCommand::PartialOff, None),
-        "PING" => (PacketCommand::Ping, None),
-        "TEXT" => (PacketCommand::Text, payload),
-        _ => (PacketCommand::Text, Some(input)),
-    }
-}
+    let mut panel: Box<dyn Panel> = match &cli.preview {
+        Some(path) => Box::new(PreviewPanel::new(path, cli.rotate)),
+        None => Box::new(powered(Epd2in13V4::new(pins)?, cli.pin_pwr)?),
+    };
+    let epd = panel.as_mut();
 
-fn respond(stream: &mut UnixStream, message: &str) -> io::Result<()> {
-    stream.write_all(message.as_bytes())?;
-    stream.write_all(b"\n")?;
-    stream.flush()
+    let fg_color = if cli.reverse_color {
+        BinaryColor::Off
+    } else {
+        BinaryColor::On
+    };
+    let bg_color = if cli.reverse_color {
+        BinaryColor::On
+    } else {
+        BinaryColor::Off
+    };
+
+    let command = cli.command.clone().unwrap_or(Command::Write {
+        text: None,
+        file: None,
+        page_interval: None,
+        partial: false,
+    });
+
+    match command {
+        Command::Clear => {
+            maybe_init(epd, &cli)?;
+            clear_panel(epd, &cli, bg_color)?;
+            epd.sleep()?;
+        }
+        Command::Write {
+            text,
+            file,
+            page_interval,
+            partial,
+        } => {
+            let message = match (text.as_deref(), file) {
+                (_, Some(path)) => std::fs::read_to_string(path)?.trim_end().to_string(),
+                (Some("-"), None) => io::read_to_string(io::stdin())?.trim_end().to_string(),
+                (Some(t), None) => decode_newlines(t),
+                (None, None) => {
+                    "Hello from Rust! Pass --write --text \"your message\" to set custom text."
+                        .to_string()
+                }
+            };
+            let layout = text_layout(&cli, fg_color, bg_color);
+            if partial && page_interval.is_none() {
+                let fb = build_framebuffer(&message, &layout, cli.rotate);
+                present_over_stored(epd, &fb, &cli)?;
+                epd.sleep()?;
+                return Ok(());
+            }
+            maybe_init(epd, &cli)?;
+            let mut pager = Pager::new(&message);
+            let pages = pager.count(&layout, cli.rotate);
+            match page_interval {
+                Some(interval) if pages > 1 => {
+                    let mut view = LiveView::default();
+                    loop {
+                        view.show(epd, &pager.framebuffer(&layout, cli.rotate), &cli)?;
+                        thread::sleep(interval);
+                        pager.turn("next", &layout, cli.rotate);
+                    }
+                }
+                _ => {
+                    if pages > 1 {
+                        warn!("Text needs {pages} pages; showing the first (see --page-interval)");
+                    }
+                    render_text(epd, &message, &layout, &cli)?;
+                }
+            }
+            epd.sleep()?;
+        }
+        Command::Qr { data, caption } => {
+            maybe_init(epd, &cli)?;
+            let layout = text_layout(&cli, fg_color, bg_color);
+            let fb = build_qr_framebuffer(&data, caption.as_deref(), &layout, cli.rotate)?;
+            present(epd, &fb, false, &cli)?;
+            epd.sleep()?;
+        }
+        Command::DrawImage { path, dither, fit } => {
+            let fb = build_image_framebuffer(GrayImage::open(&path)?, dither, fit, &cli);
+            maybe_init(epd, &cli)?;
+            present(epd, &fb, false, &cli)?;
+            epd.sleep()?;
+        }
+        Command::TestPattern { delay, cell } => {
+            maybe_init(epd, &cli)?;
+            let area = panel_area(cli.rotate);
+            let patterns = [
+                TestPattern::Checkerboard { cell },
+                TestPattern::Stripes { width: cell },
+                TestPattern::Black,
+                TestPattern::White,
+            ];
+            for pattern in patterns {
+                info!("Showing {}", pattern.name());
+                let mut fb = MonoImage::new(area.size.width, area.size.height);
+                pattern.draw(&mut fb);
+                present(epd, &fb, false, &cli)?;
+                thread::sleep(Duration::from_secs(delay));
+            }
+            info!("Showing label");
+            let label = format!(
+                "Waveshare 2.13\" V4\n{}x{} px, rotate {}\nBUSY={} DC={}\nRST={} CS={}",
+                Epd2in13V4::WIDTH,
+                Epd2in13V4::HEIGHT,
+                cli.rotate,
+                pins.busy,
+                pins.dc,
+                pins.rst,
+                pins.cs
+            );
+            let layout = TextLayout {
+                alignment: "center,middle".parse()?,
+                ..text_layout(&cli, fg_color, bg_color)
+            };
+            render_text(epd, &label, &layout, &cli)?;
+            epd.sleep()?;
+        }
+        Command::Bench { iterations } => {
+            run_bench(epd, iterations.max(1))?;
+            epd.sleep()?;
+        }
+        Command::Sysinfo { interval, once } => {
+            maybe_init(epd, &cli)?;
+            let layout = text_layout(&cli, fg_color, bg_color);
+            let mut view = LiveView::default();
+            loop {
+                let rows = SystemSnapshot::collect().rows();
+                let rows: Vec<Vec<&str>> = rows.iter().map(|(k, v)| vec![*k, v.as_str()]).collect();
+                let fb = build_rows_framebuffer(&rows, &layout, cli.rotate);
+                view.show(epd, &fb, &cli)?;
+                if once {
+                    break;
+                }
+                thread::sleep(interval);
+            }
+            epd.sleep()?;
+        }
+        Command::Docker {
+            socket,
+            interval,
+            once,
+        } => {
+            maybe_init(epd, &cli)?;
+            let layout = text_layout(&cli, fg_color, bg_color);
+            let mut view = LiveView::default();
+            let mut events: Option<DockerEvents> = None;
+            loop {
+                // Subscribe before listing, so no change falls between the two.
+                if events.is_none() && !once {
+                    match DockerEvents::open(&socket) {
+                        Ok(stream) => events = Some(stream),
+                        Err(err) => warn!("Cannot follow Docker events: {err}"),
+                    }
+                }
+                let fb = match docker::containers(&socket) {
+                    Ok(containers) => build_docker_framebuffer(&containers, &layout, cli.rotate),
+                    Err(err) => {
+                        warn!("Cannot list containers: {err}");
+                        let message = format!("Docker unavailable\n{err}");
+                        build_framebuffer(&message, &layout, cli.rotate)
+                    }
+                };
+                view.show(epd, &fb, &cli)?;
+                if once {
+                    break;
+                }
+                match events.as_mut().map(|stream| stream.wait(interval)) {
+                    Some(Ok(_)) => {}
+                    Some(Err(err)) => {
+                        warn!("Lost Docker events: {err}");
+                        events = None;
+                        thread::sleep(DOCKER_RETRY.min(interval));
+                    }
+                    None => thread::sleep(interval),
+                }
+            }
+            epd.sleep()?;
+        }
+        Command::Pihole {
+            url,
+            api,
+            password,
+            token,
+            interval,
+            once,
+        } => {
+            let api = match api {
+                5 => PiholeApi::V5 { token },
+                _ => PiholeApi::V6 { password },
+            };
+            let mut client = PiholeClient::new(&url, api);
+            maybe_init(epd, &cli)?;
+            let layout = text_layout(&cli, fg_color, bg_color);
+            let mut view = LiveView::default();
+            let mut shown = false;
+            loop {
+                let fb = match client.stats() {
+                    Ok(fresh) => {
+                        shown = true;
+                        let rows = fresh.rows();
+                        let rows: Vec<Vec<&str>> =
+                            rows.iter().map(|(k, v)| vec![*k, v.as_str()]).collect();
+                        build_rows_framebuffer(&rows, &layout, cli.rotate)
+                    }
+                    Err(err) if shown => {
+                        warn!("Keeping previous Pi-hole stats: {err}");
+                        thread::sleep(interval);
+                        continue;
+                    }
+                    Err(err) => {
+                        warn!("Cannot read Pi-hole stats: {err}");
+                        let message = format!("Pi-hole unavailable\n{err}");
+                        build_framebuffer(&message, &layout, cli.rotate)
+                    }
+                };
+                view.show(epd, &fb, &cli)?;
+                if once {
+                    break;
+                }
+                thread::sleep(interval);
+            }
+            epd.sleep()?;
+        }
+        Command::NowPlaying {
+            mpd,
+            password,
+            interval,
+            once,
+        } => {
+            maybe_init(epd, &cli)?;
+            let layout = text_layout(&cli, fg_color, bg_color);
+            let mut view = LiveView::default();
+            let mut client: Option<MpdClient> = None;
+            loop {
+                if client.is_none() {
+                    match MpdClient::connect(&mpd, password.as_deref()) {
+                        Ok(connected) => client = Some(connected),
+                        Err(err) => warn!("Cannot reach MPD at {mpd}: {err}"),
+                    }
+                }
+                let playing = match client.as_mut().map(MpdClient::now_playing) {
+                    Some(Ok(playing)) => Some(playing),
+                    Some(Err(err)) => {
+                        warn!("Lost MPD at {mpd}: {err}");
+                        client = None;
+                        None
+                    }
+                    None => None,
+                };
+                let fb = build_now_playing_framebuffer(playing.as_ref(), &layout, cli.rotate);
+                view.show(epd, &fb, &cli)?;
+                if once {
+                    break;
+                }
+                // Only a playing song needs the clock; otherwise wait for the next change.
+                let timeout = match &playing {
+                    Some(playing) if playing.state == PlayState::Playing => interval,
+                    _ => NOW_PLAYING_IDLE,
+                };
+                match client.as_mut() {
+                    Some(connected) => {
+                        if let Err(err) = connected.wait(timeout) {
+                            warn!("Lost MPD at {mpd}: {err}");
+                            client = None;
+                        }
+                    }
+                    None => thread::sleep(interval),
+                }
+            }
+            epd.sleep()?;
+        }
+        Command::Thermal {
+            interval,
+            window,
+            once,
+        } => {
+            maybe_init(epd, &cli)?;
+            let layout = text_layout(&cli, fg_color, bg_color);
+            let window = window.clamp(2, MAX_CHART_POINTS);
+            let mut history = VecDeque::with_capacity(window);
+            let mut view = LiveView::default();
+            loop {
+                let zones = sysinfo::thermal_zones();
+                if let Some((_, celsius)) = zones.first() {
+                    if history.len() == window {
+                        history.pop_front();
+                    }
+                    history.push_back(*celsius);
+                }
+                let throttled = Throttled::read();
+                let fb = build_thermal_framebuffer(
+                    history.make_contiguous(),
+                    &zones,
+                    throttled,
+                    &layout,
+                    cli.rotate,
+                );
+                view.show(epd, &fb, &cli)?;
+                if once {
+                    break;
+                }
+                thread::sleep(interval);
+            }
+            epd.sleep()?;
+        }
+        Command::Network { interval, once } => {
+            maybe_init(epd, &cli)?;
+            let layout = TextLayout {
+                auto_fit: true,
+                ..text_layout(&cli, fg_color, bg_color)
+            };
+            let watcher = match NetlinkWatcher::open() {
+                Ok(watcher) => Some(watcher),
+                Err(err) => {
+                    warn!("Cannot watch network changes, refreshing every {interval:?}: {err}");
+                    None
+                }
+            };
+            let mut view = LiveView::default();
+            loop {
+                let text = NetworkSnapshot::collect().lines().join("\n");
+                view.show(epd, &build_framebuffer(&text, &layout, cli.rotate), &cli)?;
+                if once {
+                    break;
+                }
+                match &watcher {
+                    Some(watcher) => {
+                        if watcher.wait(interval)? {
+                            info!("Network changed");
+                        }
+                    }
+                    None => thread::sleep(interval),
+                }
+            }
+            epd.sleep()?;
+        }
+        #[cfg(feature = "ups")]
+        Command::Battery {
+            chip,
+            address,
+            cells,
+            interval,
+            once,
+        } => {
+            let mut ups = rpi_einkserver_rs::ups::UpsMonitor::open(chip, address, cells)?;
+            maybe_init(epd, &cli)?;
+            let layout = text_layout(&cli, fg_color, bg_color);
+            let mut view = LiveView::default();
+            loop {
+                let reading = ups.read()?;
+                let fb = build_battery_framebuffer(&reading, &layout, cli.rotate);
+                view.show(epd, &fb, &cli)?;
+                if once {
+                    break;
+                }
+                thread::sleep(interval);
+            }
+            epd.sleep()?;
+        }
+        Command::Countdown { until, label } => {
+            maybe_init(epd, &cli)?;
+            let layout = TextLayout {
+                alignment: "center,middle".parse()?,
+                auto_fit: true,
+                ..text_layout(&cli, fg_color, bg_color)
+            };
+            let mut view = LiveView::default();
+            loop {
+                let remaining = until.signed_duration_since(Local::now());
+                let done = remaining <= TimeDelta::zero();
+                let body = if done {
+                    "Now!".to_string()
+                } else {
+                    format_remaining(remaining)
+                };
+                let message = match &label {
+                    Some(label) => format!("{label}\n{body}"),
+                    None => body,
+                };
+                view.show(epd, &build_framebuffer(&message, &layout, cli.rotate), &cli)?;
+                if done {
+                    break;
+                }
+                // Wake at the next minute boundary of the remaining time.
+                let into_minute = remaining.num_seconds() % 60;
+                thread::sleep(Duration::from_secs(into_minute.max(1) as u64));
+            }
+            epd.sleep()?;
+        }
+        Command::Pomodoro {
+            work,
+            short_break,
+            long_break,
+            sessions,
+            buzzer,
+            once,
+        } => {
+            maybe_init(epd, &cli)?;
+            let layout = text_layout(&cli, fg_color, bg_color);
+            let buzzer = buzzer.map(Buzzer::open).transpose()?;
+            let plan = PomodoroPlan {
+                work,
+                short_break,
+                long_break,
+                sessions,
+            };
+            run_pomodoro(epd, &cli, &layout, &plan, buzzer.as_ref(), once)?;
+            epd.sleep()?;
+        }
+        Command::Todo { file, buttons } => {
+            maybe_init(epd, &cli)?;
+            let layout = text_layout(&cli, fg_color, bg_color);
+            run_todo(epd, &cli, &layout, &file, &buttons)?;
+        }
+        Command::Watch { file } => {
+            maybe_init(epd, &cli)?;
+            let layout = text_layout(&cli, fg_color, bg_color);
+            run_watch(epd, &cli, &layout, &file)?;
+        }
+        Command::Exec {
+            every,
+            once,
+            command,
+        } => {
+            maybe_init(epd, &cli)?;
+            let layout = text_layout(&cli, fg_color, bg_color);
+            let command = command.join(" ");
+            let mut view = LiveView::default();
+            loop {
+                let text = run_shell(&command, SHELL_TIMEOUT)?;
+                view.show(epd, &build_framebuffer(&text, &layout, cli.rotate), &cli)?;
+                if once {
+                    break;
+                }
+                thread::sleep(every);
+            }
+            epd.sleep()?;
+        }
+        Command::Template { file, vars } => {
+            let source = if file.as_os_str() == "-" {
+                io::read_to_string(io::stdin())?
+            } else {
+                std::fs::read_to_string(&file)?
+            };
+            let message = template::render(&source, &vars.into_iter().collect())?;
+            maybe_init(epd, &cli)?;
+            let layout = text_layout(&cli, fg_color, bg_color);
+            render_text(epd, message.trim_end(), &layout, &cli)?;
+            epd.sleep()?;
+        }
+        Command::RenderLayout { file } => {
+            let spec = LayoutSpec::parse_for_path(&std::fs::read_to_string(&file)?, &file)?;
+            let base_dir = file.parent().unwrap_or(Path::new("."));
+            let fb = spec.render(panel_area(cli.rotate).size, base_dir)?;
+            maybe_init(epd, &cli)?;
+            present(epd, &fb, false, &cli)?;
+            epd.sleep()?;
+        }
+        Command::Ticker { text, speed, loops } => {
+            let text = match text.as_str() {
+                "-" => io::read_to_string(io::stdin())?,
+                _ => text,
+            };
+            maybe_init(epd, &cli)?;
+            let layout = text_layout(&cli, fg_color, bg_color);
+            let ticker = Ticker::new(&text, &layout, panel_area(cli.rotate).size);
+            let step = layout.font.char_size().width as f32;
+            let speed = speed.max(0.1);
+            let mut view = LiveView::default();
+            let start = Instant::now();
+            loop {
+                let offset = (start.elapsed().as_secs_f32() * speed * step) as u64;
+                if loops.is_some_and(|n| offset >= n as u64 * ticker.period() as u64) {
+                    break;
+                }
+                view.show(epd, &ticker.frame(offset), &cli)?;
+                thread::sleep(Duration::from_secs_f32(1.0 / speed));
+            }
+            epd.sleep()?;
+        }
+        Command::TickerQuotes {
+            symbols,
+            interval,
+            url,
+            price,
+            change,
+            previous,
+            once,
+        } => {
+            let source = QuoteSource {
+                url,
+                price,
+                previous: change.is_none().then_some(previous),
+                change,
+            };
+            maybe_init(epd, &cli)?;
+            let layout = text_layout(&cli, fg_color, bg_color);
+            let mut view = LiveView::default();
+            let mut latest: Vec<Option<Quote>> = vec![None; symbols.len()];
+            loop {
+                for (symbol, last) in symbols.iter().zip(&mut latest) {
+                    match source.fetch(symbol) {
+                        Ok(quote) => *last = Some(quote),
+                        Err(err) => warn!("Keeping previous quote for {symbol}: {err}"),
+                    }
+                }
+                let fb = build_quotes_framebuffer(&symbols, &latest, &layout, cli.rotate);
+                view.show(epd, &fb, &cli)?;
+                if once {
+                    break;
+                }
+                thread::sleep(interval);
+            }
+            epd.sleep()?;
+        }
+        Command::Rss {
+            url,
+            rotate,
+            refresh,
+            summary,
+        } => {
+            let mut feed = fetch_feed(&url)?;
+            let mut fetched = Instant::now();
+            maybe_init(epd, &cli)?;
+            let layout = text_layout(&cli, fg_color, bg_color);
+            let mut view = LiveView::default();
+            let mut index = 0;
+            loop {
+                if fetched.elapsed() >= refresh {
+                    match fetch_feed(&url) {
+                        Ok(fresh) => {
+                            feed = fresh;
+                            index = 0;
+                        }
+                        Err(err) => warn!("Keeping previous headlines: {err}"),
+                    }
+                    fetched = Instant::now();
+                }
+                let headline = &feed.headlines[index % feed.headlines.len()];
+                let mut message = headline.title.clone();
+                if let Some(text) = headline.summary.as_ref().filter(|_| summary) {
+                    message.push_str("\n\n");
+                    message.push_str(text);
+                }
+                view.show(epd, &build_framebuffer(&message, &layout, cli.rotate), &cli)?;
+                index += 1;
+                thread::sleep(rotate);
+            }
+        }
+        Command::Markdown { file } => {
+            let source = if file.as_os_str() == "-" {
+                io::read_to_string(io::stdin())?
+            } else {
+                std::fs::read_to_string(&file)?
+            };
+            maybe_init(epd, &cli)?;
+            let layout = text_layout(&cli, fg_color, bg_color);
+            let (mut fb, area) = layout.frame(panel_area(cli.rotate).size);
+            draw_markdown(&mut fb, area, &source, &layout);
+            present(epd, &fb, false, &cli)?;
+            epd.sleep()?;
+        }
+        Command::Calendar { ics, month } => {
+            let events = match ics {
+                Some(path) => ics::parse_events(&std::fs::read_to_string(path)?),
+                None => Vec::new(),
+            };
+            let today = Local::now().date_naive();
+            let month = month.unwrap_or(today);
+            let first = month.with_day(1).unwrap_or(month);
+            let next = first.checked_add_months(Months::new(1)).unwrap_or(NaiveDate::MAX);
+            let midnight = |date: NaiveDate| date.and_time(Default::default());
+            let events = ics::expand(&events, midnight(first), midnight(next));
+            let count = |day: u32| {
+                events
+                    .iter()
+                    .filter(|e| e.start.date() == month.with_day(day).unwrap_or(month))
+                    .count()
+            };
+            maybe_init(epd, &cli)?;
+            let layout = text_layout(&cli, fg_color, bg_color);
+            let (mut fb, area) = layout.frame(panel_area(cli.rotate).size);
+            draw_month(&mut fb, area, month, Some(today), count, &layout);
+            present(epd, &fb, false, &cli)?;
+            epd.sleep()?;
+        }
+        Command::Agenda {
+            ics,
+            days,
+            refresh,
+            once,
+        } => {
+            let mut events = load_calendar(&ics)?;
+            let mut fetched = Instant::now();
+            maybe_init(epd, &cli)?;
+            let layout = text_layout(&cli, fg_color, bg_color);
+            let mut view = LiveView::default();
+            loop {
+                if fetched.elapsed() >= refresh {
+                    match load_calendar(&ics) {
+                        Ok(fresh) => events = fresh,
+                        Err(err) => warn!("Keeping previous events: {err}"),
+                    }
+                    fetched = Instant::now();
+                }
+                let now = Local::now().naive_local();
+                let fb = build_agenda_framebuffer(&events, now, days, &layout, cli.rotate);
+                view.show(epd, &fb, &cli)?;
+                if once {
+                    break;
+                }
+                // Redraw often enough for events to start and end on time; unchanged
+                // frames are skipped.
+                thread::sleep(AGENDA_REDRAW.min(refresh));
+            }
+            epd.sleep()?;
+        }
+        Command::Refresh { cycles } => {
+            // The fast LUT does not drive the particles hard enough to clear ghosting.
+            epd.init()?;
+            for _ in 0..cycles {
+                epd.clear(BinaryColor::On)?;
+                epd.clear(BinaryColor::Off)?;
+            }
+            match FrameStore::new(&cli.persist_frame).load()? {
+                Some(frame) => {
+                    epd.display(frame.data())?;
+                    remember_base(&cli, &frame);
+                }
+                None => warn!("No stored frame to restore; leaving the panel white"),
+            }
+            epd.sleep()?;
+        }
+        Command::Sleep => epd.sleep()?,
+        Command::Wake => {
+            maybe_init(epd, &cli)?;
+            match FrameStore::new(&cli.persist_frame).load()? {
+                Some(frame) => show_base(epd, &cli, &frame)?,
+                None => warn!("No stored frame to restore"),
+            }
+        }
+        Command::Status
+        | Command::Completions { .. }
+        | Command::Mangen
+        | Command::Client { .. } => {
+            unreachable!("handled before opening the panel")
+        }
+        Command::Repl => run_repl(epd, &cli, fg_color, bg_color)?,
+        Command::Serve(ref args) => run_server(epd, &cli, fg_color, bg_color, args)?,
+        Command::ServeHttp(ref args) => run_http_server(epd, &cli, fg_color, bg_color, args)?,
+        #[cfg(feature = "grpc")]
+        Command::ServeGrpc { listen } => run_grpc_server(epd, &cli, fg_color, bg_color, listen)?,
+        Command::ServeMqtt {
+            ref broker,
+            ref topic,
+            ref client_id,
+            ref username,
+            ref password,
+            ref ha_discovery,
+            ref buttons,
+        } => {
+            let mut options = rumqttc::MqttOptions::new(client_id, &broker.host, broker.port);
+            if let (Some(username), Some(password)) = (username, password) {
+                options.set_credentials(username, password);
+            }
+            let discovery = ha_discovery
+                .as_deref()
+                .map(|prefix| ha_discovery_configs(prefix, client_id, topic))
+                .unwrap_or_default();
+            run_mqtt_client(
+                epd, &cli, fg_color, bg_color, options, topic, &discovery, buttons,
+            )?
+        }
+    }
+
+    Ok(())
+}
+
+/// Log to stderr at a level picked by `-v`/`-q`, unless `RUST_LOG` is set.
+fn init_logging(cli: &Cli) {
+    let level = match (cli.verbose, cli.quiet) {
+        (0, 0) => "info",
+        (1, _) => "debug",
+        (_, 0) => "trace",
+        (_, 1) => "warn",
+        _ => "error",
+    };
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(level));
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(io::stderr)
+        .init();
+}
+
+fn maybe_init(epd: &mut dyn Panel, cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
+    if cli.noinit {
+        info!("Skipping panel initialization as requested");
+        return Ok(());
+    }
+
+    if cli.fast {
+        epd.init_fast()?;
+    } else {
+        epd.init()?;
+    }
+    Ok(())
+}
+
+fn render_text(
+    epd: &mut dyn Panel,
+    message: &str,
+    layout: &TextLayout,
+    cli: &Cli,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let fb = build_framebuffer(message, layout, cli.rotate);
+    present(epd, &fb, false, cli)
+}
+
+/// Rotate a canvas-oriented frame onto the panel and push it using the partial, fast or
+/// full refresh path.
+fn present(
+    epd: &mut dyn Panel,
+    fb: &MonoImage,
+    partial: bool,
+    cli: &Cli,
+) -> Result<(), Box<dyn std::error::Error>> {
+    present_as(epd, fb, update_mode(partial, cli), cli)
+}
+
+/// Partial if requested, otherwise fast or full depending on `--fast`.
+fn update_mode(partial: bool, cli: &Cli) -> UpdateMode {
+    if partial {
+        UpdateMode::Partial
+    } else if cli.fast {
+        UpdateMode::Fast
+    } else {
+        UpdateMode::Normal
+    }
+}
+
+/// Like [`present`] with an explicit refresh mode. A partial refresh needs a base shown
+/// earlier with [`show_base`].
+fn present_as(
+    epd: &mut dyn Panel,
+    fb: &MonoImage,
+    mode: UpdateMode,
+    cli: &Cli,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let fb = fb.rotated(cli.rotate);
+    let timing = epd.display_timed(fb.data(), mode)?;
+    info!(
+        ?mode,
+        transfer_ms = timing.transfer.as_millis() as u64,
+        refresh_ms = timing.refresh.as_millis() as u64,
+        "Refreshed panel"
+    );
+    remember_frame(cli, &fb, mode);
+    if !matches!(mode, UpdateMode::Partial) {
+        remember_base(cli, &fb);
+    }
+    Ok(())
+}
+
+/// Partial refresh of a canvas frame that differs from the last one only inside `area`, so
+/// only that part is sent to the panel. Needs a base like any partial refresh.
+fn present_region(
+    epd: &mut dyn Panel,
+    fb: &MonoImage,
+    area: Rectangle,
+    cli: &Cli,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let panel = fb.rotated(cli.rotate);
+    let window = panel_window(area, cli.rotate);
+    let timing = epd.display_partial_window(panel.data(), window)?;
+    info!(
+        x = window.top_left.x,
+        y = window.top_left.y,
+        width = window.size.width,
+        height = window.size.height,
+        transfer_ms = timing.transfer.as_millis() as u64,
+        refresh_ms = timing.refresh.as_millis() as u64,
+        "Refreshed panel region"
+    );
+    remember_frame(cli, &panel, UpdateMode::Partial);
+    Ok(())
+}
+
+/// Where a canvas rectangle ends up on the panel once the canvas is turned by `rotation`
+/// (the mapping of [`MonoImage::rotated`]).
+fn panel_window(area: Rectangle, rotation: Rotation) -> Rectangle {
+    let canvas = panel_area(rotation).size;
+    let (width, height) = (canvas.width as i32, canvas.height as i32);
+    let turn = |p: Point| match rotation {
+        Rotation::Deg0 => p,
+        Rotation::Deg90 => Point::new(height - 1 - p.y, p.x),
+        Rotation::Deg180 => Point::new(width - 1 - p.x, height - 1 - p.y),
+        Rotation::Deg270 => Point::new(p.y, width - 1 - p.x),
+    };
+    match area.bottom_right() {
+        Some(corner) => Rectangle::with_corners(turn(area.top_left), turn(corner)),
+        None => Rectangle::zero(),
+    }
+}
+
+/// `layout` without the border and margin meant for a whole screen, for text filling a
+/// small part of it.
+fn bare_layout(layout: &TextLayout) -> TextLayout {
+    TextLayout {
+        border: false,
+        margin: 0,
+        ..*layout
+    }
+}
+
+/// Whether `area` is non-empty and lies within the canvas.
+fn fits_canvas(area: &Rectangle, rotation: Rotation) -> bool {
+    let canvas = panel_area(rotation);
+    !area.is_zero_sized()
+        && canvas.contains(area.top_left)
+        && area
+            .bottom_right()
+            .is_some_and(|corner| canvas.contains(corner))
+}
+
+/// Partial refresh in a fresh process, using the frames stored by earlier runs instead of
+/// initializing the panel and drawing a base. Without a stored base this falls back to a
+/// full refresh that becomes the base for the next call.
+fn present_over_stored(
+    epd: &mut dyn Panel,
+    fb: &MonoImage,
+    cli: &Cli,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let store = FrameStore::new(&cli.persist_frame);
+    let panel = fb.rotated(cli.rotate);
+    let previous = match (store.base().load()?, store.load()?) {
+        (Some(_), Some(previous)) if previous.data().len() == panel.data().len() => previous,
+        _ => {
+            info!("No stored partial-update base; doing a full refresh first");
+            maybe_init(epd, cli)?;
+            return show_base(epd, cli, &panel);
+        }
+    };
+    let timing = epd.display_partial_over(previous.data(), panel.data())?;
+    info!(
+        mode = ?UpdateMode::Partial,
+        transfer_ms = timing.transfer.as_millis() as u64,
+        refresh_ms = timing.refresh.as_millis() as u64,
+        "Refreshed panel over the stored frame"
+    );
+    remember_frame(cli, &panel, UpdateMode::Partial);
+    Ok(())
+}
+
+/// Full refresh of a panel-orientation frame that also becomes the partial-update base.
+fn show_base(
+    epd: &mut dyn Panel,
+    cli: &Cli,
+    panel: &MonoImage,
+) -> Result<(), Box<dyn std::error::Error>> {
+    epd.display_base(panel.data())?;
+    remember_frame(cli, panel, UpdateMode::Normal);
+    remember_base(cli, panel);
+    Ok(())
+}
+
+/// Save a panel-orientation frame as the last one shown, and the refresh that showed it;
+/// failures are only reported.
+fn remember_frame(cli: &Cli, fb: &MonoImage, mode: UpdateMode) {
+    let store = FrameStore::new(&cli.persist_frame);
+    save_frame(&store, fb);
+    if let Err(err) = store.save_mode(mode) {
+        warn!(
+            "Could not store the refresh mode next to {}: {err}",
+            store.path().display()
+        );
+    }
+}
+
+/// `--restore`: pick up the frame the previous run left on the panel. Returns the refresh
+/// that showed it, or `None` when nothing was restored.
+fn restore_frame(
+    epd: &mut dyn Panel,
+    cli: &Cli,
+) -> Result<Option<UpdateMode>, Box<dyn std::error::Error>> {
+    let Some(how) = cli.restore else {
+        return Ok(None);
+    };
+    let store = FrameStore::new(&cli.persist_frame);
+    let Some(frame) = store.load()? else {
+        info!("No stored frame to restore");
+        return Ok(None);
+    };
+    if frame.size() != panel_area(Rotation::Deg0).size {
+        warn!("The stored frame does not fit the panel; not restoring it");
+        return Ok(None);
+    }
+    let mode = store.load_mode()?.unwrap_or(UpdateMode::Normal);
+    match how {
+        Restore::Keep => {
+            epd.display_partial_over(frame.data(), frame.data())?;
+            info!("Loaded the last frame as the partial-update base");
+        }
+        Restore::Redraw => {
+            show_base(epd, cli, &frame)?;
+            info!("Showed the last frame again");
+        }
+    }
+    Ok(Some(mode))
+}
+
+/// Save a panel-orientation frame shown by a full refresh as the partial-update base.
+fn remember_base(cli: &Cli, fb: &MonoImage) {
+    save_frame(&FrameStore::new(&cli.persist_frame).base(), fb);
+}
+
+fn save_frame(store: &FrameStore, fb: &MonoImage) {
+    if let Err(err) = store.save(fb) {
+        warn!("Could not store frame in {}: {err}", store.path().display());
+    }
+}
+
+/// Partial refreshes between full ones in a [`LiveView`]; the full refresh clears ghosting.
+const FULL_REFRESH_EVERY: u32 = 30;
+
+/// Keeps a periodically changing screen up to date. The first frame is a full refresh that
+/// also becomes the partial-update base; later frames use partial refreshes, with a full
+/// one every [`FULL_REFRESH_EVERY`] updates. Unchanged frames are skipped.
+#[derive(Default)]
+struct LiveView {
+    partials: Option<u32>,
+    last: Option<Vec<u8>>,
+}
+
+impl LiveView {
+    fn show(
+        &mut self,
+        epd: &mut dyn Panel,
+        fb: &MonoImage,
+        cli: &Cli,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if self.last.as_deref() == Some(fb.data()) {
+            return Ok(());
+        }
+        match self.partials {
+            Some(count) if count < FULL_REFRESH_EVERY => {
+                present(epd, fb, true, cli)?;
+                self.partials = Some(count + 1);
+            }
+            _ => {
+                show_base(epd, cli, &fb.rotated(cli.rotate))?;
+                info!("Refreshed panel with a new partial base");
+                self.partials = Some(0);
+            }
+        }
+        self.last = Some(fb.data().to_vec());
+        Ok(())
+    }
+}
+
+/// `watch`: show `file` and re-render it on every write, rename or recreation. The parent
+/// directory is watched so editors that save by replacing the file are handled too.
+fn run_watch(
+    epd: &mut dyn Panel,
+    cli: &Cli,
+    layout: &TextLayout,
+    file: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let dir = match file.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let name = file.file_name().ok_or("--file must name a file")?;
+
+    let mut inotify = Inotify::init()?;
+    inotify.watches().add(
+        dir,
+        WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO | WatchMask::CREATE | WatchMask::DELETE,
+    )?;
+
+    let mut view = LiveView::default();
+    let mut buffer = [0; 4096];
+    loop {
+        let text = match std::fs::read_to_string(file) {
+            Ok(text) => text.trim_end().to_string(),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err.into()),
+        };
+        view.show(epd, &build_framebuffer(&text, layout, cli.rotate), cli)?;
+
+        // Block until an event concerns our file; a burst of events yields one render.
+        loop {
+            let events = inotify.read_events_blocking(&mut buffer)?;
+            if events.into_iter().any(|event| event.name == Some(name)) {
+                break;
+            }
+        }
+    }
+}
+
+/// How often `pomodoro` redraws the countdown.
+const POMODORO_STEP: Duration = Duration::from_secs(30);
+
+/// Height of the `pomodoro` progress bar.
+const POMODORO_BAR: u32 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PomodoroPhase {
+    Work,
+    Break,
+    LongBreak,
+}
+
+impl PomodoroPhase {
+    fn label(self) -> &'static str {
+        match self {
+            PomodoroPhase::Work => "Work",
+            PomodoroPhase::Break => "Break",
+            PomodoroPhase::LongBreak => "Long break",
+        }
+    }
+
+    /// Beeps that announce the phase.
+    fn beeps(self) -> u32 {
+        match self {
+            PomodoroPhase::Work => 1,
+            PomodoroPhase::Break => 2,
+            PomodoroPhase::LongBreak => 3,
+        }
+    }
+}
+
+/// The lengths of the phases of `pomodoro`.
+struct PomodoroPlan {
+    work: Duration,
+    short_break: Duration,
+    long_break: Duration,
+    /// Work sessions before a long break.
+    sessions: u32,
+}
+
+impl PomodoroPlan {
+    fn length(&self, phase: PomodoroPhase) -> Duration {
+        match phase {
+            PomodoroPhase::Work => self.work,
+            PomodoroPhase::Break => self.short_break,
+            PomodoroPhase::LongBreak => self.long_break,
+        }
+    }
+
+    /// What follows `phase` of work session `session` (counted from 1), and the session
+    /// then.
+    fn next(&self, phase: PomodoroPhase, session: u32) -> (PomodoroPhase, u32) {
+        match phase {
+            PomodoroPhase::Work if session.is_multiple_of(self.sessions) => {
+                (PomodoroPhase::LongBreak, session)
+            }
+            PomodoroPhase::Work => (PomodoroPhase::Break, session),
+            PomodoroPhase::Break | PomodoroPhase::LongBreak => (PomodoroPhase::Work, session + 1),
+        }
+    }
+}
+
+/// Run work sessions and breaks in turn until the process is stopped (or after the first
+/// frame with `once`). The countdown is redrawn on every [`POMODORO_STEP`] of a phase, and
+/// the buzzer, if any, announces each phase as it begins.
+fn run_pomodoro(
+    epd: &mut dyn Panel,
+    cli: &Cli,
+    layout: &TextLayout,
+    plan: &PomodoroPlan,
+    buzzer: Option<&Buzzer>,
+    once: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut view = LiveView::default();
+    let (mut phase, mut session) = (PomodoroPhase::Work, 1);
+    loop {
+        let length = plan.length(phase);
+        let start = Instant::now();
+        info!(phase = phase.label(), session, "Pomodoro phase begins");
+        if let Some(buzzer) = buzzer {
+            buzzer.beep(phase.beeps());
+        }
+        loop {
+            let elapsed = start.elapsed();
+            if elapsed >= length {
+                break;
+            }
+            let set = (session - 1) % plan.sessions + 1;
+            let fb = build_pomodoro_framebuffer(
+                phase,
+                &format!("{set}/{}", plan.sessions),
+                length - elapsed,
+                length,
+                layout,
+                cli.rotate,
+            );
+            view.show(epd, &fb, cli)?;
+            if once {
+                return Ok(());
+            }
+            let steps = (elapsed.as_secs() / POMODORO_STEP.as_secs() + 1) as u32;
+            let wake = (POMODORO_STEP * steps).min(length);
+            thread::sleep(wake.saturating_sub(start.elapsed()));
+        }
+        (phase, session) = plan.next(phase, session);
+    }
+}
+
+/// The phase and its place in the set on top, the time left in large digits, and a bar
+/// filling up as the phase goes by.
+fn build_pomodoro_framebuffer(
+    phase: PomodoroPhase,
+    set: &str,
+    remaining: Duration,
+    length: Duration,
+    layout: &TextLayout,
+    rotation: Rotation,
+) -> MonoImage {
+    let (mut fb, area) = layout.frame(panel_area(rotation).size);
+    let cell = layout.font.char_size();
+    let color = layout.foreground;
+    layout
+        .font
+        .draw_text(&mut fb, phase.label(), area.top_left, color)
+        .ok();
+    let set_width = display_width(set) as i32 * cell.width as i32;
+    let right = area.top_left.x + area.size.width as i32;
+    layout
+        .font
+        .draw_text(
+            &mut fb,
+            set,
+            Point::new(right - set_width, area.top_left.y),
+            color,
+        )
+        .ok();
+
+    let seconds = remaining.as_secs_f64().ceil() as u64;
+    let countdown = format!("{:02}:{:02}", seconds / 60, seconds % 60);
+    let top = cell.height + REGION_GAP;
+    let digits = Rectangle::new(
+        area.top_left + Point::new(0, top as i32),
+        Size::new(
+            area.size.width,
+            area.size
+                .height
+                .saturating_sub(top + REGION_GAP + POMODORO_BAR),
+        ),
+    );
+    draw_big_text(&mut fb, digits, &countdown, color);
+
+    let bar = Rectangle::new(
+        Point::new(
+            area.top_left.x,
+            area.top_left.y + area.size.height.saturating_sub(POMODORO_BAR) as i32,
+        ),
+        Size::new(area.size.width, POMODORO_BAR),
+    );
+    bar.into_styled(PrimitiveStyle::with_stroke(color, 1))
+        .draw(&mut fb)
+        .ok();
+    let inner = bar.offset(-2);
+    let done = 1.0 - remaining.as_secs_f32() / length.as_secs_f32().max(f32::EPSILON);
+    let filled = (inner.size.width as f32 * done.clamp(0.0, 1.0)).round() as u32;
+    Rectangle::new(inner.top_left, Size::new(filled, inner.size.height))
+        .into_styled(PrimitiveStyle::with_fill(color))
+        .draw(&mut fb)
+        .ok();
+    fb
+}
+
+/// What a `todo` button does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TodoButton {
+    Up,
+    Down,
+    Toggle,
+}
+
+fn parse_todo_button(input: &str) -> Result<(u8, TodoButton), String> {
+    let (pin, action) = input
+        .split_once(':')
+        .ok_or_else(|| format!("expected PIN:ACTION, got '{input}'"))?;
+    let pin = pin
+        .parse()
+        .map_err(|_| format!("invalid button pin '{pin}'"))?;
+    let action = match action {
+        "up" => TodoButton::Up,
+        "down" => TodoButton::Down,
+        "toggle" => TodoButton::Toggle,
+        _ => {
+            return Err(format!(
+                "unknown button action '{action}' (up, down or toggle)"
+            ))
+        }
+    };
+    Ok((pin, action))
+}
+
+/// What wakes `todo` up.
+enum TodoInput {
+    FileChanged,
+    Button(TodoButton),
+}
+
+/// Show the to-do list in `file` until the process is stopped, reloading it whenever it
+/// changes on disk. With `buttons`, the first item starts out selected; toggling an item
+/// re-reads the file, flips the checkbox on the same line and writes it back at once.
+fn run_todo(
+    epd: &mut dyn Panel,
+    cli: &Cli,
+    layout: &TextLayout,
+    file: &Path,
+    buttons: &[(u8, TodoButton)],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let dir = match file.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let name = file
+        .file_name()
+        .ok_or("--file must name a file")?
+        .to_owned();
+    let (inputs_tx, inputs) = mpsc::channel();
+
+    let mut inotify = Inotify::init()?;
+    inotify.watches().add(
+        dir,
+        WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO | WatchMask::CREATE | WatchMask::DELETE,
+    )?;
+    let changes = inputs_tx.clone();
+    thread::spawn(move || {
+        let mut buffer = [0; 4096];
+        while let Ok(events) = inotify.read_events_blocking(&mut buffer) {
+            let ours = events
+                .into_iter()
+                .any(|event| event.name == Some(name.as_os_str()));
+            if ours && changes.send(TodoInput::FileChanged).is_err() {
+                return;
+            }
+        }
+    });
+    if !buttons.is_empty() {
+        let pins: Vec<u8> = buttons.iter().map(|(pin, _)| *pin).collect();
+        let (presses_tx, presses) = mpsc::channel();
+        buttons::watch(&pins, presses_tx)?;
+        info!(?pins, "Watching buttons");
+        let buttons = buttons.to_vec();
+        thread::spawn(move || {
+            let actions = presses.into_iter().filter_map(|pin| {
+                buttons
+                    .iter()
+                    .find(|(button, _)| *button == pin)
+                    .map(|(_, action)| *action)
+            });
+            for action in actions {
+                if inputs_tx.send(TodoInput::Button(action)).is_err() {
+                    return;
+                }
+            }
+        });
+    }
+
+    let load = || -> io::Result<TodoList> {
+        match std::fs::read_to_string(file) {
+            Ok(text) => Ok(TodoList::parse(&text)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(TodoList::parse("")),
+            Err(err) => Err(err),
+        }
+    };
+    let mut list = load()?;
+    let mut selected = (!buttons.is_empty()).then_some(0);
+    let mut view = LiveView::default();
+    loop {
+        let count = list.items().len();
+        let current = selected
+            .filter(|_| count > 0)
+            .map(|index| index.min(count - 1));
+        let fb = list.render(
+            current,
+            layout.font,
+            layout.foreground,
+            layout.background,
+            panel_area(cli.rotate).size,
+        );
+        view.show(epd, &fb, cli)?;
+        let Ok(input) = inputs.recv() else {
+            return Ok(());
+        };
+        match (input, current) {
+            (TodoInput::FileChanged, _) => list = load()?,
+            (TodoInput::Button(TodoButton::Up), Some(index)) => {
+                selected = Some((index + count - 1) % count);
+            }
+            (TodoInput::Button(TodoButton::Down), Some(index)) => {
+                selected = Some((index + 1) % count);
+            }
+            (TodoInput::Button(TodoButton::Toggle), Some(index)) => {
+                // Edits whose change event is still queued must not be written over.
+                let line = list.items()[index].line();
+                list = load()?;
+                let Some(index) = list.item_on_line(line) else {
+                    warn!(line = line + 1, "The to-do item moved on disk; not toggling it");
+                    continue;
+                };
+                selected = Some(index);
+                list.toggle(index);
+                write_todo(file, &list.to_text())?;
+                let item = &list.items()[index];
+                info!(item = item.text, done = item.done, "Toggled to-do item");
+            }
+            (TodoInput::Button(_), None) => {}
+        }
+    }
+}
+
+/// Replace the to-do file through a temporary file next to it, following a symlink to the
+/// real file and keeping its permissions.
+fn write_todo(file: &Path, text: &str) -> io::Result<()> {
+    let target = std::fs::canonicalize(file)?;
+    let tmp = target.with_file_name(format!(
+        ".{}.tmp",
+        target.file_name().unwrap_or_default().to_string_lossy()
+    ));
+    std::fs::write(&tmp, text)?;
+    std::fs::set_permissions(&tmp, std::fs::metadata(&target)?.permissions())?;
+    std::fs::rename(&tmp, &target)
+}
+
+/// Download and parse a feed.
+fn fetch_feed(url: &str) -> Result<Feed, Box<dyn std::error::Error>> {
+    let body = ureq::get(url)
+        .timeout(Duration::from_secs(30))
+        .call()?
+        .into_string()?;
+    let feed = Feed::parse(&body)?;
+    info!(
+        url,
+        title = feed.title.as_deref().unwrap_or(""),
+        items = feed.headlines.len(),
+        "Fetched feed"
+    );
+    Ok(feed)
+}
+
+/// Events of an iCalendar feed at `source`: an `http(s)://` or `webcal://` URL, or a file.
+fn load_calendar(source: &str) -> Result<Vec<ics::Event>, Box<dyn std::error::Error>> {
+    let url = match source.strip_prefix("webcal://") {
+        Some(rest) => Some(format!("https://{rest}")),
+        None if source.starts_with("http://") || source.starts_with("https://") => {
+            Some(source.to_string())
+        }
+        None => None,
+    };
+    let text = match url {
+        Some(url) => ureq::get(&url)
+            .timeout(Duration::from_secs(30))
+            .call()?
+            .into_string()?,
+        None => std::fs::read_to_string(source)?,
+    };
+    let events = ics::parse_events(&text);
+    info!(source, events = events.len(), "Fetched calendar");
+    Ok(events)
+}
+
+/// Longest a shell command of `exec` or a menu `run` entry may take before it is killed.
+const SHELL_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Run `command` through `sh -c` and return its stdout. A failing command shows its stderr
+/// and exit status instead, so problems are visible on the panel. One still running after
+/// `timeout` is killed, with everything it started, and shows what it printed so far.
+fn run_shell(command: &str, timeout: Duration) -> io::Result<String> {
+    let mut child = process::Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(process::Stdio::null())
+        .stdout(process::Stdio::piped())
+        .stderr(process::Stdio::piped())
+        .process_group(0)
+        .spawn()?;
+    let drain = |pipe: Option<Box<dyn Read + Send>>| {
+        thread::spawn(move || {
+            let mut bytes = Vec::new();
+            if let Some(mut pipe) = pipe {
+                pipe.read_to_end(&mut bytes).ok();
+            }
+            bytes
+        })
+    };
+    let stdout = drain(child.stdout.take().map(|pipe| Box::new(pipe) as _));
+    let stderr = drain(child.stderr.take().map(|pipe| Box::new(pipe) as _));
+    let group = child.id() as libc::pid_t;
+    let (status_tx, status) = mpsc::channel();
+    thread::spawn(move || status_tx.send(child.wait()).ok());
+    let status = match status.recv_timeout(timeout) {
+        Ok(status) => Some(status?),
+        Err(_) => {
+            warn!(command, "Shell command timed out; killing it");
+            unsafe { libc::kill(-group, libc::SIGKILL) };
+            None
+        }
+    };
+    let stdout = stdout.join().unwrap_or_default();
+    let stderr = stderr.join().unwrap_or_default();
+    let stdout = String::from_utf8_lossy(&stdout);
+    let outcome = match status {
+        Some(status) if status.success() => return Ok(stdout.trim_end().to_string()),
+        Some(status) => status.to_string(),
+        None => format!("timed out after {}s", timeout.as_secs()),
+    };
+    let stderr = String::from_utf8_lossy(&stderr);
+    Ok(format!(
+        "{}\n{}\n[{outcome}]",
+        stdout.trim_end(),
+        stderr.trim_end(),
+    )
+    .trim()
+    .to_string())
+}
+
+/// Parse `YYYY-MM` into the first day of that month.
+fn parse_month(input: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(&format!("{}-01", input.trim()), "%Y-%m-%d")
+        .map_err(|_| format!("invalid month '{input}' (expected e.g. 2025-12)"))
+}
+
+/// Accept RFC 3339 or a zone-less `YYYY-MM-DDTHH:MM[:SS]` taken as local time.
+fn parse_local_time(input: &str) -> Result<DateTime<Local>, String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(input) {
+        return Ok(time.with_timezone(&Local));
+    }
+    ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(input, format).ok())
+        .and_then(|naive| naive.and_local_timezone(Local).earliest())
+        .ok_or_else(|| format!("invalid time '{input}' (expected e.g. 2025-12-31T23:59)"))
+}
+
+/// `12d 04h 31m`, dropping leading zero units; rounds up so the last minute shows `1m`.
+fn format_remaining(remaining: TimeDelta) -> String {
+    let minutes = (remaining.num_seconds() + 59) / 60;
+    let (days, hours, mins) = (minutes / 1440, minutes / 60 % 24, minutes % 60);
+    if days > 0 {
+        format!("{days}d {hours:02}h {mins:02}m")
+    } else if hours > 0 {
+        format!("{hours}h {mins:02}m")
+    } else {
+        format!("{mins}m")
+    }
+}
+
+/// Parse `90`, `90s`, `5m` or `2h` into a duration (bare numbers are seconds).
+fn parse_interval(input: &str) -> Result<Duration, String> {
+    let input = input.trim();
+    let (number, unit) = match input.find(|c: char| !c.is_ascii_digit()) {
+        Some(idx) => input.split_at(idx),
+        None => (input, "s"),
+    };
+    let value: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid interval '{input}' (expected e.g. 30s, 5m, 1h)"))?;
+    let scale = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        _ => {
+            return Err(format!(
+                "unknown unit in interval '{input}' (use s, m or h)"
+            ))
+        }
+    };
+    let secs = value
+        .checked_mul(scale)
+        .ok_or_else(|| format!("interval '{input}' is too long"))?;
+    Ok(Duration::from_secs(secs))
+}
+
+/// [`parse_interval`] for lengths that must not be zero.
+fn parse_nonzero_interval(input: &str) -> Result<Duration, String> {
+    match parse_interval(input)? {
+        Duration::ZERO => Err(format!("interval '{}' must be longer than zero", input.trim())),
+        interval => Ok(interval),
+    }
+}
+
+/// `bench`: alternate a checkerboard and a white frame in each refresh mode and print
+/// per-mode transfer and refresh statistics. Initializes the panel itself since fast and
+/// partial modes need their own setup.
+fn run_bench(epd: &mut dyn Panel, iterations: u32) -> Result<(), Box<dyn std::error::Error>> {
+    let size = panel_area(Rotation::Deg0).size;
+    let mut checker = MonoImage::new(size.width, size.height);
+    TestPattern::Checkerboard { cell: 8 }.draw(&mut checker);
+    let white = blank_framebuffer(size, BinaryColor::Off);
+    let frames = [&checker, &white];
+
+    for (name, mode) in [
+        ("full", UpdateMode::Normal),
+        ("fast", UpdateMode::Fast),
+        ("partial", UpdateMode::Partial),
+    ] {
+        match mode {
+            UpdateMode::Fast => epd.init_fast()?,
+            UpdateMode::Partial => {
+                epd.init()?;
+                epd.display_base(white.data())?;
+            }
+            UpdateMode::Normal => epd.init()?,
+        }
+
+        let mut timings = Vec::new();
+        for i in 0..iterations {
+            let frame = frames[i as usize % frames.len()];
+            timings.push(epd.display_timed(frame.data(), mode)?);
+        }
+        let transfer: Vec<_> = timings.iter().map(|t| t.transfer).collect();
+        let refresh: Vec<_> = timings.iter().map(|t| t.refresh).collect();
+        println!(
+            "{name:<8} transfer {}  refresh {}",
+            summarize(&transfer),
+            summarize(&refresh)
+        );
+    }
+    Ok(())
+}
+
+/// `avg/min/max` of a non-empty set of durations, in milliseconds.
+fn summarize(samples: &[Duration]) -> String {
+    let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+    let total: Duration = samples.iter().sum();
+    let min = samples.iter().min().copied().unwrap_or_default();
+    let max = samples.iter().max().copied().unwrap_or_default();
+    format!(
+        "avg {:.1} ms (min {:.1}, max {:.1})",
+        ms(total) / samples.len() as f64,
+        ms(min),
+        ms(max)
+    )
+}
+
+/// Clear the panel to `bg` and record the blank frame as the last one shown.
+fn clear_panel(
+    epd: &mut dyn Panel,
+    cli: &Cli,
+    bg: BinaryColor,
+) -> Result<(), Box<dyn std::error::Error>> {
+    epd.clear(bg)?;
+    let blank = blank_framebuffer(panel_area(Rotation::Deg0).size, bg);
+    remember_frame(cli, &blank, UpdateMode::Normal);
+    remember_base(cli, &blank);
+    Ok(())
+}
+
+/// Switch on the panel power pin of `epd`, if there is one.
+fn powered(epd: Epd2in13V4, pwr: Option<u8>) -> Result<Epd2in13V4, EpdError> {
+    match pwr {
+        Some(pin) => epd.with_power_pin(pin),
+        None => Ok(epd),
+    }
+}
+
+/// `status`: report what this invocation would drive, reading only the BUSY pin.
+fn print_status(cli: &Cli, pins: EpdPins) {
+    println!(
+        "Panel: Waveshare 2.13\" V4, {}x{} px",
+        Epd2in13V4::WIDTH,
+        Epd2in13V4::HEIGHT
+    );
+    let pwr = cli.pin_pwr.map_or("none".to_string(), |pin| pin.to_string());
+    println!(
+        "Pins (BCM): BUSY={} DC={} RST={} CS={} PWR={pwr}",
+        pins.busy, pins.dc, pins.rst, pins.cs
+    );
+    println!(
+        "SPI: /dev/spidev0.0 (SPI0, CE0), mode 0, {} MHz",
+        Epd2in13V4::SPI_CLOCK_HZ / 1_000_000
+    );
+
+    let busy = Gpio::new()
+        .and_then(|gpio| gpio.get(pins.busy))
+        .map(|pin| pin.into_input().is_high());
+    match busy {
+        Ok(true) => println!("BUSY: high (controller busy or panel not connected)"),
+        Ok(false) => println!("BUSY: low (idle)"),
+        Err(err) => println!("BUSY: unreadable ({err})"),
+    }
+    // The HAT only wires MOSI, so the controller's temperature register cannot be read.
+    println!("Temperature: not readable (SPI is write-only on this HAT)");
+    println!("Rotation: {} degrees", cli.rotate);
+
+    let store = FrameStore::new(&cli.persist_frame);
+    print_stored_frame("Last frame", &store);
+    print_stored_frame("Partial base", &store.base());
+}
+
+fn print_stored_frame(label: &str, store: &FrameStore) {
+    match store.info() {
+        Ok(Some(info)) => {
+            let age = info.modified.elapsed().unwrap_or_default();
+            println!(
+                "{label}: {}x{}, stored {} ago in {}",
+                info.width,
+                info.height,
+                format_age(age),
+                store.path().display()
+            );
+        }
+        Ok(None) => println!("{label}: none stored in {}", store.path().display()),
+        Err(err) => println!("{label}: {err}"),
+    }
+}
+
+/// Coarse human-readable duration, e.g. `42s`, `5m`, `3h`, `2d`.
+fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    match secs {
+        0..60 => format!("{secs}s"),
+        60..3600 => format!("{}m", secs / 60),
+        3600..86400 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
+
+/// Text layout for this run, seeded from the global CLI flags.
+fn text_layout(cli: &Cli, fg: BinaryColor, bg: BinaryColor) -> TextLayout {
+    TextLayout {
+        alignment: cli.align,
+        auto_fit: cli.auto_fit,
+        hyphenate: !cli.no_hyphenate,
+        markup: cli.markup,
+        quality: if cli.smooth {
+            TextQuality::Smooth
+        } else {
+            TextQuality::Crisp
+        },
+        ..TextLayout::new(cli.font, fg, bg)
+    }
+}
+
+/// Drawing area before rotation: the panel itself, or its landscape form for 90/270.
+fn panel_area(rotation: Rotation) -> Rectangle {
+    let panel = Size::new(Epd2in13V4::WIDTH as u32, Epd2in13V4::HEIGHT as u32);
+    Rectangle::new(Point::new(0, 0), rotation.canvas_size(panel))
+}
+
+fn build_framebuffer(message: &str, layout: &TextLayout, rotation: Rotation) -> MonoImage {
+    layout.render(message, panel_area(rotation).size)
+}
+
+/// A message that may need several screens, shown one page at a time.
+struct Pager {
+    message: String,
+    page: usize,
+}
+
+impl Pager {
+    fn new(message: &str) -> Self {
+        Self {
+            message: message.to_string(),
+            page: 0,
+        }
+    }
+
+    /// Pages needed with `layout`, which can change between turns (e.g. `FONT`).
+    fn count(&self, layout: &TextLayout, rotation: Rotation) -> usize {
+        let area = layout.frame(panel_area(rotation).size).1;
+        layout.page_count(&self.message, area.size)
+    }
+
+    /// Move according to `next` (or nothing), `prev` or a 1-based page number; returns
+    /// `false` for anything else.
+    fn turn(&mut self, arg: &str, layout: &TextLayout, rotation: Rotation) -> bool {
+        let count = self.count(layout, rotation);
+        let arg = arg.trim();
+        self.page = if arg.is_empty() || arg.eq_ignore_ascii_case("next") {
+            (self.page + 1) % count
+        } else if arg.eq_ignore_ascii_case("prev") {
+            (self.page + count - 1) % count
+        } else {
+            match arg.parse::<usize>() {
+                Ok(n) if (1..=count).contains(&n) => n - 1,
+                _ => return false,
+            }
+        };
+        true
+    }
+
+    fn framebuffer(&self, layout: &TextLayout, rotation: Rotation) -> MonoImage {
+        layout.render_page(&self.message, panel_area(rotation).size, self.page)
+    }
+}
+
+/// `COLUMNS a|b|c`: one independently wrapped text region per `|`-separated part.
+fn build_columns_framebuffer(payload: &str, layout: &TextLayout, rotation: Rotation) -> MonoImage {
+    let (mut fb, area) = layout.frame(panel_area(rotation).size);
+    let columns: Vec<&str> = payload.split('|').map(str::trim).collect();
+    draw_columns(&mut fb, area, &columns, layout);
+    fb
+}
+
+/// `PROGRESS <percent> [label]`: a bar with its label above and the percentage below.
+fn build_progress_framebuffer(
+    percent: f32,
+    label: &str,
+    layout: &TextLayout,
+    rotation: Rotation,
+) -> MonoImage {
+    let (mut fb, area) = layout.frame(panel_area(rotation).size);
+    draw_progress(&mut fb, area, percent, label, layout);
+    fb
+}
+
+/// `battery`: the charge beside a battery icon, with the voltage and current under it.
+#[cfg(feature = "ups")]
+fn build_battery_framebuffer(
+    reading: &rpi_einkserver_rs::ups::BatteryReading,
+    layout: &TextLayout,
+    rotation: Rotation,
+) -> MonoImage {
+    let (mut fb, area) = layout.frame(panel_area(rotation).size);
+    let mut detail = format!("{:.2} V", reading.voltage);
+    if let Some(current) = reading.current_ma {
+        detail.push_str(&format!("\n{current:+.0} mA"));
+    }
+    rpi_einkserver_rs::layout::draw_battery(
+        &mut fb,
+        area,
+        reading.percent,
+        reading.charging(),
+        &detail,
+        layout,
+    );
+    fb
+}
+
+/// An I2C address in hex (`0x42`) or decimal.
+#[cfg(feature = "ups")]
+fn parse_i2c_address(input: &str) -> Result<u16, String> {
+    let address = match input.strip_prefix("0x").or(input.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => input.parse(),
+    }
+    .map_err(|err| format!("invalid I2C address '{input}': {err}"))?;
+    if address > 0x7f {
+        return Err(format!("I2C address '{input}' is out of range (0x00-0x7f)"));
+    }
+    Ok(address)
+}
+
+/// How often `agenda` redraws between fetches.
+const AGENDA_REDRAW: Duration = Duration::from_secs(60);
+
+/// `agenda`: the events of `days` days from `now` that have not ended, under a heading for
+/// each day with any. Times are shown as start and end where there is room, events under
+/// way in inverse video; ones that started before today are listed under it. Lines that do
+/// not fit give way to a count of the events left out.
+fn build_agenda_framebuffer(
+    events: &[ics::Event],
+    now: NaiveDateTime,
+    days: u32,
+    layout: &TextLayout,
+    rotation: Rotation,
+) -> MonoImage {
+    let today = now.date();
+    let horizon = (today + TimeDelta::days(days as i64)).and_time(Default::default());
+    let occurrences = ics::expand(events, now, horizon);
+    let upcoming: Vec<&ics::Event> = occurrences
+        .iter()
+        .filter(|event| {
+            let over = event.end.map_or(event.start < now, |end| end <= now);
+            !over && event.start < horizon
+        })
+        .collect();
+    if upcoming.is_empty() {
+        let centered = TextLayout {
+            alignment: "center,middle".parse().unwrap_or_default(),
+            ..*layout
+        };
+        return build_framebuffer("No events", &centered, rotation);
+    }
+
+    let (mut fb, area) = layout.frame(panel_area(rotation).size);
+    let cell = layout.font.char_size();
+    let row = cell.height + 2;
+    let columns = (area.size.width / cell.width) as usize;
+    let ranges = columns >= 30;
+    // Headings and events, each a line: (text, heading, under way).
+    let mut lines: Vec<(String, bool, bool)> = Vec::new();
+    let mut day = None;
+    for event in &upcoming {
+        let date = event.start.date().max(today);
+        if day != Some(date) {
+            let heading = match (date - today).num_days() {
+                0 => "Today".to_string(),
+                1 => "Tomorrow".to_string(),
+                _ => date.format("%a %-d %b").to_string(),
+            };
+            lines.push((heading, true, false));
+            day = Some(date);
+        }
+        let time = match (event.all_day, event.end) {
+            (true, _) => "All day".to_string(),
+            (false, Some(end)) if ranges => {
+                format!("{}-{}", event.start.format("%H:%M"), end.format("%H:%M"))
+            }
+            (false, _) => event.start.format("%H:%M").to_string(),
+        };
+        let under_way = event.start <= now;
+        lines.push((format!("{time} {}", event.summary), false, under_way));
+    }
+
+    let rows = (area.size.height / row) as usize;
+    let events_shown = |count: usize| lines[..count].iter().filter(|line| !line.1).count();
+    let (shown, more) = if lines.len() > rows {
+        let shown = rows.saturating_sub(1);
+        (shown, upcoming.len() - events_shown(shown))
+    } else {
+        (lines.len(), 0)
+    };
+    let mut y = area.top_left.y;
+    for (text, heading, under_way) in &lines[..shown] {
+        let text = fit_columns(text, columns, layout.font);
+        let mut color = layout.foreground;
+        if *under_way {
+            Rectangle::new(
+                Point::new(area.top_left.x, y),
+                Size::new(area.size.width, row),
+            )
+            .into_styled(PrimitiveStyle::with_fill(layout.foreground))
+            .draw(&mut fb)
+            .ok();
+            color = layout.background;
+        }
+        let origin = Point::new(area.top_left.x, y + 1);
+        layout.font.draw_text(&mut fb, &text, origin, color).ok();
+        if *heading {
+            let width = display_width(&text) as u32 * cell.width;
+            let x = area.top_left.x + (width + REGION_GAP) as i32;
+            let middle = y + row as i32 / 2;
+            Line::new(
+                Point::new(x, middle),
+                Point::new(area.top_left.x + area.size.width as i32 - 1, middle),
+            )
+            .into_styled(PrimitiveStyle::with_stroke(layout.foreground, 1))
+            .draw(&mut fb)
+            .ok();
+        }
+        y += row as i32;
+    }
+    if more > 0 {
+        let origin = Point::new(area.top_left.x, y + 1);
+        let text = format!("+{more} more");
+        layout
+            .font
+            .draw_text(&mut fb, &text, origin, layout.foreground)
+            .ok();
+    }
+    fb
+}
+
+/// `ticker-quotes`: a line per symbol with an arrow for the direction of the day's change,
+/// the symbol, and the price and change aligned to the right. Symbols not fetched yet show
+/// `?`; lines that do not fit are dropped.
+fn build_quotes_framebuffer(
+    symbols: &[String],
+    quotes: &[Option<Quote>],
+    layout: &TextLayout,
+    rotation: Rotation,
+) -> MonoImage {
+    let (mut fb, area) = layout.frame(panel_area(rotation).size);
+    let cell = layout.font.char_size();
+    let row = cell.height.max(ICON_SIZE) + 2;
+    let right = area.top_left.x + area.size.width as i32;
+    // Room for `+12.34%`.
+    let change_width = 7 * cell.width;
+    let text_offset = (row - 2 - cell.height) as i32 / 2;
+    let rows = (area.size.height / row) as usize;
+    let draw_right = |fb: &mut MonoImage, text: &str, right: i32, y: i32| {
+        let x = right - (text.chars().count() as u32 * cell.width) as i32;
+        layout
+            .font
+            .draw_text(fb, text, Point::new(x, y), layout.foreground)
+            .ok();
+    };
+    for (index, (symbol, quote)) in symbols.iter().zip(quotes).take(rows).enumerate() {
+        let y = area.top_left.y + (index as u32 * row) as i32;
+        let text_y = y + text_offset;
+        let change = quote.as_ref().and_then(|quote| quote.change_percent);
+        let arrow = match change {
+            Some(change) if change >= 0.005 => Some("arrow_up"),
+            Some(change) if change <= -0.005 => Some("arrow_down"),
+            _ => None,
+        };
+        if let Some(icon) = arrow.and_then(icons::icon) {
+            icon.draw(
+                &mut fb,
+                Point::new(area.top_left.x, y),
+                1,
+                layout.foreground,
+            );
+        }
+        let x = area.top_left.x + (ICON_SIZE + REGION_GAP) as i32;
+        layout
+            .font
+            .draw_text(&mut fb, symbol, Point::new(x, text_y), layout.foreground)
+            .ok();
+        let price = quote
+            .as_ref()
+            .map_or_else(|| "?".to_string(), |quote| format_price(quote.price));
+        let price_right = right - (change_width + REGION_GAP) as i32;
+        // The price covers a long symbol rather than the other way round.
+        let price_left = price_right - (price.len() as u32 * cell.width) as i32;
+        Rectangle::new(
+            Point::new(price_left - REGION_GAP as i32, y),
+            Size::new(price.len() as u32 * cell.width + REGION_GAP, row),
+        )
+        .into_styled(PrimitiveStyle::with_fill(layout.background))
+        .draw(&mut fb)
+        .ok();
+        draw_right(&mut fb, &price, price_right, text_y);
+        if let Some(change) = change {
+            draw_right(&mut fb, &format!("{change:+.2}%"), right, text_y);
+        }
+    }
+    fb
+}
+
+/// A price with cents, or with four decimals below one.
+fn format_price(price: f64) -> String {
+    if price.abs() < 1.0 {
+        format!("{price:.4}")
+    } else {
+        format!("{price:.2}")
+    }
+}
+
+/// Wait before following Docker events again after losing them.
+const DOCKER_RETRY: Duration = Duration::from_secs(10);
+
+/// Narrowest column of the `docker` list; wider panels get more columns.
+const DOCKER_COLUMN_WIDTH: u32 = 112;
+
+/// `docker`: a count of the containers up, then one line per container with an icon for
+/// how it is doing, in as many columns and rows as fit.
+fn build_docker_framebuffer(
+    containers: &[Container],
+    layout: &TextLayout,
+    rotation: Rotation,
+) -> MonoImage {
+    let (mut fb, area) = layout.frame(panel_area(rotation).size);
+    let cell = layout.font.char_size();
+    let row = cell.height.max(ICON_SIZE) + 2;
+    let up = containers
+        .iter()
+        .filter(|container| container.health() == docker::Health::Up)
+        .count();
+    let heading = format!("Docker: {up}/{} up", containers.len());
+    layout
+        .font
+        .draw_text(&mut fb, &heading, area.top_left, layout.foreground)
+        .ok();
+    let top = area.top_left.y + (cell.height + REGION_GAP) as i32;
+    let rows = (area.size.height.saturating_sub(cell.height + REGION_GAP) / row) as usize;
+    let slots = split_columns(
+        area,
+        (area.size.width / DOCKER_COLUMN_WIDTH).max(1) as usize,
+        REGION_GAP,
+    );
+    let capacity = rows * slots.len();
+    // The last slot says how many more there are if not all fit.
+    let shown = if containers.len() > capacity {
+        capacity.saturating_sub(1)
+    } else {
+        containers.len()
+    };
+    let position = |index: usize| {
+        let slot = slots[index / rows.max(1)];
+        let y = top + ((index % rows.max(1)) as u32 * row) as i32;
+        (slot, y)
+    };
+    let text_offset = (row - 2 - cell.height) as i32 / 2;
+    for (index, container) in containers.iter().take(shown).enumerate() {
+        let (slot, y) = position(index);
+        let icon = match container.health() {
+            docker::Health::Up => "check",
+            docker::Health::Degraded => "warning",
+            docker::Health::Down => "cross",
+        };
+        if let Some(icon) = icons::icon(icon) {
+            icon.draw(
+                &mut fb,
+                Point::new(slot.top_left.x, y),
+                1,
+                layout.foreground,
+            );
+        }
+        let room = slot.size.width.saturating_sub(ICON_SIZE + REGION_GAP) / cell.width;
+        let name: String = container.name.chars().take(room as usize).collect();
+        let x = slot.top_left.x + (ICON_SIZE + REGION_GAP) as i32;
+        layout
+            .font
+            .draw_text(
+                &mut fb,
+                &name,
+                Point::new(x, y + text_offset),
+                layout.foreground,
+            )
+            .ok();
+    }
+    if shown < containers.len() {
+        let (slot, y) = position(shown);
+        let more = format!("+{} more", containers.len() - shown);
+        layout
+            .font
+            .draw_text(
+                &mut fb,
+                &more,
+                Point::new(slot.top_left.x, y + text_offset),
+                layout.foreground,
+            )
+            .ok();
+    }
+    fb
+}
+
+/// Longest `now-playing` goes without asking MPD for the state while nothing plays.
+const NOW_PLAYING_IDLE: Duration = Duration::from_secs(600);
+
+/// Height of the `now-playing` progress bar.
+const NOW_PLAYING_BAR: u32 = 8;
+
+/// `now-playing`: title, artist and album over a progress bar with the elapsed and total
+/// time, or a line saying why nothing plays.
+fn build_now_playing_framebuffer(
+    playing: Option<&NowPlaying>,
+    layout: &TextLayout,
+    rotation: Rotation,
+) -> MonoImage {
+    let centered = TextLayout {
+        alignment: Alignment {
+            horizontal: HAlign::Center,
+            vertical: VAlign::Middle,
+        },
+        ..*layout
+    };
+    let playing = match playing {
+        None => return build_framebuffer("MPD unavailable", &centered, rotation),
+        Some(playing) if playing.state == PlayState::Stopped => {
+            return build_framebuffer("Stopped", &centered, rotation)
+        }
+        Some(playing) => playing,
+    };
+    let (mut fb, area) = layout.frame(panel_area(rotation).size);
+    let line = layout.font.char_size().height;
+    let footer = NOW_PLAYING_BAR + REGION_GAP + line;
+    let song = [&playing.title, &playing.artist, &playing.album]
+        .into_iter()
+        .flatten()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .join("\n");
+    let text_area = Rectangle::new(
+        area.top_left,
+        Size::new(
+            area.size.width,
+            area.size.height.saturating_sub(footer + REGION_GAP),
+        ),
+    );
+    TextLayout {
+        auto_fit: true,
+        ..centered
+    }
+    .draw(&mut fb, text_area, &song);
+
+    let bar_top = area.top_left.y + area.size.height.saturating_sub(footer) as i32;
+    let bar = Rectangle::new(
+        Point::new(area.top_left.x, bar_top),
+        Size::new(area.size.width, NOW_PLAYING_BAR),
+    );
+    bar.into_styled(PrimitiveStyle::with_stroke(layout.foreground, 1))
+        .draw(&mut fb)
+        .ok();
+    if let Some(percent) = playing.percent() {
+        let inner = bar.offset(-2);
+        let filled = (inner.size.width as f32 * percent / 100.0).round() as u32;
+        Rectangle::new(inner.top_left, Size::new(filled, inner.size.height))
+            .into_styled(PrimitiveStyle::with_fill(layout.foreground))
+            .draw(&mut fb)
+            .ok();
+    }
+    let clock = |time: Duration| format!("{}:{:02}", time.as_secs() / 60, time.as_secs() % 60);
+    let mut times = match (playing.elapsed, playing.duration) {
+        (Some(elapsed), Some(duration)) => format!("{} / {}", clock(elapsed), clock(duration)),
+        (Some(elapsed), None) => clock(elapsed),
+        _ => String::new(),
+    };
+    if playing.state == PlayState::Paused {
+        times.insert_str(
+            0,
+            if times.is_empty() {
+                "Paused"
+            } else {
+                "Paused  "
+            },
+        );
+    }
+    TextLayout {
+        auto_fit: false,
+        ..centered
+    }
+    .draw(
+        &mut fb,
+        Rectangle::new(
+            Point::new(
+                area.top_left.x,
+                bar_top + (NOW_PLAYING_BAR + REGION_GAP) as i32,
+            ),
+            Size::new(area.size.width, line),
+        ),
+        &times,
+    );
+    fb
+}
+
+/// `thermal`: the history of the first zone as a sparkline over a status line with the
+/// other zones and the throttling state, led by a warning icon while it is throttled.
+fn build_thermal_framebuffer(
+    history: &[f32],
+    zones: &[(String, f32)],
+    throttled: Option<Throttled>,
+    layout: &TextLayout,
+    rotation: Rotation,
+) -> MonoImage {
+    let Some((name, _)) = zones.first() else {
+        return build_framebuffer("No thermal zones", layout, rotation);
+    };
+    let (mut fb, area) = layout.frame(panel_area(rotation).size);
+    let mut status: Vec<String> = zones[1..]
+        .iter()
+        .map(|(name, celsius)| format!("{name} {celsius:.1} C"))
+        .collect();
+    let warning = icons::icon("warning")
+        .filter(|_| throttled.is_some_and(|flags| !flags.active().is_empty()));
+    if let Some(flags) = throttled {
+        let (active, occurred) = (flags.active(), flags.occurred());
+        status.push(if !active.is_empty() {
+            active.join(", ")
+        } else if !occurred.is_empty() {
+            format!("was {}", occurred.join(", "))
+        } else {
+            "not throttled".to_string()
+        });
+    }
+
+    let line = layout.font.char_size().height.max(ICON_SIZE);
+    let status_height = if status.is_empty() { 0 } else { line + 4 };
+    let chart = Rectangle::new(
+        area.top_left,
+        Size::new(
+            area.size.width,
+            area.size.height.saturating_sub(status_height),
+        ),
+    );
+    draw_sparkline(&mut fb, chart, history, &format!("{name} C"), layout);
+    if !status.is_empty() {
+        let top = area.top_left.y + (area.size.height - line) as i32;
+        let mut x = area.top_left.x;
+        if let Some(icon) = warning {
+            icon.draw(&mut fb, Point::new(x, top), 1, layout.foreground);
+            x += (ICON_SIZE + REGION_GAP) as i32;
+        }
+        let text_top = top + (line - layout.font.char_size().height) as i32 / 2;
+        layout
+            .font
+            .draw_text(
+                &mut fb,
+                &status.join("  "),
+                Point::new(x, text_top),
+                layout.foreground,
+            )
+            .ok();
+    }
+    fb
+}
+
+/// `CHART`: the series as a sparkline.
+fn build_chart_framebuffer(chart: &Chart, layout: &TextLayout, rotation: Rotation) -> MonoImage {
+    let (mut fb, area) = layout.frame(panel_area(rotation).size);
+    let values: Vec<f32> = chart.values.iter().copied().collect();
+    draw_sparkline(&mut fb, area, &values, &chart.label, layout);
+    fb
+}
+
+/// A `PROGRESS` value: a number from 0 to 100, optionally followed by `%`.
+fn parse_percent(input: &str) -> Option<f32> {
+    let percent: f32 = input.strip_suffix('%').unwrap_or(input).parse().ok()?;
+    (0.0..=100.0).contains(&percent).then_some(percent)
+}
+
+/// `TABLE k|v\nk|v`: rows separated by newlines, cells by `|`.
+fn build_table_framebuffer(payload: &str, layout: &TextLayout, rotation: Rotation) -> MonoImage {
+    let rows: Vec<Vec<&str>> = payload
+        .lines()
+        .filter(|row| !row.trim().is_empty())
+        .map(|row| row.split('|').map(str::trim).collect())
+        .collect();
+    build_rows_framebuffer(&rows, layout, rotation)
+}
+
+fn build_rows_framebuffer(
+    rows: &[Vec<&str>],
+    layout: &TextLayout,
+    rotation: Rotation,
+) -> MonoImage {
+    let (mut fb, area) = layout.frame(panel_area(rotation).size);
+    draw_table(&mut fb, area, rows, layout);
+    fb
+}
+
+/// QR code centered on the panel, with an optional caption line wrapped below it.
+fn build_qr_framebuffer(
+    data: &str,
+    caption: Option<&str>,
+    layout: &TextLayout,
+    rotation: Rotation,
+) -> Result<MonoImage, qrcode::types::QrError> {
+    let area = panel_area(rotation);
+    let mut fb = blank_framebuffer(area.size, layout.background);
+    let Some(caption) = caption.filter(|c| !c.trim().is_empty()) else {
+        draw_qr(&mut fb, data, area)?;
+        return Ok(fb);
+    };
+
+    let margin = layout.margin;
+    let text_width = area.size.width.saturating_sub(margin * 2);
+    let caption_layout = TextLayout {
+        alignment: "center,top".parse().unwrap_or_default(),
+        ..*layout
+    };
+    let text_height = caption_layout.measure(caption, text_width);
+    // Keep the code square and center code plus caption as one block.
+    let qr_side = area
+        .size
+        .width
+        .min(area.size.height.saturating_sub(text_height + margin));
+    let top = (area.size.height - qr_side - text_height - margin) as i32 / 2;
+    draw_qr(
+        &mut fb,
+        data,
+        Rectangle::new(Point::new(0, top), Size::new(area.size.width, qr_side)),
+    )?;
+    let text_area = Rectangle::new(
+        Point::new(margin as i32, top + qr_side as i32),
+        Size::new(text_width, text_height),
+    );
+    caption_layout.draw(&mut fb, text_area, caption);
+    Ok(fb)
+}
+
+fn build_barcode_framebuffer(
+    kind: BarcodeKind,
+    data: &str,
+    bg: BinaryColor,
+    rotation: Rotation,
+) -> Result<MonoImage, barcoders::error::Error> {
+    let area = panel_area(rotation);
+    let mut fb = blank_framebuffer(area.size, bg);
+    draw_barcode(&mut fb, kind, data, area)?;
+    Ok(fb)
+}
+
+/// Scale a decoded image onto the panel and dither it down to 1bpp.
+/// A raw panel frame (`MonoImage::data`) as it is, or else an image file dithered to fit
+/// the panel with the default settings.
+fn frame_or_image(bytes: &[u8], cli: &Cli) -> Result<MonoImage, image::ImageError> {
+    match decode_frame(FrameEncoding::Raw, bytes, cli.rotate) {
+        Ok(fb) => Ok(fb),
+        Err(_) => Ok(build_image_framebuffer(
+            GrayImage::decode(bytes)?,
+            DitherMethod::default(),
+            FitMode::default(),
+            cli,
+        )),
+    }
+}
+
+fn build_image_framebuffer(
+    gray: GrayImage,
+    method: DitherMethod,
+    fit: FitMode,
+    cli: &Cli,
+) -> MonoImage {
+    let area = panel_area(cli.rotate).size;
+    let mut gray = gray.fit(area.width, area.height, fit);
+    if cli.reverse_color {
+        gray.invert();
+    }
+    dither(&gray, method)
+}
+
+fn blank_framebuffer(size: Size, bg: BinaryColor) -> MonoImage {
+    let mut fb = MonoImage::new(size.width, size.height);
+    fb.clear(bg);
+    fb
+}
+
+fn run_repl(
+    epd: &mut dyn Panel,
+    cli: &Cli,
+    fg: BinaryColor,
+    bg: BinaryColor,
+) -> Result<(), Box<dyn std::error::Error>> {
+    maybe_init(epd, cli)?;
+    let restored = restore_frame(epd, cli)?;
+
+    println!(
+        "REPL ready. Commands: /clear, /partial, /nopartial, /font <name|auto>, /align <spec>, /page [next|prev|N]. Type text to display. Ctrl-D to exit."
+    );
+
+    let stop = shutdown_signal()?;
+    let lines = stdin_lines();
+    let mut partial = matches!(restored, Some(UpdateMode::Partial));
+    let mut layout = text_layout(cli, fg, bg);
+    let mut pager: Option<Pager> = None;
+
+    while let Some(line) = recv_until(&lines, &stop) {
+        let line = line?;
+
+        if line.starts_with('/') {
+            match line.as_str() {
+                "/clear" => {
+                    clear_panel(epd, cli, bg)?;
+                    pager = None;
+                }
+                "/partial" => {
+                    let blank = blank_framebuffer(panel_area(Rotation::Deg0).size, bg);
+                    show_base(epd, cli, &blank)?;
+                    partial = true;
+                    println!("Partial updates enabled.");
+                }
+                "/nopartial" => {
+                    partial = false;
+                    println!("Partial updates disabled.");
+                }
+                other if other.starts_with("/font") => {
+                    let name = other.trim_start_matches("/font").trim();
+                    if name.eq_ignore_ascii_case("auto") {
+                        layout.auto_fit = true;
+                        println!("Font size will be picked automatically.");
+                    } else {
+                        match name.parse::<FontChoice>() {
+                            Ok(choice) => {
+                                layout.font = choice;
+                                layout.auto_fit = false;
+                                println!("Font set to {choice}.");
+                            }
+                            Err(err) => println!("{err}"),
+                        }
+                    }
+                }
+                other if other.starts_with("/page") => match pager.as_mut() {
+                    Some(pager) => {
+                        if pager.turn(other.trim_start_matches("/page"), &layout, cli.rotate) {
+                            present(epd, &pager.framebuffer(&layout, cli.rotate), partial, cli)?;
+                            println!(
+                                "Page {}/{}.",
+                                pager.page + 1,
+                                pager.count(&layout, cli.rotate)
+                            );
+                        } else {
+                            println!("Usage: /page [next|prev|N]");
+                        }
+                    }
+                    None => println!("No text to page through."),
+                },
+                other if other.starts_with("/align") => {
+                    match other.trim_start_matches("/align").parse::<Alignment>() {
+                        Ok(alignment) => {
+                            layout.alignment = alignment;
+                            println!("Alignment set to {alignment}.");
+                        }
+                        Err(err) => println!("{err}"),
+                    }
+                }
+                other => {
+                    println!("Unknown command: {other}");
+                }
+            }
+            continue;
+        }
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let text = decode_newlines(&line);
+        let fb = build_framebuffer(&text, &layout, cli.rotate);
+        present(epd, &fb, partial, cli)?;
+        let paged = Pager::new(&text);
+        let pages = paged.count(&layout, cli.rotate);
+        if pages > 1 {
+            println!("Showing page 1/{pages}; /page flips.");
+        }
+        pager = Some(paged);
+    }
+
+    if stop.load(Ordering::Relaxed) {
+        info!("Signal received; putting the panel to sleep");
+    }
+    epd.sleep()?;
+    Ok(())
+}
+
+/// Set once SIGINT or SIGTERM arrives. Long-running modes check it between commands, so a
+/// refresh in progress completes (and its frame is stored) before they shut down; a second
+/// signal exits immediately.
+fn shutdown_signal() -> io::Result<Arc<AtomicBool>> {
+    let stop = Arc::new(AtomicBool::new(false));
+    for signal in [SIGINT, SIGTERM] {
+        signal_hook::flag::register_conditional_shutdown(signal, 130, Arc::clone(&stop))?;
+        signal_hook::flag::register(signal, Arc::clone(&stop))?;
+    }
+    Ok(stop)
+}
+
+/// Lines from stdin, read on their own thread so the caller can keep watching for signals.
+fn stdin_lines() -> mpsc::Receiver<io::Result<String>> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+/// The next item from `rx`, or `None` once it is closed or `stop` is set.
+fn recv_until<T>(rx: &mpsc::Receiver<T>, stop: &AtomicBool) -> Option<T> {
+    while !stop.load(Ordering::Relaxed) {
+        match rx.recv_timeout(Duration::from_millis(100)) {
+            Ok(item) => return Some(item),
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => return None,
+        }
+    }
+    None
+}
+
+fn decode_newlines(input: &str) -> String {
+    input.replace("\\n", "\n")
+}
+
+/// gRPC requests waiting for the display; further ones get `RESOURCE_EXHAUSTED`.
+#[cfg(feature = "grpc")]
+const GRPC_QUEUE: usize = 16;
+
+#[cfg(feature = "grpc")]
+fn run_grpc_server(
+    epd: &mut dyn Panel,
+    cli: &Cli,
+    fg: BinaryColor,
+    bg: BinaryColor,
+    listen: SocketAddr,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use rpi_einkserver_rs::grpc::{self, DisplayEvent, DisplayService, GrpcJob};
+
+    maybe_init(epd, cli)?;
+    let restored = restore_frame(epd, cli)?;
+    let (jobs_tx, jobs) = mpsc::sync_channel::<GrpcJob>(GRPC_QUEUE);
+    let (service, events) = DisplayService::new(jobs_tx);
+    grpc::spawn_server(listen, service).map_err(|source| CliError::HttpBind {
+        addr: listen.to_string(),
+        source,
+    })?;
+    info!("gRPC server listening on {listen}");
+
+    let stop = shutdown_signal()?;
+    let mut session = Session::new(text_layout(cli, fg, bg));
+    session.resume(restored);
+    notify_systemd(&[NotifyState::Ready]);
+    let mut watchdog = Watchdog::new();
+    while !stop.load(Ordering::Relaxed) {
+        watchdog.tick();
+        let job = match jobs.recv_timeout(Duration::from_millis(100)) {
+            Ok(job) => job,
+            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        };
+        let (command, clears) = match &job.command {
+            grpc::GrpcCommand::Text(_) => ("text", false),
+            grpc::GrpcCommand::Image(_) => ("image", false),
+            grpc::GrpcCommand::Clear => ("clear", true),
+        };
+        let outcome = handle_grpc(job.command, epd, cli, bg, &mut session);
+        info!(command, ok = outcome.is_ok(), "gRPC request");
+        if let Ok(reply) = &outcome {
+            let event = DisplayEvent {
+                event: if clears { "clear" } else { "refresh" }.to_string(),
+                command: if clears { "" } else { command }.to_string(),
+                mode: reply.mode.clone(),
+                source: "grpc".to_string(),
+            };
+            // Nobody may be subscribed.
+            events.send(event).ok();
+        }
+        job.reply.send(outcome).ok();
+    }
+
+    info!("Signal received; putting the panel to sleep");
+    notify_systemd(&[NotifyState::Stopping]);
+    epd.sleep()?;
+    Ok(())
+}
+
+/// Carry out a gRPC request on the panel, with the session's settings as defaults.
+#[cfg(feature = "grpc")]
+fn handle_grpc(
+    command: rpi_einkserver_rs::grpc::GrpcCommand,
+    epd: &mut dyn Panel,
+    cli: &Cli,
+    bg: BinaryColor,
+    session: &mut Session,
+) -> Result<rpi_einkserver_rs::grpc::DisplayReply, tonic::Status> {
+    use rpi_einkserver_rs::grpc::{DisplayReply, GrpcCommand};
+    use tonic::Status;
+
+    let panel_failure = |err: Box<dyn std::error::Error>| match failure_code(&*err) {
+        "BUSY_TIMEOUT" => Status::unavailable(err.to_string()),
+        "BAD_INPUT" => Status::invalid_argument(err.to_string()),
+        _ => Status::internal(err.to_string()),
+    };
+    let parse_mode = |name: &str| match name {
+        "" => Ok(None),
+        name => parse_update_mode(name)
+            .map(Some)
+            .ok_or_else(|| Status::invalid_argument(format!("unknown mode '{name}'"))),
+    };
+    let (fb, mode) = match command {
+        GrpcCommand::Clear => {
+            clear_panel(epd, cli, bg).map_err(panel_failure)?;
+            session.display.has_base = false;
+            session.pager = None;
+            return Ok(DisplayReply::default());
+        }
+        GrpcCommand::Text(request) => {
+            if request.text.trim().is_empty() {
+                return Err(Status::invalid_argument("empty text"));
+            }
+            let mut layout = session.layout;
+            if !request.font.is_empty() && !set_font(&mut layout, &request.font) {
+                return Err(Status::invalid_argument(format!(
+                    "unknown font '{}'",
+                    request.font
+                )));
+            }
+            if !request.align.is_empty() {
+                layout.alignment = request.align.parse().map_err(Status::invalid_argument)?;
+            }
+            let fb = build_framebuffer(request.text.trim_end(), &layout, cli.rotate);
+            (fb, parse_mode(&request.mode)?)
+        }
+        GrpcCommand::Image(request) => {
+            let method = match request.dither.as_str() {
+                "" => DitherMethod::default(),
+                name => name.parse().map_err(Status::invalid_argument)?,
+            };
+            let fit = match request.fit.as_str() {
+                "" => FitMode::default(),
+                name => name.parse().map_err(Status::invalid_argument)?,
+            };
+            let gray = GrayImage::decode(&request.image)
+                .map_err(|err| Status::invalid_argument(format!("cannot decode image: {err}")))?;
+            let fb = build_image_framebuffer(gray, method, fit, cli);
+            (fb, parse_mode(&request.mode)?)
+        }
+    };
+    let refresh = session.plan(mode, cli);
+    show_planned(epd, &fb, refresh, cli).map_err(panel_failure)?;
+    session.pager = None;
+    // A frame shown as the new base gets a full refresh.
+    let mode = mode_name(refresh.unwrap_or(UpdateMode::Normal));
+    Ok(DisplayReply {
+        mode: mode.to_string(),
+    })
+}
+
+/// An MQTT broker address from `--broker`.
+#[derive(Debug, Clone)]
+struct Broker {
+    host: String,
+    port: u16,
+}
+
+fn parse_broker(input: &str) -> Result<Broker, String> {
+    let address = match input.split_once("://") {
+        Some(("mqtt" | "tcp", address)) => address,
+        Some((scheme, _)) => {
+            return Err(format!(
+                "unsupported broker scheme '{scheme}' (use mqtt://)"
+            ))
+        }
+        None => input,
+    };
+    let address = address.trim_end_matches('/');
+    let (host, port) = match address.rsplit_once(':') {
+        Some((host, port)) => {
+            let port = port
+                .parse()
+                .map_err(|_| format!("invalid port in broker URL '{input}'"))?;
+            (host, port)
+        }
+        None => (address, 1883),
+    };
+    if host.is_empty() {
+        return Err(format!("missing host in broker URL '{input}'"));
+    }
+    Ok(Broker {
+        host: host.to_string(),
+        port,
+    })
+}
+
+/// Largest MQTT message accepted, enough for a photo on `<topic>/image`.
+const MAX_MQTT_PAYLOAD: usize = 8 * 1024 * 1024;
+
+/// Home Assistant MQTT discovery messages, as `(config topic, payload)`: a notify entity
+/// that shows its message and a button that clears the panel, both on one device with
+/// our availability and the status JSON as attributes.
+fn ha_discovery_configs(
+    prefix: &str,
+    client_id: &str,
+    topic: &str,
+) -> Vec<(String, serde_json::Value)> {
+    let prefix = prefix.trim_end_matches('/');
+    let topic = topic.trim_end_matches('/');
+    let node: String = client_id
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    let device = serde_json::json!({
+        "identifiers": [node],
+        "name": "E-Paper display",
+        "manufacturer": "Waveshare",
+        "model": "2.13\" e-Paper HAT V4",
+    });
+    let entity = |component: &str, object: &str, name: &str, command: &str| {
+        let config = serde_json::json!({
+            "name": name,
+            "unique_id": format!("{node}_{object}"),
+            "command_topic": format!("{topic}/{command}"),
+            "availability_topic": format!("{topic}/availability"),
+            "json_attributes_topic": format!("{topic}/status"),
+            "device": device,
+        });
+        (
+            format!("{prefix}/{component}/{node}/{object}/config"),
+            config,
+        )
+    };
+    vec![
+        entity("notify", "message", "Message", "text"),
+        entity("button", "clear", "Clear", "clear"),
+    ]
+}
+
+/// Longest `last_shown` text in the status, the limit of a Home Assistant state.
+const MAX_LAST_SHOWN: usize = 255;
+
+/// Show MQTT messages until the process is killed. Connection failures are logged and
+/// retried; subscriptions, `discovery` configs and the retained `online` availability are
+/// renewed on every (re)connect, and the broker publishes `offline` as our last will.
+/// Presses of the `buttons` are published to `<topic>/button` as they come.
+#[allow(clippy::too_many_arguments)]
+fn run_mqtt_client(
+    epd: &mut dyn Panel,
+    cli: &Cli,
+    fg: BinaryColor,
+    bg: BinaryColor,
+    mut options: rumqttc::MqttOptions,
+    topic: &str,
+    discovery: &[(String, serde_json::Value)],
+    buttons: &[u8],
+) -> Result<(), Box<dyn std::error::Error>> {
+    use rumqttc::{Event, LastWill, Packet, QoS};
+
+    let topic = topic.trim_end_matches('/');
+    let availability = format!("{topic}/availability");
+    options
+        .set_keep_alive(Duration::from_secs(30))
+        .set_max_packet_size(MAX_MQTT_PAYLOAD, MAX_MQTT_PAYLOAD)
+        .set_last_will(LastWill::new(
+            &availability,
+            "offline",
+            QoS::AtLeastOnce,
+            true,
+        ));
+    let (client, mut connection) = rumqttc::Client::new(options, 16);
+    if !buttons.is_empty() {
+        let (presses_tx, presses) = mpsc::channel();
+        buttons::watch(buttons, presses_tx)?;
+        info!(?buttons, "Publishing button presses to {topic}/button");
+        let (client, button_topic) = (client.clone(), format!("{topic}/button"));
+        thread::spawn(move || {
+            for pin in presses {
+                let published =
+                    client.try_publish(&button_topic, QoS::AtLeastOnce, false, pin.to_string());
+                if let Err(err) = published {
+                    warn!("Could not publish a press of button {pin}: {err}");
+                }
+            }
+        });
+    }
+    maybe_init(epd, cli)?;
+    let layout = text_layout(cli, fg, bg);
+
+    let send = |name: &str, payload: Vec<u8>| {
+        if let Err(err) = client.try_publish(name, QoS::AtLeastOnce, true, payload) {
+            warn!("Could not publish to {name}: {err}");
+        }
+    };
+    let publish = |suffix: &str, payload: Vec<u8>| send(&format!("{topic}/{suffix}"), payload);
+    // What the panel shows and since when, reported as `last_shown`/`last_shown_at`.
+    let mut shown: Option<(String, DateTime<Local>)> = None;
+    let status = |shown: &Option<(String, DateTime<Local>)>| {
+        let mut status = status_json(cli);
+        if let Some((what, at)) = shown {
+            status["last_shown"] = what.as_str().into();
+            status["last_shown_at"] = at.to_rfc3339().into();
+        }
+        status
+    };
+
+    for event in connection.iter() {
+        let message = match event {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                info!("Connected to MQTT broker; subscribed to {topic}/{{text,image,clear}}");
+                for suffix in ["text", "image", "clear"] {
+                    client.try_subscribe(format!("{topic}/{suffix}"), QoS::AtLeastOnce)?;
+                }
+                for (config_topic, config) in discovery {
+                    send(config_topic, config.to_string().into_bytes());
+                }
+                publish("availability", b"online".to_vec());
+                publish("status", status(&shown).to_string().into_bytes());
+                continue;
+            }
+            Ok(Event::Incoming(Packet::Publish(message))) => message,
+            Ok(_) => continue,
+            Err(err) => {
+                warn!("MQTT connection failed: {err}; retrying in 5s");
+                thread::sleep(Duration::from_secs(5));
+                continue;
+            }
+        };
+
+        let command = message.topic.rsplit('/').next().unwrap_or_default();
+        info!(
+            topic = message.topic,
+            bytes = message.payload.len(),
+            "MQTT message"
+        );
+        // `Ok(Ok(summary))` once shown, `Ok(Err(problem))` for messages that are ignored.
+        let outcome = match command {
+            "clear" => clear_panel(epd, cli, bg).map(|()| Ok(String::new())),
+            "text" => match std::str::from_utf8(&message.payload) {
+                Ok(text) if !text.trim().is_empty() => {
+                    let fb = build_framebuffer(text.trim_end(), &layout, cli.rotate);
+                    let summary = text.trim().chars().take(MAX_LAST_SHOWN).collect();
+                    present(epd, &fb, false, cli).map(|()| Ok(summary))
+                }
+                Ok(_) => Ok(Err("empty text".to_string())),
+                Err(_) => Ok(Err("text is not UTF-8".to_string())),
+            },
+            "image" => match GrayImage::decode(&message.payload) {
+                Ok(gray) => {
+                    let fb = build_image_framebuffer(
+                        gray,
+                        DitherMethod::default(),
+                        FitMode::default(),
+                        cli,
+                    );
+                    present(epd, &fb, false, cli).map(|()| Ok("(image)".to_string()))
+                }
+                Err(err) => Ok(Err(format!("cannot decode image: {err}"))),
+            },
+            _ => continue,
+        };
+        let problem = match outcome {
+            Ok(Ok(summary)) => {
+                shown = Some((summary, Local::now()));
+                None
+            }
+            Ok(Err(problem)) => {
+                warn!("Ignored MQTT {command}: {problem}");
+                Some(problem)
+            }
+            Err(err) => {
+                error!("MQTT {command} failed: {err}");
+                Some(err.to_string())
+            }
+        };
+        let mut report = status(&shown);
+        match problem {
+            None => report["last_command"] = command.into(),
+            Some(problem) => report["error"] = problem.into(),
+        }
+        publish("status", report.to_string().into_bytes());
+    }
+    Ok(())
+}
+
+fn run_server(
+    epd: &mut dyn Panel,
+    cli: &Cli,
+    fg: BinaryColor,
+    bg: BinaryColor,
+    args: &ServeArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let socket = args.socket.as_path();
+    // Under socket activation systemd owns the socket file, and the panel is initialized
+    // only when the first command arrives.
+    let (listener, activated) = match activated_listener()? {
+        Some(listener) => {
+            info!("Using the Unix socket passed by systemd");
+            if args.socket_mode.is_some() || args.socket_group.is_some() {
+                warn!("Ignoring --socket-mode/--socket-group; set SocketMode=/SocketGroup= in the socket unit");
+            }
+            (listener, true)
+        }
+        None => {
+            if socket.exists() {
+                std::fs::remove_file(socket)?;
+            }
+
+            maybe_init(epd, cli)?;
+
+            let listener = UnixListener::bind(socket).map_err(|source| CliError::SocketBind {
+                path: socket.to_path_buf(),
+                source,
+            })?;
+            set_socket_permissions(socket, args.socket_mode, args.socket_group).map_err(
+                |source| CliError::SocketPermissions {
+                    path: socket.to_path_buf(),
+                    source,
+                },
+            )?;
+            info!(
+                "Unix socket server listening on {}",
+                socket.to_string_lossy()
+            );
+            (listener, false)
+        }
+    };
+    // Restoring needs the panel, so it is initialized right away even when activated.
+    let mut needs_init = activated;
+    if needs_init && cli.restore.is_some() {
+        maybe_init(epd, cli)?;
+        needs_init = false;
+    }
+    let restored = restore_frame(epd, cli)?;
+    info!("Protocol: newline-delimited packets. Commands: TEXT <msg> (default), QR <payload>, BARCODE [CODE128|EAN13|EAN8] <data>, COLUMNS <a|b>, TABLE <k|v\\n...>, LAYOUT <json>, FRAME RAW|RLE <len>, IMAGE <base64>|BYTES <len>, REGION <x> <y> <w> <h> TEXT|BITS, LAYER SET|DEL|Z|LIST|CLEAR, SHOW <duration> <msg>, AT <rfc3339> <msg>, FONT <name|auto>, ALIGN <spec>, PAGE [NEXT|PREV|<n>], CLEAR, PARTIAL_ON, PARTIAL_OFF, BINARY, TERM, SUBSCRIBE, ASYNC, WAIT [<id>], PING, MODE [<mode>], STATUS, SNAPSHOT [PNG|RAW], LINE|RECT|CIRCLE|PIXEL <coords> then FLUSH, BEGIN ... COMMIT [<mode>]|ABORT, TEMPLATE SAVE|SHOW|DEL|LIST, PLAYLIST ADD|START|STOP|CLEAR|LIST, CLOCK ON [pos=..] [font=..] [format=..]|OFF, MENU [OPEN|CLOSE|UP|DOWN|SELECT|BACK], PROGRESS <0-100> [label], CHART [ADD] <values> [window=<n>] [label]|CLEAR, ICON <name> [pos=x,y] [scale=n]|LIST; any of them after PRIORITY <level>. Lines starting with {{ are JSON requests.");
+
+    // Each client gets a thread that reads its packets; this thread is the only one driving
+    // the panel and works through them by priority, then in arrival order.
+    let stop = shutdown_signal()?;
+    let (jobs_tx, jobs) = mpsc::sync_channel::<SocketJob>(args.queue as usize);
+    let layout = text_layout(cli, fg, bg);
+    let subscribers = Subscribers::default();
+    let templates = Rc::new(RefCell::new(Templates::load(args.templates.clone())?));
+    let mut extras = args
+        .panels
+        .iter()
+        .map(|spec| {
+            ExtraPanel::open(
+                spec,
+                cli,
+                args.min_interval,
+                subscribers.clone(),
+                Rc::clone(&templates),
+            )
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut state = ServerState::new(args.min_interval, templates);
+    state.display = DisplayMode::resumed(restored);
+    if let Some(path) = &args.menu {
+        let menu = Rc::new(Menu::parse_for_path(&std::fs::read_to_string(path)?, path)?);
+        info!(path = %path.display(), "Loaded menu");
+        for extra in &mut extras {
+            extra.state.menu = Some(Rc::clone(&menu));
+        }
+        state.menu = Some(menu);
+    }
+    let main = PanelInfo {
+        name: None,
+        health: Arc::clone(&state.health),
+    };
+    let panels: Arc<[PanelInfo]> = std::iter::once(main)
+        .chain(extras.iter().map(|extra| PanelInfo {
+            name: Some(extra.name.clone()),
+            health: Arc::clone(&extra.state.health),
+        }))
+        .collect();
+    let fifo = match &args.fifo {
+        Some(path) => {
+            let created = create_fifo(path)
+                .and_then(|created| {
+                    set_socket_permissions(path, args.socket_mode, args.socket_group)?;
+                    Ok(created)
+                })
+                .map_err(|source| CliError::Fifo {
+                    path: path.clone(),
+                    source,
+                })?;
+            info!("Reading commands from FIFO {}", path.display());
+            let (reader_path, jobs, panels) = (path.clone(), jobs_tx.clone(), Arc::clone(&panels));
+            thread::spawn(move || {
+                if let Err(err) = read_fifo(&reader_path, &jobs, layout, &panels) {
+                    error!("FIFO error: {err}");
+                }
+            });
+            created.then(|| path.clone())
+        }
+        None => None,
+    };
+    if !args.buttons.is_empty() {
+        let pins: Vec<u8> = args.buttons.iter().map(|button| button.pin).collect();
+        let (presses_tx, presses) = mpsc::channel();
+        buttons::watch(&pins, presses_tx)?;
+        info!(?pins, "Watching buttons");
+        let (buttons, jobs) = (args.buttons.clone(), jobs_tx.clone());
+        let (panels, subscribers) = (Arc::clone(&panels), subscribers.clone());
+        thread::spawn(move || {
+            handle_buttons(&presses, &buttons, &jobs, layout, &panels, &subscribers);
+        });
+    }
+    let listening = subscribers.clone();
+    thread::spawn(move || {
+        for conn in listener.incoming() {
+            match conn {
+                Ok(stream) => {
+                    let (jobs, panels) = (jobs_tx.clone(), Arc::clone(&panels));
+                    let subscribers = listening.clone();
+                    thread::spawn(move || {
+                        debug!("Client connected");
+                        let served =
+                            handle_connection(stream, &jobs, layout, &panels, &subscribers);
+                        if let Err(err) = served {
+                            error!("Connection error: {err}");
+                        }
+                        debug!("Client disconnected");
+                    });
+                }
+                Err(err) => error!("Accept error: {err}"),
+            }
+        }
+    });
+
+    let mut counting = CountingPanel::new(epd, Rc::clone(&state.refreshes))
+        .with_events(|event| subscribers.panel_event(event, None));
+    let mut clocked = ClockPanel::new(&mut counting, Rc::clone(&state.clock), cli.rotate);
+    let epd: &mut dyn Panel = &mut clocked;
+    let mut pending = Vec::new();
+    let mut idle = IdleSleep::new(args.idle_sleep);
+    let mut stale = StaleWatch::new(args.stale_after, args.stale_action);
+    notify_systemd(&[NotifyState::Ready]);
+    let mut watchdog = Watchdog::new();
+    while !stop.load(Ordering::Relaxed) {
+        watchdog.tick();
+        for extra in &mut extras {
+            extra.run_due(bg);
+        }
+        if state.timer_due() {
+            let _busy = state.health.busy();
+            let before = state.refresh_count();
+            let ran = idle
+                .wake(epd, cli)
+                .and_then(|()| state.run_due_timers(epd, cli, bg));
+            if state.refreshed_since(before) {
+                stale.touch();
+            }
+            if let Err(err) = ran {
+                error!("Scheduled update failed: {err}");
+            }
+        }
+        if !needs_init && state.clock.borrow().due() {
+            let _busy = state.health.busy();
+            // A new minute is no activity of its own: a sleeping panel is woken for it and
+            // goes back to sleep.
+            let last_active = idle.last_active;
+            let updated = idle
+                .wake(epd, cli)
+                .and_then(|()| update_clock(epd, cli, bg, &state.clock));
+            idle.last_active = last_active;
+            if let Err(err) = updated {
+                error!("Clock update failed: {err}");
+            }
+        }
+        if state.playlist_due() {
+            let _busy = state.health.busy();
+            let before = state.refresh_count();
+            let shown = idle
+                .wake(epd, cli)
+                .and_then(|()| state.advance_playlist(epd, cli));
+            if state.refreshed_since(before) {
+                stale.touch();
+            }
+            if let Err(err) = shown {
+                error!("Playlist update failed: {err}");
+            }
+        }
+        if state.shell_done() {
+            let _busy = state.health.busy();
+            let before = state.refresh_count();
+            let shown = idle
+                .wake(epd, cli)
+                .and_then(|()| state.show_shell_output(epd, cli));
+            if state.refreshed_since(before) {
+                stale.touch();
+            }
+            if let Err(err) = shown {
+                error!("Could not show the menu command's output: {err}");
+            }
+        }
+        if state.deferred.is_some() {
+            let _busy = state.health.busy();
+            let before = state.refresh_count();
+            let shown = idle
+                .wake(epd, cli)
+                .and_then(|()| state.show_deferred(epd, cli));
+            if state.refresh_count() != before {
+                stale.touch();
+            }
+            if let Err(err) = shown {
+                error!("Coalesced update failed: {err}");
+            }
+        }
+        if !needs_init && stale.due() {
+            let _busy = state.health.busy();
+            let acted = idle
+                .wake(epd, cli)
+                .and_then(|()| stale.act(epd, cli, &layout));
+            state.display.has_base = false;
+            state.content_replaced();
+            if let Err(err) = acted {
+                error!("Could not act on stale content: {err}");
+            }
+        }
+        let slept = if pending.is_empty() && !needs_init {
+            idle.check(epd)
+        } else {
+            Ok(())
+        };
+        if let Err(err) = slept {
+            error!("Could not put the idle panel to sleep: {err}");
+        }
+        // Run the most urgent waiting job (the oldest among equals) that isn't held back by
+        // a `SHOW` message; otherwise wait for more.
+        pending.extend(jobs.try_iter());
+        let holds = |job: &SocketJob| match &job.packet.panel {
+            None => state.holds(&job.packet),
+            Some(name) => extras
+                .iter()
+                .any(|extra| &extra.name == name && extra.state.holds(&job.packet)),
+        };
+        let next = pending
+            .iter()
+            .enumerate()
+            .filter(|(_, job)| !holds(job))
+            .max_by_key(|&(index, job)| (job.packet.priority, std::cmp::Reverse(index)))
+            .map(|(index, _)| index);
+        let Some(index) = next else {
+            match jobs.recv_timeout(Duration::from_millis(100)) {
+                Ok(job) => pending.push(job),
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+            }
+            continue;
+        };
+        let mut job = pending.remove(index);
+        if let Some(name) = &job.packet.panel {
+            // The connection only lets through names of extra panels.
+            let extra = extras
+                .iter_mut()
+                .find(|extra| &extra.name == name)
+                .expect("packets name known panels");
+            let reply = extra.handle(&job.packet, bg, &mut job.session);
+            job.reply.send((job.session, reply)).ok();
+            continue;
+        }
+        let busy = job.packet.changes_screen().then(|| state.health.busy());
+        if needs_init {
+            if let Err(err) = maybe_init(epd, cli) {
+                error!("Cannot initialize the panel: {err}");
+                let reply = failure_reply(&job.packet.line, &*err);
+                job.reply.send((job.session, reply)).ok();
+                continue;
+            }
+            needs_init = false;
+        }
+        let woken = if job.packet.changes_screen() {
+            idle.wake(epd, cli)
+        } else {
+            Ok(())
+        };
+        if let Err(err) = woken {
+            error!("Cannot wake the panel: {err}");
+            let reply = failure_reply(&job.packet.line, &*err);
+            job.reply.send((job.session, reply)).ok();
+            continue;
+        }
+        state.queued = pending.len();
+        let before = state.refresh_count();
+        job.session.display = state.display;
+        job.session.pager = state.pager.take();
+        let reply = handle_packet(&job.packet, epd, cli, bg, &mut job.session, &mut state)
+            .unwrap_or_else(|err| {
+                error!("Command failed: {err}");
+                failure_reply(&job.packet.line, &*err)
+            });
+        state.display = job.session.display;
+        state.pager = job.session.pager.take();
+        if state.refreshed_since(before) {
+            stale.touch();
+        }
+        drop(busy);
+        job.reply.send((job.session, reply)).ok();
+    }
+
+    // Clients still connected see their connection close.
+    info!("Signal received; shutting down");
+    notify_systemd(&[NotifyState::Stopping]);
+    let removed = if activated {
+        Ok(())
+    } else {
+        std::fs::remove_file(socket)
+    };
+    if let Err(err) = removed {
+        warn!("Could not remove {}: {err}", socket.display());
+    }
+    if let Some(fifo) = fifo {
+        std::fs::remove_file(&fifo)
+            .unwrap_or_else(|err| warn!("Could not remove {}: {err}", fifo.display()));
+    }
+    if !needs_init && !idle.asleep {
+        epd.sleep()?;
+    }
+    for extra in &mut extras {
+        extra.panel.sleep()?;
+    }
+    Ok(())
+}
+
+/// `--idle-sleep`: deep sleep for the panel after a quiet spell, and a transparent wake-up
+/// before the next update.
+struct IdleSleep {
+    after: Option<Duration>,
+    last_active: Instant,
+    asleep: bool,
+}
+
+impl IdleSleep {
+    fn new(after: Option<Duration>) -> Self {
+        Self {
+            after,
+            last_active: Instant::now(),
+            asleep: false,
+        }
+    }
+
+    /// Put the panel to sleep once it has been idle long enough.
+    fn check(&mut self, epd: &mut dyn Panel) -> Result<(), EpdError> {
+        let Some(after) = self.after else {
+            return Ok(());
+        };
+        if !self.asleep && self.last_active.elapsed() >= after {
+            info!("Idle for {}s; putting the panel to sleep", after.as_secs());
+            epd.sleep()?;
+            self.asleep = true;
+        }
+        Ok(())
+    }
+
+    /// Note activity, and wake the panel first if it sleeps. Deep sleep loses the
+    /// controller's memory, so the last frame is loaded again as the partial-update base,
+    /// with a partial refresh that changes nothing on screen.
+    fn wake(&mut self, epd: &mut dyn Panel, cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
+        self.last_active = Instant::now();
+        if !self.asleep {
+            return Ok(());
+        }
+        info!("Waking the panel");
+        maybe_init(epd, cli)?;
+        self.asleep = false;
+        if let Some(frame) = FrameStore::new(&cli.persist_frame).load()? {
+            let panel = panel_area(Rotation::Deg0).size;
+            if frame.size() == panel {
+                epd.display_partial_over(frame.data(), frame.data())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A panel added with `serve --panel`, with display state of its own. Its frames are stored
+/// (and previewed) next to the main panel's, with its name added to the file names.
+struct ExtraPanel {
+    name: String,
+    panel: Box<dyn Panel>,
+    cli: Cli,
+    state: ServerState,
+    subscribers: Subscribers,
+}
+
+impl ExtraPanel {
+    /// Open and initialize the panel, and bring back its last frame under `--restore`.
+    fn open(
+        spec: &PanelSpec,
+        cli: &Cli,
+        min_interval: Option<Duration>,
+        subscribers: Subscribers,
+        templates: Rc<RefCell<Templates>>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut cli = cli.clone();
+        cli.persist_frame = named_path(&cli.persist_frame, &spec.name);
+        cli.preview = cli.preview.map(|path| named_path(&path, &spec.name));
+        let mut panel: Box<dyn Panel> = match &cli.preview {
+            Some(path) => Box::new(PreviewPanel::new(path, cli.rotate)),
+            None => Box::new(powered(
+                Epd2in13V4::on_bus(spec.bus, spec.slave_select, spec.pins)?,
+                spec.pwr,
+            )?),
+        };
+        maybe_init(panel.as_mut(), &cli)?;
+        let mut state = ServerState::new(min_interval, templates);
+        state.display = DisplayMode::resumed(restore_frame(panel.as_mut(), &cli)?);
+        info!(panel = spec.name, "Opened extra panel");
+        Ok(Self {
+            name: spec.name.clone(),
+            panel,
+            cli,
+            state,
+            subscribers,
+        })
+    }
+
+    /// Carry out a client's command on this panel.
+    fn handle(&mut self, packet: &SocketPacket, bg: BinaryColor, session: &mut Session) -> String {
+        let _busy = packet.changes_screen().then(|| self.state.health.busy());
+        let (subscribers, name) = (&self.subscribers, self.name.as_str());
+        let mut counting =
+            CountingPanel::new(self.panel.as_mut(), Rc::clone(&self.state.refreshes))
+                .with_events(|event| subscribers.panel_event(event, Some(name)));
+        let clock = Rc::clone(&self.state.clock);
+        let mut epd = ClockPanel::new(&mut counting, clock, self.cli.rotate);
+        let before = self.state.refresh_count();
+        session.display = self.state.display;
+        session.pager = self.state.pager.take();
+        let reply = handle_packet(packet, &mut epd, &self.cli, bg, session, &mut self.state)
+            .unwrap_or_else(|err| {
+                error!(panel = self.name, "Command failed: {err}");
+                failure_reply(&packet.line, &*err)
+            });
+        self.state.display = session.display;
+        self.state.pager = session.pager.take();
+        self.state.refreshed_since(before);
+        reply
+    }
+
+    /// Show scheduled and held-back updates that are due.
+    fn run_due(&mut self, bg: BinaryColor) {
+        let (subscribers, name) = (&self.subscribers, self.name.as_str());
+        let mut counting =
+            CountingPanel::new(self.panel.as_mut(), Rc::clone(&self.state.refreshes))
+                .with_events(|event| subscribers.panel_event(event, Some(name)));
+        let clock = Rc::clone(&self.state.clock);
+        let mut epd = ClockPanel::new(&mut counting, clock, self.cli.rotate);
+        if self.state.clock.borrow().due() {
+            let _busy = self.state.health.busy();
+            if let Err(err) = update_clock(&mut epd, &self.cli, bg, &self.state.clock) {
+                error!(panel = self.name, "Clock update failed: {err}");
+            }
+        }
+        if self.state.timer_due() {
+            let _busy = self.state.health.busy();
+            let before = self.state.refresh_count();
+            let ran = self.state.run_due_timers(&mut epd, &self.cli, bg);
+            self.state.refreshed_since(before);
+            if let Err(err) = ran {
+                error!(panel = self.name, "Scheduled update failed: {err}");
+            }
+        }
+        if self.state.playlist_due() {
+            let _busy = self.state.health.busy();
+            let before = self.state.refresh_count();
+            let shown = self.state.advance_playlist(&mut epd, &self.cli);
+            self.state.refreshed_since(before);
+            if let Err(err) = shown {
+                error!(panel = self.name, "Playlist update failed: {err}");
+            }
+        }
+        if self.state.shell_done() {
+            let _busy = self.state.health.busy();
+            let before = self.state.refresh_count();
+            let shown = self.state.show_shell_output(&mut epd, &self.cli);
+            self.state.refreshed_since(before);
+            if let Err(err) = shown {
+                error!(panel = self.name, "Could not show the menu command's output: {err}");
+            }
+        }
+        if let Err(err) = self.state.show_deferred(&mut epd, &self.cli) {
+            error!(panel = self.name, "Coalesced update failed: {err}");
+        }
+    }
+}
+
+/// `path` with a panel name before its extension: `last-frame.epf` becomes
+/// `last-frame.<name>.epf`.
+fn named_path(path: &Path, name: &str) -> PathBuf {
+    match path.extension() {
+        Some(extension) => path.with_extension(format!("{name}.{}", extension.to_string_lossy())),
+        None => path.with_extension(name),
+    }
+}
+
+/// What `--stale-after` does with content nobody updates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StaleAction {
+    Clear,
+    Banner,
+}
+
+fn parse_stale_action(input: &str) -> Result<StaleAction, String> {
+    match input.to_ascii_lowercase().as_str() {
+        "clear" => Ok(StaleAction::Clear),
+        "banner" => Ok(StaleAction::Banner),
+        _ => Err(format!("expected `clear` or `banner`, got '{input}'")),
+    }
+}
+
+/// `--stale-after`: clear the panel or mark its content as stale once no update has come in
+/// for a while. The action is taken once; the next update starts the wait again.
+struct StaleWatch {
+    after: Option<Duration>,
+    action: StaleAction,
+    last_update: Instant,
+    /// When the content was last updated, for the banner.
+    updated_at: DateTime<Local>,
+    stale: bool,
+}
+
+impl StaleWatch {
+    fn new(after: Option<Duration>, action: StaleAction) -> Self {
+        Self {
+            after,
+            action,
+            last_update: Instant::now(),
+            updated_at: Local::now(),
+            stale: false,
+        }
+    }
+
+    /// Note that a client just updated the content.
+    fn touch(&mut self) {
+        self.last_update = Instant::now();
+        self.updated_at = Local::now();
+        self.stale = false;
+    }
+
+    /// Whether the content has gone stale and the action has not been taken yet.
+    fn due(&self) -> bool {
+        self.after
+            .is_some_and(|after| !self.stale && self.last_update.elapsed() >= after)
+    }
+
+    /// Take `--stale-action`; the panel must be awake. A banner is drawn along the bottom
+    /// of the last frame, in `layout`'s colors swapped.
+    fn act(
+        &mut self,
+        epd: &mut dyn Panel,
+        cli: &Cli,
+        layout: &TextLayout,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        // Whatever happens, try only once per quiet spell.
+        self.stale = true;
+        let since = self.updated_at.format("%H:%M");
+        match self.action {
+            StaleAction::Clear => {
+                info!("No update since {since}; clearing the panel");
+                clear_panel(epd, cli, layout.background)
+            }
+            StaleAction::Banner => {
+                let Some(mut fb) = last_canvas(cli) else {
+                    return Ok(());
+                };
+                info!("No update since {since}; marking the content as stale");
+                let banner = TextLayout {
+                    font: FontChoice::Font6x10,
+                    alignment: Alignment {
+                        horizontal: HAlign::Center,
+                        vertical: VAlign::Middle,
+                    },
+                    foreground: layout.background,
+                    background: layout.foreground,
+                    auto_fit: false,
+                    markup: false,
+                    ..bare_layout(layout)
+                };
+                let size = Size::new(fb.size().width, FontChoice::Font6x10.char_size().height + 4);
+                let patch = banner.render(&format!("STALE since {since}"), size);
+                let origin = Point::new(0, (fb.size().height - size.height) as i32);
+                blit(&mut fb, &patch, origin);
+                present(epd, &fb, false, cli)
+            }
+        }
+    }
+}
+
+/// Which corner `CLOCK` keeps the time in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Corner {
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "tl" => Some(Corner::TopLeft),
+            "tr" => Some(Corner::TopRight),
+            "bl" => Some(Corner::BottomLeft),
+            "br" => Some(Corner::BottomRight),
+            _ => None,
+        }
+    }
+}
+
+/// The corner clock of `CLOCK ON`.
+#[derive(Debug, Clone, PartialEq)]
+struct Clock {
+    corner: Corner,
+    /// A `strftime` format, e.g. `%H:%M`.
+    format: String,
+    layout: TextLayout,
+}
+
+/// Longest `CLOCK` format.
+const MAX_CLOCK_FORMAT: usize = 64;
+
+impl Clock {
+    /// Parse the options of `CLOCK ON`: `pos=tl|tr|bl|br`, `font=<name>` and
+    /// `format=<strftime>`, which takes the rest of the line. Colors come from `layout`.
+    fn parse(args: &str, layout: &TextLayout) -> Option<Self> {
+        let mut clock = Clock {
+            corner: Corner::TopRight,
+            format: "%H:%M".to_string(),
+            layout: TextLayout {
+                font: FontChoice::Font6x10,
+                alignment: Alignment {
+                    horizontal: HAlign::Center,
+                    vertical: VAlign::Middle,
+                },
+                auto_fit: false,
+                markup: false,
+                ..bare_layout(layout)
+            },
+        };
+        let mut rest = args.trim_start();
+        while !rest.is_empty() {
+            if let Some(format) = rest.strip_prefix("format=") {
+                clock.format = format.trim_end().to_string();
+                break;
+            }
+            let (word, tail) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            rest = tail.trim_start();
+            match word.split_once('=')? {
+                ("pos", corner) => clock.corner = Corner::from_name(corner)?,
+                ("font", font) => clock.layout.font = font.parse().ok()?,
+                _ => return None,
+            }
+        }
+        let valid = !clock.format.is_empty()
+            && clock.format.len() <= MAX_CLOCK_FORMAT
+            && !StrftimeItems::new(&clock.format).any(|item| matches!(item, Item::Error));
+        valid.then_some(clock)
+    }
+
+    fn text(&self) -> String {
+        Local::now().format(&self.format).to_string()
+    }
+
+    /// The box `text` takes on a canvas of `canvas`, a pixel of padding included.
+    fn area(&self, text: &str, canvas: Size) -> Rectangle {
+        let cell = self.layout.font.char_size();
+        let size = Size::new(
+            (display_width(text) as u32 * cell.width + 4).min(canvas.width),
+            (cell.height + 2).min(canvas.height),
+        );
+        let x = match self.corner {
+            Corner::TopLeft | Corner::BottomLeft => 0,
+            Corner::TopRight | Corner::BottomRight => canvas.width - size.width,
+        };
+        let y = match self.corner {
+            Corner::TopLeft | Corner::TopRight => 0,
+            Corner::BottomLeft | Corner::BottomRight => canvas.height - size.height,
+        };
+        Rectangle::new(Point::new(x as i32, y as i32), size)
+    }
+
+    /// A panel frame with `text` drawn over it in the clock's corner.
+    fn draw_over(&self, text: &str, image: &[u8], rotate: Rotation) -> Vec<u8> {
+        let panel = panel_area(Rotation::Deg0).size;
+        let Ok(frame) = MonoImage::from_data(panel.width, panel.height, image) else {
+            return image.to_vec();
+        };
+        let mut canvas = frame.rotated(rotate.inverse());
+        let area = self.area(text, canvas.size());
+        blit(
+            &mut canvas,
+            &self.layout.render(text, area.size),
+            area.top_left,
+        );
+        canvas.rotated(rotate).data().to_vec()
+    }
+}
+
+/// The clock a panel should show and the one it shows, shared by the display loop, which
+/// keeps them in step, and the [`ClockPanel`] that draws it.
+#[derive(Default)]
+struct ClockOverlay {
+    clock: Option<Clock>,
+    /// The clock drawn on the panel and the time it says.
+    shown: Option<(Clock, String)>,
+}
+
+impl ClockOverlay {
+    /// The clock to draw now, with the time.
+    fn current(&self) -> Option<(Clock, String)> {
+        self.clock
+            .as_ref()
+            .map(|clock| (clock.clone(), clock.text()))
+    }
+
+    /// Whether the panel shows another time than it should, or a clock switched off.
+    fn due(&self) -> bool {
+        self.shown != self.current()
+    }
+}
+
+/// A panel that draws the `CLOCK` over every frame it shows. Frames are stored without it,
+/// so commands that build on the last frame never see the clock.
+struct ClockPanel<'a> {
+    panel: &'a mut dyn Panel,
+    overlay: Rc<RefCell<ClockOverlay>>,
+    rotate: Rotation,
+}
+
+impl<'a> ClockPanel<'a> {
+    fn new(panel: &'a mut dyn Panel, overlay: Rc<RefCell<ClockOverlay>>, rotate: Rotation) -> Self {
+        Self {
+            panel,
+            overlay,
+            rotate,
+        }
+    }
+
+    fn draw(&self, clock: Option<&(Clock, String)>, image: &[u8]) -> Vec<u8> {
+        match clock {
+            Some((clock, text)) => clock.draw_over(text, image, self.rotate),
+            None => image.to_vec(),
+        }
+    }
+
+    /// Show `image` with the current clock through `show`, and note the clock as shown.
+    fn show<T>(
+        &mut self,
+        image: &[u8],
+        show: impl FnOnce(&mut dyn Panel, &[u8]) -> Result<T, EpdError>,
+    ) -> Result<T, EpdError> {
+        let current = self.overlay.borrow().current();
+        let image = self.draw(current.as_ref(), image);
+        let result = show(self.panel, &image)?;
+        self.overlay.borrow_mut().shown = current;
+        Ok(result)
+    }
+
+    /// Where the clocks in `clocks` are, in panel coordinates.
+    fn clock_window(&self, clocks: &[Option<&(Clock, String)>]) -> Option<Rectangle> {
+        let canvas = panel_area(self.rotate).size;
+        clocks
+            .iter()
+            .flatten()
+            .map(|(clock, text)| panel_window(clock.area(text, canvas), self.rotate))
+            .reduce(enclosing)
+    }
+}
+
+/// The smallest rectangle holding both `a` and `b`.
+fn enclosing(a: Rectangle, b: Rectangle) -> Rectangle {
+    match (a.bottom_right(), b.bottom_right()) {
+        (Some(a_end), Some(b_end)) => Rectangle::with_corners(
+            a.top_left.component_min(b.top_left),
+            a_end.component_max(b_end),
+        ),
+        (Some(_), None) => a,
+        _ => b,
+    }
+}
+
+impl Panel for ClockPanel<'_> {
+    fn init(&mut self) -> Result<(), EpdError> {
+        self.panel.init()
+    }
+
+    fn init_fast(&mut self) -> Result<(), EpdError> {
+        self.panel.init_fast()
+    }
+
+    fn clear(&mut self, color: BinaryColor) -> Result<(), EpdError> {
+        self.panel.clear(color)?;
+        self.overlay.borrow_mut().shown = None;
+        Ok(())
+    }
+
+    fn display_base(&mut self, image: &[u8]) -> Result<(), EpdError> {
+        self.show(image, |panel, image| panel.display_base(image))
+    }
+
+    fn display_timed(&mut self, image: &[u8], mode: UpdateMode) -> Result<RefreshTiming, EpdError> {
+        self.show(image, |panel, image| panel.display_timed(image, mode))
+    }
+
+    fn display_partial_over(
+        &mut self,
+        previous: &[u8],
+        image: &[u8],
+    ) -> Result<RefreshTiming, EpdError> {
+        let previous = self.draw(self.overlay.borrow().shown.as_ref(), previous);
+        self.show(image, |panel, image| {
+            panel.display_partial_over(&previous, image)
+        })
+    }
+
+    fn display_partial_window(
+        &mut self,
+        image: &[u8],
+        window: Rectangle,
+    ) -> Result<RefreshTiming, EpdError> {
+        // The clock may have changed too, even outside the window.
+        let clocks = {
+            let overlay = self.overlay.borrow();
+            self.clock_window(&[overlay.shown.as_ref(), overlay.current().as_ref()])
+        };
+        let window = clocks.map_or(window, |clocks| enclosing(window, clocks));
+        self.show(image, |panel, image| {
+            panel.display_partial_window(image, window)
+        })
+    }
+
+    fn sleep(&mut self) -> Result<(), EpdError> {
+        self.panel.sleep()
+    }
+
+    fn is_busy(&self) -> bool {
+        self.panel.is_busy()
+    }
+}
+
+/// Bring the `CLOCK` on the panel up to date with a partial refresh over the last frame,
+/// if the time changed or the clock was switched on or off.
+fn update_clock(
+    epd: &mut dyn Panel,
+    cli: &Cli,
+    bg: BinaryColor,
+    overlay: &RefCell<ClockOverlay>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !overlay.borrow().due() {
+        return Ok(());
+    }
+    let frame = last_canvas(cli)
+        .unwrap_or_else(|| blank_framebuffer(panel_area(cli.rotate).size, bg))
+        .rotated(cli.rotate);
+    epd.display_partial_over(frame.data(), frame.data())?;
+    Ok(())
+}
+
+fn set_socket_permissions(path: &Path, mode: Option<u32>, group: Option<u32>) -> io::Result<()> {
+    if let Some(gid) = group {
+        std::os::unix::fs::chown(path, None, Some(gid))?;
+    }
+    if let Some(mode) = mode {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    }
+    Ok(())
+}
+
+fn parse_socket_mode(input: &str) -> Result<u32, String> {
+    match u32::from_str_radix(input.trim_start_matches("0o"), 8) {
+        Ok(mode) if mode <= 0o777 => Ok(mode),
+        _ => Err(format!(
+            "invalid mode '{input}' (expected octal such as 0660)"
+        )),
+    }
+}
+
+/// A group id given as a number or looked up by name.
+fn parse_group(input: &str) -> Result<u32, String> {
+    if let Ok(gid) = input.parse() {
+        return Ok(gid);
+    }
+    let name = CString::new(input).map_err(|_| format!("invalid group name '{input}'"))?;
+    let mut group: libc::group = unsafe { std::mem::zeroed() };
+    let mut found = std::ptr::null_mut();
+    let mut buf = vec![0 as libc::c_char; 16 * 1024];
+    // SAFETY: all pointers are valid for the call and `buf.len()` is the buffer's size;
+    // `found` is either null or points to `group`.
+    let status = unsafe {
+        libc::getgrnam_r(
+            name.as_ptr(),
+            &mut group,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut found,
+        )
+    };
+    if status != 0 || found.is_null() {
+        return Err(format!("unknown group '{input}'"));
+    }
+    Ok(group.gr_gid)
+}
+
+/// The listening socket passed by systemd socket activation (`LISTEN_FDS`), if any.
+fn activated_listener() -> io::Result<Option<UnixListener>> {
+    let Some(fd) = sd_notify::listen_fds()?.next() else {
+        return Ok(None);
+    };
+    // SAFETY: the descriptors in `LISTEN_FDS` are open and handed to this process for its
+    // own use; `listen_fds` unsets the variables, so nothing else takes ownership of it.
+    Ok(Some(unsafe { UnixListener::from_raw_fd(fd) }))
+}
+
+/// Tell systemd about a state change; without `NOTIFY_SOCKET` this does nothing.
+fn notify_systemd(state: &[NotifyState]) {
+    if let Err(err) = sd_notify::notify(false, state) {
+        warn!("Could not notify systemd: {err}");
+    }
+}
+
+/// Keep-alives for a unit with `WatchdogSec=`, sent from the loop that drives the panel so a
+/// hung refresh gets the service restarted.
+struct Watchdog {
+    /// Half the watchdog timeout, or `None` when systemd does not expect keep-alives.
+    interval: Option<Duration>,
+    last: Instant,
+}
+
+impl Watchdog {
+    fn new() -> Self {
+        let mut usec = 0;
+        let enabled = sd_notify::watchdog_enabled(false, &mut usec);
+        Self {
+            interval: enabled.then(|| Duration::from_micros(usec) / 2),
+            last: Instant::now(),
+        }
+    }
+
+    /// Send `WATCHDOG=1` if half the timeout has passed since the last one.
+    fn tick(&mut self) {
+        if self
+            .interval
+            .is_some_and(|interval| self.last.elapsed() >= interval)
+        {
+            notify_systemd(&[NotifyState::Watchdog]);
+            self.last = Instant::now();
+        }
+    }
 }
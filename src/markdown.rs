@@ -0,0 +1,151 @@
+//! A small Markdown subset for notes and reminders: `#` headings, `-`/`*` bullet lists,
+//! `---` horizontal rules and plain paragraphs. Inline `*bold*`/`_underline_`/`~inverse~`
+//! spans work when the layout has `markup` enabled.
+
+use embedded_graphics::{
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::{Line, PrimitiveStyle, Rectangle},
+};
+
+use crate::markup::BOLD_MARKER;
+use crate::text::{Alignment, FontChoice, TextLayout};
+
+/// Vertical space between blocks.
+const BLOCK_GAP: u32 = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Block {
+    Heading { level: u8, text: String },
+    Bullet(String),
+    Rule,
+    Paragraph(String),
+}
+
+/// Split `source` into blocks. Consecutive plain lines are joined into one paragraph and
+/// blank lines end it.
+pub fn parse_blocks(source: &str) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut paragraph: Vec<&str> = Vec::new();
+    let flush = |paragraph: &mut Vec<&str>, blocks: &mut Vec<Block>| {
+        if !paragraph.is_empty() {
+            blocks.push(Block::Paragraph(paragraph.join(" ")));
+            paragraph.clear();
+        }
+    };
+
+    for line in source.lines().map(str::trim) {
+        let hashes = line.chars().take_while(|&c| c == '#').count();
+        let block = if line.is_empty() {
+            None
+        } else if (1..=6).contains(&hashes) && line[hashes..].starts_with(' ') {
+            Some(Block::Heading {
+                level: hashes as u8,
+                text: line[hashes..].trim().to_string(),
+            })
+        } else if is_rule(line) {
+            Some(Block::Rule)
+        } else if let Some(item) = line.strip_prefix("- ").or_else(|| line.strip_prefix("* ")) {
+            Some(Block::Bullet(item.trim().to_string()))
+        } else {
+            paragraph.push(line);
+            continue;
+        };
+        flush(&mut paragraph, &mut blocks);
+        blocks.extend(block);
+    }
+    flush(&mut paragraph, &mut blocks);
+    blocks
+}
+
+/// `---`, `***` or `___`, optionally spaced out.
+fn is_rule(line: &str) -> bool {
+    let marks: Vec<char> = line.chars().filter(|c| !c.is_whitespace()).collect();
+    marks.len() >= 3 && matches!(marks[0], '-' | '*' | '_') && marks.iter().all(|&c| c == marks[0])
+}
+
+/// Draw `source` top-down inside `area`; content past the bottom edge is dropped. Body text
+/// uses `layout`'s font; headings use larger bold fonts.
+pub fn draw_markdown<D>(target: &mut D, area: Rectangle, source: &str, layout: &TextLayout)
+where
+    D: DrawTarget<Color = BinaryColor>,
+{
+    let body = TextLayout {
+        alignment: Alignment::default(),
+        auto_fit: false,
+        ..*layout
+    };
+    let bottom = area.top_left.y + area.size.height as i32;
+    let mut y = area.top_left.y;
+
+    for block in parse_blocks(source) {
+        if y >= bottom {
+            break;
+        }
+        let remaining = (bottom - y) as u32;
+        match block {
+            Block::Rule => {
+                let mid = y + BLOCK_GAP as i32 / 2;
+                let right = area.top_left.x + area.size.width as i32 - 1;
+                Line::new(Point::new(area.top_left.x, mid), Point::new(right, mid))
+                    .into_styled(PrimitiveStyle::with_stroke(layout.foreground, 1))
+                    .draw(target)
+                    .ok();
+                y += BLOCK_GAP as i32 + 1;
+            }
+            Block::Heading { level, text } => {
+                let heading = TextLayout {
+                    font: heading_font(level, layout.font),
+                    ..body
+                };
+                let text = format!("{BOLD_MARKER}{text}{BOLD_MARKER}");
+                let height = heading.measure(&text, area.size.width).min(remaining);
+                let region = Rectangle::new(
+                    Point::new(area.top_left.x, y),
+                    Size::new(area.size.width, height),
+                );
+                heading.draw(target, region, &text);
+                y += (height + BLOCK_GAP) as i32;
+            }
+            Block::Bullet(text) => {
+                let cell = body.font.char_size();
+                let indent = cell.width * 2;
+                let width = area.size.width.saturating_sub(indent);
+                let height = body.measure(&text, width).min(remaining);
+                let dot = Size::new(3, 3);
+                let dot_top = y + (cell.height as i32 - dot.height as i32) / 2;
+                Rectangle::new(Point::new(area.top_left.x + 2, dot_top), dot)
+                    .into_styled(PrimitiveStyle::with_fill(layout.foreground))
+                    .draw(target)
+                    .ok();
+                let region = Rectangle::new(
+                    Point::new(area.top_left.x + indent as i32, y),
+                    Size::new(width, height),
+                );
+                body.draw(target, region, &text);
+                y += (height + BLOCK_GAP / 2) as i32;
+            }
+            Block::Paragraph(text) => {
+                let height = body.measure(&text, area.size.width).min(remaining);
+                let region = Rectangle::new(
+                    Point::new(area.top_left.x, y),
+                    Size::new(area.size.width, height),
+                );
+                body.draw(target, region, &text);
+                y += (height + BLOCK_GAP) as i32;
+            }
+        }
+    }
+}
+
+/// `#` uses the largest font, `##` the next one, deeper levels stay at the body size.
+fn heading_font(level: u8, body: FontChoice) -> FontChoice {
+    if body.is_cjk() {
+        return body;
+    }
+    match level {
+        1 => FontChoice::Font10x20,
+        2 => FontChoice::Font9x15,
+        _ => body,
+    }
+}
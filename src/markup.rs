@@ -0,0 +1,98 @@
+//! Tiny inline markup for highlighting parts of a message: `*bold*`, `_underline_` and
+//! `~inverse~`.
+//!
+//! [`parse_markup`] turns the delimiters into private-use marker characters that toggle a
+//! style. Markers have no width, survive transliteration and wrapping, and are consumed by
+//! the text renderer, so a highlighted span can even continue across a line break.
+
+/// Toggles bold (drawn by double-striking glyphs one pixel apart).
+pub const BOLD_MARKER: char = '\u{E000}';
+/// Toggles a 1px underline below the glyphs.
+pub const UNDERLINE_MARKER: char = '\u{E001}';
+/// Toggles inverse video (light text on a filled background).
+pub const INVERSE_MARKER: char = '\u{E002}';
+
+/// Active styles while drawing a run of text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SpanStyle {
+    pub bold: bool,
+    pub underline: bool,
+    pub inverse: bool,
+}
+
+impl SpanStyle {
+    /// Apply a marker character; returns `false` if `ch` is not a marker.
+    pub fn toggle(&mut self, ch: char) -> bool {
+        match ch {
+            BOLD_MARKER => self.bold = !self.bold,
+            UNDERLINE_MARKER => self.underline = !self.underline,
+            INVERSE_MARKER => self.inverse = !self.inverse,
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// Whether `ch` is one of the style markers produced by [`parse_markup`].
+pub fn is_marker(ch: char) -> bool {
+    matches!(ch, BOLD_MARKER | UNDERLINE_MARKER | INVERSE_MARKER)
+}
+
+fn marker_for(delimiter: char) -> Option<char> {
+    match delimiter {
+        '*' => Some(BOLD_MARKER),
+        '_' => Some(UNDERLINE_MARKER),
+        '~' => Some(INVERSE_MARKER),
+        _ => None,
+    }
+}
+
+/// Replace markup delimiters with style markers. A delimiter opens a span when it starts a
+/// word (`*key*`, not `2*3*4`) and a matching delimiter closes it at the end of a word;
+/// anything unmatched is kept literally. A backslash escapes the next delimiter.
+pub fn parse_markup(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+    let mut open = [false; 3];
+
+    let is_boundary = |idx: Option<usize>| match idx.and_then(|i| chars.get(i)) {
+        None => true,
+        Some(ch) => ch.is_whitespace() || ch.is_ascii_punctuation(),
+    };
+    let is_text = |idx: usize| chars.get(idx).is_some_and(|ch| !ch.is_whitespace());
+
+    let mut i = 0;
+    while i < chars.len() {
+        let ch = chars[i];
+        if ch == '\\' && chars.get(i + 1).and_then(|&c| marker_for(c)).is_some() {
+            out.push(chars[i + 1]);
+            i += 2;
+            continue;
+        }
+
+        let Some(marker) = marker_for(ch) else {
+            out.push(ch);
+            i += 1;
+            continue;
+        };
+        let slot = marker as usize - BOLD_MARKER as usize;
+        let prev = i.checked_sub(1);
+
+        let can_close = open[slot] && prev.is_some_and(is_text) && is_boundary(Some(i + 1));
+        let can_open = !open[slot]
+            && is_boundary(prev)
+            && is_text(i + 1)
+            && (i + 2..chars.len()).any(|j| {
+                chars[j] == ch && chars[j - 1] != '\\' && is_text(j - 1) && is_boundary(Some(j + 1))
+            });
+
+        if can_close || can_open {
+            open[slot] = !open[slot];
+            out.push(marker);
+        } else {
+            out.push(ch);
+        }
+        i += 1;
+    }
+    out
+}
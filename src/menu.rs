@@ -0,0 +1,319 @@
+//! A paged menu for running the panel as a standalone appliance with a few buttons. Entries
+//! open submenus, run protocol commands or run shell commands, optionally after a yes/no
+//! confirmation.
+//!
+//! ```json
+//! {
+//!   "title": "Office",
+//!   "items": [
+//!     { "label": "Weather", "command": "TEMPLATE SHOW weather" },
+//!     { "label": "System", "items": [
+//!       { "label": "Uptime", "run": "uptime -p" },
+//!       { "label": "Reboot", "run": "sudo reboot", "confirm": true }
+//!     ] }
+//!   ]
+//! }
+//! ```
+//!
+//! The entries of the open level are listed as many to a page as fit under a title bar,
+//! and moving the selection past the end of a page scrolls to the next one.
+
+use std::path::Path;
+
+use embedded_graphics::{
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
+};
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{
+    buffer::MonoImage,
+    text::{fit_columns, FontChoice},
+};
+
+#[derive(Debug, Error)]
+pub enum MenuError {
+    #[error("invalid JSON menu: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("invalid TOML menu: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("entry '{label}': {message}")]
+    Entry { label: String, message: String },
+}
+
+/// A menu file: the title of the top level and its entries.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Menu {
+    #[serde(default = "default_title")]
+    pub title: String,
+    pub items: Vec<MenuItem>,
+}
+
+fn default_title() -> String {
+    "Menu".to_string()
+}
+
+/// One entry: exactly one of `command`, `run` or `items`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MenuItem {
+    pub label: String,
+    /// A protocol command, such as `TEMPLATE SHOW weather`.
+    #[serde(default)]
+    pub command: Option<String>,
+    /// A shell command, run through `sh -c`; its output is shown.
+    #[serde(default)]
+    pub run: Option<String>,
+    /// Ask before running `command` or `run`.
+    #[serde(default)]
+    pub confirm: bool,
+    /// The entries of a submenu, titled with the label.
+    #[serde(default)]
+    pub items: Vec<MenuItem>,
+}
+
+/// What selecting an entry asks for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MenuAction {
+    Command(String),
+    Shell(String),
+}
+
+impl Menu {
+    pub fn from_json(source: &str) -> Result<Self, MenuError> {
+        let menu: Self = serde_json::from_str(source)?;
+        menu.check()?;
+        Ok(menu)
+    }
+
+    pub fn from_toml(source: &str) -> Result<Self, MenuError> {
+        let menu: Self = toml::from_str(source)?;
+        menu.check()?;
+        Ok(menu)
+    }
+
+    /// Parse TOML if `path` ends in `.toml`, JSON otherwise.
+    pub fn parse_for_path(source: &str, path: &Path) -> Result<Self, MenuError> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("toml") => Self::from_toml(source),
+            _ => Self::from_json(source),
+        }
+    }
+
+    fn check(&self) -> Result<(), MenuError> {
+        if self.items.is_empty() {
+            return Err(MenuError::Entry {
+                label: self.title.clone(),
+                message: "a menu needs entries".to_string(),
+            });
+        }
+        self.items.iter().try_for_each(MenuItem::check)
+    }
+}
+
+impl MenuItem {
+    fn check(&self) -> Result<(), MenuError> {
+        let invalid = |message: &str| MenuError::Entry {
+            label: self.label.clone(),
+            message: message.to_string(),
+        };
+        let actions = [
+            self.command.is_some(),
+            self.run.is_some(),
+            !self.items.is_empty(),
+        ];
+        if actions.iter().filter(|&&set| set).count() != 1 {
+            return Err(invalid("needs exactly one of command, run or items"));
+        }
+        if self.confirm && !self.items.is_empty() {
+            return Err(invalid("only commands can ask for confirmation"));
+        }
+        if self.command.as_deref().is_some_and(|c| c.contains('\n')) {
+            return Err(invalid("a command must fit on one line"));
+        }
+        self.items.iter().try_for_each(MenuItem::check)
+    }
+
+    fn action(&self) -> Option<MenuAction> {
+        match (&self.command, &self.run) {
+            (Some(command), _) => Some(MenuAction::Command(command.clone())),
+            (None, Some(run)) => Some(MenuAction::Shell(run.clone())),
+            (None, None) => None,
+        }
+    }
+}
+
+/// Where a user is in a [`Menu`]: the submenus opened on the way, the selected entry, and
+/// whether a confirmation is being asked for.
+#[derive(Debug, Clone, Default)]
+pub struct MenuState {
+    path: Vec<usize>,
+    selected: usize,
+    /// Asking whether to run the selected entry, with "Yes" selected if `true`.
+    confirming: Option<bool>,
+}
+
+impl MenuState {
+    /// The top level, with its first entry selected.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The title and entries of the open level.
+    fn level<'a>(&self, menu: &'a Menu) -> (&'a str, &'a [MenuItem]) {
+        let (mut title, mut items) = (menu.title.as_str(), menu.items.as_slice());
+        for &index in &self.path {
+            title = &items[index].label;
+            items = &items[index].items;
+        }
+        (title, items)
+    }
+
+    /// Select the entry above, wrapping around to the last.
+    pub fn up(&mut self, menu: &Menu) {
+        if let Some(yes) = self.confirming.as_mut() {
+            *yes = !*yes;
+            return;
+        }
+        let count = self.level(menu).1.len();
+        self.selected = (self.selected + count - 1) % count;
+    }
+
+    /// Select the entry below, wrapping around to the first.
+    pub fn down(&mut self, menu: &Menu) {
+        if let Some(yes) = self.confirming.as_mut() {
+            *yes = !*yes;
+            return;
+        }
+        let count = self.level(menu).1.len();
+        self.selected = (self.selected + 1) % count;
+    }
+
+    /// Leave the confirmation or the submenu, with the entry it was opened from selected.
+    /// Returns `false` at the top level, which has nothing to go back to.
+    pub fn back(&mut self) -> bool {
+        if self.confirming.take().is_some() {
+            return true;
+        }
+        match self.path.pop() {
+            Some(index) => {
+                self.selected = index;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Open the selected submenu, ask for confirmation, or return the entry's action.
+    pub fn select(&mut self, menu: &Menu) -> Option<MenuAction> {
+        let item = &self.level(menu).1[self.selected];
+        match self.confirming.take() {
+            Some(true) => item.action(),
+            Some(false) => None,
+            None if !item.items.is_empty() => {
+                self.path.push(self.selected);
+                self.selected = 0;
+                None
+            }
+            None if item.confirm => {
+                self.confirming = Some(false);
+                None
+            }
+            None => item.action(),
+        }
+    }
+
+    /// Draw the open level, or the confirmation, onto a canvas of `size`: a title bar, then
+    /// the page of entries with the selected one, in inverse video. Submenus end in `>`.
+    pub fn render(
+        &self,
+        menu: &Menu,
+        font: FontChoice,
+        foreground: BinaryColor,
+        background: BinaryColor,
+        size: Size,
+    ) -> MonoImage {
+        let (title, items) = self.level(menu);
+        let cell = font.char_size();
+        let columns = (size.width / cell.width).max(1) as usize;
+        let top = cell.height + TITLE_GAP;
+        let rows = (size.height.saturating_sub(top) / cell.height).max(1) as usize;
+        let mut fb = MonoImage::new(size.width, size.height);
+        fb.clear(background);
+
+        let (heading, entries, selected) = match self.confirming {
+            Some(yes) => {
+                let label = &items[self.selected].label;
+                let entries = vec!["No".to_string(), "Yes".to_string()];
+                (format!("{label}?"), entries, usize::from(yes))
+            }
+            None => {
+                let entries = items
+                    .iter()
+                    .map(|item| {
+                        if item.items.is_empty() {
+                            item.label.clone()
+                        } else {
+                            format!("{} >", item.label)
+                        }
+                    })
+                    .collect();
+                (title.to_string(), entries, self.selected)
+            }
+        };
+        let page = selected / rows;
+        let pages = entries.len().div_ceil(rows);
+        let heading = if pages > 1 {
+            let counter = format!(" {}/{pages}", page + 1);
+            let room = columns.saturating_sub(counter.len());
+            format!("{}{counter}", fit_columns(&heading, room, font))
+        } else {
+            heading
+        };
+        row(&mut fb, 0, &heading, true, font, foreground, background);
+        for (index, entry) in entries.iter().enumerate().skip(page * rows).take(rows) {
+            let y = top + (index - page * rows) as u32 * cell.height;
+            let text = fit_columns(entry, columns, font);
+            row(
+                &mut fb,
+                y,
+                &text,
+                index == selected,
+                font,
+                foreground,
+                background,
+            );
+        }
+        fb
+    }
+}
+
+/// Blank pixels between the title bar and the first entry.
+const TITLE_GAP: u32 = 2;
+
+/// One full-width line of text at `y`, inverted if `highlight`.
+fn row(
+    fb: &mut MonoImage,
+    y: u32,
+    text: &str,
+    highlight: bool,
+    font: FontChoice,
+    foreground: BinaryColor,
+    background: BinaryColor,
+) {
+    let height = font.char_size().height;
+    let color = if highlight {
+        Rectangle::new(Point::new(0, y as i32), Size::new(fb.width(), height))
+            .into_styled(PrimitiveStyle::with_fill(foreground))
+            .draw(fb)
+            .ok();
+        background
+    } else {
+        foreground
+    };
+    font.draw_text(fb, text, Point::new(0, y as i32), color)
+        .ok();
+}
@@ -0,0 +1,182 @@
+//! A minimal client for the MPD protocol, enough to show what is playing: the current song,
+//! the player state and position, and `idle` to hear about changes as they happen.
+
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum MpdError {
+    #[error("MPD connection failed: {0}")]
+    Io(#[from] io::Error),
+    #[error("not an MPD server: {0}")]
+    Greeting(String),
+    #[error("MPD refused the command: {0}")]
+    Ack(String),
+    #[error("MPD closed the connection")]
+    Closed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlayState {
+    Playing,
+    Paused,
+    Stopped,
+}
+
+/// What the player is doing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NowPlaying {
+    pub state: PlayState,
+    pub artist: Option<String>,
+    /// The title tag, or for radio streams without one, the stream name or file name.
+    pub title: Option<String>,
+    pub album: Option<String>,
+    pub elapsed: Option<Duration>,
+    /// Length of the song; unknown for streams.
+    pub duration: Option<Duration>,
+}
+
+impl NowPlaying {
+    /// How far into the song the player is, from 0 to 100.
+    pub fn percent(&self) -> Option<f32> {
+        let (elapsed, duration) = (self.elapsed?, self.duration?);
+        if duration.is_zero() {
+            return None;
+        }
+        Some((elapsed.as_secs_f32() / duration.as_secs_f32() * 100.0).clamp(0.0, 100.0))
+    }
+}
+
+/// A connection to an MPD server.
+pub struct MpdClient {
+    stream: TcpStream,
+    reader: BufReader<TcpStream>,
+}
+
+impl MpdClient {
+    /// Connect to `address` (`host:port`) and log in with `password` if given.
+    pub fn connect(address: &str, password: Option<&str>) -> Result<Self, MpdError> {
+        let stream = TcpStream::connect(address)?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut greeting = String::new();
+        reader.read_line(&mut greeting)?;
+        if !greeting.starts_with("OK MPD ") {
+            return Err(MpdError::Greeting(greeting.trim_end().to_string()));
+        }
+        let mut client = Self { stream, reader };
+        if let Some(password) = password {
+            client.command(&format!("password {}", quote(password)))?;
+        }
+        Ok(client)
+    }
+
+    pub fn now_playing(&mut self) -> Result<NowPlaying, MpdError> {
+        let status = self.command("status")?;
+        let song = self.command("currentsong")?;
+        let field = |pairs: &[(String, String)], key: &str| {
+            pairs
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(key))
+                .map(|(_, value)| value.clone())
+        };
+        let seconds = |value: Option<String>| {
+            value
+                .and_then(|value| value.parse::<f64>().ok())
+                .filter(|seconds| seconds.is_finite() && *seconds >= 0.0)
+                .map(Duration::from_secs_f64)
+        };
+        let state = match field(&status, "state").as_deref() {
+            Some("play") => PlayState::Playing,
+            Some("pause") => PlayState::Paused,
+            _ => PlayState::Stopped,
+        };
+        let file_name =
+            field(&song, "file").map(|file| file.rsplit('/').next().unwrap_or(&file).to_string());
+        Ok(NowPlaying {
+            state,
+            artist: field(&song, "Artist").or(field(&song, "AlbumArtist")),
+            title: field(&song, "Title").or(field(&song, "Name")).or(file_name),
+            album: field(&song, "Album"),
+            elapsed: seconds(field(&status, "elapsed")),
+            duration: seconds(field(&status, "duration").or(field(&song, "duration"))),
+        })
+    }
+
+    /// Wait up to `timeout` for the player or the playlist to change; returns whether they
+    /// did.
+    pub fn wait(&mut self, timeout: Duration) -> Result<bool, MpdError> {
+        self.send("idle player playlist")?;
+        self.stream
+            .set_read_timeout(Some(timeout.max(Duration::from_millis(1))))?;
+        let mut line = String::new();
+        let changed = match self.reader.read_line(&mut line) {
+            Ok(0) => return Err(MpdError::Closed),
+            Ok(_) => true,
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                false
+            }
+            Err(err) => return Err(err.into()),
+        };
+        self.stream.set_read_timeout(None)?;
+        if changed {
+            // A `changed: player` line was read; the rest of the reply follows.
+            if line.starts_with("ACK") {
+                return Err(MpdError::Ack(line.trim_end().to_string()));
+            }
+            if line.trim_end() != "OK" {
+                self.response()?;
+            }
+        } else {
+            // `noidle` ends the wait with whatever changed meanwhile, possibly nothing.
+            self.send("noidle")?;
+            self.response()?;
+        }
+        Ok(changed)
+    }
+
+    /// Send `command` and read its `key: value` reply.
+    fn command(&mut self, command: &str) -> Result<Vec<(String, String)>, MpdError> {
+        self.send(command)?;
+        self.response()
+    }
+
+    fn send(&mut self, command: &str) -> Result<(), MpdError> {
+        self.stream.write_all(format!("{command}\n").as_bytes())?;
+        Ok(())
+    }
+
+    fn response(&mut self) -> Result<Vec<(String, String)>, MpdError> {
+        let mut pairs = Vec::new();
+        loop {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Err(MpdError::Closed);
+            }
+            let line = line.trim_end_matches(['\r', '\n']);
+            if line == "OK" {
+                return Ok(pairs);
+            }
+            if line.starts_with("ACK") {
+                return Err(MpdError::Ack(line.to_string()));
+            }
+            if let Some((key, value)) = line.split_once(": ") {
+                pairs.push((key.to_string(), value.to_string()));
+            }
+        }
+    }
+}
+
+/// `value` as a quoted MPD argument.
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
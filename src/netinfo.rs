@@ -0,0 +1,319 @@
+//! Network details for the `network` screen: the addresses of each interface, the Wi-Fi
+//! network and its signal, and a netlink socket that tells when any of it changes.
+
+use std::{
+    collections::BTreeMap,
+    ffi::CStr,
+    fs, io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    path::Path,
+    time::Duration,
+};
+
+use crate::sysinfo;
+
+/// One reading of the host's network state.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NetworkSnapshot {
+    pub hostname: Option<String>,
+    /// Interfaces that are up with at least one address, sorted by name. Loopback is left
+    /// out.
+    pub interfaces: Vec<Interface>,
+    /// The first wireless interface that is connected.
+    pub wifi: Option<Wifi>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Interface {
+    pub name: String,
+    /// IPv4 addresses first, then IPv6 ones; IPv6 link-local addresses are left out.
+    pub addresses: Vec<IpAddr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Wifi {
+    pub interface: String,
+    pub ssid: Option<String>,
+    /// Signal level in dBm.
+    pub signal_dbm: Option<i32>,
+}
+
+impl Wifi {
+    /// The signal level on a 0-100 scale, taking -90 dBm as nothing and -30 dBm as full.
+    pub fn signal_percent(&self) -> Option<u8> {
+        let dbm = self.signal_dbm?;
+        Some(((dbm + 90) * 100 / 60).clamp(0, 100) as u8)
+    }
+}
+
+impl NetworkSnapshot {
+    pub fn collect() -> Self {
+        let interfaces = interfaces().unwrap_or_default();
+        let wifi = interfaces
+            .iter()
+            .filter(|iface| {
+                Path::new("/sys/class/net")
+                    .join(&iface.name)
+                    .join("wireless")
+                    .exists()
+            })
+            .find_map(|iface| wifi(&iface.name));
+        Self {
+            hostname: sysinfo::hostname(),
+            interfaces,
+            wifi,
+        }
+    }
+
+    /// The snapshot as lines of text: the hostname, each interface with its addresses
+    /// indented under it, and the Wi-Fi network with its signal.
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec![self.hostname.clone().unwrap_or_else(|| "?".to_string())];
+        if self.interfaces.is_empty() {
+            lines.push("No network".to_string());
+        }
+        for iface in &self.interfaces {
+            let mut addresses = iface.addresses.iter();
+            if let Some(first) = addresses.next() {
+                lines.push(format!("{} {first}", iface.name));
+            }
+            lines.extend(addresses.map(|address| format!("  {address}")));
+        }
+        if let Some(wifi) = &self.wifi {
+            let mut line = format!("Wi-Fi {}", wifi.ssid.as_deref().unwrap_or("?"));
+            if let (Some(dbm), Some(percent)) = (wifi.signal_dbm, wifi.signal_percent()) {
+                line.push_str(&format!(" {dbm} dBm {percent}%"));
+            }
+            lines.push(line);
+        }
+        lines
+    }
+}
+
+/// Addresses of the interfaces that are up, from `getifaddrs`.
+fn interfaces() -> io::Result<Vec<Interface>> {
+    let mut list: *mut libc::ifaddrs = std::ptr::null_mut();
+    // SAFETY: `list` is a valid out-pointer; the list is freed below.
+    if unsafe { libc::getifaddrs(&mut list) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut by_name: BTreeMap<String, Vec<IpAddr>> = BTreeMap::new();
+    let mut entry = list;
+    while !entry.is_null() {
+        // SAFETY: `entry` is a node of the list returned by `getifaddrs`, whose names are
+        // NUL-terminated and whose addresses match their family.
+        let ifa = unsafe { &*entry };
+        entry = ifa.ifa_next;
+        let flags = ifa.ifa_flags as libc::c_int;
+        if flags & libc::IFF_UP == 0 || flags & libc::IFF_LOOPBACK != 0 || ifa.ifa_addr.is_null() {
+            continue;
+        }
+        let address = match unsafe { (*ifa.ifa_addr).sa_family } as libc::c_int {
+            libc::AF_INET => {
+                let addr = unsafe { &*(ifa.ifa_addr as *const libc::sockaddr_in) };
+                IpAddr::V4(Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr)))
+            }
+            libc::AF_INET6 => {
+                let addr = unsafe { &*(ifa.ifa_addr as *const libc::sockaddr_in6) };
+                let address = Ipv6Addr::from(addr.sin6_addr.s6_addr);
+                if address.segments()[0] & 0xffc0 == 0xfe80 {
+                    continue;
+                }
+                IpAddr::V6(address)
+            }
+            _ => continue,
+        };
+        let name = unsafe { CStr::from_ptr(ifa.ifa_name) }
+            .to_string_lossy()
+            .into_owned();
+        by_name.entry(name).or_default().push(address);
+    }
+    // SAFETY: `list` came from `getifaddrs` and is no longer referenced.
+    unsafe { libc::freeifaddrs(list) };
+    Ok(by_name
+        .into_iter()
+        .map(|(name, mut addresses)| {
+            addresses.sort_by_key(IpAddr::is_ipv6);
+            Interface { name, addresses }
+        })
+        .collect())
+}
+
+/// The network `interface` is associated with and its signal, or `None` if it is not
+/// connected.
+fn wifi(interface: &str) -> Option<Wifi> {
+    let ssid = ssid(interface);
+    let signal_dbm = signal(interface);
+    (ssid.is_some() || signal_dbm.is_some()).then(|| Wifi {
+        interface: interface.to_string(),
+        ssid,
+        signal_dbm,
+    })
+}
+
+/// Signal level from `/proc/net/wireless`, as in `wlan0: 0000   54.  -56.  -256 ...`.
+fn signal(interface: &str) -> Option<i32> {
+    let text = fs::read_to_string("/proc/net/wireless").ok()?;
+    let line = text.lines().find_map(|line| {
+        let (name, rest) = line.split_once(':')?;
+        (name.trim() == interface).then_some(rest)
+    })?;
+    let level: f32 = line
+        .split_whitespace()
+        .nth(2)?
+        .trim_end_matches('.')
+        .parse()
+        .ok()?;
+    Some(level as i32)
+}
+
+/// `SIOCGIWESSID` from `linux/wireless.h`.
+const SIOCGIWESSID: libc::c_ulong = 0x8B1B;
+/// Longest SSID.
+const ESSID_MAX: usize = 32;
+
+/// `struct iwreq` with its `essid` member, padded to the kernel's size.
+#[repr(C)]
+struct IwReq {
+    name: [libc::c_char; libc::IFNAMSIZ],
+    pointer: *mut libc::c_void,
+    length: u16,
+    flags: u16,
+    _padding: [u8; 8],
+}
+
+/// The SSID of `interface`, asked for through the wireless extensions ioctl, which
+/// cfg80211 still answers.
+fn ssid(interface: &str) -> Option<String> {
+    if interface.len() >= libc::IFNAMSIZ {
+        return None;
+    }
+    let mut essid = [0u8; ESSID_MAX + 1];
+    let mut request = IwReq {
+        name: [0; libc::IFNAMSIZ],
+        pointer: essid.as_mut_ptr().cast(),
+        length: essid.len() as u16,
+        flags: 0,
+        _padding: [0; 8],
+    };
+    for (slot, byte) in request.name.iter_mut().zip(interface.bytes()) {
+        *slot = byte as libc::c_char;
+    }
+    // SAFETY: a datagram socket only serves as a handle for the ioctl and is closed by
+    // `OwnedFd`; `request` points at `essid`, which outlives the call.
+    let socket = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+    if socket < 0 {
+        return None;
+    }
+    let socket = unsafe { OwnedFd::from_raw_fd(socket) };
+    if unsafe { libc::ioctl(socket.as_raw_fd(), SIOCGIWESSID as _, &mut request) } != 0 {
+        return None;
+    }
+    let length = (request.length as usize).min(ESSID_MAX);
+    let ssid = String::from_utf8_lossy(&essid[..length])
+        .trim_end_matches('\0')
+        .to_string();
+    (!ssid.is_empty()).then_some(ssid)
+}
+
+/// A netlink socket subscribed to link and address changes.
+pub struct NetlinkWatcher {
+    socket: OwnedFd,
+}
+
+/// How long to keep reading after a change, so that a burst of them, as when an interface
+/// comes up and gets its addresses, counts once.
+const SETTLE: Duration = Duration::from_millis(500);
+
+impl NetlinkWatcher {
+    pub fn open() -> io::Result<Self> {
+        // SAFETY: plain socket calls on a descriptor owned by `OwnedFd`; `address` is a
+        // valid `sockaddr_nl` of the size passed.
+        let socket = unsafe {
+            libc::socket(
+                libc::AF_NETLINK,
+                libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+                libc::NETLINK_ROUTE,
+            )
+        };
+        if socket < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let socket = unsafe { OwnedFd::from_raw_fd(socket) };
+        let mut address: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+        address.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        address.nl_groups =
+            (libc::RTMGRP_LINK | libc::RTMGRP_IPV4_IFADDR | libc::RTMGRP_IPV6_IFADDR) as u32;
+        let bound = unsafe {
+            libc::bind(
+                socket.as_raw_fd(),
+                (&address as *const libc::sockaddr_nl).cast(),
+                std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            )
+        };
+        if bound != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { socket })
+    }
+
+    /// Wait up to `timeout` for a change; returns whether there was one.
+    pub fn wait(&self, timeout: Duration) -> io::Result<bool> {
+        if !self.readable(timeout)? {
+            return Ok(false);
+        }
+        self.drain()?;
+        while self.readable(SETTLE)? {
+            self.drain()?;
+        }
+        Ok(true)
+    }
+
+    fn readable(&self, timeout: Duration) -> io::Result<bool> {
+        let mut poll = libc::pollfd {
+            fd: self.socket.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let millis = timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+        // SAFETY: `poll` is a single valid `pollfd`.
+        match unsafe { libc::poll(&mut poll, 1, millis) } {
+            -1 => {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::Interrupted {
+                    Ok(false)
+                } else {
+                    Err(err)
+                }
+            }
+            ready => Ok(ready > 0),
+        }
+    }
+
+    /// Discard the pending messages; only the fact that something changed matters.
+    fn drain(&self) -> io::Result<()> {
+        let mut buffer = [0u8; 8192];
+        loop {
+            // SAFETY: `buffer` is writable for its whole length.
+            let read = unsafe {
+                libc::recv(
+                    self.socket.as_raw_fd(),
+                    buffer.as_mut_ptr().cast(),
+                    buffer.len(),
+                    libc::MSG_DONTWAIT,
+                )
+            };
+            if read < 0 {
+                let err = io::Error::last_os_error();
+                return match err.kind() {
+                    io::ErrorKind::WouldBlock => Ok(()),
+                    io::ErrorKind::Interrupted => continue,
+                    // Changes were lost while the buffer was full; there was one anyway.
+                    _ if err.raw_os_error() == Some(libc::ENOBUFS) => Ok(()),
+                    _ => Err(err),
+                };
+            }
+        }
+    }
+}
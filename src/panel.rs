@@ -0,0 +1,270 @@
+//! The operations the CLI and server need from a display, so the real panel can be swapped
+//! for a stand-in such as [`crate::preview::PreviewPanel`].
+
+use std::{
+    cell::Cell,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use embedded_graphics::{pixelcolor::BinaryColor, primitives::Rectangle};
+
+use crate::epd2in13_v4::{Epd2in13V4, EpdError, RefreshTiming, UpdateMode};
+
+/// A 122x250 1-bit display fed with frames in the [`crate::MonoImage::data`] layout.
+pub trait Panel {
+    fn init(&mut self) -> Result<(), EpdError>;
+    fn init_fast(&mut self) -> Result<(), EpdError>;
+    fn clear(&mut self, color: BinaryColor) -> Result<(), EpdError>;
+    /// Show `image` and make it the reference for later partial refreshes.
+    fn display_base(&mut self, image: &[u8]) -> Result<(), EpdError>;
+    fn display_timed(&mut self, image: &[u8], mode: UpdateMode) -> Result<RefreshTiming, EpdError>;
+    /// Partial refresh without a prior base in this process; `previous` is what the panel
+    /// currently shows.
+    fn display_partial_over(
+        &mut self,
+        previous: &[u8],
+        image: &[u8],
+    ) -> Result<RefreshTiming, EpdError>;
+    fn sleep(&mut self) -> Result<(), EpdError>;
+    fn is_busy(&self) -> bool;
+
+    fn display(&mut self, image: &[u8]) -> Result<(), EpdError> {
+        self.display_timed(image, UpdateMode::Normal).map(|_| ())
+    }
+
+    fn display_fast(&mut self, image: &[u8]) -> Result<(), EpdError> {
+        self.display_timed(image, UpdateMode::Fast).map(|_| ())
+    }
+
+    fn display_partial(&mut self, image: &[u8]) -> Result<(), EpdError> {
+        self.display_timed(image, UpdateMode::Partial).map(|_| ())
+    }
+
+    /// Partial refresh where only `window` (panel coordinates) changed; a panel may send just
+    /// that part. By default the whole frame is sent.
+    fn display_partial_window(
+        &mut self,
+        image: &[u8],
+        _window: Rectangle,
+    ) -> Result<RefreshTiming, EpdError> {
+        self.display_timed(image, UpdateMode::Partial)
+    }
+}
+
+/// A panel that shows nothing, for composing a frame off-screen through the code that
+/// would otherwise refresh the real one.
+pub struct DiscardPanel;
+
+impl Panel for DiscardPanel {
+    fn init(&mut self) -> Result<(), EpdError> {
+        Ok(())
+    }
+
+    fn init_fast(&mut self) -> Result<(), EpdError> {
+        Ok(())
+    }
+
+    fn clear(&mut self, _color: BinaryColor) -> Result<(), EpdError> {
+        Ok(())
+    }
+
+    fn display_base(&mut self, _image: &[u8]) -> Result<(), EpdError> {
+        Ok(())
+    }
+
+    fn display_timed(
+        &mut self,
+        _image: &[u8],
+        _mode: UpdateMode,
+    ) -> Result<RefreshTiming, EpdError> {
+        Ok(RefreshTiming::default())
+    }
+
+    fn display_partial_over(
+        &mut self,
+        _previous: &[u8],
+        _image: &[u8],
+    ) -> Result<RefreshTiming, EpdError> {
+        Ok(RefreshTiming::default())
+    }
+
+    fn sleep(&mut self) -> Result<(), EpdError> {
+        Ok(())
+    }
+
+    fn is_busy(&self) -> bool {
+        false
+    }
+}
+
+impl Panel for Epd2in13V4 {
+    fn init(&mut self) -> Result<(), EpdError> {
+        Epd2in13V4::init(self)
+    }
+
+    fn init_fast(&mut self) -> Result<(), EpdError> {
+        Epd2in13V4::init_fast(self)
+    }
+
+    fn clear(&mut self, color: BinaryColor) -> Result<(), EpdError> {
+        Epd2in13V4::clear(self, color)
+    }
+
+    fn display_base(&mut self, image: &[u8]) -> Result<(), EpdError> {
+        Epd2in13V4::display_base(self, image)
+    }
+
+    fn display_timed(&mut self, image: &[u8], mode: UpdateMode) -> Result<RefreshTiming, EpdError> {
+        Epd2in13V4::display_timed(self, image, mode)
+    }
+
+    fn display_partial_over(
+        &mut self,
+        previous: &[u8],
+        image: &[u8],
+    ) -> Result<RefreshTiming, EpdError> {
+        Epd2in13V4::display_partial_over(self, previous, image)
+    }
+
+    fn display_partial_window(
+        &mut self,
+        image: &[u8],
+        window: Rectangle,
+    ) -> Result<RefreshTiming, EpdError> {
+        Epd2in13V4::display_partial_window(self, image, window)
+    }
+
+    fn sleep(&mut self) -> Result<(), EpdError> {
+        Epd2in13V4::sleep(self)
+    }
+
+    fn is_busy(&self) -> bool {
+        Epd2in13V4::is_busy(self)
+    }
+}
+
+/// Refreshes seen by a [`CountingPanel`], by kind. Clearing and showing a base count as full
+/// refreshes.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RefreshCounts {
+    pub full: u64,
+    pub fast: u64,
+    pub partial: u64,
+}
+
+/// Something a [`CountingPanel`] saw its panel do.
+#[derive(Debug, Clone, Copy)]
+pub enum PanelEvent {
+    /// A refresh finished, after `took` from the start of the call.
+    Refresh { mode: UpdateMode, took: Duration },
+    /// The panel went into deep sleep.
+    Sleep,
+}
+
+/// A panel that counts the refreshes passing through it into a shared [`RefreshCounts`],
+/// and optionally reports them as [`PanelEvent`]s.
+pub struct CountingPanel<'a> {
+    panel: &'a mut dyn Panel,
+    counts: Rc<Cell<RefreshCounts>>,
+    on_event: Option<Box<dyn FnMut(PanelEvent) + 'a>>,
+}
+
+impl<'a> CountingPanel<'a> {
+    pub fn new(panel: &'a mut dyn Panel, counts: Rc<Cell<RefreshCounts>>) -> Self {
+        Self {
+            panel,
+            counts,
+            on_event: None,
+        }
+    }
+
+    /// Call `on_event` for every refresh and sleep.
+    pub fn with_events(mut self, on_event: impl FnMut(PanelEvent) + 'a) -> Self {
+        self.on_event = Some(Box::new(on_event));
+        self
+    }
+
+    fn count(&mut self, mode: UpdateMode, started: Instant) {
+        let mut counts = self.counts.get();
+        match mode {
+            UpdateMode::Normal => counts.full += 1,
+            UpdateMode::Fast => counts.fast += 1,
+            UpdateMode::Partial => counts.partial += 1,
+        }
+        self.counts.set(counts);
+        self.report(PanelEvent::Refresh {
+            mode,
+            took: started.elapsed(),
+        });
+    }
+
+    fn report(&mut self, event: PanelEvent) {
+        if let Some(on_event) = self.on_event.as_mut() {
+            on_event(event);
+        }
+    }
+}
+
+impl Panel for CountingPanel<'_> {
+    fn init(&mut self) -> Result<(), EpdError> {
+        self.panel.init()
+    }
+
+    fn init_fast(&mut self) -> Result<(), EpdError> {
+        self.panel.init_fast()
+    }
+
+    fn clear(&mut self, color: BinaryColor) -> Result<(), EpdError> {
+        let started = Instant::now();
+        self.panel.clear(color)?;
+        self.count(UpdateMode::Normal, started);
+        Ok(())
+    }
+
+    fn display_base(&mut self, image: &[u8]) -> Result<(), EpdError> {
+        let started = Instant::now();
+        self.panel.display_base(image)?;
+        self.count(UpdateMode::Normal, started);
+        Ok(())
+    }
+
+    fn display_timed(&mut self, image: &[u8], mode: UpdateMode) -> Result<RefreshTiming, EpdError> {
+        let started = Instant::now();
+        let timing = self.panel.display_timed(image, mode)?;
+        self.count(mode, started);
+        Ok(timing)
+    }
+
+    fn display_partial_over(
+        &mut self,
+        previous: &[u8],
+        image: &[u8],
+    ) -> Result<RefreshTiming, EpdError> {
+        let started = Instant::now();
+        let timing = self.panel.display_partial_over(previous, image)?;
+        self.count(UpdateMode::Partial, started);
+        Ok(timing)
+    }
+
+    fn display_partial_window(
+        &mut self,
+        image: &[u8],
+        window: Rectangle,
+    ) -> Result<RefreshTiming, EpdError> {
+        let started = Instant::now();
+        let timing = self.panel.display_partial_window(image, window)?;
+        self.count(UpdateMode::Partial, started);
+        Ok(timing)
+    }
+
+    fn sleep(&mut self) -> Result<(), EpdError> {
+        self.panel.sleep()?;
+        self.report(PanelEvent::Sleep);
+        Ok(())
+    }
+
+    fn is_busy(&self) -> bool {
+        self.panel.is_busy()
+    }
+}
@@ -0,0 +1,50 @@
+//! Diagnostic images for checking wiring and judging ghosting.
+
+use embedded_graphics::pixelcolor::BinaryColor;
+
+use crate::buffer::MonoImage;
+
+/// Full-frame test images, in the order `test-pattern` shows them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestPattern {
+    /// Alternating black and white squares of `cell` pixels.
+    Checkerboard {
+        cell: u32,
+    },
+    /// Vertical black and white bars of `width` pixels.
+    Stripes {
+        width: u32,
+    },
+    Black,
+    White,
+}
+
+impl TestPattern {
+    /// Human-readable name for progress output.
+    pub fn name(&self) -> &'static str {
+        match self {
+            TestPattern::Checkerboard { .. } => "checkerboard",
+            TestPattern::Stripes { .. } => "stripes",
+            TestPattern::Black => "black",
+            TestPattern::White => "white",
+        }
+    }
+
+    /// Fill `image` with this pattern.
+    pub fn draw(&self, image: &mut MonoImage) {
+        for y in 0..image.height() {
+            for x in 0..image.width() {
+                let ink = match *self {
+                    TestPattern::Checkerboard { cell } => {
+                        let cell = cell.max(1);
+                        (x / cell + y / cell) % 2 == 0
+                    }
+                    TestPattern::Stripes { width } => (x / width.max(1)) % 2 == 0,
+                    TestPattern::Black => true,
+                    TestPattern::White => false,
+                };
+                image.set_pixel(x, y, BinaryColor::from(ink));
+            }
+        }
+    }
+}
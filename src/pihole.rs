@@ -0,0 +1,200 @@
+//! Today's statistics from a Pi-hole's API: queries, how many were blocked, and the client
+//! asking the most. Both the REST API of Pi-hole 6 and the `api.php` of Pi-hole 5 are
+//! spoken.
+
+use std::time::Duration;
+
+use serde_json::Value;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum PiholeError {
+    #[error("Pi-hole request failed: {0}")]
+    Http(#[from] Box<ureq::Error>),
+    #[error("Pi-hole replied with invalid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Pi-hole rejected the password")]
+    Auth,
+    #[error("Pi-hole reply lacks '{0}'; is the API version right?")]
+    Missing(&'static str),
+}
+
+/// Which API to use, with its credentials.
+#[derive(Debug, Clone)]
+pub enum PiholeApi {
+    /// Pi-hole 6: `/api/...`, logged in with the web password (none if it has no password).
+    V6 { password: Option<String> },
+    /// Pi-hole 5: `/admin/api.php`, with the API token from the settings page.
+    V5 { token: Option<String> },
+}
+
+/// Today's numbers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PiholeStats {
+    pub queries: u64,
+    pub blocked: u64,
+    pub percent_blocked: f32,
+    /// Name (or address) and query count of the busiest client.
+    pub top_client: Option<(String, u64)>,
+}
+
+impl PiholeStats {
+    /// Label/value pairs ready for a two-column table.
+    pub fn rows(&self) -> Vec<(&'static str, String)> {
+        let mut rows = vec![
+            ("Blocked", group_thousands(self.blocked)),
+            ("Blocked %", format!("{:.1}%", self.percent_blocked)),
+            ("Queries", group_thousands(self.queries)),
+        ];
+        if let Some((client, count)) = &self.top_client {
+            rows.push((
+                "Top client",
+                format!("{client} ({})", group_thousands(*count)),
+            ));
+        }
+        rows
+    }
+}
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A Pi-hole at a base URL such as `http://pi.hole`.
+pub struct PiholeClient {
+    base: String,
+    api: PiholeApi,
+    /// The Pi-hole 6 session, once logged in.
+    sid: Option<String>,
+}
+
+impl PiholeClient {
+    pub fn new(base: &str, api: PiholeApi) -> Self {
+        Self {
+            base: base.trim_end_matches('/').to_string(),
+            api,
+            sid: None,
+        }
+    }
+
+    pub fn stats(&mut self) -> Result<PiholeStats, PiholeError> {
+        match self.api.clone() {
+            PiholeApi::V6 { password } => match self.stats_v6(password.as_deref()) {
+                // The session expired; log in again once.
+                Err(PiholeError::Http(err)) if is_unauthorized(&err) && self.sid.is_some() => {
+                    self.sid = None;
+                    self.stats_v6(password.as_deref())
+                }
+                result => result,
+            },
+            PiholeApi::V5 { token } => self.stats_v5(token.as_deref()),
+        }
+    }
+
+    fn stats_v6(&mut self, password: Option<&str>) -> Result<PiholeStats, PiholeError> {
+        if let (Some(password), None) = (password, &self.sid) {
+            self.sid = Some(self.login(password)?);
+        }
+        let summary = self.get_v6("/api/stats/summary")?;
+        let top = self.get_v6("/api/stats/top_clients?count=1")?;
+        let queries = &summary["queries"];
+        let top_client = top["clients"].get(0).and_then(|client| {
+            let name = client["name"]
+                .as_str()
+                .filter(|name| !name.is_empty())
+                .or(client["ip"].as_str())?;
+            Some((name.to_string(), client["count"].as_u64()?))
+        });
+        Ok(PiholeStats {
+            queries: queries["total"]
+                .as_u64()
+                .ok_or(PiholeError::Missing("queries.total"))?,
+            blocked: queries["blocked"]
+                .as_u64()
+                .ok_or(PiholeError::Missing("queries.blocked"))?,
+            percent_blocked: queries["percent_blocked"]
+                .as_f64()
+                .ok_or(PiholeError::Missing("queries.percent_blocked"))?
+                as f32,
+            top_client,
+        })
+    }
+
+    /// Log in to Pi-hole 6 and return the session id.
+    fn login(&self, password: &str) -> Result<String, PiholeError> {
+        let reply = ureq::post(&format!("{}/api/auth", self.base))
+            .timeout(TIMEOUT)
+            .set("Content-Type", "application/json")
+            .send_string(&serde_json::json!({ "password": password }).to_string());
+        let reply: Value = match reply {
+            Ok(reply) => serde_json::from_reader(reply.into_reader())?,
+            Err(err) if is_unauthorized(&err) => return Err(PiholeError::Auth),
+            Err(err) => return Err(Box::new(err).into()),
+        };
+        let session = &reply["session"];
+        match session["sid"].as_str() {
+            Some(sid) if session["valid"].as_bool() == Some(true) => Ok(sid.to_string()),
+            _ => Err(PiholeError::Auth),
+        }
+    }
+
+    fn get_v6(&self, path: &str) -> Result<Value, PiholeError> {
+        let mut request = ureq::get(&format!("{}{path}", self.base)).timeout(TIMEOUT);
+        if let Some(sid) = &self.sid {
+            request = request.set("X-FTL-SID", sid);
+        }
+        let reply = request.call().map_err(Box::new)?;
+        Ok(serde_json::from_reader(reply.into_reader())?)
+    }
+
+    fn stats_v5(&self, token: Option<&str>) -> Result<PiholeStats, PiholeError> {
+        let get = |query: &str| -> Result<Value, PiholeError> {
+            let mut request =
+                ureq::get(&format!("{}/admin/api.php?{query}", self.base)).timeout(TIMEOUT);
+            if let Some(token) = token {
+                request = request.query("auth", token);
+            }
+            let reply = request.call().map_err(Box::new)?;
+            Ok(serde_json::from_reader(reply.into_reader())?)
+        };
+        let summary = get("summaryRaw")?;
+        // `{"top_sources": {"laptop|192.168.1.20": 523, ...}}`, or `[]` without a token.
+        let sources = get("getQuerySources=1")?;
+        let top_client = sources["top_sources"].as_object().and_then(|sources| {
+            let (client, count) = sources
+                .iter()
+                .filter_map(|(client, count)| Some((client, count.as_u64()?)))
+                .max_by_key(|(_, count)| *count)?;
+            let name = client.split('|').find(|part| !part.is_empty())?;
+            Some((name.to_string(), count))
+        });
+        Ok(PiholeStats {
+            queries: summary["dns_queries_today"]
+                .as_u64()
+                .ok_or(PiholeError::Missing("dns_queries_today"))?,
+            blocked: summary["ads_blocked_today"]
+                .as_u64()
+                .ok_or(PiholeError::Missing("ads_blocked_today"))?,
+            percent_blocked: summary["ads_percentage_today"]
+                .as_f64()
+                .ok_or(PiholeError::Missing("ads_percentage_today"))?
+                as f32,
+            top_client,
+        })
+    }
+}
+
+fn is_unauthorized(err: &ureq::Error) -> bool {
+    matches!(err, ureq::Error::Status(401, _))
+}
+
+/// `1234567` as `1,234,567`.
+fn group_thousands(value: u64) -> String {
+    let digits = value.to_string();
+    let mut grouped = String::new();
+    for (index, digit) in digits.chars().enumerate() {
+        if index > 0 && (digits.len() - index).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
@@ -0,0 +1,125 @@
+//! A [`Panel`] that writes each frame to a PNG file instead of driving SPI/GPIO, for
+//! iterating on layouts away from the Pi.
+
+use embedded_graphics::pixelcolor::BinaryColor;
+use std::{
+    io,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use crate::{
+    buffer::{MonoImage, Rotation},
+    epd2in13_v4::{Epd2in13V4, EpdError, RefreshTiming, UpdateMode},
+    panel::Panel,
+};
+
+pub struct PreviewPanel {
+    path: PathBuf,
+    rotation: Rotation,
+    screen: MonoImage,
+}
+
+impl PreviewPanel {
+    /// Frames sent to the panel are turned back by `rotation` before saving, so the PNG
+    /// reads the way the mounted panel is viewed.
+    pub fn new(path: impl Into<PathBuf>, rotation: Rotation) -> Self {
+        let mut screen = MonoImage::new(Epd2in13V4::WIDTH as u32, Epd2in13V4::HEIGHT as u32);
+        screen.clear(BinaryColor::Off);
+        Self {
+            path: path.into(),
+            rotation,
+            screen,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// What the panel would currently show, in panel orientation.
+    pub fn screen(&self) -> &MonoImage {
+        &self.screen
+    }
+
+    fn show(&mut self, image: &[u8]) -> Result<(), EpdError> {
+        let size = self.screen.data().len();
+        self.screen = MonoImage::from_data(self.screen.width(), self.screen.height(), image)
+            .map_err(|_| EpdError::BufferSize {
+                expected: size,
+                actual: image.len(),
+            })?;
+        self.save()
+    }
+
+    fn save(&self) -> Result<(), EpdError> {
+        let view = self.screen.rotated(self.rotation.inverse());
+        to_gray(&view).save(&self.path).map_err(io::Error::other)?;
+        Ok(())
+    }
+}
+
+fn to_gray(image: &MonoImage) -> image::GrayImage {
+    image::GrayImage::from_fn(image.width(), image.height(), |x, y| {
+        image::Luma([if image.pixel(x, y) == BinaryColor::On {
+            0
+        } else {
+            255
+        }])
+    })
+}
+
+/// Encode `image` as a black-and-white PNG.
+pub fn encode_png(image: &MonoImage) -> Result<Vec<u8>, image::ImageError> {
+    let mut png = Vec::new();
+    to_gray(image).write_to(&mut io::Cursor::new(&mut png), image::ImageFormat::Png)?;
+    Ok(png)
+}
+
+impl Panel for PreviewPanel {
+    fn init(&mut self) -> Result<(), EpdError> {
+        Ok(())
+    }
+
+    fn init_fast(&mut self) -> Result<(), EpdError> {
+        Ok(())
+    }
+
+    fn clear(&mut self, color: BinaryColor) -> Result<(), EpdError> {
+        self.screen.clear(color);
+        self.save()
+    }
+
+    fn display_base(&mut self, image: &[u8]) -> Result<(), EpdError> {
+        self.show(image)
+    }
+
+    fn display_timed(
+        &mut self,
+        image: &[u8],
+        _mode: UpdateMode,
+    ) -> Result<RefreshTiming, EpdError> {
+        let start = Instant::now();
+        self.show(image)?;
+        Ok(RefreshTiming {
+            transfer: start.elapsed(),
+            refresh: Default::default(),
+        })
+    }
+
+    fn display_partial_over(
+        &mut self,
+        _previous: &[u8],
+        image: &[u8],
+    ) -> Result<RefreshTiming, EpdError> {
+        self.display_timed(image, UpdateMode::Partial)
+    }
+
+    fn sleep(&mut self) -> Result<(), EpdError> {
+        Ok(())
+    }
+
+    fn is_busy(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,114 @@
+//! Prices of stocks and cryptocurrencies for `ticker-quotes`, fetched from any JSON endpoint
+//! that takes the symbol in its URL and has the price, and either the day's change or the
+//! previous close, at fixed places in its reply.
+
+use std::time::Duration;
+
+use serde_json::Value;
+use thiserror::Error;
+
+/// Yahoo Finance's chart endpoint, which needs no key and knows both `AAPL` and `BTC-USD`.
+pub const DEFAULT_URL: &str = "https://query1.finance.yahoo.com/v8/finance/chart/{symbol}";
+/// Where the chart endpoint has the last price.
+pub const DEFAULT_PRICE: &str = "/chart/result/0/meta/regularMarketPrice";
+/// Where the chart endpoint has the previous close.
+pub const DEFAULT_PREVIOUS: &str = "/chart/result/0/meta/chartPreviousClose";
+
+#[derive(Debug, Error)]
+pub enum QuoteError {
+    #[error("quote request failed: {0}")]
+    Http(#[from] Box<ureq::Error>),
+    #[error("quote endpoint replied with invalid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("no number at '{0}' in the reply")]
+    Missing(String),
+}
+
+/// Where to fetch quotes and where their numbers are, as JSON pointers.
+#[derive(Debug, Clone)]
+pub struct QuoteSource {
+    /// URL with `{symbol}` standing for the symbol.
+    pub url: String,
+    pub price: String,
+    /// The day's change in percent, if the endpoint has it.
+    pub change: Option<String>,
+    /// The previous close, to work the change out from when `change` is not set.
+    pub previous: Option<String>,
+}
+
+impl Default for QuoteSource {
+    fn default() -> Self {
+        Self {
+            url: DEFAULT_URL.to_string(),
+            price: DEFAULT_PRICE.to_string(),
+            change: None,
+            previous: Some(DEFAULT_PREVIOUS.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quote {
+    pub symbol: String,
+    pub price: f64,
+    /// Change since the previous close, in percent.
+    pub change_percent: Option<f64>,
+}
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+impl QuoteSource {
+    pub fn fetch(&self, symbol: &str) -> Result<Quote, QuoteError> {
+        let url = self.url.replace("{symbol}", &encode(symbol));
+        // Some endpoints, Yahoo's among them, turn away requests without a browser-like agent.
+        let reply = ureq::get(&url)
+            .timeout(TIMEOUT)
+            .set("User-Agent", "Mozilla/5.0 (rpi-einkserver-rs)")
+            .call()
+            .map_err(Box::new)?;
+        let body: Value = serde_json::from_reader(reply.into_reader())?;
+        self.parse(symbol, &body)
+    }
+
+    /// Read a quote for `symbol` out of an endpoint's reply.
+    pub fn parse(&self, symbol: &str, body: &Value) -> Result<Quote, QuoteError> {
+        let price = number_at(body, &self.price)?;
+        let change_percent = match (&self.change, &self.previous) {
+            (Some(change), _) => Some(number_at(body, change)?),
+            (None, Some(previous)) => number_at(body, previous)
+                .ok()
+                .filter(|previous| *previous != 0.0)
+                .map(|previous| (price - previous) / previous * 100.0),
+            (None, None) => None,
+        };
+        Ok(Quote {
+            symbol: symbol.to_string(),
+            price,
+            change_percent,
+        })
+    }
+}
+
+/// A number at `pointer`, also when it is sent as a string, as some APIs do.
+fn number_at(body: &Value, pointer: &str) -> Result<f64, QuoteError> {
+    let value = body.pointer(pointer);
+    value
+        .and_then(|value| match value {
+            Value::String(text) => text.trim().parse().ok(),
+            other => other.as_f64(),
+        })
+        .ok_or_else(|| QuoteError::Missing(pointer.to_string()))
+}
+
+/// `symbol` percent-encoded for a URL, with letters, digits and `-._~` left as they are.
+fn encode(symbol: &str) -> String {
+    symbol
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
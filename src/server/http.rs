@@ -0,0 +1,895 @@
+use embedded_graphics::pixelcolor::BinaryColor;
+use rpi_einkserver_rs::{
+    dither::{DitherMethod, FitMode, GrayImage},
+    preview::encode_png,
+    state::FrameStore,
+    webhook::{self, WebhookSource},
+    Panel,
+};
+use sd_notify::NotifyState;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::Ordering;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    build_framebuffer, build_image_framebuffer, clear_panel, frame_or_image, maybe_init,
+    notify_systemd, restore_frame, shutdown_signal, text_layout, Cli, CliError, HttpArgs, IdleSleep,
+    StaleWatch, Watchdog,
+};
+use super::socket::{
+    changes_screen, error_json, failure_code, handle_json_packet, mode_name, status_json, Session,
+};
+
+/// Largest request body accepted by the HTTP server.
+pub(crate) const MAX_HTTP_BODY: u64 = 8 * 1024 * 1024;
+
+pub(crate) fn run_http_server(
+    epd: &mut dyn Panel,
+    cli: &Cli,
+    fg: BinaryColor,
+    bg: BinaryColor,
+    args: &HttpArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let listen = args.listen.as_str();
+    let token: Option<Arc<str>> = args.token()?.map(Into::into);
+    if token.is_none() {
+        warn!("No --auth-token set; anyone who can reach {listen} can change the display");
+    }
+    let bind_error = |source: Box<dyn std::error::Error + Send + Sync>| CliError::HttpBind {
+        addr: listen.to_string(),
+        source,
+    };
+    // A WebSocket has to wait for messages while pushing events, which needs both halves of
+    // the connection; tiny_http only hands out one. So `/ws` is served by our own listener,
+    // which relays every other connection to tiny_http on a loopback port.
+    let tls = args.tls_config()?;
+    let front = TcpListener::bind(listen).map_err(|err| bind_error(err.into()))?;
+    let server = tiny_http::Server::http("127.0.0.1:0").map_err(bind_error)?;
+    let backend = server
+        .server_addr()
+        .to_ip()
+        .expect("tiny_http was bound to an IP address");
+    maybe_init(epd, cli)?;
+    let restored = restore_frame(epd, cli)?;
+    let scheme = if tls.is_some() { "HTTPS" } else { "HTTP" };
+    info!("{scheme} server listening on {listen}");
+
+    let (jobs_tx, jobs) = mpsc::sync_channel::<WsJob>(WS_QUEUE);
+    let subscribers = Arc::new(Mutex::new(Vec::<mpsc::Sender<String>>::new()));
+    let context = HttpContext {
+        backend,
+        jobs: jobs_tx,
+        subscribers: Arc::clone(&subscribers),
+        token: token.clone(),
+        tls,
+    };
+    thread::spawn(move || accept_http(front, context));
+    let broadcast = |event: serde_json::Value| {
+        let event = event.to_string();
+        let mut subscribers = subscribers.lock().expect("subscriber list poisoned");
+        subscribers.retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    };
+
+    let stop = shutdown_signal()?;
+    let mut session = Session::new(text_layout(cli, fg, bg));
+    session.resume(restored);
+    notify_systemd(&[NotifyState::Ready]);
+    let mut watchdog = Watchdog::new();
+    let mut idle = IdleSleep::new(args.idle_sleep);
+    let mut stale = StaleWatch::new(args.stale_after, args.stale_action);
+    while !stop.load(Ordering::Relaxed) {
+        watchdog.tick();
+        if stale.due() {
+            let acted = idle
+                .wake(epd, cli)
+                .and_then(|()| stale.act(epd, cli, &session.layout));
+            if let Err(err) = acted {
+                error!("Could not act on stale content: {err}");
+            }
+        }
+        if let Err(err) = idle.check(epd) {
+            error!("Could not put the idle panel to sleep: {err}");
+        }
+        if let Some(mut request) = server.recv_timeout(Duration::from_millis(50))? {
+            let method = request.method().clone();
+            let url = request.url().to_string();
+            // GitHub cannot send a bearer token; its deliveries are signed instead.
+            let signed =
+                args.webhook_secret.is_some() && url.split('?').next() == Some("/webhook/github");
+            let authorized = signed
+                || token.as_deref().is_none_or(|token| {
+                    let header = request
+                        .headers()
+                        .iter()
+                        .find(|h| h.field.equiv("Authorization"));
+                    bearer_matches(header.map(|h| h.value.as_str()), token)
+                });
+            let response = if !authorized {
+                let challenge = tiny_http::Header::from_bytes("WWW-Authenticate", "Bearer")
+                    .expect("static header is valid");
+                http_error(401, "missing or wrong bearer token").with_header(challenge)
+            } else {
+                // Only POST requests touch the panel.
+                let woken = match method {
+                    tiny_http::Method::Post => idle.wake(epd, cli),
+                    _ => Ok(()),
+                };
+                match woken.and_then(|()| {
+                    handle_http(
+                        &mut request,
+                        epd,
+                        cli,
+                        fg,
+                        bg,
+                        &mut session,
+                        args.webhook_secret.as_deref(),
+                    )
+                }) {
+                    Ok(response) => response,
+                    Err(err) => {
+                        error!("HTTP {method} {url} failed: {err}");
+                        json_response(
+                            500,
+                            &serde_json::json!({ "ok": false, "error": err.to_string() }),
+                        )
+                    }
+                }
+            };
+            info!(%method, url, status = response.status_code().0, "HTTP request");
+            if method == tiny_http::Method::Post && response.status_code().0 == 200 {
+                stale.touch();
+                let command = url
+                    .split('?')
+                    .next()
+                    .unwrap_or_default()
+                    .trim_start_matches('/');
+                broadcast(display_event(command, "http", &session, cli));
+            }
+            if let Err(err) = request.respond(response) {
+                warn!("Could not send HTTP response: {err}");
+            }
+        }
+
+        while let Ok(job) = jobs.try_recv() {
+            let touches_panel = match &job.message {
+                tungstenite::Message::Text(text) => changes_screen(text),
+                _ => true,
+            };
+            let woken = if touches_panel {
+                idle.wake(epd, cli)
+            } else {
+                Ok(())
+            };
+            let handled =
+                woken.and_then(|()| handle_ws_message(job.message, epd, cli, bg, &mut session));
+            let (reply, command) = handled.unwrap_or_else(|err| {
+                error!("WebSocket command failed: {err}");
+                (error_json(failure_code(&*err), &err.to_string()), None)
+            });
+            let shown = reply["ok"] == true;
+            job.reply.send(reply).ok();
+            if let Some(command) = command.filter(|_| shown) {
+                stale.touch();
+                broadcast(display_event(&command, "websocket", &session, cli));
+            }
+        }
+    }
+
+    info!("Signal received; putting the panel to sleep");
+    notify_systemd(&[NotifyState::Stopping]);
+    if !idle.asleep {
+        epd.sleep()?;
+    }
+    Ok(())
+}
+
+/// Messages from WebSocket clients waiting for the display.
+const WS_QUEUE: usize = 16;
+
+/// Longest request head the `serve-http` front listener reads before giving up.
+const MAX_REQUEST_HEAD: usize = 16 * 1024;
+
+/// A WebSocket message for the display thread, and where its reply goes.
+struct WsJob {
+    message: tungstenite::Message,
+    reply: mpsc::Sender<serde_json::Value>,
+}
+
+/// The event sent to WebSocket clients after a successful display command.
+fn display_event(command: &str, source: &str, session: &Session, cli: &Cli) -> serde_json::Value {
+    let mode = mode_name(session.display.refresh(cli));
+    match command {
+        "clear" => serde_json::json!({ "event": "clear", "source": source }),
+        "mode" => serde_json::json!({ "event": "mode", "mode": mode, "source": source }),
+        _ => serde_json::json!({
+            "event": "refresh",
+            "command": command,
+            "mode": mode,
+            "source": source,
+        }),
+    }
+}
+
+/// What each `serve-http` connection thread needs.
+#[derive(Clone)]
+struct HttpContext {
+    backend: SocketAddr,
+    jobs: mpsc::SyncSender<WsJob>,
+    subscribers: Arc<Mutex<Vec<mpsc::Sender<String>>>>,
+    token: Option<Arc<str>>,
+    tls: Option<Arc<rustls::ServerConfig>>,
+}
+
+/// Accept connections for `serve-http`, each on its own thread.
+fn accept_http(front: TcpListener, context: HttpContext) {
+    for stream in front.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                error!("Accept error: {err}");
+                continue;
+            }
+        };
+        let context = context.clone();
+        thread::spawn(move || {
+            if let Err(err) = serve_http_connection(stream, &context) {
+                debug!("HTTP connection ended: {err}");
+            }
+        });
+    }
+}
+
+/// Read the request head, then hand the connection to the WebSocket server or tiny_http.
+fn serve_http_connection(
+    stream: TcpStream,
+    context: &HttpContext,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // Also bounds the TLS handshake.
+    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+    let mut client = match &context.tls {
+        Some(config) => HttpStream::Tls(Box::new(rustls::StreamOwned::new(
+            rustls::ServerConnection::new(Arc::clone(config))?,
+            stream,
+        ))),
+        None => HttpStream::Plain(stream),
+    };
+    let head = read_request_head(&mut client)?;
+    let websocket = is_websocket_request(&head);
+    let client = Replay {
+        head: io::Cursor::new(head),
+        stream: client,
+    };
+    if websocket {
+        serve_websocket(client, context)
+    } else {
+        relay(client, context.backend).map_err(Into::into)
+    }
+}
+
+/// A `serve-http` client connection, encrypted when `--tls-cert` is given.
+enum HttpStream {
+    Plain(TcpStream),
+    Tls(Box<rustls::StreamOwned<rustls::ServerConnection, TcpStream>>),
+}
+
+impl HttpStream {
+    fn tcp(&self) -> &TcpStream {
+        match self {
+            HttpStream::Plain(stream) => stream,
+            HttpStream::Tls(stream) => stream.get_ref(),
+        }
+    }
+
+    /// Tell the client we are done sending.
+    fn finish(&mut self) {
+        if let HttpStream::Tls(stream) = self {
+            stream.conn.send_close_notify();
+            stream.flush().ok();
+        }
+        self.tcp().shutdown(Shutdown::Write).ok();
+    }
+}
+
+impl Read for HttpStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            HttpStream::Plain(stream) => stream.read(buf),
+            HttpStream::Tls(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for HttpStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            HttpStream::Plain(stream) => stream.write(buf),
+            HttpStream::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            HttpStream::Plain(stream) => stream.flush(),
+            HttpStream::Tls(stream) => stream.flush(),
+        }
+    }
+}
+
+/// A connection whose request head was already read: reads return the head first.
+struct Replay {
+    head: io::Cursor<Vec<u8>>,
+    stream: HttpStream,
+}
+
+impl Read for Replay {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.head.read(buf)? {
+            0 => self.stream.read(buf),
+            len => Ok(len),
+        }
+    }
+}
+
+impl Write for Replay {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+/// Read up to the blank line ending the request head; may read some of the body too.
+fn read_request_head(stream: &mut HttpStream) -> io::Result<Vec<u8>> {
+    let mut head = Vec::new();
+    let mut chunk = [0; 4096];
+    while find_bytes(&head, b"\r\n\r\n").is_none() {
+        if head.len() > MAX_REQUEST_HEAD {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "request head too large",
+            ));
+        }
+        match stream.read(&mut chunk)? {
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            len => head.extend_from_slice(&chunk[..len]),
+        }
+    }
+    Ok(head)
+}
+
+/// Whether a request head asks to upgrade `/ws` to a WebSocket.
+fn is_websocket_request(head: &[u8]) -> bool {
+    let head = String::from_utf8_lossy(head);
+    let mut lines = head.lines();
+    let target = lines
+        .next()
+        .unwrap_or_default()
+        .split(' ')
+        .nth(1)
+        .unwrap_or_default();
+    let upgrade = lines.any(|line| {
+        line.split_once(':').is_some_and(|(name, value)| {
+            name.trim().eq_ignore_ascii_case("upgrade")
+                && value.trim().eq_ignore_ascii_case("websocket")
+        })
+    });
+    upgrade && target.split('?').next() == Some("/ws")
+}
+
+/// Copy bytes both ways between a client and tiny_http until tiny_http is done.
+fn relay(client: Replay, backend: SocketAddr) -> io::Result<()> {
+    let server = TcpStream::connect(backend)?;
+    match client.stream {
+        HttpStream::Plain(stream) => relay_plain(client.head, stream, server),
+        HttpStream::Tls(_) => relay_polled(client, server),
+    }
+}
+
+/// A plain connection is copied with one blocking thread per direction, so an idle
+/// keep-alive client costs nothing but the two threads.
+fn relay_plain(
+    head: io::Cursor<Vec<u8>>,
+    mut client: TcpStream,
+    mut server: TcpStream,
+) -> io::Result<()> {
+    // The timeout that bounded reading the head would end a quiet keep-alive connection.
+    client.set_read_timeout(None)?;
+    let (mut client_in, mut server_in) = (client.try_clone()?, server.try_clone()?);
+    let upload = thread::spawn(move || {
+        let mut request = head.chain(&mut client_in);
+        io::copy(&mut request, &mut server_in).ok();
+        server_in.shutdown(Shutdown::Write).ok();
+    });
+    let copied = io::copy(&mut server, &mut client);
+    // tiny_http is done: stop waiting for more from the client, too.
+    client.shutdown(Shutdown::Both).ok();
+    upload.join().ok();
+    copied.map(drop)
+}
+
+/// Poll interval of [`relay_polled`] while bytes are flowing.
+const RELAY_POLL: Duration = Duration::from_millis(10);
+
+/// Poll interval of [`relay_polled`] once the connection has been quiet for a second.
+const RELAY_IDLE_POLL: Duration = Duration::from_millis(500);
+
+/// How long [`relay_polled`] keeps a connection with no traffic either way.
+const RELAY_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A TLS stream cannot be split across threads, so one thread polls both sides; while one
+/// side keeps sending, the other is not polled, so a large upload doesn't wait on the
+/// response. Polling slows down on a quiet connection and gives up on an idle one.
+fn relay_polled(mut client: Replay, mut server: TcpStream) -> io::Result<()> {
+    let set_poll = |client: &Replay, server: &TcpStream, poll: Duration| {
+        client.stream.tcp().set_read_timeout(Some(poll))?;
+        server.set_read_timeout(Some(poll))
+    };
+    set_poll(&client, &server, RELAY_POLL)?;
+    let mut buf = vec![0; 64 * 1024];
+    let (mut client_open, mut uploading, mut downloading) = (true, false, false);
+    let (mut last_traffic, mut slow) = (Instant::now(), false);
+    loop {
+        if client_open && !downloading {
+            uploading = false;
+            match read_ready(&mut client, &mut buf)? {
+                Some(0) => {
+                    client_open = false;
+                    server.shutdown(Shutdown::Write).ok();
+                }
+                Some(len) => {
+                    server.write_all(&buf[..len])?;
+                    uploading = true;
+                }
+                None => {}
+            }
+        }
+        if !uploading {
+            downloading = false;
+            match read_ready(&mut server, &mut buf)? {
+                Some(0) => break,
+                Some(len) => {
+                    client.write_all(&buf[..len])?;
+                    client.flush()?;
+                    downloading = true;
+                }
+                None => {}
+            }
+        }
+
+        if uploading || downloading {
+            last_traffic = Instant::now();
+            if slow {
+                set_poll(&client, &server, RELAY_POLL)?;
+                slow = false;
+            }
+        } else if last_traffic.elapsed() >= RELAY_IDLE_TIMEOUT {
+            debug!("Closing an idle HTTP connection");
+            break;
+        } else if !slow && last_traffic.elapsed() >= Duration::from_secs(1) {
+            set_poll(&client, &server, RELAY_IDLE_POLL)?;
+            slow = true;
+        }
+    }
+    client.stream.finish();
+    Ok(())
+}
+
+/// Read what is available: `None` when nothing arrived within the read timeout, `Some(0)`
+/// once the peer is gone.
+fn read_ready(stream: &mut impl Read, buf: &mut [u8]) -> io::Result<Option<usize>> {
+    match stream.read(buf) {
+        Ok(len) => Ok(Some(len)),
+        Err(err)
+            if matches!(
+                err.kind(),
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+            ) =>
+        {
+            Ok(None)
+        }
+        // A TLS client that hangs up without a close_notify.
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(Some(0)),
+        Err(err) => Err(err),
+    }
+}
+
+/// Serve one WebSocket client: each message is queued for the display thread and answered
+/// with its reply, and display events are pushed as they happen.
+fn serve_websocket(
+    stream: Replay,
+    context: &HttpContext,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use tungstenite::handshake::server::{ErrorResponse, Request, Response};
+    use tungstenite::{Error as WsError, Message};
+
+    let token = context.token.as_deref();
+    // The error type is tungstenite's callback signature.
+    #[allow(clippy::result_large_err)]
+    let check_token = |request: &Request, response: Response| {
+        let Some(token) = token else {
+            return Ok(response);
+        };
+        let header = request
+            .headers()
+            .get("Authorization")
+            .and_then(|value| value.to_str().ok());
+        let query = parse_query(request.uri().query().unwrap_or_default());
+        let param = query.iter().find(|(k, _)| k == "token").map(|(_, v)| v);
+        if bearer_matches(header, token)
+            || param.is_some_and(|param| constant_time_eq(param.as_bytes(), token.as_bytes()))
+        {
+            return Ok(response);
+        }
+        let mut refusal = ErrorResponse::new(Some("missing or wrong token".to_string()));
+        *refusal.status_mut() = tungstenite::http::StatusCode::UNAUTHORIZED;
+        Err(refusal)
+    };
+    // The handshake error holds the callback, which borrows `token`.
+    let mut socket = tungstenite::accept_hdr(stream, check_token).map_err(|err| err.to_string())?;
+    // Wake up regularly to forward events while no message arrives.
+    socket
+        .get_ref()
+        .stream
+        .tcp()
+        .set_read_timeout(Some(Duration::from_millis(100)))?;
+    let (events_tx, events) = mpsc::channel();
+    context
+        .subscribers
+        .lock()
+        .expect("subscriber list poisoned")
+        .push(events_tx);
+    info!("WebSocket client connected");
+
+    loop {
+        while let Ok(event) = events.try_recv() {
+            socket.send(Message::text(event))?;
+        }
+        match socket.read() {
+            Ok(message @ (Message::Text(_) | Message::Binary(_))) => {
+                let (reply_tx, reply) = mpsc::channel();
+                context.jobs.send(WsJob {
+                    message,
+                    reply: reply_tx,
+                })?;
+                socket.send(Message::text(reply.recv()?.to_string()))?;
+            }
+            Ok(_) => {}
+            Err(WsError::Io(err))
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) => {}
+            Err(WsError::ConnectionClosed) => break,
+            Err(err) => return Err(err.into()),
+        }
+    }
+    info!("WebSocket client disconnected");
+    Ok(())
+}
+
+/// Handle a WebSocket message on the display thread: text is a JSON request as on the
+/// Unix socket, binary is a raw panel frame or an image file. Returns the reply and the
+/// command to report to subscribers if it succeeded.
+fn handle_ws_message(
+    message: tungstenite::Message,
+    epd: &mut dyn Panel,
+    cli: &Cli,
+    bg: BinaryColor,
+    session: &mut Session,
+) -> Result<(serde_json::Value, Option<String>), Box<dyn std::error::Error>> {
+    match message {
+        tungstenite::Message::Text(text) => {
+            let reply = handle_json_packet(&text, epd, cli, bg, session, None)?;
+            let command = serde_json::from_str::<serde_json::Value>(&text)
+                .ok()
+                .and_then(|request| request["cmd"].as_str().map(str::to_string))
+                .filter(|command| command != "ping");
+            Ok((reply, command))
+        }
+        tungstenite::Message::Binary(bytes) => {
+            let fb = match frame_or_image(&bytes, cli) {
+                Ok(fb) => fb,
+                Err(err) => {
+                    let message = format!("neither a raw frame nor a decodable image: {err}");
+                    return Ok((error_json("IMAGE_INVALID", &message), None));
+                }
+            };
+            session.show(epd, &fb, None, cli)?;
+            session.pager = None;
+            Ok((serde_json::json!({ "ok": true }), Some("frame".to_string())))
+        }
+        _ => Ok((serde_json::json!({ "ok": true }), None)),
+    }
+}
+
+type HttpResponse = tiny_http::Response<io::Cursor<Vec<u8>>>;
+
+fn handle_http(
+    request: &mut tiny_http::Request,
+    epd: &mut dyn Panel,
+    cli: &Cli,
+    fg: BinaryColor,
+    bg: BinaryColor,
+    session: &mut Session,
+    webhook_secret: Option<&str>,
+) -> Result<HttpResponse, Box<dyn std::error::Error>> {
+    use tiny_http::Method;
+
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+    let params = parse_query(query);
+    let param = |name: &str| {
+        params
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    };
+
+    let fb = match (request.method(), path) {
+        (Method::Get, "/status") => return Ok(json_response(200, &status_json(cli))),
+        (Method::Get, "/screenshot.png") => {
+            let Some(frame) = FrameStore::new(&cli.persist_frame).load()? else {
+                return Ok(http_error(404, "nothing has been shown yet"));
+            };
+            let png = encode_png(&frame.rotated(cli.rotate.inverse()))?;
+            let content_type = tiny_http::Header::from_bytes("Content-Type", "image/png")
+                .expect("static header is valid");
+            return Ok(tiny_http::Response::from_data(png).with_header(content_type));
+        }
+        (Method::Post, "/clear") => {
+            clear_panel(epd, cli, bg)?;
+            session.display.has_base = false;
+            session.pager = None;
+            return Ok(json_response(200, &serde_json::json!({ "ok": true })));
+        }
+        (Method::Post, "/text") => {
+            let text = String::from_utf8(read_body(request)?)?;
+            if text.trim().is_empty() {
+                return Ok(http_error(400, "empty text"));
+            }
+            let mut layout = text_layout(cli, fg, bg);
+            if let Some(font) = param("font") {
+                if font.eq_ignore_ascii_case("auto") {
+                    layout.auto_fit = true;
+                } else {
+                    match font.parse() {
+                        Ok(font) => layout.font = font,
+                        Err(err) => return Ok(http_error(400, &err)),
+                    }
+                }
+            }
+            if let Some(align) = param("align") {
+                match align.parse() {
+                    Ok(alignment) => layout.alignment = alignment,
+                    Err(err) => return Ok(http_error(400, &err)),
+                }
+            }
+            build_framebuffer(text.trim_end(), &layout, cli.rotate)
+        }
+        (Method::Post, "/image") => {
+            let content_type = request
+                .headers()
+                .iter()
+                .find(|h| h.field.equiv("Content-Type"))
+                .map(|h| h.value.as_str().to_string())
+                .unwrap_or_default();
+            let body = read_body(request)?;
+            let bytes = match multipart_boundary(&content_type) {
+                Some(boundary) => match multipart_file(&body, &boundary) {
+                    Some(bytes) => bytes,
+                    None => return Ok(http_error(400, "no file part in multipart body")),
+                },
+                None => &body[..],
+            };
+            let method = match param("dither").map(str::parse::<DitherMethod>) {
+                Some(Err(err)) => return Ok(http_error(400, &err)),
+                parsed => parsed.and_then(Result::ok).unwrap_or_default(),
+            };
+            let fit = match param("fit").map(str::parse::<FitMode>) {
+                Some(Err(err)) => return Ok(http_error(400, &err)),
+                parsed => parsed.and_then(Result::ok).unwrap_or_default(),
+            };
+            match GrayImage::decode(bytes) {
+                Ok(gray) => build_image_framebuffer(gray, method, fit, cli),
+                Err(err) => return Ok(http_error(400, &format!("cannot decode image: {err}"))),
+            }
+        }
+        (Method::Post, path) if path.starts_with("/webhook/") => {
+            let source = match &path["/webhook/".len()..] {
+                "github" => WebhookSource::GitHub,
+                "grafana" => WebhookSource::Grafana,
+                "generic" => WebhookSource::Generic,
+                _ => return Ok(http_error(404, "no such webhook")),
+            };
+            let header = |name: &'static str| {
+                request
+                    .headers()
+                    .iter()
+                    .find(|h| h.field.equiv(name))
+                    .map(|h| h.value.as_str().to_string())
+            };
+            let event = header("X-GitHub-Event");
+            let signature = header("X-Hub-Signature-256");
+            let body = read_body(request)?;
+            let unsigned = match (source, webhook_secret) {
+                (WebhookSource::GitHub, Some(secret)) => {
+                    !github_signature_matches(secret, &body, signature.as_deref())
+                }
+                _ => false,
+            };
+            if unsigned {
+                return Ok(http_error(401, "missing or wrong webhook signature"));
+            }
+            let payload = match serde_json::from_slice(&body) {
+                Ok(payload) => payload,
+                // Plain text is fine for the generic hook.
+                Err(_) if source == WebhookSource::Generic => {
+                    serde_json::Value::String(String::from_utf8_lossy(&body).into_owned())
+                }
+                Err(err) => return Ok(http_error(400, &format!("invalid JSON: {err}"))),
+            };
+            let Some(summary) = webhook::summarize(source, event.as_deref(), &payload) else {
+                return Ok(http_error(422, "nothing to show in this payload"));
+            };
+            info!(summary, "Webhook");
+            build_framebuffer(&summary, &text_layout(cli, fg, bg), cli.rotate)
+        }
+        (_, "/status" | "/screenshot.png" | "/clear" | "/text" | "/image") => {
+            return Ok(http_error(405, "method not allowed"));
+        }
+        (_, path) if path.starts_with("/webhook/") => {
+            return Ok(http_error(405, "method not allowed"));
+        }
+        _ => return Ok(http_error(404, "no such endpoint")),
+    };
+
+    session.show(epd, &fb, None, cli)?;
+    session.pager = None;
+    Ok(json_response(200, &serde_json::json!({ "ok": true })))
+}
+
+fn read_body(request: &mut tiny_http::Request) -> io::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    request
+        .as_reader()
+        .take(MAX_HTTP_BODY + 1)
+        .read_to_end(&mut body)?;
+    if body.len() as u64 > MAX_HTTP_BODY {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "request body too large",
+        ));
+    }
+    Ok(body)
+}
+
+/// `a=1&b=two` pairs, with `+` and `%XX` decoded.
+fn parse_query(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key), percent_decode(value))
+        })
+        .collect()
+}
+
+fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                match std::str::from_utf8(&bytes[i + 1..i + 3])
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                {
+                    Some(byte) => {
+                        out.push(byte);
+                        i += 2;
+                    }
+                    None => out.push(b'%'),
+                }
+            }
+            byte => out.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn multipart_boundary(content_type: &str) -> Option<String> {
+    let (mime, params) = content_type.split_once(';')?;
+    if !mime.trim().eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+    params.split(';').find_map(|param| {
+        let (key, value) = param.split_once('=')?;
+        key.trim()
+            .eq_ignore_ascii_case("boundary")
+            .then(|| value.trim().trim_matches('"').to_string())
+    })
+}
+
+/// The content of the first part of a `multipart/form-data` body that carries a file
+/// (has a `filename`), or of the first part at all.
+fn multipart_file<'a>(body: &'a [u8], boundary: &str) -> Option<&'a [u8]> {
+    let delimiter = format!("--{boundary}");
+    let mut parts = Vec::new();
+    let mut rest = body;
+    while let Some(start) = find_bytes(rest, delimiter.as_bytes()) {
+        rest = &rest[start + delimiter.len()..];
+        if rest.starts_with(b"--") {
+            break;
+        }
+        let end = find_bytes(rest, delimiter.as_bytes()).unwrap_or(rest.len());
+        let part = rest[..end].strip_prefix(b"\r\n").unwrap_or(&rest[..end]);
+        let part = part.strip_suffix(b"\r\n").unwrap_or(part);
+        let split = find_bytes(part, b"\r\n\r\n")?;
+        let headers = String::from_utf8_lossy(&part[..split]).to_ascii_lowercase();
+        parts.push((headers.contains("filename="), &part[split + 4..]));
+    }
+    parts
+        .iter()
+        .find(|(is_file, _)| *is_file)
+        .or(parts.first())
+        .map(|(_, content)| *content)
+}
+
+fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Whether an `Authorization` header value is `Bearer <token>`.
+fn bearer_matches(header: Option<&str>, token: &str) -> bool {
+    header
+        .and_then(|value| value.trim().strip_prefix("Bearer "))
+        .is_some_and(|given| constant_time_eq(given.trim().as_bytes(), token.as_bytes()))
+}
+
+/// Whether an `X-Hub-Signature-256` header value is `sha256=<hex HMAC of body>` keyed with
+/// `secret`, as GitHub signs webhook deliveries.
+fn github_signature_matches(secret: &str, body: &[u8], header: Option<&str>) -> bool {
+    let Some(hex) = header.and_then(|value| value.trim().strip_prefix("sha256=")) else {
+        return false;
+    };
+    let signature: Option<Vec<u8>> = (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+        })
+        .collect();
+    let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, secret.as_bytes());
+    signature.is_some_and(|signature| ring::hmac::verify(&key, body, &signature).is_ok())
+}
+
+/// Compare secrets without returning early at the first difference.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+fn json_response(status: u16, body: &serde_json::Value) -> HttpResponse {
+    let content_type = tiny_http::Header::from_bytes("Content-Type", "application/json")
+        .expect("static header is valid");
+    tiny_http::Response::from_data(body.to_string().into_bytes())
+        .with_status_code(status)
+        .with_header(content_type)
+}
+
+fn http_error(status: u16, message: &str) -> HttpResponse {
+    json_response(
+        status,
+        &serde_json::json!({ "ok": false, "error": message }),
+    )
+}
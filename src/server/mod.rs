@@ -0,0 +1,5 @@
+//! The front ends of `serve`: the HTTP and WebSocket server, and the line and binary
+//! protocol spoken over the Unix socket.
+
+pub(crate) mod http;
+pub(crate) mod socket;
@@ -0,0 +1,3456 @@
+use base64::prelude::{Engine as _, BASE64_STANDARD};
+use chrono::{DateTime, Local, TimeDelta};
+use embedded_graphics::{
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::{Circle, Line, PrimitiveStyle, Rectangle},
+};
+use rpi_einkserver_rs::{
+    buffer::{BarcodeKind, FrameError, Rotation},
+    epd2in13_v4::EpdError,
+    icons::{self, ICON_SIZE},
+    layout_spec::LayoutSpec,
+    menu::{Menu, MenuAction, MenuState},
+    panel::{DiscardPanel, PanelEvent, RefreshCounts},
+    preview::encode_png,
+    state::FrameStore,
+    template::{self, TemplateError},
+    terminal::Terminal,
+    text::{Alignment, TextLayout},
+    Epd2in13V4, FontChoice, MonoImage, Panel, UpdateMode,
+};
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::ffi::CString;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::TrySendError;
+use std::sync::{mpsc, Arc, Condvar, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+use crate::{
+    bare_layout, blank_framebuffer, build_barcode_framebuffer, build_chart_framebuffer,
+    build_columns_framebuffer, build_framebuffer, build_progress_framebuffer, build_qr_framebuffer,
+    build_table_framebuffer, clear_panel, decode_newlines, fits_canvas, frame_or_image, named_path,
+    panel_area, parse_interval, parse_percent, present, present_as, present_region, run_shell,
+    show_base, update_mode, ButtonSpec, Cli, Clock, ClockOverlay, Failure, LiveView, Pager,
+    SHELL_TIMEOUT,
+};
+use super::http::MAX_HTTP_BODY;
+
+/// A packet read from a socket client, for the display thread.
+pub(crate) struct SocketPacket {
+    pub(crate) line: String,
+    /// The binary data following a `FRAME` or `IMAGE BYTES` header.
+    data: Option<Vec<u8>>,
+    pub(crate) priority: Priority,
+    /// The `--panel` named by an `@NAME` prefix; `None` for the main one.
+    pub(crate) panel: Option<String>,
+    /// The id of a `#<id>` prefix, for the reply.
+    tag: Option<String>,
+    /// Output for the connection's `TERM` screen rather than a command.
+    terminal: bool,
+}
+
+impl SocketPacket {
+    pub(crate) fn changes_screen(&self) -> bool {
+        self.terminal || changes_screen(&self.line)
+    }
+}
+
+/// How urgent a socket command is. More urgent commands leave the display queue first, and
+/// while a `SHOW` message is up, less urgent screen changes wait until it expires.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub(crate) enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+    Urgent,
+}
+
+impl Priority {
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "low" => Some(Priority::Low),
+            "normal" => Some(Priority::Normal),
+            "high" => Some(Priority::High),
+            "urgent" => Some(Priority::Urgent),
+            _ => None,
+        }
+    }
+}
+
+/// Split off a `#<id>` prefix, which the reply repeats. Only digits make an id, so that
+/// text such as `#hashtag` is left alone.
+fn split_tag(line: &str) -> (Option<String>, &str) {
+    let (word, rest) = split_word(line);
+    match word.strip_prefix('#') {
+        Some(id) if !id.is_empty() && id.len() <= 20 && id.bytes().all(|b| b.is_ascii_digit()) => {
+            (Some(id.to_string()), rest)
+        }
+        _ => (None, line),
+    }
+}
+
+/// The reply to a packet with a `#<id>` prefix, e.g. `#42 OK TEXT`, with the status of an
+/// `ERR` reply filled in.
+fn tagged<'a>(tag: Option<&str>, reply: &'a str) -> Cow<'a, str> {
+    match tag {
+        Some(tag) => Cow::Owned(format!("#{tag} {}", with_status(reply))),
+        None => Cow::Borrowed(reply),
+    }
+}
+
+/// Split off an `@NAME` prefix that sends a command to another `--panel`.
+fn split_panel(line: &str) -> (Option<String>, &str) {
+    match line.strip_prefix('@') {
+        Some(rest) => {
+            let (name, command) = split_word(rest);
+            (Some(name.to_string()), command)
+        }
+        None => (None, line),
+    }
+}
+
+/// Take the priority from a `PRIORITY <level> ` prefix, or from the `priority` field of a
+/// JSON request. Returns the error reply for an unknown level.
+fn split_priority(line: &str) -> Result<(Priority, &str), String> {
+    let unknown = |level: &str| format!("unknown priority '{level}' (low, normal, high, urgent)");
+    if line.starts_with('{') {
+        // Malformed JSON is reported when the request runs.
+        let request = serde_json::from_str::<serde_json::Value>(line).unwrap_or_default();
+        return match request.get("priority") {
+            None => Ok((Priority::Normal, line)),
+            Some(level) => match level.as_str().and_then(Priority::from_name) {
+                Some(priority) => Ok((priority, line)),
+                None => Err(serde_json::json!({
+                    "ok": false,
+                    "error": "PRIORITY_UNKNOWN",
+                    "message": unknown(level.as_str().unwrap_or_default()),
+                })
+                .to_string()),
+            },
+        };
+    }
+    let (keyword, rest) = split_word(line);
+    if !keyword.eq_ignore_ascii_case("PRIORITY") {
+        return Ok((Priority::Normal, line));
+    }
+    let (level, command) = split_word(rest);
+    match Priority::from_name(level) {
+        Some(priority) => Ok((priority, command)),
+        None => {
+            debug!("{}", unknown(level));
+            Err("ERR PRIORITY_UNKNOWN".to_string())
+        }
+    }
+}
+
+/// Whether a packet may change what the panel shows, as opposed to settings and queries.
+pub(crate) fn changes_screen(line: &str) -> bool {
+    if line.starts_with('{') {
+        let request = serde_json::from_str::<serde_json::Value>(line).unwrap_or_default();
+        return !matches!(
+            request["cmd"].as_str(),
+            Some("ping" | "status" | "snapshot" | "mode")
+        );
+    }
+    let (command, payload) = parse_packet(line);
+    if let PacketCommand::Template = command {
+        let action = split_word(payload.unwrap_or_default()).0;
+        return action.eq_ignore_ascii_case("SHOW");
+    }
+    if let PacketCommand::Chart = command {
+        let action = split_word(payload.unwrap_or_default()).0;
+        return !action.eq_ignore_ascii_case("CLEAR");
+    }
+    if let PacketCommand::Icon = command {
+        let name = split_word(payload.unwrap_or_default()).0;
+        return !name.eq_ignore_ascii_case("LIST");
+    }
+    !matches!(
+        command,
+        PacketCommand::Ping
+            | PacketCommand::Status
+            | PacketCommand::Snapshot
+            | PacketCommand::Font
+            | PacketCommand::Align
+            | PacketCommand::PartialOff
+            | PacketCommand::Mode
+            | PacketCommand::At
+            | PacketCommand::Binary
+            | PacketCommand::Term
+            | PacketCommand::Subscribe
+            | PacketCommand::Async
+            | PacketCommand::Wait
+            | PacketCommand::Line
+            | PacketCommand::Rect
+            | PacketCommand::Circle
+            | PacketCommand::Pixel
+            | PacketCommand::Begin
+            | PacketCommand::Abort
+            | PacketCommand::Playlist
+            | PacketCommand::Clock
+    )
+}
+
+/// Whether a packet waits for `COMMIT` while a batch is open: screen changes, and the
+/// settings and shapes they are drawn with.
+fn batched(line: &str) -> bool {
+    if line.starts_with('{') {
+        return changes_screen(line);
+    }
+    match parse_packet(line).0 {
+        PacketCommand::Font
+        | PacketCommand::Align
+        | PacketCommand::Line
+        | PacketCommand::Rect
+        | PacketCommand::Circle
+        | PacketCommand::Pixel => true,
+        PacketCommand::Show | PacketCommand::PartialOn | PacketCommand::Commit => false,
+        _ => changes_screen(line),
+    }
+}
+
+/// Most packets one `BEGIN` ... `COMMIT` batch may hold.
+const MAX_BATCH: usize = 64;
+
+/// A queued packet with its client's session, which travels with it and comes back with
+/// the reply (or the error that ends the connection).
+pub(crate) struct SocketJob {
+    pub(crate) packet: SocketPacket,
+    pub(crate) session: Session,
+    pub(crate) reply: mpsc::Sender<(Session, String)>,
+}
+
+/// Per-connection state of the socket protocol, shared by all clients of `serve-http`.
+pub(crate) struct Session {
+    pub(crate) layout: TextLayout,
+    /// How content is refreshed. Under `serve` this belongs to the panel rather than the
+    /// connection: the display thread lends it to each command.
+    pub(crate) display: DisplayMode,
+    /// The last text message, kept for `PAGE` until other content replaces it. Under
+    /// `serve` the display thread lends it like `display`.
+    pub(crate) pager: Option<Pager>,
+    /// Shapes queued by `LINE`, `RECT`, `CIRCLE` and `PIXEL` for the next `FLUSH`.
+    shapes: Vec<Shape>,
+    /// The screen of a connection in `TERM` mode and how it was last shown.
+    terminal: Option<(Terminal, LiveView)>,
+    /// Packets held back since `BEGIN`, which `COMMIT` composes into one frame.
+    batch: Option<Vec<SocketPacket>>,
+}
+
+impl Session {
+    pub(crate) fn new(layout: TextLayout) -> Self {
+        Self {
+            layout,
+            display: DisplayMode::default(),
+            pager: None,
+            shapes: Vec::new(),
+            terminal: None,
+            batch: None,
+        }
+    }
+
+    /// Carry on from a frame brought back by `--restore`.
+    pub(crate) fn resume(&mut self, restored: Option<UpdateMode>) {
+        self.display = DisplayMode::resumed(restored);
+    }
+
+    /// Show a canvas frame with `mode`, or the session's default. A partial refresh without
+    /// a base shows the frame as the new base instead.
+    pub(crate) fn show(
+        &mut self,
+        epd: &mut dyn Panel,
+        fb: &MonoImage,
+        mode: Option<UpdateMode>,
+        cli: &Cli,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let refresh = self.plan(mode, cli);
+        show_planned(epd, fb, refresh, cli)
+    }
+
+    /// The refresh [`Session::show`] uses for `mode`, with `has_base` updated as if it had
+    /// happened; `None` means showing the frame as the new base.
+    pub(crate) fn plan(&mut self, mode: Option<UpdateMode>, cli: &Cli) -> Option<UpdateMode> {
+        self.display.plan(mode, cli)
+    }
+
+    /// Draw `patch` at `origin` over the last frame shown and refresh just that area with a
+    /// partial update. Without a base, the whole frame is shown as the new base instead.
+    fn show_region(
+        &mut self,
+        epd: &mut dyn Panel,
+        patch: &MonoImage,
+        origin: Point,
+        bg: BinaryColor,
+        cli: &Cli,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut fb =
+            last_canvas(cli).unwrap_or_else(|| blank_framebuffer(panel_area(cli.rotate).size, bg));
+        blit(&mut fb, patch, origin);
+        self.show_area(epd, &fb, Rectangle::new(origin, patch.size()), cli)
+    }
+
+    /// Show a canvas frame by partially refreshing the rectangle where it differs from the
+    /// last frame shown; nothing happens when they are the same. Without a stored last frame
+    /// it is shown like any other.
+    fn show_changes(
+        &mut self,
+        epd: &mut dyn Panel,
+        fb: &MonoImage,
+        cli: &Cli,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        match last_canvas(cli) {
+            Some(shown) => match changed_area(&shown, fb) {
+                Some(area) => self.show_area(epd, fb, area, cli),
+                None => Ok(()),
+            },
+            None => self.show(epd, fb, None, cli),
+        }
+    }
+
+    /// Partially refresh `area` of a canvas frame, or show it all as the base if there is
+    /// none yet.
+    fn show_area(
+        &mut self,
+        epd: &mut dyn Panel,
+        fb: &MonoImage,
+        area: Rectangle,
+        cli: &Cli,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if self.display.has_base {
+            present_region(epd, fb, area, cli)
+        } else {
+            show_base(epd, cli, &fb.rotated(cli.rotate))?;
+            self.display.has_base = true;
+            Ok(())
+        }
+    }
+}
+
+/// How a panel refreshes content that doesn't ask for a refresh of its own (`MODE`,
+/// `PARTIAL_ON`), and whether partial refreshes can build on what it shows.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct DisplayMode {
+    /// `None` is the default: full refreshes, or fast ones with `--fast`.
+    default: Option<UpdateMode>,
+    /// The controller holds the frame on screen as the base for partial refreshes. Any
+    /// full or fast refresh, whoever asked for it, replaces the base.
+    pub(crate) has_base: bool,
+}
+
+impl DisplayMode {
+    /// The mode after `--restore` brought back a frame shown with `restored`: the
+    /// controller holds it as the base, and partial mode continues if a partial refresh
+    /// showed it.
+    pub(crate) fn resumed(restored: Option<UpdateMode>) -> Self {
+        match restored {
+            Some(mode) => Self {
+                default: matches!(mode, UpdateMode::Partial).then_some(mode),
+                has_base: true,
+            },
+            None => Self::default(),
+        }
+    }
+
+    fn partial(&self) -> bool {
+        matches!(self.default, Some(UpdateMode::Partial))
+    }
+
+    /// The refresh for content that doesn't choose one.
+    pub(crate) fn refresh(&self, cli: &Cli) -> UpdateMode {
+        self.default.unwrap_or(update_mode(false, cli))
+    }
+
+    /// The refresh to show a frame with for `mode`, or the default, with `has_base` updated
+    /// as if it had happened; `None` means showing the frame as the new base.
+    fn plan(&mut self, mode: Option<UpdateMode>, cli: &Cli) -> Option<UpdateMode> {
+        match mode.unwrap_or(self.refresh(cli)) {
+            UpdateMode::Partial if !self.has_base => {
+                self.has_base = true;
+                None
+            }
+            mode => {
+                self.has_base &= matches!(mode, UpdateMode::Partial);
+                Some(mode)
+            }
+        }
+    }
+}
+
+/// Show a canvas frame with a refresh from [`Session::plan`].
+pub(crate) fn show_planned(
+    epd: &mut dyn Panel,
+    fb: &MonoImage,
+    refresh: Option<UpdateMode>,
+    cli: &Cli,
+) -> Result<(), Box<dyn std::error::Error>> {
+    match refresh {
+        None => show_base(epd, cli, &fb.rotated(cli.rotate)),
+        Some(mode) => present_as(epd, fb, mode, cli),
+    }
+}
+
+/// The last frame shown, turned back to canvas orientation, if one is stored.
+pub(crate) fn last_canvas(cli: &Cli) -> Option<MonoImage> {
+    let canvas = panel_area(cli.rotate).size;
+    FrameStore::new(&cli.persist_frame)
+        .load()
+        .unwrap_or_else(|err| {
+            warn!("Cannot load the last frame: {err}");
+            None
+        })
+        .map(|frame| frame.rotated(cli.rotate.inverse()))
+        .filter(|frame| frame.size() == canvas)
+}
+
+/// Copy `patch` onto `fb` with its top-left corner at `origin`, clipped to `fb`.
+pub(crate) fn blit(fb: &mut MonoImage, patch: &MonoImage, origin: Point) {
+    for y in 0..patch.height() {
+        for x in 0..patch.width() {
+            let (fx, fy) = (origin.x + x as i32, origin.y + y as i32);
+            if fx >= 0 && fy >= 0 {
+                fb.set_pixel(fx as u32, fy as u32, patch.pixel(x, y));
+            }
+        }
+    }
+}
+
+/// The smallest rectangle holding every pixel that differs between two same-sized frames.
+fn changed_area(old: &MonoImage, new: &MonoImage) -> Option<Rectangle> {
+    let mut corners: Option<(Point, Point)> = None;
+    for y in 0..new.height() {
+        for x in 0..new.width() {
+            if old.pixel(x, y) == new.pixel(x, y) {
+                continue;
+            }
+            let p = Point::new(x as i32, y as i32);
+            corners = Some(match corners {
+                None => (p, p),
+                Some((min, max)) => (min.component_min(p), max.component_max(p)),
+            });
+        }
+    }
+    corners.map(|(min, max)| Rectangle::with_corners(min, max))
+}
+
+/// What `PING` reports about a panel. The display thread keeps it up to date and client
+/// threads read it, so `PING` is answered even while a refresh hangs.
+pub(crate) struct Health {
+    started: Instant,
+    /// The display thread is carrying out a screen change for this panel.
+    busy: AtomicBool,
+    last_refresh: Mutex<Option<Instant>>,
+}
+
+impl Health {
+    fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            busy: AtomicBool::new(false),
+            last_refresh: Mutex::new(None),
+        }
+    }
+
+    /// Mark the panel busy until the returned guard is dropped.
+    pub(crate) fn busy(self: &Arc<Self>) -> BusyGuard {
+        self.busy.store(true, Ordering::Relaxed);
+        BusyGuard(Arc::clone(self))
+    }
+
+    fn last_refresh(&self) -> Option<Instant> {
+        *self
+            .last_refresh
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn refreshed(&self) {
+        *self
+            .last_refresh
+            .lock()
+            .unwrap_or_else(|err| err.into_inner()) = Some(Instant::now());
+    }
+
+    /// The `PING` reply, e.g. `PONG uptime=3215 busy=0 last=12s`; `last=never` before the
+    /// first refresh.
+    fn pong(&self) -> String {
+        let last = match self.last_refresh() {
+            Some(last) => format!("{}s", last.elapsed().as_secs()),
+            None => "never".to_string(),
+        };
+        format!(
+            "PONG uptime={} busy={} last={last}",
+            self.started.elapsed().as_secs(),
+            u8::from(self.busy.load(Ordering::Relaxed)),
+        )
+    }
+}
+
+pub(crate) struct BusyGuard(Arc<Health>);
+
+impl Drop for BusyGuard {
+    fn drop(&mut self) {
+        self.0.busy.store(false, Ordering::Relaxed);
+    }
+}
+
+/// State of `serve` kept on the display thread and shared by all clients.
+pub(crate) struct ServerState {
+    scene: Scene,
+    /// Shared with the other panels, so any of them can show a template.
+    templates: Rc<RefCell<Templates>>,
+    playlist: Playlist,
+    /// Shared with the [`ClockPanel`] the panel is drawn through.
+    pub(crate) clock: Rc<RefCell<ClockOverlay>>,
+    timers: Vec<Timer>,
+    /// Shared with the client threads, which answer `PING` from it.
+    pub(crate) health: Arc<Health>,
+    /// The panel's refresh mode, lent to the session of each command, so `MODE` and
+    /// `PARTIAL_ON` apply to every connection and all of them know whether there is a base.
+    pub(crate) display: DisplayMode,
+    /// The text message on the panel, lent to the session of each command like `display`,
+    /// so `PAGE` turns it whichever client (or button) asks.
+    pub(crate) pager: Option<Pager>,
+    /// The `--menu`, shared with the other panels.
+    pub(crate) menu: Option<Rc<Menu>>,
+    /// The menu on the panel, if it is open.
+    menu_open: Option<OpenMenu>,
+    /// How the `PROGRESS` bar or `CHART` on the panel was shown, so updates are partial
+    /// refreshes.
+    live: Option<LiveView>,
+    /// The series of `CHART`, kept while other content is shown so points can still be added.
+    chart: Chart,
+    /// Filled in by the [`CountingPanel`] the display loop draws through.
+    pub(crate) refreshes: Rc<Cell<RefreshCounts>>,
+    /// Jobs waiting behind the one being handled.
+    pub(crate) queued: usize,
+    /// `--min-interval` between `TEXT` refreshes.
+    min_interval: Option<Duration>,
+    /// The latest `TEXT` frame that came too soon, and the refresh planned for it.
+    pub(crate) deferred: Option<(MonoImage, Option<UpdateMode>)>,
+    /// The menu `run` command working on another thread, shown once it is done.
+    shell: Option<ShellRun>,
+}
+
+/// A menu `run` command started by [`handle_menu`], and how to show its output.
+struct ShellRun {
+    /// Set by the thread running the command when it is done.
+    output: Arc<OnceLock<String>>,
+    layout: TextLayout,
+    mode: Option<UpdateMode>,
+}
+
+/// A `MENU` on screen, and what it covers up.
+struct OpenMenu {
+    state: MenuState,
+    view: LiveView,
+    /// The screen and text message from before, brought back when the menu is closed.
+    previous: Option<MonoImage>,
+    pager: Option<Pager>,
+}
+
+/// The values of `CHART`, oldest first, and the label shown with them.
+pub(crate) struct Chart {
+    pub(crate) values: VecDeque<f32>,
+    /// Most values kept; `CHART ADD` drops the oldest beyond it.
+    window: usize,
+    pub(crate) label: String,
+}
+
+impl Default for Chart {
+    fn default() -> Self {
+        Self {
+            values: VecDeque::new(),
+            window: DEFAULT_CHART_WINDOW,
+            label: String::new(),
+        }
+    }
+}
+
+/// Values a `CHART` keeps unless `window=` says otherwise.
+pub(crate) const DEFAULT_CHART_WINDOW: usize = 60;
+
+/// Most values a `CHART` takes or keeps.
+pub(crate) const MAX_CHART_POINTS: usize = 500;
+
+/// Most `SHOW`/`AT` timers pending at once.
+const MAX_TIMERS: usize = 64;
+
+/// Longest a `SHOW` message stays up.
+const MAX_SHOW_DURATION: Duration = Duration::from_secs(24 * 3600);
+
+/// Templates that `PLAYLIST` cycles through, each shown for a while.
+#[derive(Default)]
+struct Playlist {
+    entries: Vec<PlaylistEntry>,
+    /// The entry shown next.
+    index: usize,
+    /// When it is due; `None` while the playlist is stopped.
+    due: Option<Instant>,
+}
+
+struct PlaylistEntry {
+    template: String,
+    vars: HashMap<String, String>,
+    dwell: Duration,
+}
+
+/// Most entries in a playlist.
+const MAX_PLAYLIST: usize = 64;
+
+/// Longest a playlist entry stays up.
+const MAX_PLAYLIST_DWELL: Duration = Duration::from_secs(24 * 3600);
+
+/// A screen change scheduled by `SHOW` or `AT`.
+struct Timer {
+    due: DateTime<Local>,
+    priority: Priority,
+    action: TimerAction,
+}
+
+enum TimerAction {
+    /// `AT`: show a message with the layout its connection had.
+    Show { message: String, layout: TextLayout },
+    /// A `SHOW` message ran out: put `previous` back (or blank the screen), unless something
+    /// else replaced `shown` in the meantime.
+    Restore {
+        shown: MonoImage,
+        previous: Option<MonoImage>,
+    },
+}
+
+impl ServerState {
+    pub(crate) fn new(min_interval: Option<Duration>, templates: Rc<RefCell<Templates>>) -> Self {
+        Self {
+            scene: Scene::default(),
+            templates,
+            playlist: Playlist::default(),
+            clock: Rc::default(),
+            timers: Vec::new(),
+            health: Arc::new(Health::new()),
+            display: DisplayMode::default(),
+            pager: None,
+            menu: None,
+            menu_open: None,
+            live: None,
+            chart: Chart::default(),
+            refreshes: Rc::default(),
+            queued: 0,
+            min_interval,
+            deferred: None,
+            shell: None,
+        }
+    }
+
+    /// Forget the text message, menu and live widget on screen, once something else
+    /// replaced them.
+    pub(crate) fn content_replaced(&mut self) {
+        self.pager = None;
+        self.menu_open = None;
+        self.live = None;
+    }
+
+    pub(crate) fn refresh_count(&self) -> u64 {
+        let counts = self.refreshes.get();
+        counts.full + counts.fast + counts.partial
+    }
+
+    /// Note the refreshes since `before`, which make a held-back `TEXT` frame outdated.
+    /// Returns whether there were any.
+    pub(crate) fn refreshed_since(&mut self, before: u64) -> bool {
+        let refreshed = self.refresh_count() != before;
+        if refreshed {
+            self.health.refreshed();
+            self.deferred = None;
+        }
+        refreshed
+    }
+
+    /// Whether a `TEXT` message now would come too soon after the last refresh.
+    fn too_soon(&self) -> bool {
+        self.min_interval
+            .zip(self.health.last_refresh())
+            .is_some_and(|(interval, last)| last.elapsed() < interval)
+    }
+
+    /// Show the held-back `TEXT` frame once `--min-interval` has passed.
+    pub(crate) fn show_deferred(
+        &mut self,
+        epd: &mut dyn Panel,
+        cli: &Cli,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if self.too_soon() {
+            return Ok(());
+        }
+        let Some((fb, refresh)) = self.deferred.take() else {
+            return Ok(());
+        };
+        info!("Showing the latest of the coalesced messages");
+        show_planned(epd, &fb, refresh, cli)?;
+        self.health.refreshed();
+        Ok(())
+    }
+
+    /// Whether the menu `run` command has finished, so its output can be shown.
+    pub(crate) fn shell_done(&self) -> bool {
+        self.shell
+            .as_ref()
+            .is_some_and(|run| run.output.get().is_some())
+    }
+
+    /// Show the output of a finished menu `run` command like `TEXT`, pageable with `PAGE`.
+    pub(crate) fn show_shell_output(
+        &mut self,
+        epd: &mut dyn Panel,
+        cli: &Cli,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(run) = self.shell.take_if(|run| run.output.get().is_some()) else {
+            return Ok(());
+        };
+        let output = run.output.get().map_or("", String::as_str);
+        let output = if output.trim().is_empty() {
+            "(no output)"
+        } else {
+            output
+        };
+        let fb = build_framebuffer(output, &run.layout, cli.rotate);
+        let refresh = self.display.plan(run.mode, cli);
+        self.content_replaced();
+        show_planned(epd, &fb, refresh, cli)?;
+        self.pager = Some(Pager::new(output));
+        Ok(())
+    }
+
+    pub(crate) fn timer_due(&self) -> bool {
+        let now = Local::now();
+        self.timers.iter().any(|timer| timer.due <= now)
+    }
+
+    /// Whether the playlist should move on. It waits while a `SHOW` message or the menu is
+    /// up.
+    pub(crate) fn playlist_due(&self) -> bool {
+        self.playlist.due.is_some_and(|due| due <= Instant::now())
+            && self.alert().is_none()
+            && self.menu_open.is_none()
+    }
+
+    /// Show the playlist's next entry and schedule the one after it. An entry whose
+    /// template is gone or does not render is skipped.
+    pub(crate) fn advance_playlist(
+        &mut self,
+        epd: &mut dyn Panel,
+        cli: &Cli,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let playlist = &mut self.playlist;
+        if playlist.entries.is_empty() {
+            playlist.due = None;
+            return Ok(());
+        }
+        let entry = &playlist.entries[playlist.index % playlist.entries.len()];
+        playlist.index = (playlist.index + 1) % playlist.entries.len();
+        // `None` stops the playlist, should the dwell ever overflow the clock.
+        playlist.due = Instant::now().checked_add(entry.dwell);
+        let canvas = panel_area(cli.rotate).size;
+        match self
+            .templates
+            .borrow()
+            .render(&entry.template, &entry.vars, canvas)
+        {
+            Ok(fb) => {
+                debug!(template = entry.template, "Showing the next playlist entry");
+                let refresh = self.display.plan(None, cli);
+                self.pager = None;
+                show_planned(epd, &fb, refresh, cli)
+            }
+            Err(reply) => {
+                warn!(
+                    template = entry.template,
+                    reply, "Skipping a playlist entry"
+                );
+                Ok(())
+            }
+        }
+    }
+
+    /// Priority of the most urgent `SHOW` message that has not expired yet.
+    fn alert(&self) -> Option<Priority> {
+        self.timers
+            .iter()
+            .filter(|timer| matches!(timer.action, TimerAction::Restore { .. }))
+            .map(|timer| timer.priority)
+            .max()
+    }
+
+    /// Whether a packet has to wait for a more urgent `SHOW` message to expire.
+    pub(crate) fn holds(&self, packet: &SocketPacket) -> bool {
+        self.alert()
+            .is_some_and(|alert| packet.priority < alert && packet.changes_screen())
+    }
+
+    /// Carry out the timers that are due, oldest first. Called from the display loop, which
+    /// wakes up at least every 100 ms.
+    pub(crate) fn run_due_timers(
+        &mut self,
+        epd: &mut dyn Panel,
+        cli: &Cli,
+        bg: BinaryColor,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if self.timers.is_empty() {
+            return Ok(());
+        }
+        let now = Local::now();
+        let held = self.alert();
+        // An `AT` message less urgent than the `SHOW` message on screen waits for it.
+        let (mut due, waiting): (Vec<Timer>, Vec<Timer>) = std::mem::take(&mut self.timers)
+            .into_iter()
+            .partition(|timer| {
+                let ready = match timer.action {
+                    TimerAction::Show { .. } => held.is_none_or(|alert| timer.priority >= alert),
+                    TimerAction::Restore { .. } => true,
+                };
+                timer.due <= now && ready
+            });
+        self.timers = waiting;
+        due.sort_by_key(|timer| timer.due);
+        for timer in due {
+            match timer.action {
+                TimerAction::Show { message, layout } => {
+                    info!("Showing a scheduled message");
+                    present(
+                        epd,
+                        &build_framebuffer(&message, &layout, cli.rotate),
+                        false,
+                        cli,
+                    )?;
+                    self.display.has_base = false;
+                    self.content_replaced();
+                }
+                TimerAction::Restore { shown, previous } => {
+                    let current = last_canvas(cli);
+                    if current.is_some_and(|current| changed_area(&current, &shown).is_none()) {
+                        info!("Message expired; restoring the previous screen");
+                        let canvas = panel_area(cli.rotate).size;
+                        let fb = previous.unwrap_or_else(|| blank_framebuffer(canvas, bg));
+                        present(epd, &fb, false, cli)?;
+                        self.display.has_base = false;
+                        self.content_replaced();
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Split off the first whitespace-separated word; the rest keeps its inner spacing.
+fn split_word(input: &str) -> (&str, &str) {
+    let input = input.trim_start();
+    match input.split_once(char::is_whitespace) {
+        Some((word, rest)) => (word, rest.trim_start()),
+        None => (input, ""),
+    }
+}
+
+/// Most shapes a connection can queue before `FLUSH`.
+const MAX_SHAPES: usize = 4096;
+
+/// A drawing primitive queued by `LINE`, `RECT`, `CIRCLE` or `PIXEL`, in canvas coordinates.
+enum Shape {
+    Line(Line),
+    Rect { area: Rectangle, fill: bool },
+    Circle(Circle),
+    Pixel(Point),
+}
+
+impl Shape {
+    /// Parse the arguments of a drawing command: `x1 y1 x2 y2`, `x y w h [fill]`, `x y r`
+    /// or `x y`. Coordinates may lie off the screen, within 1024 pixels.
+    fn parse(cmd: PacketCommand, args: &str) -> Option<Self> {
+        let words: Vec<&str> = args.split_whitespace().collect();
+        let (numbers, fill) = match words.split_last() {
+            Some((last, rest)) if last.eq_ignore_ascii_case("fill") => (rest, true),
+            _ => (&words[..], false),
+        };
+        let numbers: Vec<i32> = numbers
+            .iter()
+            .map(|word| word.parse().ok().filter(|n: &i32| n.abs() <= 1024))
+            .collect::<Option<_>>()?;
+        let shape = match (cmd, numbers.as_slice(), fill) {
+            (PacketCommand::Line, &[x1, y1, x2, y2], false) => {
+                Shape::Line(Line::new(Point::new(x1, y1), Point::new(x2, y2)))
+            }
+            (PacketCommand::Rect, &[x, y, w, h], fill) if w > 0 && h > 0 => Shape::Rect {
+                area: Rectangle::new(Point::new(x, y), Size::new(w as u32, h as u32)),
+                fill,
+            },
+            (PacketCommand::Circle, &[x, y, r], false) if r >= 0 => {
+                Shape::Circle(Circle::with_center(Point::new(x, y), 2 * r as u32 + 1))
+            }
+            (PacketCommand::Pixel, &[x, y], false) => Shape::Pixel(Point::new(x, y)),
+            _ => return None,
+        };
+        Some(shape)
+    }
+
+    fn draw(&self, fb: &mut MonoImage, color: BinaryColor) {
+        let stroke = PrimitiveStyle::with_stroke(color, 1);
+        match self {
+            Shape::Line(line) => line.into_styled(stroke).draw(fb).ok(),
+            Shape::Rect { area, fill } => {
+                let style = if *fill {
+                    PrimitiveStyle::with_fill(color)
+                } else {
+                    stroke
+                };
+                area.into_styled(style).draw(fb).ok()
+            }
+            Shape::Circle(circle) => circle.into_styled(stroke).draw(fb).ok(),
+            Shape::Pixel(point) => Pixel(*point, color).draw(fb).ok(),
+        };
+    }
+}
+
+/// Layouts saved by `TEMPLATE SAVE`: layout JSON with `{{name}}` placeholders, by name.
+/// With `serve --templates` they are also kept on disk.
+pub(crate) struct Templates {
+    dir: Option<PathBuf>,
+    sources: BTreeMap<String, String>,
+}
+
+/// Longest template name.
+const MAX_TEMPLATE_NAME: usize = 64;
+
+impl Templates {
+    /// The templates stored in `dir`, if given; a missing directory holds none yet.
+    pub(crate) fn load(dir: Option<PathBuf>) -> io::Result<Self> {
+        let mut sources = BTreeMap::new();
+        if let Some(dir) = &dir {
+            let entries = match std::fs::read_dir(dir) {
+                Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+                entries => Some(entries?),
+            };
+            for entry in entries.into_iter().flatten() {
+                let path = entry?.path();
+                let name = path.file_stem().and_then(|stem| stem.to_str());
+                match name {
+                    Some(name) if path.extension() == Some("json".as_ref()) => {
+                        sources.insert(name.to_string(), std::fs::read_to_string(&path)?);
+                    }
+                    _ => {}
+                }
+            }
+            info!(count = sources.len(), dir = %dir.display(), "Loaded templates");
+        }
+        Ok(Self { dir, sources })
+    }
+
+    fn valid_name(name: &str) -> bool {
+        !name.is_empty()
+            && name.len() <= MAX_TEMPLATE_NAME
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    }
+
+    fn save(&mut self, name: &str, source: &str) -> io::Result<()> {
+        if let Some(dir) = &self.dir {
+            std::fs::create_dir_all(dir)?;
+            std::fs::write(dir.join(format!("{name}.json")), source)?;
+        }
+        self.sources.insert(name.to_string(), source.to_string());
+        Ok(())
+    }
+
+    /// Fill in template `name` with `vars` and render the layout, or the error reply.
+    fn render(
+        &self,
+        name: &str,
+        vars: &HashMap<String, String>,
+        canvas: Size,
+    ) -> Result<MonoImage, String> {
+        let source = self
+            .sources
+            .get(name)
+            .ok_or_else(|| "ERR TEMPLATE_UNKNOWN".to_string())?;
+        let json = template::render(source, vars).map_err(|err| match err {
+            TemplateError::UnknownVariable(var) => format!("ERR VARIABLE_UNKNOWN {var}"),
+            _ => "ERR TEMPLATE_INVALID".to_string(),
+        })?;
+        LayoutSpec::from_json(&json)
+            .and_then(|spec| spec.render(canvas, Path::new(".")))
+            .map_err(|err| {
+                warn!("Template {name} gave an invalid layout: {err}");
+                "ERR LAYOUT_INVALID".to_string()
+            })
+    }
+
+    /// Forget a template; returns whether there was one.
+    fn remove(&mut self, name: &str) -> io::Result<bool> {
+        if self.sources.remove(name).is_none() {
+            return Ok(false);
+        }
+        if let Some(dir) = &self.dir {
+            std::fs::remove_file(dir.join(format!("{name}.json")))?;
+        }
+        Ok(true)
+    }
+}
+
+/// Named text layers composed into one screen by `LAYER` commands, shared by all socket
+/// clients. Layers are drawn by increasing `z`, then in the order they were added.
+#[derive(Default)]
+struct Scene {
+    layers: Vec<Layer>,
+}
+
+struct Layer {
+    name: String,
+    pos: Point,
+    /// `None` reaches to the right and bottom edges of the screen.
+    size: Option<Size>,
+    layout: TextLayout,
+    z: i32,
+    text: String,
+}
+
+impl Scene {
+    fn render(&self, canvas: Size, bg: BinaryColor) -> MonoImage {
+        let mut fb = blank_framebuffer(canvas, bg);
+        let mut layers: Vec<&Layer> = self.layers.iter().collect();
+        layers.sort_by_key(|layer| layer.z);
+        for layer in layers {
+            let size = layer.size.unwrap_or_else(|| {
+                Size::new(
+                    canvas.width.saturating_sub(layer.pos.x as u32),
+                    canvas.height.saturating_sub(layer.pos.y as u32),
+                )
+            });
+            blit(&mut fb, &layer.layout.render(&layer.text, size), layer.pos);
+        }
+        fb
+    }
+
+    fn find(&mut self, name: &str) -> Option<&mut Layer> {
+        self.layers.iter_mut().find(|layer| layer.name == name)
+    }
+}
+
+/// `LAYER SET` options; those left out keep the layer's current value.
+#[derive(Debug, Default)]
+struct LayerOptions<'a> {
+    pos: Option<Point>,
+    size: Option<Size>,
+    font: Option<&'a str>,
+    align: Option<&'a str>,
+    z: Option<i32>,
+    text: Option<&'a str>,
+}
+
+/// Parse `key=value` options; `text=` takes the rest of the line, spaces included.
+fn parse_layer_options(args: &str) -> Option<LayerOptions<'_>> {
+    let pair = |value: &str| -> Option<(u32, u32)> {
+        let (a, b) = value.split_once(',')?;
+        Some((a.trim().parse().ok()?, b.trim().parse().ok()?))
+    };
+    let mut options = LayerOptions::default();
+    let mut rest = args.trim_start();
+    while !rest.is_empty() {
+        if let Some(text) = rest.strip_prefix("text=") {
+            options.text = Some(text);
+            break;
+        }
+        let (word, tail) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        rest = tail.trim_start();
+        let (key, value) = word.split_once('=')?;
+        match key {
+            "pos" => {
+                let (x, y) = pair(value)?;
+                options.pos = Some(Point::new(x as i32, y as i32));
+            }
+            "size" => {
+                let (width, height) = pair(value)?;
+                options.size = Some(Size::new(width, height));
+            }
+            "font" => options.font = Some(value),
+            "align" => options.align = Some(value),
+            "z" => options.z = Some(value.parse().ok()?),
+            _ => return None,
+        }
+    }
+    Some(options)
+}
+
+/// Set the font by name, or `auto` to fit each message; `false` for an unknown name.
+pub(crate) fn set_font(layout: &mut TextLayout, name: &str) -> bool {
+    if name.eq_ignore_ascii_case("auto") {
+        layout.auto_fit = true;
+        return true;
+    }
+    match name.parse::<FontChoice>() {
+        Ok(choice) => {
+            layout.font = choice;
+            layout.auto_fit = false;
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// Run `LAYER SET|DEL|Z|LIST|CLEAR` and refresh what changed. Returns the reply.
+fn handle_layer(
+    args: &str,
+    epd: &mut dyn Panel,
+    cli: &Cli,
+    bg: BinaryColor,
+    session: &mut Session,
+    scene: &mut Scene,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let (action, rest) = split_word(args);
+    let (name, rest) = split_word(rest);
+    match action.to_ascii_uppercase().as_str() {
+        "LIST" => {
+            let names: Vec<&str> = scene
+                .layers
+                .iter()
+                .map(|layer| layer.name.as_str())
+                .collect();
+            return Ok(format!("OK LAYER {}", names.join(" "))
+                .trim_end()
+                .to_string());
+        }
+        "CLEAR" => scene.layers.clear(),
+        _ if name.is_empty() => return Ok("ERR LAYER_INVALID".to_string()),
+        "SET" => {
+            let Some(options) = parse_layer_options(rest) else {
+                return Ok("ERR LAYER_INVALID".to_string());
+            };
+            let canvas = panel_area(cli.rotate);
+            if options.pos.is_some_and(|pos| !canvas.contains(pos))
+                || options
+                    .size
+                    .is_some_and(|size| size.width == 0 || size.height == 0)
+            {
+                return Ok("ERR LAYER_INVALID".to_string());
+            }
+            let existing = scene.layers.iter().position(|layer| layer.name == name);
+            let mut layout = match existing {
+                Some(index) => scene.layers[index].layout,
+                None => bare_layout(&session.layout),
+            };
+            if options
+                .font
+                .is_some_and(|font| !set_font(&mut layout, font))
+            {
+                return Ok("ERR FONT_UNKNOWN".to_string());
+            }
+            if let Some(align) = options.align {
+                match align.parse::<Alignment>() {
+                    Ok(alignment) => layout.alignment = alignment,
+                    Err(_) => return Ok("ERR ALIGN_UNKNOWN".to_string()),
+                }
+            }
+            let index = existing.unwrap_or_else(|| {
+                scene.layers.push(Layer {
+                    name: name.to_string(),
+                    pos: Point::zero(),
+                    size: None,
+                    layout,
+                    z: 0,
+                    text: String::new(),
+                });
+                scene.layers.len() - 1
+            });
+            let layer = &mut scene.layers[index];
+            layer.layout = layout;
+            layer.pos = options.pos.unwrap_or(layer.pos);
+            layer.size = options.size.or(layer.size);
+            layer.z = options.z.unwrap_or(layer.z);
+            if let Some(text) = options.text {
+                layer.text = decode_newlines(text);
+            }
+        }
+        "DEL" => {
+            let before = scene.layers.len();
+            scene.layers.retain(|layer| layer.name != name);
+            if scene.layers.len() == before {
+                return Ok("ERR LAYER_UNKNOWN".to_string());
+            }
+        }
+        "Z" => {
+            let Some(layer) = scene.find(name) else {
+                return Ok("ERR LAYER_UNKNOWN".to_string());
+            };
+            match rest.trim().parse() {
+                Ok(z) => layer.z = z,
+                Err(_) => return Ok("ERR LAYER_INVALID".to_string()),
+            }
+        }
+        _ => return Ok("ERR LAYER_INVALID".to_string()),
+    }
+    let fb = scene.render(panel_area(cli.rotate).size, bg);
+    session.show_changes(epd, &fb, cli)?;
+    Ok("OK LAYER".to_string())
+}
+
+/// Run `TEMPLATE SAVE|SHOW|DEL|LIST`, showing with `mode` (from a suffix such as
+/// `TEMPLATE_FAST`) or the session's default. Returns the reply.
+fn handle_template(
+    args: &str,
+    mode: Option<UpdateMode>,
+    epd: &mut dyn Panel,
+    cli: &Cli,
+    session: &mut Session,
+    state: &mut ServerState,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let (action, rest) = split_word(args);
+    let (name, rest) = split_word(rest);
+    let mut templates = state.templates.borrow_mut();
+    match action.to_ascii_uppercase().as_str() {
+        "LIST" => {
+            let names: Vec<&str> = templates.sources.keys().map(String::as_str).collect();
+            Ok(format!("OK TEMPLATE {}", names.join(" "))
+                .trim_end()
+                .to_string())
+        }
+        _ if !Templates::valid_name(name) => Ok("ERR TEMPLATE_INVALID".to_string()),
+        "SAVE" => {
+            let source = rest.trim();
+            if let Err(err) = template::check(source) {
+                debug!("Rejected template {name}: {err}");
+                return Ok("ERR TEMPLATE_INVALID".to_string());
+            }
+            templates.save(name, source)?;
+            Ok("OK TEMPLATE SAVE".to_string())
+        }
+        "DEL" => Ok(if templates.remove(name)? {
+            "OK TEMPLATE DEL"
+        } else {
+            "ERR TEMPLATE_UNKNOWN"
+        }
+        .to_string()),
+        "SHOW" => {
+            let Some(vars) = parse_template_vars(rest) else {
+                return Ok("ERR TEMPLATE_INVALID".to_string());
+            };
+            match templates.render(name, &vars, panel_area(cli.rotate).size) {
+                Ok(fb) => {
+                    session.show(epd, &fb, mode, cli)?;
+                    Ok("OK TEMPLATE SHOW".to_string())
+                }
+                Err(reply) => Ok(reply),
+            }
+        }
+        _ => Ok("ERR TEMPLATE_INVALID".to_string()),
+    }
+}
+
+/// Run `PLAYLIST ADD|START|STOP|CLEAR|LIST`. The display loop shows the entries.
+fn handle_playlist(args: &str, state: &mut ServerState) -> String {
+    let (action, rest) = split_word(args);
+    let playlist = &mut state.playlist;
+    match action.to_ascii_uppercase().as_str() {
+        "ADD" => {
+            let (template, rest) = split_word(rest);
+            let (dwell, vars) = split_word(rest);
+            if !state.templates.borrow().sources.contains_key(template) {
+                return "ERR TEMPLATE_UNKNOWN".to_string();
+            }
+            let dwell = match parse_interval(dwell) {
+                Ok(dwell) if !dwell.is_zero() && dwell <= MAX_PLAYLIST_DWELL => dwell,
+                _ => return "ERR DURATION_INVALID".to_string(),
+            };
+            let Some(vars) = parse_template_vars(vars) else {
+                return "ERR TEMPLATE_INVALID".to_string();
+            };
+            if playlist.entries.len() >= MAX_PLAYLIST {
+                return "ERR PLAYLIST_FULL".to_string();
+            }
+            playlist.entries.push(PlaylistEntry {
+                template: template.to_string(),
+                vars,
+                dwell,
+            });
+            format!("OK PLAYLIST ADD {}", playlist.entries.len())
+        }
+        "START" if playlist.entries.is_empty() => "ERR PLAYLIST_EMPTY".to_string(),
+        "START" => {
+            playlist.index = 0;
+            playlist.due = Some(Instant::now());
+            "OK PLAYLIST START".to_string()
+        }
+        "STOP" => {
+            playlist.due = None;
+            "OK PLAYLIST STOP".to_string()
+        }
+        "CLEAR" => {
+            *playlist = Playlist::default();
+            "OK PLAYLIST CLEAR".to_string()
+        }
+        "LIST" => {
+            let status = if playlist.due.is_some() {
+                "running"
+            } else {
+                "stopped"
+            };
+            let entries: Vec<String> = playlist
+                .entries
+                .iter()
+                .map(|entry| format!("{}:{}s", entry.template, entry.dwell.as_secs()))
+                .collect();
+            format!("OK PLAYLIST {status} {}", entries.join(" "))
+                .trim_end()
+                .to_string()
+        }
+        _ => "ERR PLAYLIST_INVALID".to_string(),
+    }
+}
+
+/// Run `MENU [OPEN|CLOSE|UP|DOWN|SELECT|BACK]`. A closed menu opens on anything but `CLOSE`
+/// and `BACK`; a bare `MENU` closes an open one, as does `BACK` at the top level, bringing
+/// back the screen from before. Selecting a command closes the menu and runs it in its
+/// place, with its reply; a shell command shows its output like `TEXT`.
+#[allow(clippy::too_many_arguments)]
+fn handle_menu(
+    args: &str,
+    priority: Priority,
+    mode: Option<UpdateMode>,
+    epd: &mut dyn Panel,
+    cli: &Cli,
+    bg: BinaryColor,
+    session: &mut Session,
+    state: &mut ServerState,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let Some(menu) = state.menu.clone() else {
+        return Ok("ERR NO_MENU".to_string());
+    };
+    let action = args.trim().to_ascii_uppercase();
+    let canvas = panel_area(cli.rotate).size;
+    let layout = &session.layout;
+    let render = |open: &MenuState| {
+        open.render(
+            &menu,
+            layout.font,
+            layout.foreground,
+            layout.background,
+            canvas,
+        )
+    };
+    let Some(open) = state.menu_open.as_mut() else {
+        match action.as_str() {
+            "" | "OPEN" | "UP" | "DOWN" | "SELECT" => {}
+            "CLOSE" | "BACK" => return Ok("OK MENU CLOSE".to_string()),
+            _ => return Ok("ERR MENU_INVALID".to_string()),
+        }
+        let mut open = OpenMenu {
+            state: MenuState::new(),
+            view: LiveView::default(),
+            previous: last_canvas(cli),
+            pager: None,
+        };
+        open.view.show(epd, &render(&open.state), cli)?;
+        open.pager = session.pager.take();
+        session.display.has_base = true;
+        state.menu_open = Some(open);
+        return Ok("OK MENU".to_string());
+    };
+    let (close, selected) = match action.as_str() {
+        "OPEN" => (false, None),
+        "UP" => {
+            open.state.up(&menu);
+            (false, None)
+        }
+        "DOWN" => {
+            open.state.down(&menu);
+            (false, None)
+        }
+        "SELECT" => (false, open.state.select(&menu)),
+        "BACK" => (!open.state.back(), None),
+        "" | "CLOSE" => (true, None),
+        _ => return Ok("ERR MENU_INVALID".to_string()),
+    };
+    if !close && selected.is_none() {
+        open.view.show(epd, &render(&open.state), cli)?;
+        session.display.has_base = true;
+        return Ok("OK MENU".to_string());
+    }
+    let open = state.menu_open.take().expect("the menu is open");
+    match selected {
+        None => {
+            let fb = open
+                .previous
+                .unwrap_or_else(|| blank_framebuffer(canvas, bg));
+            session.show(epd, &fb, mode, cli)?;
+            session.pager = open.pager;
+            Ok("OK MENU CLOSE".to_string())
+        }
+        Some(MenuAction::Command(line)) => {
+            info!(command = line, "Running a menu command");
+            let packet = SocketPacket {
+                line,
+                data: None,
+                priority,
+                panel: None,
+                tag: None,
+                terminal: false,
+            };
+            handle_packet(&packet, epd, cli, bg, session, state)
+        }
+        Some(MenuAction::Shell(command)) => {
+            // The command runs on its own thread so the panel keeps serving clients; the
+            // display loop shows its output once it is done.
+            info!(command, "Running a menu shell command");
+            let notice = format!("Running {command}...");
+            let fb = build_framebuffer(&notice, &session.layout, cli.rotate);
+            session.show(epd, &fb, mode, cli)?;
+            let output = Arc::new(OnceLock::new());
+            let done = Arc::clone(&output);
+            thread::spawn(move || {
+                let output = run_shell(&command, SHELL_TIMEOUT)
+                    .unwrap_or_else(|err| format!("Cannot run {command}: {err}"));
+                done.set(output).ok();
+            });
+            state.shell = Some(ShellRun {
+                output,
+                layout: session.layout,
+                mode,
+            });
+            Ok("OK MENU RUN".to_string())
+        }
+    }
+}
+
+/// Run `CHART <v1,v2,...> [window=<n>] [label]`, `CHART ADD <value> [window=<n>] [label]`
+/// or `CHART CLEAR`. The first replaces the series, the second appends to it, dropping the
+/// oldest values beyond the window; both show it. A label, once given, stays until replaced.
+fn handle_chart(
+    args: &str,
+    epd: &mut dyn Panel,
+    cli: &Cli,
+    session: &mut Session,
+    state: &mut ServerState,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let (first, rest) = split_word(args);
+    let chart = &mut state.chart;
+    if first.eq_ignore_ascii_case("CLEAR") {
+        chart.values.clear();
+        return Ok("OK CHART 0".to_string());
+    }
+    let (values, rest) = if first.eq_ignore_ascii_case("ADD") {
+        split_word(rest)
+    } else {
+        (first, rest)
+    };
+    let Some(values) = values
+        .split(',')
+        .map(|value| value.parse::<f32>().ok().filter(|value| value.is_finite()))
+        .collect::<Option<Vec<f32>>>()
+    else {
+        return Ok("ERR CHART_INVALID".to_string());
+    };
+    let (option, label) = split_word(rest);
+    let (window, label) = match option.strip_prefix("window=") {
+        Some(window) => match window.parse() {
+            Ok(window) if (1..=MAX_CHART_POINTS).contains(&window) => (Some(window), label),
+            _ => return Ok("ERR CHART_INVALID".to_string()),
+        },
+        None => (None, rest),
+    };
+    if values.len() > MAX_CHART_POINTS {
+        return Ok("ERR CHART_TOO_LARGE".to_string());
+    }
+    if !first.eq_ignore_ascii_case("ADD") {
+        chart.values.clear();
+    }
+    chart.window = window.unwrap_or(chart.window);
+    chart.values.extend(values);
+    let excess = chart.values.len().saturating_sub(chart.window);
+    chart.values.drain(..excess);
+    if !label.trim().is_empty() {
+        chart.label = label.trim().to_string();
+    }
+    let fb = build_chart_framebuffer(chart, &session.layout, cli.rotate);
+    let view = state.live.get_or_insert_with(LiveView::default);
+    view.show(epd, &fb, cli)?;
+    session.display.has_base = true;
+    session.pager = None;
+    Ok(format!("OK CHART {}", state.chart.values.len()))
+}
+
+/// The `name=value` pairs of `TEMPLATE SHOW`. A value with spaces goes in double quotes,
+/// with `\"` for a quote inside; `\n` is a line break either way. Values are escaped for
+/// the JSON strings their placeholders stand in.
+fn parse_template_vars(args: &str) -> Option<HashMap<String, String>> {
+    let mut vars = HashMap::new();
+    let mut rest = args.trim_start();
+    while !rest.is_empty() {
+        let (name, after) = rest.split_once('=')?;
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return None;
+        }
+        let (value, after) = match after.strip_prefix('"') {
+            Some(quoted) => {
+                let mut value = String::new();
+                let mut chars = quoted.char_indices();
+                let end = loop {
+                    match chars.next()? {
+                        (index, '"') => break index + 1,
+                        (_, '\\') => match chars.next()?.1 {
+                            'n' => value.push('\n'),
+                            escaped => value.push(escaped),
+                        },
+                        (_, c) => value.push(c),
+                    }
+                };
+                (value, &quoted[end..])
+            }
+            None => {
+                let end = after.find(char::is_whitespace).unwrap_or(after.len());
+                (decode_newlines(&after[..end]), &after[end..])
+            }
+        };
+        if !(after.is_empty() || after.starts_with(char::is_whitespace)) {
+            return None;
+        }
+        let json = serde_json::Value::from(value).to_string();
+        vars.insert(name.to_string(), json[1..json.len() - 1].to_string());
+        rest = after.trim_start();
+    }
+    Some(vars)
+}
+
+/// `COMMIT`: carry out a batch off-screen, starting from a copy of the stored frame, then
+/// show the frame it leaves behind with one refresh (`mode`, or the session's default).
+/// When a packet fails the screen is left alone and the reply names the packet; settings
+/// and layers changed by the packets before it keep their new values.
+fn commit_batch(
+    batch: &[SocketPacket],
+    mode: Option<UpdateMode>,
+    epd: &mut dyn Panel,
+    cli: &Cli,
+    bg: BinaryColor,
+    session: &mut Session,
+    state: &mut ServerState,
+) -> Result<String, Box<dyn std::error::Error>> {
+    // Each packet stores its frame here instead, so the next one builds on it.
+    let scratch = Cli {
+        persist_frame: named_path(&cli.persist_frame, "batch"),
+        ..cli.clone()
+    };
+    let store = FrameStore::new(&scratch.persist_frame);
+    store.remove()?;
+    if let Some(frame) = FrameStore::new(&cli.persist_frame).load()? {
+        store.save(&frame)?;
+    }
+    // Refresh planning and `--min-interval` concern the real panel only.
+    let (display, min_interval) = (session.display, state.min_interval.take());
+    let mut failure = None;
+    for (index, packet) in batch.iter().enumerate() {
+        let reply = handle_packet(packet, &mut DiscardPanel, &scratch, bg, session, state)
+            .unwrap_or_else(|err| failure_reply(&packet.line, &*err));
+        if is_failure(&reply) {
+            failure = Some(format!(
+                "ERR BATCH_FAILED command {} of {}: {reply}",
+                index + 1,
+                batch.len()
+            ));
+            break;
+        }
+    }
+    state.min_interval = min_interval;
+    session.display = display;
+    let composed = last_canvas(&scratch);
+    store.remove()?;
+    if let Some(failure) = failure {
+        return Ok(failure);
+    }
+    let shown = last_canvas(cli);
+    match composed {
+        Some(fb) if shown.is_none_or(|shown| shown.data() != fb.data()) => {
+            session.show(epd, &fb, mode, cli)?;
+        }
+        _ => debug!("The batch left the screen as it was"),
+    }
+    Ok(format!("OK COMMIT {}", batch.len()))
+}
+
+/// Whether a reply, plain or JSON, reports an error.
+fn is_failure(reply: &str) -> bool {
+    if reply.starts_with('{') {
+        let reply = serde_json::from_str::<serde_json::Value>(reply).unwrap_or_default();
+        return reply["ok"] == false;
+    }
+    reply.starts_with("ERR")
+}
+
+/// Read packets from one client and pass them to the display thread one at a time, so
+/// replies keep their order. When the queue is full the packet is refused with `ERR BUSY`.
+/// After `ASYNC`, screen changes are answered `ACCEPTED <id>` as soon as they are queued,
+/// and their replies are kept for `WAIT`.
+pub(crate) fn handle_connection(
+    stream: UnixStream,
+    jobs: &mpsc::SyncSender<SocketJob>,
+    layout: TextLayout,
+    panels: &[PanelInfo],
+    subscribers: &Subscribers,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let writer = Arc::new(Mutex::new(ClientWriter {
+        stream,
+        binary: false,
+    }));
+    let send = |message: &str| writer.lock().expect("client writer poisoned").send(message);
+    let results = AsyncResults::default();
+
+    // Switched on by `BINARY`, `TERM` and `SUBSCRIBE`, for the rest of the connection.
+    let mut binary = false;
+    let mut terminal = false;
+    let mut subscribed = false;
+    let mut asynchronous = false;
+    // The last `ACCEPTED` id.
+    let mut accepted = 0;
+
+    thread::scope(|scope| {
+        // Packets go through another thread, which waits for the display, so that the
+        // reader can acknowledge them early.
+        let (outbox, packets) = mpsc::sync_channel(ASYNC_BACKLOG);
+        let sessions = PanelSessions::new(layout, panels);
+        let results = &results;
+        scope.spawn(move || submit_packets(&packets, jobs, sessions, results));
+
+        loop {
+            let incoming = if binary {
+                read_binary_packet(&mut reader)?
+            } else if terminal {
+                read_terminal_lines(&mut reader)?
+            } else {
+                read_line_packet(&mut reader)?
+            };
+            let packet = match incoming {
+                Incoming::Eof => break,
+                Incoming::Skip => continue,
+                Incoming::Reject { reply, close } => {
+                    send(&reply)?;
+                    if close {
+                        // The payload cannot be skipped safely, so drop the connection.
+                        break;
+                    }
+                    continue;
+                }
+                Incoming::Packet(packet) => packet,
+            };
+            let tag = packet.tag.clone();
+            let answer = |message: &str| send(&tagged(tag.as_deref(), message));
+            let (command, payload) = parse_packet(&packet.line);
+            match command {
+                _ if packet.terminal => {}
+                PacketCommand::Binary
+                | PacketCommand::Term
+                | PacketCommand::Subscribe
+                | PacketCommand::Async
+                | PacketCommand::Wait
+                    if packet.panel.is_some() =>
+                {
+                    answer("ERR COMMAND_INVALID")?;
+                    continue;
+                }
+                PacketCommand::Binary => {
+                    answer("OK BINARY")?;
+                    binary = true;
+                    writer.lock().expect("client writer poisoned").binary = true;
+                    continue;
+                }
+                PacketCommand::Term if binary => {
+                    answer("ERR TERM_BINARY")?;
+                    continue;
+                }
+                PacketCommand::Term => {
+                    answer("OK TERM")?;
+                    terminal = true;
+                    continue;
+                }
+                PacketCommand::Subscribe => {
+                    answer("OK SUBSCRIBE")?;
+                    if !std::mem::replace(&mut subscribed, true) {
+                        let (events, writer) = (subscribers.add(), Arc::clone(&writer));
+                        thread::spawn(move || forward_events(&events, &writer));
+                    }
+                    continue;
+                }
+                PacketCommand::Async => {
+                    match payload
+                        .unwrap_or_default()
+                        .trim()
+                        .to_ascii_uppercase()
+                        .as_str()
+                    {
+                        "" | "ON" => asynchronous = true,
+                        "OFF" => asynchronous = false,
+                        _ => {
+                            answer("ERR ASYNC_INVALID")?;
+                            continue;
+                        }
+                    }
+                    answer("OK ASYNC")?;
+                    continue;
+                }
+                PacketCommand::Wait => {
+                    let reply = match payload.map(str::trim).filter(|id| !id.is_empty()) {
+                        None => {
+                            drop(results.wait_all(accepted));
+                            "OK WAIT".to_string()
+                        }
+                        Some(id) => match id.parse() {
+                            Ok(id) if id <= accepted => results
+                                .wait(id)
+                                .unwrap_or_else(|| "ERR ID_UNKNOWN".to_string()),
+                            _ => "ERR ID_UNKNOWN".to_string(),
+                        },
+                    };
+                    answer(&reply)?;
+                    continue;
+                }
+                // Answered here, so it works while the display thread is stuck in a refresh.
+                PacketCommand::Ping => {
+                    let reply = match panels
+                        .iter()
+                        .find(|info| info.name.as_deref() == packet.panel.as_deref())
+                    {
+                        Some(info) => info.health.pong(),
+                        None => "ERR PANEL_UNKNOWN".to_string(),
+                    };
+                    answer(&reply)?;
+                    continue;
+                }
+                _ => {}
+            }
+
+            let is_json = packet.line.starts_with('{');
+            if asynchronous && !packet.terminal && packet.changes_screen() {
+                let id = accepted + 1;
+                let reply = match outbox.try_send((packet, ReplyTo::Kept(id))) {
+                    Ok(()) => {
+                        accepted = id;
+                        if is_json {
+                            serde_json::json!({ "ok": true, "accepted": id }).to_string()
+                        } else {
+                            format!("ACCEPTED {id}")
+                        }
+                    }
+                    Err(TrySendError::Full(_)) => busy_reply(is_json),
+                    Err(TrySendError::Disconnected(_)) => break,
+                };
+                answer(&reply)?;
+                continue;
+            }
+            let (reply_tx, reply) = mpsc::channel();
+            if outbox.send((packet, ReplyTo::Client(reply_tx))).is_err() {
+                break;
+            }
+            let Ok(reply) = reply.recv() else {
+                break;
+            };
+            debug!(response = reply, "Socket reply");
+            // A terminal only talks back when something goes wrong.
+            if !(terminal && reply.starts_with("OK")) {
+                answer(&reply)?;
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Packets a connection can have waiting for the display after `ASYNC`; more are refused
+/// with `ERR BUSY`.
+const ASYNC_BACKLOG: usize = 16;
+
+/// Where the reply to a packet goes.
+enum ReplyTo {
+    /// The reader, which is waiting to pass it on.
+    Client(mpsc::Sender<String>),
+    /// [`AsyncResults`], under the id the packet was `ACCEPTED` with.
+    Kept(u64),
+}
+
+/// Pass a connection's packets to the display thread one at a time, each with the session
+/// of the panel it is for. Accepted packets wait for room in the queue; the others are
+/// refused with `ERR BUSY` when it is full.
+fn submit_packets(
+    packets: &mpsc::Receiver<(SocketPacket, ReplyTo)>,
+    jobs: &mpsc::SyncSender<SocketJob>,
+    mut sessions: PanelSessions,
+    results: &AsyncResults,
+) {
+    let (reply_tx, replies) = mpsc::channel();
+    for (packet, reply_to) in packets {
+        let is_json = packet.line.starts_with('{');
+        let panel = packet.panel.clone();
+        let reply = match sessions.take(panel.as_deref()) {
+            None => "ERR PANEL_UNKNOWN".to_string(),
+            Some(session) => {
+                let job = SocketJob {
+                    packet,
+                    session,
+                    reply: reply_tx.clone(),
+                };
+                let queued = match reply_to {
+                    ReplyTo::Kept(_) => jobs
+                        .send(job)
+                        .map_err(|err| TrySendError::Disconnected(err.0)),
+                    ReplyTo::Client(_) => jobs.try_send(job),
+                };
+                let (session, reply) = match queued {
+                    Ok(()) => match replies.recv() {
+                        Ok(returned) => returned,
+                        Err(_) => break,
+                    },
+                    Err(TrySendError::Full(job)) => {
+                        warn!("Display queue full; refusing a packet");
+                        (job.session, busy_reply(is_json))
+                    }
+                    Err(TrySendError::Disconnected(_)) => break,
+                };
+                sessions.put(panel, session);
+                reply
+            }
+        };
+        match reply_to {
+            ReplyTo::Client(client) => {
+                client.send(reply).ok();
+            }
+            ReplyTo::Kept(id) => {
+                if reply.starts_with("ERR") {
+                    warn!(id, reply, "Accepted command failed");
+                }
+                results.finish(id, reply);
+            }
+        }
+    }
+    results.close();
+}
+
+fn busy_reply(is_json: bool) -> String {
+    if is_json {
+        error_json("BUSY", "the display queue is full; retry later").to_string()
+    } else {
+        "ERR BUSY".to_string()
+    }
+}
+
+/// Replies to a connection's `ACCEPTED` packets, for `WAIT`.
+#[derive(Default)]
+struct AsyncResults {
+    state: Mutex<AsyncState>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct AsyncState {
+    /// Packets are handled in order, so all ids up to this one are done.
+    finished: u64,
+    /// Replies nobody has waited for yet, the oldest dropped beyond [`MAX_RESULTS`].
+    replies: BTreeMap<u64, String>,
+    /// Nothing more will finish: the display thread is gone.
+    closed: bool,
+}
+
+/// Replies kept for `WAIT` per connection.
+const MAX_RESULTS: usize = 256;
+
+impl AsyncResults {
+    fn finish(&self, id: u64, reply: String) {
+        let mut state = self.state.lock().expect("async results poisoned");
+        state.finished = id;
+        state.replies.insert(id, reply);
+        if state.replies.len() > MAX_RESULTS {
+            state.replies.pop_first();
+        }
+        self.changed.notify_all();
+    }
+
+    fn close(&self) {
+        self.state.lock().expect("async results poisoned").closed = true;
+        self.changed.notify_all();
+    }
+
+    /// Wait until packet `id` is done and take its reply; `None` if it was taken already,
+    /// dropped, or will never be done.
+    fn wait(&self, id: u64) -> Option<String> {
+        self.wait_all(id).replies.remove(&id)
+    }
+
+    /// Wait until every packet up to `id` is done (or never will be).
+    fn wait_all(&self, id: u64) -> std::sync::MutexGuard<'_, AsyncState> {
+        let state = self.state.lock().expect("async results poisoned");
+        self.changed
+            .wait_while(state, |state| state.finished < id && !state.closed)
+            .expect("async results poisoned")
+    }
+}
+
+/// The sending side of a client connection. After `SUBSCRIBE`, events are written from
+/// another thread, so every message is sent under a lock.
+struct ClientWriter {
+    stream: UnixStream,
+    binary: bool,
+}
+
+impl ClientWriter {
+    fn send(&mut self, message: &str) -> io::Result<()> {
+        respond(&mut self.stream, self.binary, message)
+    }
+}
+
+/// Connections that sent `SUBSCRIBE`, each with a channel to the thread writing its events.
+#[derive(Clone, Default)]
+pub(crate) struct Subscribers(Arc<Mutex<Vec<mpsc::Sender<String>>>>);
+
+impl Subscribers {
+    fn add(&self) -> mpsc::Receiver<String> {
+        let (events_tx, events) = mpsc::channel();
+        self.0
+            .lock()
+            .expect("subscriber list poisoned")
+            .push(events_tx);
+        events
+    }
+
+    /// Send an event line to every subscriber, forgetting those that went away.
+    fn publish(&self, event: &str) {
+        let mut subscribers = self.0.lock().expect("subscriber list poisoned");
+        subscribers.retain(|subscriber| subscriber.send(event.to_string()).is_ok());
+    }
+
+    /// Publish what a panel did: `EVENT REFRESH full 812ms` or `EVENT SLEEP`, with
+    /// ` panel=NAME` after it for a `--panel`.
+    pub(crate) fn panel_event(&self, event: PanelEvent, panel: Option<&str>) {
+        let mut line = match event {
+            PanelEvent::Refresh { mode, took } => {
+                format!("EVENT REFRESH {} {}ms", mode_name(mode), took.as_millis())
+            }
+            PanelEvent::Sleep => "EVENT SLEEP".to_string(),
+        };
+        if let Some(panel) = panel {
+            line.push_str(&format!(" panel={panel}"));
+        }
+        self.publish(&line);
+    }
+}
+
+/// Write the events of a subscribed connection until it goes away.
+fn forward_events(events: &mpsc::Receiver<String>, writer: &Mutex<ClientWriter>) {
+    for event in events {
+        let sent = writer.lock().expect("client writer poisoned").send(&event);
+        if sent.is_err() {
+            break;
+        }
+    }
+}
+
+/// What client threads know about a panel of `serve`.
+pub(crate) struct PanelInfo {
+    /// `None` for the main panel, otherwise the name given to `--panel`.
+    pub(crate) name: Option<String>,
+    pub(crate) health: Arc<Health>,
+}
+
+/// A client's sessions: one for each panel it addressed.
+struct PanelSessions<'a> {
+    sessions: HashMap<Option<String>, Session>,
+    layout: TextLayout,
+    panels: &'a [PanelInfo],
+}
+
+impl<'a> PanelSessions<'a> {
+    fn new(layout: TextLayout, panels: &'a [PanelInfo]) -> Self {
+        Self {
+            sessions: HashMap::new(),
+            layout,
+            panels,
+        }
+    }
+
+    /// The panel called `panel` (`None` for the main one), if there is one.
+    fn panel(&self, panel: Option<&str>) -> Option<&'a PanelInfo> {
+        self.panels
+            .iter()
+            .find(|info| info.name.as_deref() == panel)
+    }
+
+    /// Take the session for a panel to hand to the display thread, starting one if needed;
+    /// `None` if there is no such panel.
+    fn take(&mut self, panel: Option<&str>) -> Option<Session> {
+        if let Some(session) = self.sessions.remove(&panel.map(str::to_string)) {
+            return Some(session);
+        }
+        self.panel(panel)?;
+        Some(Session::new(self.layout))
+    }
+
+    fn put(&mut self, panel: Option<String>, session: Session) {
+        self.sessions.insert(panel, session);
+    }
+}
+
+/// Create the named pipe for `serve --fifo`, or use the one already there. Returns whether
+/// it was created, and so should be removed on shutdown.
+pub(crate) fn create_fifo(path: &Path) -> io::Result<bool> {
+    match std::fs::metadata(path) {
+        Ok(meta) if meta.file_type().is_fifo() => return Ok(false),
+        Ok(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "a file that is not a FIFO is in the way",
+            ))
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err),
+    }
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "path contains a NUL byte"))?;
+    // SAFETY: `c_path` is a NUL-terminated string that outlives the call.
+    if unsafe { libc::mkfifo(c_path.as_ptr(), 0o666) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(true)
+}
+
+/// Feed what is written to the `--fifo` into the display queue, like one client that never
+/// disconnects and whose replies only go to the log. The FIFO is opened again once each
+/// writer is done.
+pub(crate) fn read_fifo(
+    path: &Path,
+    jobs: &mpsc::SyncSender<SocketJob>,
+    layout: TextLayout,
+    panels: &[PanelInfo],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut sessions = PanelSessions::new(layout, panels);
+    loop {
+        // Blocks until a writer opens the FIFO.
+        let mut reader = BufReader::new(std::fs::File::open(path)?);
+        loop {
+            let packet = match read_line_packet(&mut reader)? {
+                Incoming::Eof => break,
+                Incoming::Skip => continue,
+                Incoming::Reject { reply, close } => {
+                    warn!(reply, "Rejected a FIFO packet");
+                    if close {
+                        break;
+                    }
+                    continue;
+                }
+                Incoming::Packet(packet) => packet,
+            };
+            if matches!(
+                parse_packet(&packet.line).0,
+                PacketCommand::Binary
+                    | PacketCommand::Term
+                    | PacketCommand::Subscribe
+                    | PacketCommand::Async
+                    | PacketCommand::Wait
+            ) {
+                warn!("BINARY, TERM, SUBSCRIBE, ASYNC and WAIT are not available on the FIFO");
+                continue;
+            }
+            // With the queue full the writer simply waits, as it would for any pipe.
+            if !run_unattended(packet, &mut sessions, jobs, "FIFO") {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Run a packet whose reply nobody waits for, such as a FIFO line, with the session of its
+/// panel, and log the reply. Waits for room in the queue. Returns `false` once the display
+/// thread is gone.
+fn run_unattended(
+    packet: SocketPacket,
+    sessions: &mut PanelSessions,
+    jobs: &mpsc::SyncSender<SocketJob>,
+    source: &str,
+) -> bool {
+    let panel = packet.panel.clone();
+    let Some(session) = sessions.take(panel.as_deref()) else {
+        warn!(panel, "Unknown panel in a {source} packet");
+        return true;
+    };
+    let (reply_tx, reply) = mpsc::channel();
+    let job = SocketJob {
+        packet,
+        session,
+        reply: reply_tx,
+    };
+    if jobs.send(job).is_err() {
+        return false;
+    }
+    let Ok((returned, reply)) = reply.recv() else {
+        return false;
+    };
+    if reply.starts_with("ERR") {
+        warn!(reply, "{source} command failed");
+    } else {
+        debug!(response = reply, "{source} reply");
+    }
+    sessions.put(panel, returned);
+    true
+}
+
+/// Tell `SUBSCRIBE` clients about each button press, as `EVENT BUTTON <pin>`, and run the
+/// command of the button, like one more client whose replies only go to the log.
+pub(crate) fn handle_buttons(
+    presses: &mpsc::Receiver<u8>,
+    buttons: &[ButtonSpec],
+    jobs: &mpsc::SyncSender<SocketJob>,
+    layout: TextLayout,
+    panels: &[PanelInfo],
+    subscribers: &Subscribers,
+) {
+    let mut sessions = PanelSessions::new(layout, panels);
+    for pin in presses {
+        info!(pin, "Button pressed");
+        subscribers.publish(&format!("EVENT BUTTON {pin}"));
+        let Some(command) = buttons
+            .iter()
+            .find(|button| button.pin == pin)
+            .and_then(|button| button.command.as_deref())
+        else {
+            continue;
+        };
+        let packet = match read_line_packet(&mut format!("{command}\n").as_bytes()) {
+            Ok(Incoming::Packet(packet)) => packet,
+            Ok(Incoming::Reject { reply, .. }) => {
+                warn!(pin, reply, "Rejected a button command");
+                continue;
+            }
+            Ok(_) => continue,
+            Err(err) => {
+                warn!(pin, "Cannot read a button command: {err}");
+                continue;
+            }
+        };
+        if !run_unattended(packet, &mut sessions, jobs, "Button") {
+            return;
+        }
+    }
+}
+
+/// What a socket client sent next.
+enum Incoming {
+    Eof,
+    /// Nothing to do, such as an empty line.
+    Skip,
+    Packet(SocketPacket),
+    /// A malformed packet, answered without the display thread; `close` ends the
+    /// connection.
+    Reject {
+        reply: String,
+        close: bool,
+    },
+}
+
+/// Read a line, and the binary data that follows a `FRAME` or `IMAGE BYTES` header.
+fn read_line_packet(reader: &mut impl BufRead) -> io::Result<Incoming> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(Incoming::Eof);
+    }
+    let trimmed = line.trim_end_matches(&['\r', '\n'][..]);
+    if trimmed.is_empty() {
+        return Ok(Incoming::Skip);
+    }
+    let (tag, trimmed) = split_tag(trimmed);
+    let (panel, trimmed) = split_panel(trimmed);
+    let reject = |reply: &str, close| Incoming::Reject {
+        reply: tagged(tag.as_deref(), reply).into_owned(),
+        close,
+    };
+    let (priority, trimmed) = match split_priority(trimmed) {
+        Ok(split) => split,
+        Err(reply) => return Ok(reject(&reply, false)),
+    };
+
+    // The length from a binary header, the largest length allowed, and the command.
+    let header = match parse_packet(trimmed) {
+        _ if trimmed.starts_with('{') => None,
+        (PacketCommand::Frame, args) => Some((
+            parse_frame_header(args.unwrap_or_default()).map(|(_, len)| len),
+            MAX_FRAME_BYTES,
+            "FRAME",
+        )),
+        (PacketCommand::Image, args) => match parse_image_args(args.unwrap_or_default()) {
+            ImagePayload::Bytes(len) => Some((len, MAX_IMAGE_BYTES, "IMAGE")),
+            ImagePayload::Base64(_) => None,
+        },
+        (PacketCommand::Region, args) => match parse_region_args(args.unwrap_or_default()) {
+            Some((_, RegionContent::Bits(len))) => Some((len, MAX_FRAME_BYTES, "REGION")),
+            _ => None,
+        },
+        _ => None,
+    };
+    let data = match header {
+        None => None,
+        Some((None, _, command)) => {
+            return Ok(reject(&format!("ERR {command}_HEADER"), false));
+        }
+        Some((Some(len), limit, command)) if len > limit => {
+            return Ok(reject(&format!("ERR {command}_TOO_LARGE"), true));
+        }
+        Some((Some(len), _, _)) => {
+            let mut bytes = vec![0; len];
+            reader.read_exact(&mut bytes)?;
+            Some(bytes)
+        }
+    };
+    Ok(Incoming::Packet(SocketPacket {
+        line: trimmed.to_string(),
+        data,
+        priority,
+        panel,
+        tag,
+        terminal: false,
+    }))
+}
+
+/// Read a line of `TERM` output, plus any further lines that have already arrived, so a
+/// burst of output is shown with one refresh.
+fn read_terminal_lines(reader: &mut BufReader<impl Read>) -> io::Result<Incoming> {
+    let mut lines = String::new();
+    if reader.read_line(&mut lines)? == 0 {
+        return Ok(Incoming::Eof);
+    }
+    while reader.buffer().contains(&b'\n') {
+        reader.read_line(&mut lines)?;
+    }
+    let lines = lines.strip_suffix('\n').unwrap_or(&lines);
+    Ok(Incoming::Packet(SocketPacket {
+        line: lines.to_string(),
+        data: None,
+        priority: Priority::Normal,
+        panel: None,
+        tag: None,
+        terminal: true,
+    }))
+}
+
+/// Opcodes of the binary protocol entered with `BINARY`. Every message in either direction
+/// is the opcode, the payload length as a big-endian `u32`, and the payload.
+const OP_COMMAND: u8 = 0x01;
+const OP_FRAME_RAW: u8 = 0x02;
+const OP_FRAME_RLE: u8 = 0x03;
+const OP_IMAGE: u8 = 0x04;
+const OP_REGION: u8 = 0x05;
+const OP_REPLY: u8 = 0x80;
+
+/// Read one message of the binary protocol and turn it into the equivalent packet of the
+/// line protocol.
+fn read_binary_packet(reader: &mut impl Read) -> io::Result<Incoming> {
+    let mut header = [0; 5];
+    match reader.read_exact(&mut header) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(Incoming::Eof),
+        Err(err) => return Err(err),
+    }
+    let len = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+    if len > MAX_IMAGE_BYTES {
+        return Ok(Incoming::Reject {
+            reply: "ERR BINARY_TOO_LARGE".to_string(),
+            close: true,
+        });
+    }
+    let mut payload = vec![0; len];
+    reader.read_exact(&mut payload)?;
+
+    let reject = |reply: &str| Incoming::Reject {
+        reply: reply.to_string(),
+        close: false,
+    };
+    let (line, data) = match header[0] {
+        OP_COMMAND => match String::from_utf8(payload) {
+            Ok(line) => {
+                let (tag, line) = split_tag(line.trim_end_matches(&['\r', '\n'][..]));
+                let (panel, line) = split_panel(line);
+                match split_priority(line) {
+                    Ok((priority, line)) => {
+                        let packet = SocketPacket {
+                            line: line.to_string(),
+                            data: None,
+                            priority,
+                            panel,
+                            tag,
+                            terminal: false,
+                        };
+                        return Ok(Incoming::Packet(packet));
+                    }
+                    Err(reply) => return Ok(reject(&tagged(tag.as_deref(), &reply))),
+                }
+            }
+            Err(_) => return Ok(reject("ERR COMMAND_INVALID")),
+        },
+        OP_FRAME_RAW => (format!("FRAME RAW {len}"), Some(payload)),
+        OP_FRAME_RLE => (format!("FRAME RLE {len}"), Some(payload)),
+        OP_IMAGE => (format!("IMAGE BYTES {len}"), Some(payload)),
+        // x, y, width and height as big-endian `u16`s, then the packed rows.
+        OP_REGION if len >= 8 => {
+            let field = |i: usize| u16::from_be_bytes([payload[i], payload[i + 1]]);
+            let (x, y, width, height) = (field(0), field(2), field(4), field(6));
+            let bits = payload[8..].to_vec();
+            let line = format!("REGION {x} {y} {width} {height} BITS {}", bits.len());
+            (line, Some(bits))
+        }
+        OP_REGION => return Ok(reject("ERR REGION_INVALID")),
+        _ => return Ok(reject("ERR OPCODE_UNKNOWN")),
+    };
+    Ok(Incoming::Packet(SocketPacket {
+        line,
+        data,
+        priority: Priority::Normal,
+        panel: None,
+        tag: None,
+        terminal: false,
+    }))
+}
+
+/// Execute one packet on the display thread and return the reply line.
+pub(crate) fn handle_packet(
+    packet: &SocketPacket,
+    epd: &mut dyn Panel,
+    cli: &Cli,
+    bg: BinaryColor,
+    session: &mut Session,
+    state: &mut ServerState,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let trimmed = packet.line.as_str();
+    if packet.terminal {
+        let canvas = panel_area(cli.rotate).size;
+        let layout = &session.layout;
+        let (terminal, view) = session
+            .terminal
+            .get_or_insert_with(|| (Terminal::new(layout.font, canvas), LiveView::default()));
+        for line in trimmed.split('\n') {
+            terminal.write_line(line);
+        }
+        let fb = terminal.render(layout.foreground, layout.background);
+        view.show(epd, &fb, cli)?;
+        session.display.has_base = true;
+        session.pager = None;
+        return Ok("OK".to_string());
+    }
+    if let Some(batch) = session.batch.as_mut().filter(|_| batched(trimmed)) {
+        let is_json = trimmed.starts_with('{');
+        if batch.len() >= MAX_BATCH {
+            return Ok(if is_json {
+                error_json("BATCH_FULL", "COMMIT or ABORT the open batch first").to_string()
+            } else {
+                "ERR BATCH_FULL".to_string()
+            });
+        }
+        batch.push(SocketPacket {
+            line: packet.line.clone(),
+            data: packet.data.clone(),
+            priority: packet.priority,
+            panel: None,
+            tag: None,
+            terminal: false,
+        });
+        return Ok(if is_json {
+            serde_json::json!({ "ok": true, "batched": batch.len() }).to_string()
+        } else {
+            format!("OK BATCHED {}", batch.len())
+        });
+    }
+    if packet.changes_screen() {
+        let command = (!trimmed.starts_with('{')).then(|| parse_packet(trimmed).0);
+        if !matches!(command, Some(PacketCommand::Menu)) {
+            state.menu_open = None;
+        }
+        if !matches!(
+            command,
+            Some(PacketCommand::Progress | PacketCommand::Chart)
+        ) {
+            state.live = None;
+        }
+    }
+    if trimmed.starts_with('{') {
+        let reply = handle_json_packet(trimmed, epd, cli, bg, session, Some(state))?;
+        return Ok(reply.to_string());
+    }
+
+    let (cmd, payload) = parse_packet(trimmed);
+    let mode = packet_mode(trimmed);
+    info!(command = ?cmd, bytes = trimmed.len(), "Socket command");
+    if let PacketCommand::Menu = cmd {
+        let args = payload.unwrap_or_default();
+        return handle_menu(args, packet.priority, mode, epd, cli, bg, session, state);
+    }
+    if let PacketCommand::Template = cmd {
+        let reply = handle_template(payload.unwrap_or_default(), mode, epd, cli, session, state)?;
+        if reply == "OK TEMPLATE SHOW" {
+            session.pager = None;
+        }
+        return Ok(reply);
+    }
+    if let PacketCommand::Layer = cmd {
+        let reply = handle_layer(
+            payload.unwrap_or_default(),
+            epd,
+            cli,
+            bg,
+            session,
+            &mut state.scene,
+        )?;
+        if reply == "OK LAYER" {
+            session.pager = None;
+        }
+        return Ok(reply);
+    }
+    let response = match cmd {
+        PacketCommand::Show | PacketCommand::PartialOn | PacketCommand::Begin
+            if session.batch.is_some() =>
+        {
+            "ERR BATCH_OPEN"
+        }
+        PacketCommand::Begin => {
+            session.batch = Some(Vec::new());
+            "OK BEGIN"
+        }
+        PacketCommand::Abort => match session.batch.take() {
+            Some(_) => "OK ABORT",
+            None => "ERR NO_BATCH",
+        },
+        PacketCommand::Commit => match session.batch.take() {
+            Some(batch) => return commit_batch(&batch, mode, epd, cli, bg, session, state),
+            None => "ERR NO_BATCH",
+        },
+        PacketCommand::Clear => {
+            clear_panel(epd, cli, bg)?;
+            session.display.has_base = false;
+            "OK CLEAR"
+        }
+        PacketCommand::PartialOn => {
+            let blank = blank_framebuffer(panel_area(Rotation::Deg0).size, bg);
+            show_base(epd, cli, &blank)?;
+            session.display.default = Some(UpdateMode::Partial);
+            session.display.has_base = true;
+            "OK PARTIAL_ON"
+        }
+        PacketCommand::PartialOff => {
+            session.display.default = None;
+            "OK PARTIAL_OFF"
+        }
+        PacketCommand::Mode => {
+            let display = &mut session.display;
+            let Some(name) = payload.map(str::trim).filter(|name| !name.is_empty()) else {
+                return Ok(format!(
+                    "MODE {} base={}",
+                    mode_name(display.refresh(cli)),
+                    u8::from(display.has_base)
+                ));
+            };
+            match parse_update_mode(name) {
+                Some(mode) => {
+                    display.default = Some(mode);
+                    "OK MODE"
+                }
+                None => "ERR MODE_UNKNOWN",
+            }
+        }
+        PacketCommand::Ping => return Ok(state.health.pong()),
+        PacketCommand::Line
+        | PacketCommand::Rect
+        | PacketCommand::Circle
+        | PacketCommand::Pixel => match Shape::parse(cmd, payload.unwrap_or_default()) {
+            None => "ERR SHAPE_INVALID",
+            Some(_) if session.shapes.len() >= MAX_SHAPES => "ERR SHAPES_FULL",
+            Some(shape) => {
+                session.shapes.push(shape);
+                "OK QUEUED"
+            }
+        },
+        PacketCommand::Flush => {
+            let mut fb = last_canvas(cli)
+                .unwrap_or_else(|| blank_framebuffer(panel_area(cli.rotate).size, bg));
+            for shape in session.shapes.drain(..) {
+                shape.draw(&mut fb, session.layout.foreground);
+            }
+            session.show_changes(epd, &fb, cli)?;
+            "OK FLUSH"
+        }
+        PacketCommand::Status => return Ok(session_status(cli, session, Some(state)).to_string()),
+        PacketCommand::Snapshot => {
+            let raw = match payload
+                .unwrap_or_default()
+                .trim()
+                .to_ascii_uppercase()
+                .as_str()
+            {
+                "" | "PNG" => false,
+                "RAW" => true,
+                _ => return Ok("ERR FORMAT_UNKNOWN".to_string()),
+            };
+            return Ok(match snapshot(cli, raw)? {
+                Some(data) => format!("OK SNAPSHOT {data}"),
+                None => "ERR NO_FRAME".to_string(),
+            });
+        }
+        // The connection thread switches protocols and never queues these.
+        PacketCommand::Binary => "OK BINARY",
+        PacketCommand::Term => "OK TERM",
+        PacketCommand::Subscribe => "OK SUBSCRIBE",
+        PacketCommand::Async => "OK ASYNC",
+        PacketCommand::Wait => "OK WAIT",
+        PacketCommand::Layer => unreachable!("LAYER is handled above"),
+        PacketCommand::Template => unreachable!("TEMPLATE is handled above"),
+        PacketCommand::Menu => unreachable!("MENU is handled above"),
+        PacketCommand::Chart => {
+            let args = payload.unwrap_or_default();
+            return handle_chart(args, epd, cli, session, state);
+        }
+        PacketCommand::Playlist => return Ok(handle_playlist(payload.unwrap_or_default(), state)),
+        PacketCommand::Clock => {
+            let (action, options) = split_word(payload.unwrap_or_default());
+            let clock = &mut state.clock.borrow_mut().clock;
+            match action.to_ascii_uppercase().as_str() {
+                "OFF" => {
+                    *clock = None;
+                    "OK CLOCK"
+                }
+                "ON" => match Clock::parse(options, &session.layout) {
+                    Some(parsed) => {
+                        *clock = Some(parsed);
+                        "OK CLOCK"
+                    }
+                    None => "ERR CLOCK_INVALID",
+                },
+                _ => "ERR CLOCK_INVALID",
+            }
+        }
+        PacketCommand::Font => {
+            if set_font(&mut session.layout, payload.unwrap_or_default().trim()) {
+                "OK FONT"
+            } else {
+                "ERR FONT_UNKNOWN"
+            }
+        }
+        PacketCommand::Align => match payload.unwrap_or_default().parse::<Alignment>() {
+            Ok(alignment) => {
+                session.layout.alignment = alignment;
+                "OK ALIGN"
+            }
+            Err(_) => "ERR ALIGN_UNKNOWN",
+        },
+        PacketCommand::Qr => match payload.map(str::trim).filter(|p| !p.is_empty()) {
+            None => "IGNORED EMPTY",
+            Some(data) => match build_qr_framebuffer(data, None, &session.layout, cli.rotate) {
+                Ok(fb) => {
+                    session.show(epd, &fb, mode, cli)?;
+                    "OK QR"
+                }
+                Err(_) => "ERR QR_TOO_LONG",
+            },
+        },
+        PacketCommand::Barcode => match payload.map(str::trim).filter(|p| !p.is_empty()) {
+            None => "IGNORED EMPTY",
+            Some(args) => match parse_barcode_args(args) {
+                Some((kind, data)) => match build_barcode_framebuffer(kind, data, bg, cli.rotate) {
+                    Ok(fb) => {
+                        session.show(epd, &fb, mode, cli)?;
+                        "OK BARCODE"
+                    }
+                    Err(_) => "ERR BARCODE_INVALID",
+                },
+                None => "ERR BARCODE_INVALID",
+            },
+        },
+        PacketCommand::Progress => {
+            let (value, label) = split_word(payload.unwrap_or_default());
+            match parse_percent(value) {
+                Some(percent) => {
+                    let label = decode_newlines(label);
+                    let fb =
+                        build_progress_framebuffer(percent, &label, &session.layout, cli.rotate);
+                    let view = state.live.get_or_insert_with(LiveView::default);
+                    view.show(epd, &fb, cli)?;
+                    session.display.has_base = true;
+                    "OK PROGRESS"
+                }
+                None => "ERR PROGRESS_INVALID",
+            }
+        }
+        PacketCommand::Columns | PacketCommand::Table => {
+            let text = decode_newlines(payload.unwrap_or_default());
+            if text.trim().is_empty() {
+                "IGNORED EMPTY"
+            } else if matches!(cmd, PacketCommand::Columns) {
+                let fb = build_columns_framebuffer(&text, &session.layout, cli.rotate);
+                session.show(epd, &fb, mode, cli)?;
+                "OK COLUMNS"
+            } else {
+                let fb = build_table_framebuffer(&text, &session.layout, cli.rotate);
+                session.show(epd, &fb, mode, cli)?;
+                "OK TABLE"
+            }
+        }
+        PacketCommand::Layout => {
+            let source = payload.unwrap_or_default();
+            match LayoutSpec::from_json(source)
+                .and_then(|spec| spec.render(panel_area(cli.rotate).size, Path::new(".")))
+            {
+                Ok(fb) => {
+                    session.show(epd, &fb, mode, cli)?;
+                    "OK LAYOUT"
+                }
+                Err(err) => {
+                    warn!("Rejected layout: {err}");
+                    "ERR LAYOUT_INVALID"
+                }
+            }
+        }
+        PacketCommand::Frame => {
+            // The connection thread has checked the header and read the payload.
+            let header = parse_frame_header(payload.unwrap_or_default());
+            match (header, &packet.data) {
+                (Some((encoding, _)), Some(bytes)) => {
+                    match decode_frame(encoding, bytes, cli.rotate) {
+                        Ok(fb) => {
+                            session.show(epd, &fb, mode, cli)?;
+                            "OK FRAME"
+                        }
+                        Err(_) => "ERR FRAME_INVALID",
+                    }
+                }
+                _ => "ERR FRAME_HEADER",
+            }
+        }
+        PacketCommand::Image => {
+            let bytes = match parse_image_args(payload.unwrap_or_default()) {
+                ImagePayload::Base64(text) => BASE64_STANDARD.decode(text).ok(),
+                // The connection thread has checked the header and read the payload.
+                ImagePayload::Bytes(_) => packet.data.clone(),
+            };
+            match bytes {
+                None => "ERR IMAGE_INVALID",
+                Some(bytes) if bytes.is_empty() => "IGNORED EMPTY",
+                Some(bytes) => match frame_or_image(&bytes, cli) {
+                    Ok(fb) => {
+                        session.show(epd, &fb, mode, cli)?;
+                        "OK IMAGE"
+                    }
+                    Err(err) => {
+                        warn!("Rejected image: {err}");
+                        "ERR IMAGE_INVALID"
+                    }
+                },
+            }
+        }
+        PacketCommand::Region => match parse_region_args(payload.unwrap_or_default()) {
+            Some((area, content)) if fits_canvas(&area, cli.rotate) => {
+                let patch = match content {
+                    RegionContent::Text(text) => {
+                        let layout = bare_layout(&session.layout);
+                        Some(layout.render(&decode_newlines(text), area.size))
+                    }
+                    // The connection thread has checked the header and read the payload.
+                    RegionContent::Bits(_) => packet.data.as_deref().and_then(|bytes| {
+                        MonoImage::from_data(area.size.width, area.size.height, bytes).ok()
+                    }),
+                };
+                match patch {
+                    Some(patch) => {
+                        session.show_region(epd, &patch, area.top_left, bg, cli)?;
+                        "OK REGION"
+                    }
+                    None => "ERR REGION_INVALID",
+                }
+            }
+            _ => "ERR REGION_INVALID",
+        },
+        PacketCommand::Icon => match parse_icon_args(payload.unwrap_or_default()) {
+            Some(("LIST", _, _)) => {
+                let names: Vec<&str> = icons::all().iter().map(|icon| icon.name).collect();
+                return Ok(format!("OK ICON {}", names.join(" ")));
+            }
+            Some((name, origin, scale)) => match icons::icon(name) {
+                Some(icon) => {
+                    let size = ICON_SIZE * scale;
+                    if fits_canvas(&Rectangle::new(origin, Size::new_equal(size)), cli.rotate) {
+                        let layout = &session.layout;
+                        let mut patch = MonoImage::new(size, size);
+                        patch.clear(layout.background);
+                        icon.draw(&mut patch, Point::zero(), scale, layout.foreground);
+                        session.show_region(epd, &patch, origin, bg, cli)?;
+                        "OK ICON"
+                    } else {
+                        "ERR ICON_INVALID"
+                    }
+                }
+                None => "ERR ICON_UNKNOWN",
+            },
+            None => "ERR ICON_INVALID",
+        },
+        PacketCommand::Show | PacketCommand::At if state.timers.len() >= MAX_TIMERS => {
+            "ERR SCHEDULE_FULL"
+        }
+        PacketCommand::Show => {
+            let (duration, text) = split_word(payload.unwrap_or_default());
+            let text = decode_newlines(text);
+            let due = parse_interval(duration)
+                .ok()
+                .filter(|duration| !duration.is_zero() && *duration <= MAX_SHOW_DURATION)
+                .and_then(|duration| TimeDelta::from_std(duration).ok())
+                .and_then(|duration| Local::now().checked_add_signed(duration));
+            match due {
+                Some(due) => {
+                    if text.trim().is_empty() {
+                        "IGNORED EMPTY"
+                    } else {
+                        let previous = last_canvas(cli);
+                        let fb = build_framebuffer(&text, &session.layout, cli.rotate);
+                        session.show(epd, &fb, mode, cli)?;
+                        state.timers.push(Timer {
+                            due,
+                            priority: packet.priority,
+                            action: TimerAction::Restore {
+                                shown: fb,
+                                previous,
+                            },
+                        });
+                        "OK SHOW"
+                    }
+                }
+                None => "ERR DURATION_INVALID",
+            }
+        }
+        PacketCommand::At => {
+            let (time, text) = split_word(payload.unwrap_or_default());
+            let message = decode_newlines(text);
+            match DateTime::parse_from_rfc3339(time) {
+                Ok(due) if !message.trim().is_empty() => {
+                    state.timers.push(Timer {
+                        due: due.with_timezone(&Local),
+                        priority: packet.priority,
+                        action: TimerAction::Show {
+                            message,
+                            layout: session.layout,
+                        },
+                    });
+                    "OK AT"
+                }
+                Ok(_) => "IGNORED EMPTY",
+                Err(_) => "ERR TIME_INVALID",
+            }
+        }
+        PacketCommand::Text => {
+            let mut layout = session.layout;
+            match parse_text_options(payload.unwrap_or_default(), &mut layout) {
+                Err(code) => code,
+                Ok((text_mode, message)) => {
+                    let text = decode_newlines(message);
+                    if text.trim().is_empty() {
+                        "IGNORED EMPTY"
+                    } else {
+                        let fb = build_framebuffer(&text, &layout, cli.rotate);
+                        let refresh = session.plan(text_mode.or(mode), cli);
+                        session.pager = Some(Pager::new(&text));
+                        if state.too_soon() {
+                            state.deferred = Some((fb, refresh));
+                            "OK COALESCED"
+                        } else {
+                            show_planned(epd, &fb, refresh, cli)?;
+                            "OK TEXT"
+                        }
+                    }
+                }
+            }
+        }
+        PacketCommand::Page => match turn_page(session, payload.unwrap_or_default(), cli) {
+            Err(code) => code,
+            Ok(fb) => {
+                session.show(epd, &fb, mode, cli)?;
+                "OK PAGE"
+            }
+        },
+    };
+    if response.starts_with("OK")
+        && !matches!(
+            cmd,
+            PacketCommand::Text
+                | PacketCommand::Page
+                | PacketCommand::Ping
+                | PacketCommand::Font
+                | PacketCommand::Align
+                | PacketCommand::PartialOn
+                | PacketCommand::PartialOff
+                | PacketCommand::Mode
+                | PacketCommand::At
+                | PacketCommand::Line
+                | PacketCommand::Rect
+                | PacketCommand::Circle
+                | PacketCommand::Pixel
+                | PacketCommand::Begin
+                | PacketCommand::Abort
+                | PacketCommand::Clock
+        )
+    {
+        session.pager = None;
+    }
+
+    Ok(response.to_string())
+}
+
+/// `full`, `fast` or `partial`, in any case.
+pub(crate) fn parse_update_mode(name: &str) -> Option<UpdateMode> {
+    match name.to_ascii_lowercase().as_str() {
+        "full" => Some(UpdateMode::Normal),
+        "fast" => Some(UpdateMode::Fast),
+        "partial" => Some(UpdateMode::Partial),
+        _ => None,
+    }
+}
+
+/// The name [`parse_update_mode`] takes for a mode.
+pub(crate) fn mode_name(mode: UpdateMode) -> &'static str {
+    match mode {
+        UpdateMode::Normal => "full",
+        UpdateMode::Fast => "fast",
+        UpdateMode::Partial => "partial",
+    }
+}
+
+/// Apply leading `key=value` style options of a `TEXT` message (`font`, `align`, `mode`,
+/// `inverse`, `markup`) to `layout`, and return the update mode and the message after them.
+/// The first word that is not a known option starts the message.
+fn parse_text_options<'a>(
+    args: &'a str,
+    layout: &mut TextLayout,
+) -> Result<(Option<UpdateMode>, &'a str), &'static str> {
+    let flag = |value: &str| match value {
+        "1" | "true" | "on" => Ok(true),
+        "0" | "false" | "off" => Ok(false),
+        _ => Err("ERR OPTION_INVALID"),
+    };
+    let mut mode = None;
+    let mut rest = args;
+    loop {
+        let (word, tail) = split_word(rest);
+        let Some((key, value)) = word.split_once('=') else {
+            break;
+        };
+        match key {
+            "font" => {
+                if !set_font(layout, value) {
+                    return Err("ERR FONT_UNKNOWN");
+                }
+            }
+            "align" => {
+                layout.alignment = value.parse().map_err(|_| "ERR ALIGN_UNKNOWN")?;
+            }
+            "mode" => mode = Some(parse_update_mode(value).ok_or("ERR MODE_UNKNOWN")?),
+            "inverse" => {
+                if flag(value)? {
+                    std::mem::swap(&mut layout.foreground, &mut layout.background);
+                }
+            }
+            "markup" => layout.markup = flag(value)?,
+            _ => break,
+        }
+        rest = tail;
+    }
+    Ok((mode, rest))
+}
+
+/// Status number of an error code, after the HTTP status with the same meaning: 503 and 429
+/// are worth retrying later, 4xx otherwise need a different request, and 500 is a fault of
+/// the panel or the server.
+fn error_status(code: &str) -> u16 {
+    match code {
+        "BUSY" | "BUSY_TIMEOUT" => 503,
+        "NO_FRAME" | "NO_TEXT" | "LAYER_UNKNOWN" | "PANEL_UNKNOWN" | "ID_UNKNOWN"
+        | "TEMPLATE_UNKNOWN" | "NO_MENU" | "ICON_UNKNOWN" => 404,
+        "SCHEDULE_FULL" | "SHAPES_FULL" | "PLAYLIST_FULL" => 429,
+        "PANEL_ERROR" | "INTERNAL" => 500,
+        code if code.ends_with("_TOO_LARGE") => 413,
+        _ => 400,
+    }
+}
+
+/// Turn `ERR <CODE> ...` into `ERR <status> <CODE> ...`; other replies are left alone.
+fn with_status(reply: &str) -> Cow<'_, str> {
+    match reply.strip_prefix("ERR ") {
+        Some(rest) => {
+            let code = rest.split_whitespace().next().unwrap_or_default();
+            Cow::Owned(format!("ERR {} {rest}", error_status(code)))
+        }
+        None => Cow::Borrowed(reply),
+    }
+}
+
+/// A JSON error reply, `{"ok":false,"error":CODE,"status":N,"message":...}`.
+pub(crate) fn error_json(code: &str, message: &str) -> serde_json::Value {
+    serde_json::json!({
+        "ok": false,
+        "error": code,
+        "status": error_status(code),
+        "message": message,
+    })
+}
+
+/// The error code for a command that failed on the panel or in the server rather than
+/// being refused: `BUSY_TIMEOUT` when the panel hangs, `PANEL_ERROR` for other panel
+/// failures, `BAD_INPUT` for content that could not be used, `INTERNAL` otherwise.
+pub(crate) fn failure_code(err: &(dyn std::error::Error + 'static)) -> &'static str {
+    match err.downcast_ref::<EpdError>() {
+        Some(EpdError::BusyTimeout(_)) => "BUSY_TIMEOUT",
+        Some(_) => "PANEL_ERROR",
+        None if Failure::classify(err) == Failure::BadInput => "BAD_INPUT",
+        None => "INTERNAL",
+    }
+}
+
+/// The reply to a command that failed with `err`, in the form of the request, so the
+/// client can retry or give up while its connection stays open.
+pub(crate) fn failure_reply(line: &str, err: &(dyn std::error::Error + 'static)) -> String {
+    let code = failure_code(err);
+    if line.starts_with('{') {
+        error_json(code, &err.to_string()).to_string()
+    } else {
+        format!("ERR {code}")
+    }
+}
+
+/// Flip the session's pager and render the new page, or say why not (`ERR ...`).
+fn turn_page(session: &mut Session, arg: &str, cli: &Cli) -> Result<MonoImage, &'static str> {
+    let pager = session.pager.as_mut().ok_or("ERR NO_TEXT")?;
+    if !pager.turn(arg, &session.layout, cli.rotate) {
+        return Err("ERR PAGE_INVALID");
+    }
+    Ok(pager.framebuffer(&session.layout, cli.rotate))
+}
+
+/// A request in the JSON flavour of the socket protocol: one object per line, with
+/// options that apply to that message only.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JsonPacket {
+    cmd: String,
+    #[serde(default)]
+    body: String,
+    /// Font name or `auto`.
+    font: Option<String>,
+    align: Option<String>,
+    markup: Option<bool>,
+    /// `full`, `fast` or `partial`; defaults to the session's mode.
+    mode: Option<String>,
+    /// QR code caption.
+    caption: Option<String>,
+    /// Barcode symbology.
+    kind: Option<String>,
+    /// Layout description for `layout`.
+    layout: Option<LayoutSpec>,
+    /// `next`, `prev` or a page number for `page`.
+    page: Option<String>,
+    /// `png` or `raw` for `snapshot`.
+    format: Option<String>,
+    /// Queue priority on the Unix socket; read before the request is queued.
+    #[allow(dead_code)]
+    priority: Option<String>,
+}
+
+/// Handle one JSON request line, answering `{"ok":true,...}` or
+/// `{"ok":false,"error":CODE,"message":...}` with the codes of the line protocol.
+pub(crate) fn handle_json_packet(
+    line: &str,
+    epd: &mut dyn Panel,
+    cli: &Cli,
+    bg: BinaryColor,
+    session: &mut Session,
+    state: Option<&ServerState>,
+) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+    let fail = |code: &str, message: String| error_json(code, &message);
+    let packet: JsonPacket = match serde_json::from_str(line) {
+        Ok(packet) => packet,
+        Err(err) => return Ok(fail("BAD_JSON", err.to_string())),
+    };
+    info!(
+        command = packet.cmd,
+        bytes = line.len(),
+        "Socket JSON command"
+    );
+
+    let mode = match packet.mode.as_deref() {
+        None => None,
+        Some(name) => match parse_update_mode(name) {
+            Some(mode) => Some(mode),
+            None => return Ok(fail("MODE_UNKNOWN", format!("unknown mode '{name}'"))),
+        },
+    };
+    let mut layout = session.layout;
+    if let Some(font) = &packet.font {
+        if font.eq_ignore_ascii_case("auto") {
+            layout.auto_fit = true;
+        } else {
+            match font.parse() {
+                Ok(font) => {
+                    layout.font = font;
+                    layout.auto_fit = false;
+                }
+                Err(err) => return Ok(fail("FONT_UNKNOWN", err)),
+            }
+        }
+    }
+    if let Some(align) = &packet.align {
+        match align.parse() {
+            Ok(alignment) => layout.alignment = alignment,
+            Err(err) => return Ok(fail("ALIGN_UNKNOWN", err)),
+        }
+    }
+    if let Some(markup) = packet.markup {
+        layout.markup = markup;
+    }
+
+    let body = packet.body.as_str();
+    let needs_body = matches!(
+        packet.cmd.as_str(),
+        "text" | "qr" | "barcode" | "image" | "columns" | "table"
+    );
+    if needs_body && body.trim().is_empty() {
+        return Ok(fail("EMPTY", "`body` is empty".to_string()));
+    }
+    let fb = match packet.cmd.as_str() {
+        "ping" => return Ok(serde_json::json!({ "ok": true, "pong": true })),
+        "snapshot" => {
+            let format = packet
+                .format
+                .as_deref()
+                .unwrap_or("png")
+                .to_ascii_lowercase();
+            let raw = match format.as_str() {
+                "png" => false,
+                "raw" => true,
+                other => {
+                    let message = format!("unknown format '{other}'");
+                    return Ok(fail("FORMAT_UNKNOWN", message));
+                }
+            };
+            return Ok(match snapshot(cli, raw)? {
+                Some(data) => serde_json::json!({ "ok": true, "format": format, "data": data }),
+                None => fail("NO_FRAME", "nothing has been shown yet".to_string()),
+            });
+        }
+        "status" => {
+            let mut status = session_status(cli, session, state);
+            status["ok"] = true.into();
+            return Ok(status);
+        }
+        "clear" => {
+            clear_panel(epd, cli, bg)?;
+            session.display.has_base = false;
+            session.pager = None;
+            return Ok(serde_json::json!({ "ok": true }));
+        }
+        "page" => {
+            let arg = packet.page.as_deref().unwrap_or("next");
+            let fb = match turn_page(session, arg, cli) {
+                Ok(fb) => fb,
+                Err(code) => {
+                    let code = code.trim_start_matches("ERR ");
+                    return Ok(fail(code, format!("cannot turn to page '{arg}'")));
+                }
+            };
+            session.show(epd, &fb, mode, cli)?;
+            let pager = session.pager.as_ref().expect("turn_page checked the pager");
+            return Ok(serde_json::json!({
+                "ok": true,
+                "page": pager.page + 1,
+                "pages": pager.count(&session.layout, cli.rotate),
+            }));
+        }
+        "mode" => {
+            let display = &mut session.display;
+            if let Some(mode) = mode {
+                display.default = Some(mode);
+            }
+            return Ok(serde_json::json!({
+                "ok": true,
+                "mode": mode_name(display.refresh(cli)),
+                "has_base": display.has_base,
+            }));
+        }
+        "text" => build_framebuffer(body, &layout, cli.rotate),
+        "qr" => match build_qr_framebuffer(body, packet.caption.as_deref(), &layout, cli.rotate) {
+            Ok(fb) => fb,
+            Err(err) => return Ok(fail("QR_TOO_LONG", err.to_string())),
+        },
+        "barcode" => {
+            let kind = match packet.kind.as_deref() {
+                None => BarcodeKind::Code128,
+                Some(name) => match BarcodeKind::from_name(name) {
+                    Some(kind) => kind,
+                    None => {
+                        let message = format!("unknown barcode kind '{name}'");
+                        return Ok(fail("BARCODE_INVALID", message));
+                    }
+                },
+            };
+            match build_barcode_framebuffer(kind, body.trim(), bg, cli.rotate) {
+                Ok(fb) => fb,
+                Err(err) => return Ok(fail("BARCODE_INVALID", err.to_string())),
+            }
+        }
+        "image" => {
+            let bytes = match BASE64_STANDARD.decode(body.trim()) {
+                Ok(bytes) => bytes,
+                Err(err) => return Ok(fail("IMAGE_INVALID", format!("bad base64: {err}"))),
+            };
+            match frame_or_image(&bytes, cli) {
+                Ok(fb) => fb,
+                Err(err) => return Ok(fail("IMAGE_INVALID", err.to_string())),
+            }
+        }
+        "columns" => build_columns_framebuffer(body, &layout, cli.rotate),
+        "table" => build_table_framebuffer(body, &layout, cli.rotate),
+        "layout" => {
+            let Some(spec) = &packet.layout else {
+                return Ok(fail("LAYOUT_INVALID", "`layout` is missing".to_string()));
+            };
+            match spec.render(panel_area(cli.rotate).size, Path::new(".")) {
+                Ok(fb) => fb,
+                Err(err) => return Ok(fail("LAYOUT_INVALID", err.to_string())),
+            }
+        }
+        other => return Ok(fail("CMD_UNKNOWN", format!("unknown command '{other}'"))),
+    };
+
+    session.show(epd, &fb, mode, cli)?;
+    // PAGE keeps working on text shown with the message's own style.
+    session.pager = (packet.cmd == "text").then(|| Pager::new(body));
+    Ok(serde_json::json!({ "ok": true }))
+}
+
+#[derive(Debug, Clone, Copy)]
+enum PacketCommand {
+    Text,
+    Qr,
+    Barcode,
+    Columns,
+    Table,
+    Layout,
+    Frame,
+    Image,
+    Region,
+    Layer,
+    Show,
+    At,
+    Binary,
+    Term,
+    Subscribe,
+    Async,
+    Wait,
+    Clear,
+    PartialOn,
+    PartialOff,
+    Mode,
+    Ping,
+    Status,
+    Snapshot,
+    Line,
+    Rect,
+    Circle,
+    Pixel,
+    Flush,
+    Font,
+    Align,
+    Page,
+    Begin,
+    Commit,
+    Abort,
+    Template,
+    Playlist,
+    Clock,
+    Menu,
+    Progress,
+    Chart,
+    Icon,
+}
+
+/// Commands that take a `_FULL`, `_FAST` or `_PARTIAL` suffix choosing their refresh.
+const MODE_COMMANDS: &[&str] = &[
+    "TEXT", "QR", "BARCODE", "COLUMNS", "TABLE", "LAYOUT", "FRAME", "IMAGE", "SHOW", "PAGE",
+    "COMMIT", "TEMPLATE",
+];
+
+/// Split a refresh mode suffix off a command name in upper case, e.g. `TEXT_FAST`.
+fn split_mode_suffix(head: &str) -> (&str, Option<UpdateMode>) {
+    let suffixes = [
+        ("_FULL", UpdateMode::Normal),
+        ("_FAST", UpdateMode::Fast),
+        ("_PARTIAL", UpdateMode::Partial),
+    ];
+    for (suffix, mode) in suffixes {
+        match head.strip_suffix(suffix) {
+            Some(name) if MODE_COMMANDS.contains(&name) => return (name, Some(mode)),
+            _ => {}
+        }
+    }
+    (head, None)
+}
+
+/// The refresh mode chosen by the command's suffix, if any.
+fn packet_mode(input: &str) -> Option<UpdateMode> {
+    let head = input.split(char::is_whitespace).next().unwrap_or_default();
+    split_mode_suffix(&head.to_ascii_uppercase()).1
+}
+
+fn parse_packet(input: &str) -> (PacketCommand, Option<&str>) {
+    let mut parts = input.splitn(2, char::is_whitespace);
+    let head = parts.next().unwrap_or("").to_ascii_uppercase();
+    let payload = parts.next();
+
+    match split_mode_suffix(&head).0 {
+        "CLEAR" => (PacketCommand::Clear, None),
+        "PARTIAL_ON" => (PacketCommand::PartialOn, None),
+        "PARTIAL_OFF" => (PacketCommand::PartialOff, None),
+        "PING" => (PacketCommand::Ping, None),
+        "STATUS" => (PacketCommand::Status, None),
+        "SNAPSHOT" | "GET_SCREEN" => (PacketCommand::Snapshot, payload),
+        "LINE" => (PacketCommand::Line, payload),
+        "RECT" => (PacketCommand::Rect, payload),
+        "CIRCLE" => (PacketCommand::Circle, payload),
+        "PIXEL" => (PacketCommand::Pixel, payload),
+        "FLUSH" => (PacketCommand::Flush, None),
+        "BINARY" => (PacketCommand::Binary, None),
+        "TERM" => (PacketCommand::Term, None),
+        "SUBSCRIBE" => (PacketCommand::Subscribe, None),
+        "ASYNC" => (PacketCommand::Async, payload),
+        "WAIT" => (PacketCommand::Wait, payload),
+        "FONT" => (PacketCommand::Font, payload),
+        "ALIGN" => (PacketCommand::Align, payload),
+        "PAGE" => (PacketCommand::Page, payload),
+        "MODE" => (PacketCommand::Mode, payload),
+        "TEXT" => (PacketCommand::Text, payload),
+        "QR" => (PacketCommand::Qr, payload),
+        "BARCODE" => (PacketCommand::Barcode, payload),
+        "COLUMNS" => (PacketCommand::Columns, payload),
+        "TABLE" => (PacketCommand::Table, payload),
+        "LAYOUT" => (PacketCommand::Layout, payload),
+        "FRAME" => (PacketCommand::Frame, payload),
+        "IMAGE" => (PacketCommand::Image, payload),
+        "REGION" => (PacketCommand::Region, payload),
+        "LAYER" => (PacketCommand::Layer, payload),
+        "SHOW" => (PacketCommand::Show, payload),
+        "AT" => (PacketCommand::At, payload),
+        "BEGIN" => (PacketCommand::Begin, None),
+        "COMMIT" => (PacketCommand::Commit, None),
+        "ABORT" => (PacketCommand::Abort, None),
+        "TEMPLATE" => (PacketCommand::Template, payload),
+        "PLAYLIST" => (PacketCommand::Playlist, payload),
+        "CLOCK" => (PacketCommand::Clock, payload),
+        "MENU" => (PacketCommand::Menu, payload),
+        "PROGRESS" => (PacketCommand::Progress, payload),
+        "CHART" => (PacketCommand::Chart, payload),
+        "ICON" => (PacketCommand::Icon, payload),
+        _ => (PacketCommand::Text, Some(input)),
+    }
+}
+
+/// Largest binary payload accepted after a `FRAME` header.
+const MAX_FRAME_BYTES: usize = 16 * 1024;
+
+/// How the bytes following a `FRAME` header are encoded.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum FrameEncoding {
+    /// Panel-format bytes as returned by `MonoImage::data`.
+    Raw,
+    /// The same bytes compressed with `MonoImage::to_rle`.
+    Rle,
+}
+
+/// Largest image accepted after an `IMAGE BYTES` header, as for HTTP bodies.
+const MAX_IMAGE_BYTES: usize = MAX_HTTP_BODY as usize;
+
+/// How the image of an `IMAGE` command arrives.
+#[derive(Debug, Clone, Copy)]
+enum ImagePayload<'a> {
+    /// Inline on the command line.
+    Base64(&'a str),
+    /// `IMAGE BYTES <len>`: that many bytes follow the newline (`None` if `<len>` is bad).
+    Bytes(Option<usize>),
+}
+
+/// Parse `IMAGE <base64>` or `IMAGE BYTES <len>` arguments. Base64 has no spaces, so it
+/// cannot be mistaken for the latter.
+fn parse_image_args(args: &str) -> ImagePayload<'_> {
+    let args = args.trim();
+    match args.split_once(char::is_whitespace) {
+        Some((keyword, len)) if keyword.eq_ignore_ascii_case("BYTES") => {
+            ImagePayload::Bytes(len.trim().parse().ok())
+        }
+        _ if args.eq_ignore_ascii_case("BYTES") => ImagePayload::Bytes(None),
+        _ => ImagePayload::Base64(args),
+    }
+}
+
+/// What a `REGION` command draws in its rectangle.
+#[derive(Debug, Clone, Copy)]
+enum RegionContent<'a> {
+    /// `TEXT <msg>`, laid out with the connection's font and alignment.
+    Text(&'a str),
+    /// `BITS <len>`: that many bytes of packed rows follow the newline (`None` if `<len>` is
+    /// bad).
+    Bits(Option<usize>),
+}
+
+/// Parse `REGION <x> <y> <w> <h> TEXT <msg>|BITS <len>` arguments.
+fn parse_region_args(args: &str) -> Option<(Rectangle, RegionContent<'_>)> {
+    let mut rest = args.trim_start();
+    let mut numbers = [0u16; 4];
+    for number in &mut numbers {
+        let (word, tail) = rest.split_once(char::is_whitespace)?;
+        *number = word.parse().ok()?;
+        rest = tail.trim_start();
+    }
+    let (keyword, tail) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    let content = match keyword.to_ascii_uppercase().as_str() {
+        "TEXT" => RegionContent::Text(tail),
+        "BITS" => RegionContent::Bits(tail.trim().parse().ok()),
+        _ => return None,
+    };
+    let [x, y, width, height] = numbers.map(u32::from);
+    let area = Rectangle::new(Point::new(x as i32, y as i32), Size::new(width, height));
+    Some((area, content))
+}
+
+/// Parse `ICON <name> [pos=<x>,<y>] [scale=<n>]` arguments; `LIST` comes back as the name.
+fn parse_icon_args(args: &str) -> Option<(&str, Point, u32)> {
+    let mut words = args.split_whitespace();
+    let name = words.next()?;
+    if name.eq_ignore_ascii_case("LIST") {
+        return Some(("LIST", Point::zero(), 1));
+    }
+    let (mut origin, mut scale) = (Point::zero(), 1);
+    for word in words {
+        match word.split_once('=')? {
+            ("pos", pos) => {
+                let (x, y) = pos.split_once(',')?;
+                origin = Point::new(x.parse().ok()?, y.parse().ok()?);
+            }
+            ("scale", value) => scale = value.parse().ok().filter(|n| (1..=8).contains(n))?,
+            _ => return None,
+        }
+    }
+    Some((name, origin, scale))
+}
+
+/// Parse `FRAME RAW|RLE <len>` arguments.
+fn parse_frame_header(args: &str) -> Option<(FrameEncoding, usize)> {
+    let mut parts = args.split_whitespace();
+    let encoding = match parts.next()?.to_ascii_uppercase().as_str() {
+        "RAW" => FrameEncoding::Raw,
+        "RLE" => FrameEncoding::Rle,
+        _ => return None,
+    };
+    let len = parts.next()?.parse().ok()?;
+    Some((encoding, len))
+}
+
+/// Frames are expected in canvas orientation, i.e. already sized for `--rotate`.
+pub(crate) fn decode_frame(
+    encoding: FrameEncoding,
+    bytes: &[u8],
+    rotation: Rotation,
+) -> Result<MonoImage, FrameError> {
+    let size = panel_area(rotation).size;
+    match encoding {
+        FrameEncoding::Raw => MonoImage::from_data(size.width, size.height, bytes),
+        FrameEncoding::Rle => MonoImage::from_rle(size.width, size.height, bytes),
+    }
+}
+
+/// Split `BARCODE` arguments into an optional symbology and the data, defaulting to Code128.
+/// `None` when a symbology is named but no data follows.
+fn parse_barcode_args(args: &str) -> Option<(BarcodeKind, &str)> {
+    let mut parts = args.splitn(2, char::is_whitespace);
+    let head = parts.next().unwrap_or("");
+    match (BarcodeKind::from_name(head), parts.next().map(str::trim)) {
+        (Some(kind), Some(data)) if !data.is_empty() => Some((kind, data)),
+        (Some(_), _) => None,
+        (None, _) => Some((BarcodeKind::Code128, args)),
+    }
+}
+
+/// Send a reply line, or an `OP_REPLY` message in binary mode.
+/// Send a reply, with the status number added to `ERR <CODE>` replies.
+fn respond(stream: &mut UnixStream, binary: bool, message: &str) -> io::Result<()> {
+    let message = with_status(message);
+    if binary {
+        stream.write_all(&[OP_REPLY])?;
+        stream.write_all(&(message.len() as u32).to_be_bytes())?;
+        stream.write_all(message.as_bytes())?;
+    } else {
+        stream.write_all(message.as_bytes())?;
+        stream.write_all(b"\n")?;
+    }
+    stream.flush()
+}
+
+pub(crate) fn status_json(cli: &Cli) -> serde_json::Value {
+    let last_frame = FrameStore::new(&cli.persist_frame).info().ok().flatten();
+    serde_json::json!({
+        "panel": "Waveshare 2.13\" V4",
+        "width": Epd2in13V4::WIDTH,
+        "height": Epd2in13V4::HEIGHT,
+        "rotation": cli.rotate.degrees(),
+        "last_frame": last_frame.map(|info| serde_json::json!({
+            "width": info.width,
+            "height": info.height,
+            "age_secs": info.modified.elapsed().unwrap_or_default().as_secs(),
+        })),
+    })
+}
+
+/// [`status_json`] plus the session's refresh mode and, under `serve`, the display loop's
+/// counters, for `STATUS`.
+fn session_status(cli: &Cli, session: &Session, state: Option<&ServerState>) -> serde_json::Value {
+    let mut status = status_json(cli);
+    status["mode"] = mode_name(session.display.refresh(cli)).into();
+    status["partial"] = serde_json::json!({
+        "enabled": session.display.partial(),
+        "has_base": session.display.has_base,
+    });
+    if let Some(state) = state {
+        let refreshes = state.refreshes.get();
+        status["refreshes"] = serde_json::json!({
+            "full": refreshes.full,
+            "fast": refreshes.fast,
+            "partial": refreshes.partial,
+        });
+        status["uptime_secs"] = state.health.started.elapsed().as_secs().into();
+        status["queue"] = state.queued.into();
+        status["scheduled"] = state.timers.len().into();
+    }
+    status
+}
+
+/// The last frame shown, base64-encoded as a PNG in viewing orientation or as a raw panel
+/// frame, for `SNAPSHOT`; `None` before anything was shown.
+fn snapshot(cli: &Cli, raw: bool) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let Some(frame) = FrameStore::new(&cli.persist_frame).load()? else {
+        return Ok(None);
+    };
+    let bytes = if raw {
+        frame.data().to_vec()
+    } else {
+        encode_png(&frame.rotated(cli.rotate.inverse()))?
+    };
+    Ok(Some(BASE64_STANDARD.encode(bytes)))
+}
@@ -0,0 +1,128 @@
+//! Persisting the last frame sent to the panel, so later runs can inspect or restore it.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+use thiserror::Error;
+
+use crate::{
+    buffer::{FrameError, MonoImage},
+    epd2in13_v4::UpdateMode,
+};
+
+/// Where the CLI keeps the last frame unless told otherwise.
+pub const DEFAULT_FRAME_PATH: &str = "/var/tmp/rpi-einkserver-rs/last-frame.epf";
+
+#[derive(Debug, Error)]
+pub enum StateError {
+    #[error("state file I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("corrupt state file: {0}")]
+    Frame(#[from] FrameError),
+}
+
+/// Summary of a stored frame, as reported by [`FrameStore::info`].
+#[derive(Debug, Clone, Copy)]
+pub struct StoredFrameInfo {
+    pub width: u32,
+    pub height: u32,
+    pub modified: SystemTime,
+}
+
+/// A panel-orientation frame kept on disk in the [`MonoImage::to_bytes_with_header`] format.
+#[derive(Debug, Clone)]
+pub struct FrameStore {
+    path: PathBuf,
+}
+
+impl FrameStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The store for the partial-update base (the frame of the last full refresh), kept
+    /// next to this one: `last-frame.epf` pairs with `last-frame.base.epf`.
+    pub fn base(&self) -> FrameStore {
+        FrameStore::new(self.path.with_extension("base.epf"))
+    }
+
+    /// Where the refresh mode of the stored frame is kept: `last-frame.mode` next to
+    /// `last-frame.epf`.
+    fn mode_path(&self) -> PathBuf {
+        self.path.with_extension("mode")
+    }
+
+    /// Record how the stored frame was shown: `full`, `fast` or `partial`.
+    pub fn save_mode(&self, mode: UpdateMode) -> io::Result<()> {
+        let name = match mode {
+            UpdateMode::Normal => "full",
+            UpdateMode::Fast => "fast",
+            UpdateMode::Partial => "partial",
+        };
+        fs::write(self.mode_path(), name)
+    }
+
+    /// How the stored frame was shown, if recorded.
+    pub fn load_mode(&self) -> Result<Option<UpdateMode>, StateError> {
+        match fs::read_to_string(self.mode_path()) {
+            Ok(name) => Ok(match name.trim() {
+                "full" => Some(UpdateMode::Normal),
+                "fast" => Some(UpdateMode::Fast),
+                "partial" => Some(UpdateMode::Partial),
+                _ => None,
+            }),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Write `frame`, creating parent directories as needed. The file is replaced
+    /// atomically so a crash never leaves a half-written frame behind.
+    pub fn save(&self, frame: &MonoImage) -> io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, frame.to_bytes_with_header())?;
+        fs::rename(tmp, &self.path)
+    }
+
+    /// The stored frame, or `None` if nothing has been saved yet.
+    pub fn load(&self) -> Result<Option<MonoImage>, StateError> {
+        match fs::read(&self.path) {
+            Ok(bytes) => Ok(Some(MonoImage::from_bytes_with_header(&bytes)?)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Delete the stored frame, its mode and its base, if there are any.
+    pub fn remove(&self) -> io::Result<()> {
+        for path in [self.path.clone(), self.mode_path(), self.base().path] {
+            match fs::remove_file(path) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Dimensions and modification time of the stored frame, if any.
+    pub fn info(&self) -> Result<Option<StoredFrameInfo>, StateError> {
+        let Some(frame) = self.load()? else {
+            return Ok(None);
+        };
+        let modified = fs::metadata(&self.path)?.modified()?;
+        Ok(Some(StoredFrameInfo {
+            width: frame.width(),
+            height: frame.height(),
+            modified,
+        }))
+    }
+}
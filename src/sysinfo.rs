@@ -0,0 +1,206 @@
+//! Host statistics for the `sysinfo` dashboard and the `thermal` screen, read straight from
+//! `/proc` and `/sys`, and the Raspberry Pi's throttling flags.
+
+use std::{
+    ffi::CString,
+    fs,
+    net::{IpAddr, UdpSocket},
+    process,
+};
+
+/// One reading of the host's vital signs. Fields that could not be read are `None`.
+#[derive(Debug, Clone, Default)]
+pub struct SystemSnapshot {
+    pub hostname: Option<String>,
+    pub ip: Option<IpAddr>,
+    /// 1, 5 and 15 minute load averages.
+    pub load: Option<[f32; 3]>,
+    /// Total and available memory in KiB.
+    pub memory_kib: Option<(u64, u64)>,
+    /// Total and free bytes on the root filesystem.
+    pub disk_bytes: Option<(u64, u64)>,
+    pub cpu_temp_celsius: Option<f32>,
+}
+
+impl SystemSnapshot {
+    pub fn collect() -> Self {
+        Self {
+            hostname: hostname(),
+            ip: primary_ip(),
+            load: load_average(),
+            memory_kib: memory(),
+            disk_bytes: disk_usage("/"),
+            cpu_temp_celsius: cpu_temperature(),
+        }
+    }
+
+    /// Label/value pairs ready for a two-column table; unreadable values show as `?`.
+    pub fn rows(&self) -> Vec<(&'static str, String)> {
+        let unknown = || "?".to_string();
+        vec![
+            ("Host", self.hostname.clone().unwrap_or_else(unknown)),
+            ("IP", self.ip.map_or_else(unknown, |ip| ip.to_string())),
+            (
+                "Load",
+                self.load
+                    .map_or_else(unknown, |[a, b, c]| format!("{a:.2} {b:.2} {c:.2}")),
+            ),
+            (
+                "Mem",
+                self.memory_kib.map_or_else(unknown, |(total, available)| {
+                    format!("{}/{} MB", (total - available) / 1024, total / 1024)
+                }),
+            ),
+            (
+                "Disk",
+                self.disk_bytes.map_or_else(unknown, |(total, free)| {
+                    let gb = |b: u64| b as f64 / 1e9;
+                    format!("{:.1}/{:.1} GB", gb(total - free), gb(total))
+                }),
+            ),
+            (
+                "Temp",
+                self.cpu_temp_celsius
+                    .map_or_else(unknown, |t| format!("{t:.1} C")),
+            ),
+        ]
+    }
+}
+
+pub fn hostname() -> Option<String> {
+    let name = fs::read_to_string("/proc/sys/kernel/hostname").ok()?;
+    Some(name.trim().to_string())
+}
+
+/// Address of the interface that carries the default route. Connecting a UDP socket only
+/// selects a route; no packet is sent.
+pub fn primary_ip() -> Option<IpAddr> {
+    let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect("192.0.2.1:9").ok()?;
+    Some(socket.local_addr().ok()?.ip())
+}
+
+fn load_average() -> Option<[f32; 3]> {
+    let text = fs::read_to_string("/proc/loadavg").ok()?;
+    let mut fields = text.split_whitespace().map(|f| f.parse().ok());
+    Some([fields.next()??, fields.next()??, fields.next()??])
+}
+
+fn memory() -> Option<(u64, u64)> {
+    let text = fs::read_to_string("/proc/meminfo").ok()?;
+    let field = |name: &str| {
+        text.lines()
+            .find_map(|line| line.strip_prefix(name))
+            .and_then(|rest| rest.trim_start_matches(':').split_whitespace().next())
+            .and_then(|kib| kib.parse().ok())
+    };
+    Some((field("MemTotal")?, field("MemAvailable")?))
+}
+
+fn disk_usage(path: &str) -> Option<(u64, u64)> {
+    let path = CString::new(path).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `path` is NUL-terminated and `stat` is a valid out-pointer.
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    let block = stat.f_frsize as u64;
+    Some((stat.f_blocks as u64 * block, stat.f_bavail as u64 * block))
+}
+
+/// SoC temperature from the first thermal zone, which is the CPU on a Raspberry Pi.
+pub fn cpu_temperature() -> Option<f32> {
+    let millis: f32 = fs::read_to_string("/sys/class/thermal/thermal_zone0/temp")
+        .ok()?
+        .trim()
+        .parse()
+        .ok()?;
+    Some(millis / 1000.0)
+}
+
+/// Temperatures of all thermal zones in degrees Celsius, named after their type, such as
+/// `cpu-thermal`, in zone order.
+pub fn thermal_zones() -> Vec<(String, f32)> {
+    let Ok(entries) = fs::read_dir("/sys/class/thermal") else {
+        return Vec::new();
+    };
+    let mut zones: Vec<(u32, String, f32)> = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let index = entry
+                .file_name()
+                .to_str()?
+                .strip_prefix("thermal_zone")?
+                .parse()
+                .ok()?;
+            let path = entry.path();
+            let millis: f32 = fs::read_to_string(path.join("temp"))
+                .ok()?
+                .trim()
+                .parse()
+                .ok()?;
+            let name = fs::read_to_string(path.join("type"))
+                .map(|name| name.trim().to_string())
+                .unwrap_or_else(|_| format!("zone{index}"));
+            Some((index, name, millis / 1000.0))
+        })
+        .collect();
+    zones.sort_by_key(|(index, ..)| *index);
+    zones
+        .into_iter()
+        .map(|(_, name, celsius)| (name, celsius))
+        .collect()
+}
+
+/// The flags reported by `vcgencmd get_throttled` on a Raspberry Pi. The low bits tell
+/// what is happening now, the same bits 16 places up whether it happened since boot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Throttled(pub u32);
+
+/// What each of the low bits of [`Throttled`] means.
+const THROTTLE_FLAGS: [&str; 4] = [
+    "under-voltage",
+    "frequency capped",
+    "throttled",
+    "soft temperature limit",
+];
+
+impl Throttled {
+    /// Ask the firmware through `vcgencmd`; `None` off a Pi or if it fails.
+    pub fn read() -> Option<Self> {
+        let output = process::Command::new("vcgencmd")
+            .arg("get_throttled")
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        Self::parse(&String::from_utf8_lossy(&output.stdout))
+    }
+
+    /// Parse a `throttled=0x50005` reply.
+    pub fn parse(reply: &str) -> Option<Self> {
+        let value = reply.trim().strip_prefix("throttled=")?;
+        let hex = value.strip_prefix("0x").unwrap_or(value);
+        u32::from_str_radix(hex, 16).ok().map(Self)
+    }
+
+    /// The conditions in effect now.
+    pub fn active(&self) -> Vec<&'static str> {
+        self.flags(0)
+    }
+
+    /// The conditions that occurred since boot.
+    pub fn occurred(&self) -> Vec<&'static str> {
+        self.flags(16)
+    }
+
+    fn flags(&self, shift: u32) -> Vec<&'static str> {
+        THROTTLE_FLAGS
+            .iter()
+            .enumerate()
+            .filter(|(bit, _)| self.0 >> (shift + *bit as u32) & 1 == 1)
+            .map(|(_, name)| *name)
+            .collect()
+    }
+}
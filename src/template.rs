@@ -0,0 +1,188 @@
+//! A minimal handlebars-like template language for recurring layouts.
+//!
+//! Supported tags:
+//! - `{{name}}` inserts a variable; unknown names are an error so typos do not go unnoticed.
+//! - `{{#if name}}…{{else}}…{{/if}}` and `{{#unless name}}…{{/unless}}` branch on whether a
+//!   variable is set to something other than an empty string, `0` or `false`.
+//! - `{{! comment}}` is dropped.
+//!
+//! There are no helpers, loops or HTML escaping: the output is plain text for the panel.
+
+use std::collections::HashMap;
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum TemplateError {
+    #[error("unknown template variable '{0}'")]
+    UnknownVariable(String),
+    #[error("unclosed '{{{{' at byte {0}")]
+    UnclosedTag(usize),
+    #[error("unexpected '{{{{{0}}}}}'")]
+    UnexpectedTag(String),
+    #[error("'{{{{#{0}}}}}' is never closed")]
+    UnclosedBlock(String),
+}
+
+#[derive(Debug)]
+enum Node {
+    Text(String),
+    Var(String),
+    Block {
+        name: String,
+        negate: bool,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+}
+
+/// A block being parsed: its opening tag and the node lists collected so far.
+struct Frame {
+    kind: &'static str,
+    name: String,
+    then: Vec<Node>,
+    otherwise: Option<Vec<Node>>,
+}
+
+impl Frame {
+    fn current(&mut self) -> &mut Vec<Node> {
+        self.otherwise.as_mut().unwrap_or(&mut self.then)
+    }
+}
+
+/// Substitute `vars` into `source`.
+pub fn render(source: &str, vars: &HashMap<String, String>) -> Result<String, TemplateError> {
+    let nodes = parse(source)?;
+    let mut out = String::with_capacity(source.len());
+    render_nodes(&nodes, vars, &mut out)?;
+    Ok(out)
+}
+
+/// Check that `source` is a well-formed template, before any variables are known.
+pub fn check(source: &str) -> Result<(), TemplateError> {
+    parse(source).map(|_| ())
+}
+
+fn parse(source: &str) -> Result<Vec<Node>, TemplateError> {
+    let mut root = Vec::new();
+    let mut stack: Vec<Frame> = Vec::new();
+    let mut rest = source;
+
+    while !rest.is_empty() {
+        let start = rest.find("{{").unwrap_or(rest.len());
+        if start > 0 {
+            let text = Node::Text(rest[..start].to_string());
+            match stack.last_mut() {
+                Some(frame) => frame.current().push(text),
+                None => root.push(text),
+            }
+        }
+        rest = &rest[start..];
+        if rest.is_empty() {
+            break;
+        }
+        let offset = source.len() - rest.len();
+        let end = rest.find("}}").ok_or(TemplateError::UnclosedTag(offset))?;
+        let tag = rest[2..end].trim();
+        rest = &rest[end + 2..];
+
+        // Like handlebars, a block or comment tag alone on its line takes the line with it,
+        // so templates can put `{{#if}}` on its own line without leaving a blank one behind.
+        let is_var = !tag.starts_with(['!', '#', '/']) && tag != "else";
+        let line_before = &source[source[..offset].rfind('\n').map_or(0, |i| i + 1)..offset];
+        let line_after = rest.split_inclusive('\n').next().unwrap_or("");
+        if !is_var && line_before.trim().is_empty() && line_after.trim().is_empty() {
+            let current = match stack.last_mut() {
+                Some(frame) => frame.current(),
+                None => &mut root,
+            };
+            if let Some(Node::Text(text)) = current.last_mut() {
+                text.truncate(text.trim_end_matches([' ', '\t']).len());
+            }
+            rest = &rest[line_after.len()..];
+        }
+
+        let node = if tag.starts_with('!') {
+            continue;
+        } else if let Some(open) = tag.strip_prefix('#') {
+            let (kind, name) = open.split_once(char::is_whitespace).unwrap_or((open, ""));
+            let kind = match kind {
+                "if" => "if",
+                "unless" => "unless",
+                _ => return Err(TemplateError::UnexpectedTag(tag.to_string())),
+            };
+            stack.push(Frame {
+                kind,
+                name: name.trim().to_string(),
+                then: Vec::new(),
+                otherwise: None,
+            });
+            continue;
+        } else if tag == "else" {
+            match stack.last_mut() {
+                Some(frame) if frame.otherwise.is_none() => frame.otherwise = Some(Vec::new()),
+                _ => return Err(TemplateError::UnexpectedTag(tag.to_string())),
+            }
+            continue;
+        } else if let Some(kind) = tag.strip_prefix('/') {
+            match stack.pop() {
+                Some(frame) if frame.kind == kind.trim() => Node::Block {
+                    negate: frame.kind == "unless",
+                    name: frame.name,
+                    then: frame.then,
+                    otherwise: frame.otherwise.unwrap_or_default(),
+                },
+                _ => return Err(TemplateError::UnexpectedTag(tag.to_string())),
+            }
+        } else {
+            Node::Var(tag.to_string())
+        };
+        match stack.last_mut() {
+            Some(frame) => frame.current().push(node),
+            None => root.push(node),
+        }
+    }
+
+    match stack.pop() {
+        Some(frame) => Err(TemplateError::UnclosedBlock(frame.kind.to_string())),
+        None => Ok(root),
+    }
+}
+
+fn render_nodes(
+    nodes: &[Node],
+    vars: &HashMap<String, String>,
+    out: &mut String,
+) -> Result<(), TemplateError> {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Var(name) => match vars.get(name) {
+                Some(value) => out.push_str(value),
+                None => return Err(TemplateError::UnknownVariable(name.clone())),
+            },
+            Node::Block {
+                name,
+                negate,
+                then,
+                otherwise,
+            } => {
+                let set = vars
+                    .get(name)
+                    .is_some_and(|v| !matches!(v.trim(), "" | "0" | "false"));
+                let branch = if set != *negate { then } else { otherwise };
+                render_nodes(branch, vars, out)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Split a `name=value` assignment as given on the command line.
+pub fn parse_var(input: &str) -> Result<(String, String), String> {
+    match input.split_once('=') {
+        Some((name, value)) if !name.trim().is_empty() => {
+            Ok((name.trim().to_string(), value.to_string()))
+        }
+        _ => Err(format!("expected NAME=VALUE, got '{input}'")),
+    }
+}
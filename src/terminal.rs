@@ -0,0 +1,281 @@
+//! A character grid fed like a small terminal, for the server's `TERM` mode.
+//!
+//! Besides printable text, newlines, carriage returns, tabs, backspaces and form feeds, it
+//! understands a subset of ANSI/VT100 control sequences so console tools render sensibly:
+//! cursor positioning and movement (`CSI H`, `f`, `A`-`D`, `G`), erasing (`CSI J`, `K`),
+//! inverse video (`CSI 7m`, `27m`, `0m`) and reset (`ESC c`). Anything else is ignored.
+
+use embedded_graphics::{
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
+};
+
+use crate::{
+    buffer::MonoImage,
+    text::{display_width, transliterate, FontChoice},
+};
+
+/// Longest control sequence kept; longer ones are dropped unparsed.
+const MAX_ESCAPE: usize = 32;
+
+/// One character cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Cell {
+    /// `'\0'` continues a full-width glyph from the cell on the left.
+    ch: char,
+    inverse: bool,
+}
+
+const BLANK: Cell = Cell {
+    ch: ' ',
+    inverse: false,
+};
+
+/// A grid of character cells with a cursor. Text wraps at the right edge and the grid
+/// scrolls up when a line is added below the last row.
+pub struct Terminal {
+    font: FontChoice,
+    size: Size,
+    columns: usize,
+    rows: usize,
+    cells: Vec<Cell>,
+    row: usize,
+    column: usize,
+    inverse: bool,
+    /// The line ended with a newline that has not moved the cursor yet, so the last row
+    /// stays usable until more output arrives.
+    pending_newline: bool,
+    /// A control sequence (after `ESC`) cut off at the end of the last write.
+    escape: Option<String>,
+}
+
+impl Terminal {
+    /// As many cells of `font` as fit in `size`, without spacing between rows.
+    pub fn new(font: FontChoice, size: Size) -> Self {
+        let cell = font.char_size();
+        let columns = (size.width / cell.width).max(1) as usize;
+        let rows = (size.height / cell.height).max(1) as usize;
+        Self {
+            font,
+            size,
+            columns,
+            rows,
+            cells: vec![BLANK; columns * rows],
+            row: 0,
+            column: 0,
+            inverse: false,
+            pending_newline: false,
+            escape: None,
+        }
+    }
+
+    /// Process a line of output. The line is taken to end with a newline, which moves the
+    /// cursor only when more output follows.
+    pub fn write_line(&mut self, line: &str) {
+        self.write(line);
+        self.pending_newline = true;
+    }
+
+    /// Process output as it arrives: text, control characters and escape sequences.
+    pub fn write(&mut self, output: &str) {
+        let output = if self.font.is_cjk() {
+            output.to_string()
+        } else {
+            transliterate(output)
+        };
+        for ch in output.chars() {
+            if let Some(escape) = self.escape.as_mut() {
+                escape.push(ch);
+                let sequence = std::mem::take(escape);
+                self.escape = self.continue_escape(sequence);
+                continue;
+            }
+            match ch {
+                '\u{1b}' => self.escape = Some(String::new()),
+                '\u{c}' => {
+                    self.erase(0, self.cells.len());
+                    self.row = 0;
+                    self.column = 0;
+                    self.pending_newline = false;
+                }
+                '\n' => {
+                    self.flush_newline();
+                    self.newline();
+                }
+                '\r' => {
+                    self.flush_newline();
+                    self.column = 0;
+                }
+                '\t' => {
+                    self.flush_newline();
+                    self.column = ((self.column / 8 + 1) * 8).min(self.columns - 1);
+                }
+                '\u{8}' => self.column = self.column.saturating_sub(1),
+                ch if ch.is_control() => {}
+                ch => self.print(ch),
+            }
+        }
+    }
+
+    /// Render the grid from the top-left corner of an image of the size it was made for,
+    /// with the given colors; inverse cells swap them.
+    pub fn render(&self, foreground: BinaryColor, background: BinaryColor) -> MonoImage {
+        let cell_size = self.font.char_size();
+        let mut fb = MonoImage::new(self.size.width, self.size.height);
+        fb.clear(background);
+        for (index, cell) in self.cells.iter().enumerate() {
+            if cell.ch == '\0' {
+                continue;
+            }
+            let position = Point::new(
+                ((index % self.columns) as u32 * cell_size.width) as i32,
+                ((index / self.columns) as u32 * cell_size.height) as i32,
+            );
+            let mut color = foreground;
+            if cell.inverse {
+                let cells = display_width(cell.ch.encode_utf8(&mut [0; 4])).max(1) as u32;
+                Rectangle::new(
+                    position,
+                    Size::new(cell_size.width * cells, cell_size.height),
+                )
+                .into_styled(PrimitiveStyle::with_fill(foreground))
+                .draw(&mut fb)
+                .ok();
+                color = background;
+            }
+            if cell.ch != ' ' {
+                let mut buf = [0; 4];
+                self.font
+                    .draw_text(&mut fb, cell.ch.encode_utf8(&mut buf), position, color)
+                    .ok();
+            }
+        }
+        fb
+    }
+
+    /// Feed one more character of an escape sequence; returns what is left to wait for.
+    fn continue_escape(&mut self, sequence: String) -> Option<String> {
+        let Some(params) = sequence.strip_prefix('[') else {
+            match sequence.as_str() {
+                "c" => *self = Self::new(self.font, self.size),
+                // Character set selection, which takes one more character.
+                "(" | ")" => return Some(sequence),
+                _ => {}
+            }
+            return None;
+        };
+        match params.chars().last() {
+            Some(end @ '\u{40}'..='\u{7e}') => {
+                self.control(&params[..params.len() - end.len_utf8()], end);
+                None
+            }
+            _ if sequence.len() < MAX_ESCAPE => Some(sequence),
+            _ => None,
+        }
+    }
+
+    /// Carry out `CSI <params> <end>`.
+    fn control(&mut self, params: &str, end: char) {
+        if params.starts_with('?') {
+            // Private modes, such as hiding the cursor.
+            return;
+        }
+        let numbers: Vec<Option<usize>> = params.split(';').map(|p| p.parse().ok()).collect();
+        let arg = |index: usize| numbers.get(index).copied().flatten();
+        let count = arg(0).unwrap_or(1).max(1);
+        if !matches!(end, 'H' | 'f') {
+            self.flush_newline();
+        }
+        match end {
+            'H' | 'f' => {
+                self.pending_newline = false;
+                self.row = arg(0).unwrap_or(1).clamp(1, self.rows) - 1;
+                self.column = arg(1).unwrap_or(1).clamp(1, self.columns) - 1;
+            }
+            'A' => self.row = self.row.saturating_sub(count),
+            'B' => self.row = (self.row + count).min(self.rows - 1),
+            'C' => self.column = (self.column + count).min(self.columns - 1),
+            'D' => self.column = self.column.saturating_sub(count),
+            'G' => self.column = count.min(self.columns) - 1,
+            'J' => {
+                let cursor = self.cursor_index();
+                match arg(0).unwrap_or(0) {
+                    0 => self.erase(cursor, self.cells.len()),
+                    1 => self.erase(0, cursor + 1),
+                    _ => self.erase(0, self.cells.len()),
+                }
+            }
+            'K' => {
+                let line = self.row * self.columns;
+                let cursor = self.cursor_index();
+                match arg(0).unwrap_or(0) {
+                    0 => self.erase(cursor, line + self.columns),
+                    1 => self.erase(line, cursor + 1),
+                    _ => self.erase(line, line + self.columns),
+                }
+            }
+            'm' => {
+                for code in &numbers {
+                    match code.unwrap_or(0) {
+                        0 | 27 => self.inverse = false,
+                        7 => self.inverse = true,
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn print(&mut self, ch: char) {
+        self.flush_newline();
+        let width = display_width(ch.encode_utf8(&mut [0; 4]));
+        if width == 0 {
+            return;
+        }
+        if self.column + width > self.columns {
+            self.newline();
+        }
+        let index = self.cursor_index();
+        self.cells[index] = Cell {
+            ch,
+            inverse: self.inverse,
+        };
+        if width > 1 && self.column + 1 < self.columns {
+            self.cells[index + 1] = Cell {
+                ch: '\0',
+                inverse: self.inverse,
+            };
+        }
+        self.column = (self.column + width).min(self.columns);
+    }
+
+    fn flush_newline(&mut self) {
+        if std::mem::take(&mut self.pending_newline) {
+            self.newline();
+        }
+    }
+
+    fn newline(&mut self) {
+        self.column = 0;
+        if self.row + 1 < self.rows {
+            self.row += 1;
+        } else {
+            self.cells.drain(..self.columns);
+            self.cells.extend(std::iter::repeat_n(BLANK, self.columns));
+        }
+    }
+
+    /// Index of the cursor's cell; a cursor past the last column counts as on it.
+    fn cursor_index(&self) -> usize {
+        self.row * self.columns + self.column.min(self.columns - 1)
+    }
+
+    fn erase(&mut self, from: usize, to: usize) {
+        let to = to.min(self.cells.len());
+        if from < to {
+            self.cells[from..to].fill(BLANK);
+        }
+    }
+}
@@ -0,0 +1,751 @@
+use deunicode::deunicode_char;
+use embedded_graphics::{
+    mono_font::{
+        iso_8859_1::{FONT_10X20, FONT_6X10, FONT_7X13, FONT_9X15},
+        MonoFont, MonoTextStyle,
+    },
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::{Line, PrimitiveStyle, Rectangle},
+    text::{Baseline, Text},
+};
+use std::{fmt, str::FromStr};
+#[cfg(feature = "cjk")]
+use u8g2_fonts::{fonts, Font, U8g2TextStyle};
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use crate::buffer::MonoImage;
+use crate::dither::{dither, DitherMethod, GrayImage};
+use crate::markup::{is_marker, parse_markup, SpanStyle};
+
+/// Built-in fonts that can be used to render text on the panel. The monospace fonts use
+/// the ISO 8859-1 glyph set, so Latin-1 text such as "café" renders as-is. With the `cjk`
+/// feature, 16px Unifont variants for Chinese, Japanese and Korean are available too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FontChoice {
+    #[default]
+    Font6x10,
+    Font7x13,
+    Font9x15,
+    Font10x20,
+    /// Unifont with the GB 2312 simplified Chinese set.
+    #[cfg(feature = "cjk")]
+    CjkChinese,
+    /// Unifont with kana and common Japanese kanji.
+    #[cfg(feature = "cjk")]
+    CjkJapanese,
+    /// Unifont with Hangul syllables.
+    #[cfg(feature = "cjk")]
+    CjkKorean,
+}
+
+impl FontChoice {
+    /// All fonts; the monospace ones come first, from smallest to largest.
+    pub const ALL: &[FontChoice] = &[
+        FontChoice::Font6x10,
+        FontChoice::Font7x13,
+        FontChoice::Font9x15,
+        FontChoice::Font10x20,
+        #[cfg(feature = "cjk")]
+        FontChoice::CjkChinese,
+        #[cfg(feature = "cjk")]
+        FontChoice::CjkJapanese,
+        #[cfg(feature = "cjk")]
+        FontChoice::CjkKorean,
+    ];
+
+    /// The embedded-graphics font backing this choice, or `None` for the CJK fonts.
+    pub fn mono_font(self) -> Option<&'static MonoFont<'static>> {
+        match self {
+            FontChoice::Font6x10 => Some(&FONT_6X10),
+            FontChoice::Font7x13 => Some(&FONT_7X13),
+            FontChoice::Font9x15 => Some(&FONT_9X15),
+            FontChoice::Font10x20 => Some(&FONT_10X20),
+            #[cfg(feature = "cjk")]
+            _ => None,
+        }
+    }
+
+    /// Short name as accepted on the CLI and in the socket protocol, e.g. `6x10`.
+    pub fn name(self) -> &'static str {
+        match self {
+            FontChoice::Font6x10 => "6x10",
+            FontChoice::Font7x13 => "7x13",
+            FontChoice::Font9x15 => "9x15",
+            FontChoice::Font10x20 => "10x20",
+            #[cfg(feature = "cjk")]
+            FontChoice::CjkChinese => "cjk-zh",
+            #[cfg(feature = "cjk")]
+            FontChoice::CjkJapanese => "cjk-ja",
+            #[cfg(feature = "cjk")]
+            FontChoice::CjkKorean => "cjk-ko",
+        }
+    }
+
+    /// Size of one character cell. For the CJK fonts this is a half-width cell; full-width
+    /// glyphs take two (see [`display_width`]).
+    pub fn char_size(self) -> Size {
+        match self.mono_font() {
+            Some(font) => font.character_size,
+            None => Size::new(8, 16),
+        }
+    }
+
+    /// Whether the font draws glyphs beyond Latin-1, so text must not be transliterated.
+    pub fn is_cjk(self) -> bool {
+        self.mono_font().is_none()
+    }
+
+    /// Draw a single line of text with the top-left corner of its first cell at `position`.
+    pub fn draw_text<D>(
+        self,
+        target: &mut D,
+        text: &str,
+        position: Point,
+        color: BinaryColor,
+    ) -> Result<Point, D::Error>
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        match self {
+            #[cfg(feature = "cjk")]
+            FontChoice::CjkChinese => draw_u8g2(
+                target,
+                text,
+                position,
+                color,
+                fonts::u8g2_font_unifont_t_gb2312,
+            ),
+            #[cfg(feature = "cjk")]
+            FontChoice::CjkJapanese => draw_u8g2(
+                target,
+                text,
+                position,
+                color,
+                fonts::u8g2_font_unifont_t_japanese3,
+            ),
+            #[cfg(feature = "cjk")]
+            FontChoice::CjkKorean => draw_u8g2(
+                target,
+                text,
+                position,
+                color,
+                fonts::u8g2_font_unifont_t_korean2,
+            ),
+            _ => {
+                let font = self.mono_font().unwrap_or(&FONT_6X10);
+                let style = MonoTextStyle::new(font, color);
+                Text::with_baseline(text, position, style, Baseline::Top).draw(target)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "cjk")]
+fn draw_u8g2<D, F>(
+    target: &mut D,
+    text: &str,
+    position: Point,
+    color: BinaryColor,
+    font: F,
+) -> Result<Point, D::Error>
+where
+    D: DrawTarget<Color = BinaryColor>,
+    F: Font,
+{
+    let style = U8g2TextStyle::new(font, color);
+    Text::with_baseline(text, position, style, Baseline::Top).draw(target)
+}
+
+/// Width of `text` in half-width cells, measured per grapheme cluster so combining marks
+/// take no space and East Asian wide glyphs take two. Soft hyphens and markup style markers
+/// are invisible.
+pub fn display_width(text: &str) -> usize {
+    text.graphemes(true)
+        .filter(|g| *g != "\u{00AD}" && !g.starts_with(is_marker))
+        .map(|g| g.width())
+        .sum()
+}
+
+impl fmt::Display for FontChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for FontChoice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let wanted = s.trim().to_ascii_lowercase();
+        FontChoice::ALL
+            .iter()
+            .copied()
+            .find(|choice| choice.name() == wanted)
+            .ok_or_else(|| {
+                let names: Vec<_> = FontChoice::ALL.iter().map(|f| f.name()).collect();
+                format!("unknown font '{s}' (expected one of {})", names.join(", "))
+            })
+    }
+}
+
+/// Glyph used when a character has no Latin-1 form and no transliteration.
+const REPLACEMENT: char = '?';
+
+/// Rewrite `text` so every character is drawable with the Latin-1 fonts. The text is
+/// NFC-normalized first so decomposed accents (`e` + U+0301) become `é`; characters still
+/// outside Latin-1 are transliterated (`€` becomes `EUR`, `ő` becomes `o`, `“` becomes `"`),
+/// leftover combining marks are dropped and anything else becomes `?`.
+pub fn transliterate(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for ch in text.nfc() {
+        if (ch as u32) < 0x100 || is_marker(ch) {
+            out.push(ch);
+            continue;
+        }
+        match deunicode_char(ch) {
+            Some(ascii) => out.push_str(ascii),
+            None => out.push(REPLACEMENT),
+        }
+    }
+    out
+}
+
+/// `text` cut to `columns` half-width cells, transliterated unless `font` has the glyphs.
+pub fn fit_columns(text: &str, columns: usize, font: FontChoice) -> String {
+    let text = if font.is_cjk() {
+        text.to_string()
+    } else {
+        transliterate(text)
+    };
+    let mut used = 0;
+    text.chars()
+        .take_while(|ch| {
+            used += display_width(ch.encode_utf8(&mut [0; 4]));
+            used <= columns
+        })
+        .collect()
+}
+
+/// Horizontal placement of each wrapped line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HAlign {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+/// Vertical placement of the whole text block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VAlign {
+    #[default]
+    Top,
+    Middle,
+    Bottom,
+}
+
+/// Combined horizontal and vertical placement, parsed from specs like `center`,
+/// `right,bottom` or `center middle`. Parts that are not given keep their default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Alignment {
+    pub horizontal: HAlign,
+    pub vertical: VAlign,
+}
+
+impl fmt::Display for Alignment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let h = match self.horizontal {
+            HAlign::Left => "left",
+            HAlign::Center => "center",
+            HAlign::Right => "right",
+        };
+        let v = match self.vertical {
+            VAlign::Top => "top",
+            VAlign::Middle => "middle",
+            VAlign::Bottom => "bottom",
+        };
+        write!(f, "{h},{v}")
+    }
+}
+
+impl FromStr for Alignment {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut alignment = Alignment::default();
+        for part in s.split([',', ' ', '-']).filter(|p| !p.is_empty()) {
+            match part.to_ascii_lowercase().as_str() {
+                "left" => alignment.horizontal = HAlign::Left,
+                "center" | "centre" => alignment.horizontal = HAlign::Center,
+                "right" => alignment.horizontal = HAlign::Right,
+                "top" => alignment.vertical = VAlign::Top,
+                "middle" => alignment.vertical = VAlign::Middle,
+                "bottom" => alignment.vertical = VAlign::Bottom,
+                other => {
+                    return Err(format!(
+                        "unknown alignment '{other}' (expected left/center/right and/or top/middle/bottom)"
+                    ));
+                }
+            }
+        }
+        Ok(alignment)
+    }
+}
+
+/// How glyphs are rasterized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TextQuality {
+    /// Glyphs are drawn pixel-for-pixel from the bitmap font.
+    #[default]
+    Crisp,
+    /// Glyph bitmaps are enlarged 2x with edge-aware smoothing, box-filtered back down to a
+    /// grayscale buffer and dithered to 1bpp: same size as `Crisp`, softer diagonals.
+    Smooth,
+}
+
+/// Scale2x (AdvMAME2x): double `image`, filling each corner of a pixel's 2x2 block from
+/// the neighbours on that side when they agree, so diagonal edges gain an intermediate step
+/// instead of becoming twice as coarse.
+fn scale2x(image: &MonoImage) -> MonoImage {
+    let (width, height) = (image.width(), image.height());
+    let mut out = MonoImage::new(width * 2, height * 2);
+    let at = |x: i64, y: i64| {
+        let x = x.clamp(0, width as i64 - 1) as u32;
+        let y = y.clamp(0, height as i64 - 1) as u32;
+        image.pixel(x, y)
+    };
+    for y in 0..height {
+        for x in 0..width {
+            let (cx, cy) = (x as i64, y as i64);
+            let p = image.pixel(x, y);
+            let (up, down) = (at(cx, cy - 1), at(cx, cy + 1));
+            let (left, right) = (at(cx - 1, cy), at(cx + 1, cy));
+            let pick = |a: BinaryColor, b: BinaryColor, c: BinaryColor, d: BinaryColor| {
+                if a == b && a != c && b != d {
+                    a
+                } else {
+                    p
+                }
+            };
+            let (ox, oy) = (x * 2, y * 2);
+            out.set_pixel(ox, oy, pick(left, up, down, right));
+            out.set_pixel(ox + 1, oy, pick(up, right, left, down));
+            out.set_pixel(ox, oy + 1, pick(down, left, right, up));
+            out.set_pixel(ox + 1, oy + 1, pick(right, down, up, left));
+        }
+    }
+    out
+}
+
+/// Extra pixels between consecutive lines.
+const LINE_SPACING: u32 = 2;
+
+/// How a message is wrapped and placed on a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextLayout {
+    pub font: FontChoice,
+    pub alignment: Alignment,
+    pub foreground: BinaryColor,
+    pub background: BinaryColor,
+    /// Blank space kept between the frame edge and the text.
+    pub margin: u32,
+    /// Draw a 1px frame around the image.
+    pub border: bool,
+    /// Ignore `font` and pick the largest monospace font that fits the whole message.
+    pub auto_fit: bool,
+    /// Allow breaking words at soft hyphens (U+00AD) when wrapping.
+    pub hyphenate: bool,
+    /// Interpret `*bold*`, `_underline_` and `~inverse~` spans (see [`crate::markup`]).
+    pub markup: bool,
+    pub quality: TextQuality,
+}
+
+impl Default for TextLayout {
+    fn default() -> Self {
+        Self {
+            font: FontChoice::default(),
+            alignment: Alignment::default(),
+            foreground: BinaryColor::On,
+            background: BinaryColor::Off,
+            margin: 6,
+            border: true,
+            auto_fit: false,
+            hyphenate: true,
+            markup: false,
+            quality: TextQuality::Crisp,
+        }
+    }
+}
+
+impl TextLayout {
+    pub fn new(font: FontChoice, foreground: BinaryColor, background: BinaryColor) -> Self {
+        Self {
+            font,
+            foreground,
+            background,
+            ..Self::default()
+        }
+    }
+
+    /// Render `message` into a fresh image of the given size, with background, border and margin.
+    pub fn render(&self, message: &str, size: Size) -> MonoImage {
+        self.render_page(message, size, 0)
+    }
+
+    /// Like [`TextLayout::render`], showing the given page of a message that does not fit.
+    pub fn render_page(&self, message: &str, size: Size, page: usize) -> MonoImage {
+        let (mut fb, area) = self.frame(size);
+        self.draw_page(&mut fb, area, message, page);
+        fb
+    }
+
+    /// A fresh image of the given size with background and border applied, plus the area
+    /// inside the margin where content goes.
+    pub fn frame(&self, size: Size) -> (MonoImage, Rectangle) {
+        let mut fb = MonoImage::new(size.width, size.height);
+        fb.clear(self.background);
+
+        let frame = Rectangle::new(Point::zero(), size);
+        if self.border {
+            frame
+                .into_styled(PrimitiveStyle::with_stroke(self.foreground, 1))
+                .draw(&mut fb)
+                .ok();
+        }
+        (fb, frame.offset(-(self.margin as i32)))
+    }
+
+    /// Wrap and draw `message` inside `area` without touching the rest of the image.
+    /// Lines that do not fit are dropped; see [`TextLayout::draw_page`] to show them.
+    pub fn draw<D>(&self, target: &mut D, area: Rectangle, message: &str)
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        self.draw_page(target, area, message, 0);
+    }
+
+    /// Like [`TextLayout::draw`], but skip the first `page` areas' worth of wrapped lines.
+    /// Pages past the end draw nothing.
+    pub fn draw_page<D>(&self, target: &mut D, area: Rectangle, message: &str, page: usize)
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        if self.quality == TextQuality::Smooth {
+            return self.draw_smooth(target, area, message, page);
+        }
+
+        if self.auto_fit {
+            return self
+                .fitted(message, area.size)
+                .draw_page(target, area, message, page);
+        }
+
+        let (max_chars, max_lines) = self.capacity(area.size);
+        let cell = self.font.char_size();
+        let line_height = cell.height + LINE_SPACING;
+
+        let mut wrapped = self.wrap(message, max_chars).into_iter();
+        // Spans left open on earlier pages carry over, as they do between lines.
+        let mut style = SpanStyle::default();
+        for line in wrapped.by_ref().take(page * max_lines) {
+            line.chars().for_each(|ch| {
+                style.toggle(ch);
+            });
+        }
+        let lines: Vec<String> = wrapped.take(max_lines).collect();
+        let block_height = (lines.len() as u32 * line_height).saturating_sub(LINE_SPACING);
+        let top = match self.alignment.vertical {
+            VAlign::Top => 0,
+            VAlign::Middle => area.size.height.saturating_sub(block_height) / 2,
+            VAlign::Bottom => area.size.height.saturating_sub(block_height),
+        };
+
+        let mut y = area.top_left.y + top as i32;
+        for line in lines {
+            let width = display_width(&line) as u32 * cell.width;
+            let left = match self.alignment.horizontal {
+                HAlign::Left => 0,
+                HAlign::Center => area.size.width.saturating_sub(width) / 2,
+                HAlign::Right => area.size.width.saturating_sub(width),
+            };
+            let origin = Point::new(area.top_left.x + left as i32, y);
+            self.draw_line(target, &line, origin, &mut style);
+            y += line_height as i32;
+        }
+    }
+
+    /// Supersample: lay the text out as usual into a mask, enlarge it 2x with Scale2x so
+    /// diagonal staircases become finer steps, average each 2x2 block back into a grayscale
+    /// buffer and dither that onto `target`. Glyphs keep the chosen font's size; only ink
+    /// pixels are written.
+    fn draw_smooth<D>(&self, target: &mut D, area: Rectangle, message: &str, page: usize)
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        let mask_layout = TextLayout {
+            foreground: BinaryColor::On,
+            background: BinaryColor::Off,
+            quality: TextQuality::Crisp,
+            ..*self
+        };
+        let mut mask = MonoImage::new(area.size.width, area.size.height);
+        mask_layout.draw_page(
+            &mut mask,
+            Rectangle::new(Point::zero(), area.size),
+            message,
+            page,
+        );
+        let mask = scale2x(&mask);
+
+        let gray = GrayImage::from_mono(&mask).downsample(2);
+        let ink = dither(&gray, DitherMethod::FloydSteinberg);
+        let pixels = (0..ink.height())
+            .flat_map(|y| (0..ink.width()).map(move |x| (x, y)))
+            .filter(|&(x, y)| ink.pixel(x, y) == BinaryColor::On)
+            .map(|(x, y)| {
+                let point = area.top_left + Point::new(x as i32, y as i32);
+                Pixel(point, self.foreground)
+            });
+        target.draw_iter(pixels).ok();
+    }
+
+    /// Draw one wrapped line as runs of equally styled text. `style` carries open spans
+    /// over to the next line.
+    fn draw_line<D>(&self, target: &mut D, line: &str, origin: Point, style: &mut SpanStyle)
+    where
+        D: DrawTarget<Color = BinaryColor>,
+    {
+        let cell = self.font.char_size();
+        let mut x = origin.x;
+        for run in line.split_inclusive(is_marker) {
+            let (text, marker) = match run.chars().last() {
+                Some(ch) if is_marker(ch) => (&run[..run.len() - ch.len_utf8()], Some(ch)),
+                _ => (run, None),
+            };
+
+            if !text.is_empty() {
+                let width = display_width(text) as u32 * cell.width;
+                let run_area =
+                    Rectangle::new(Point::new(x, origin.y), Size::new(width, cell.height));
+                let color = if style.inverse {
+                    run_area
+                        .into_styled(PrimitiveStyle::with_fill(self.foreground))
+                        .draw(target)
+                        .ok();
+                    self.background
+                } else {
+                    self.foreground
+                };
+
+                let position = Point::new(x, origin.y);
+                self.font.draw_text(target, text, position, color).ok();
+                if style.bold {
+                    let shifted = position + Point::new(1, 0);
+                    self.font.draw_text(target, text, shifted, color).ok();
+                }
+                if style.underline {
+                    let y = origin.y + cell.height as i32 - 1;
+                    Line::new(Point::new(x, y), Point::new(x + width as i32 - 1, y))
+                        .into_styled(PrimitiveStyle::with_stroke(color, 1))
+                        .draw(target)
+                        .ok();
+                }
+                x += width as i32;
+            }
+
+            if let Some(marker) = marker {
+                style.toggle(marker);
+            }
+        }
+    }
+
+    /// The largest monospace font that shows all of `message` in `size` without dropping
+    /// lines or hard-breaking words, falling back to the smallest one. CJK fonts come in a
+    /// single size, so they are returned unchanged.
+    pub fn fitted_font(&self, message: &str, size: Size) -> FontChoice {
+        if self.font.is_cjk() {
+            return self.font;
+        }
+
+        let longest_word = self
+            .prepare(message)
+            .split_whitespace()
+            .map(display_width)
+            .max()
+            .unwrap_or(0);
+        let candidates = FontChoice::ALL.iter().copied().filter(|f| !f.is_cjk());
+        let mut best = FontChoice::Font6x10;
+        for font in candidates {
+            let layout = TextLayout { font, ..*self };
+            let (max_chars, max_lines) = layout.capacity(size);
+            let fits =
+                longest_word <= max_chars && layout.wrap(message, max_chars).len() <= max_lines;
+            if fits {
+                best = font;
+            }
+        }
+        best
+    }
+
+    /// How many areas of `size` it takes to show all of `message` with
+    /// [`TextLayout::draw_page`]; at least 1.
+    pub fn page_count(&self, message: &str, size: Size) -> usize {
+        let layout = if self.auto_fit {
+            self.fitted(message, size)
+        } else {
+            *self
+        };
+        let (max_chars, max_lines) = layout.capacity(size);
+        layout
+            .wrap(message, max_chars)
+            .len()
+            .div_ceil(max_lines.max(1))
+            .max(1)
+    }
+
+    /// This layout with `auto_fit` resolved to a concrete font for `message`.
+    fn fitted(&self, message: &str, size: Size) -> TextLayout {
+        TextLayout {
+            font: self.fitted_font(message, size),
+            auto_fit: false,
+            ..*self
+        }
+    }
+
+    /// How many cells per line and how many lines fit in an area of `size`.
+    pub fn capacity(&self, size: Size) -> (usize, usize) {
+        let cell = self.font.char_size();
+        let max_chars = (size.width / cell.width).max(1) as usize;
+        let max_lines = ((size.height + LINE_SPACING) / (cell.height + LINE_SPACING)) as usize;
+        (max_chars, max_lines)
+    }
+
+    /// Height in pixels that `message` takes when wrapped to `width` pixels.
+    pub fn measure(&self, message: &str, width: u32) -> u32 {
+        let cell = self.font.char_size();
+        let max_chars = (width / cell.width).max(1) as usize;
+        let lines = self.wrap(message, max_chars).len() as u32;
+        (lines * (cell.height + LINE_SPACING)).saturating_sub(LINE_SPACING)
+    }
+
+    /// Parse markup (if enabled), transliterate (unless the font is CJK) and wrap `message`
+    /// to `max_chars` cells.
+    pub fn wrap(&self, message: &str, max_chars: usize) -> Vec<String> {
+        wrap_text(&self.prepare(message), max_chars, self.hyphenate)
+    }
+
+    fn prepare(&self, message: &str) -> String {
+        let message = if self.markup {
+            parse_markup(message)
+        } else {
+            message.to_string()
+        };
+        if self.font.is_cjk() {
+            message
+        } else {
+            transliterate(&message)
+        }
+    }
+}
+
+/// Soft hyphen: an invisible hint marking where a word may be broken.
+const SOFT_HYPHEN: char = '\u{00AD}';
+
+/// Wrap `text` into lines at most `max_chars` cells wide (see [`display_width`]). Words are
+/// kept whole where possible; with `hyphenate`, a word that does not fit may break at a
+/// soft hyphen (U+00AD), which is then drawn as `-`. Words wider than a line are split on
+/// grapheme boundaries so emoji and accented letters are never torn apart.
+pub fn wrap_text(text: &str, max_chars: usize, hyphenate: bool) -> Vec<String> {
+    let max_chars = max_chars.max(1);
+    let mut lines = Vec::new();
+    for paragraph in text.split('\n') {
+        if paragraph.is_empty() {
+            lines.push(String::new());
+            continue;
+        }
+
+        let mut current = String::new();
+        let mut current_len = 0;
+        for word in paragraph.split_whitespace() {
+            let mut rest = word;
+            while !rest.is_empty() {
+                let gap = usize::from(current_len > 0);
+                let rest_len = display_width(rest);
+                if current_len + gap + rest_len <= max_chars {
+                    if gap > 0 {
+                        current.push(' ');
+                    }
+                    push_visible(&mut current, rest);
+                    current_len += gap + rest_len;
+                    break;
+                }
+
+                let room = max_chars.saturating_sub(current_len + gap);
+                let hyphen_split = if hyphenate {
+                    split_at_hyphen(rest, room)
+                } else {
+                    None
+                };
+                if let Some((head, tail)) = hyphen_split {
+                    if gap > 0 {
+                        current.push(' ');
+                    }
+                    push_visible(&mut current, head);
+                    current.push('-');
+                    lines.push(std::mem::take(&mut current));
+                    current_len = 0;
+                    rest = tail;
+                    continue;
+                }
+
+                if current_len > 0 {
+                    lines.push(std::mem::take(&mut current));
+                    current_len = 0;
+                    continue;
+                }
+
+                let (head, tail) = split_at_width(rest, max_chars);
+                push_visible(&mut current, head);
+                lines.push(std::mem::take(&mut current));
+                rest = tail;
+            }
+        }
+
+        if !current.is_empty() {
+            lines.push(current);
+        }
+    }
+    lines
+}
+
+/// Append `text` without its soft hyphens.
+fn push_visible(line: &mut String, text: &str) {
+    line.extend(text.chars().filter(|&ch| ch != SOFT_HYPHEN));
+}
+
+/// Split `word` at the last soft hyphen whose prefix, plus the drawn `-`, fits in `room` cells.
+fn split_at_hyphen(word: &str, room: usize) -> Option<(&str, &str)> {
+    word.rmatch_indices(SOFT_HYPHEN)
+        .map(|(idx, _)| idx)
+        .find(|&idx| idx > 0 && display_width(&word[..idx]) < room)
+        .map(|idx| (&word[..idx], &word[idx + SOFT_HYPHEN.len_utf8()..]))
+}
+
+/// Split off the longest run of whole graphemes that fits in `max_chars` cells. Always takes
+/// at least one grapheme so callers make progress.
+fn split_at_width(word: &str, max_chars: usize) -> (&str, &str) {
+    let mut width = 0;
+    let mut end = 0;
+    for (idx, grapheme) in word.grapheme_indices(true) {
+        let w = display_width(grapheme);
+        if width + w > max_chars && end > 0 {
+            break;
+        }
+        width += w;
+        end = idx + grapheme.len();
+    }
+    word.split_at(end)
+}
@@ -0,0 +1,80 @@
+//! Horizontally scrolling single-line text.
+
+use embedded_graphics::prelude::*;
+
+use crate::{
+    buffer::MonoImage,
+    text::{display_width, transliterate, TextLayout, VAlign},
+};
+
+/// A message rendered once onto a long strip, from which successive frames are cut.
+///
+/// The strip starts with a blank stretch as wide as the text area, so the message enters
+/// from the right edge and leaves completely before it comes round again.
+pub struct Ticker {
+    layout: TextLayout,
+    size: Size,
+    strip: MonoImage,
+}
+
+impl Ticker {
+    /// Lay out `text` on one line (newlines become spaces) for frames of `size`, using
+    /// the font, colors, margin, border and vertical alignment of `layout`.
+    pub fn new(text: &str, layout: &TextLayout, size: Size) -> Self {
+        let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        let text = if layout.font.is_cjk() {
+            text
+        } else {
+            transliterate(&text)
+        };
+        let cell = layout.font.char_size();
+        let area = layout.frame(size).1;
+        let gap = area.size.width;
+        let width = gap + display_width(&text) as u32 * cell.width;
+
+        let mut strip = MonoImage::new(width, area.size.height);
+        strip.clear(layout.background);
+        let top = match layout.alignment.vertical {
+            VAlign::Top => 0,
+            VAlign::Middle => area.size.height.saturating_sub(cell.height) / 2,
+            VAlign::Bottom => area.size.height.saturating_sub(cell.height),
+        };
+        layout
+            .font
+            .draw_text(
+                &mut strip,
+                &text,
+                Point::new(gap as i32, top as i32),
+                layout.foreground,
+            )
+            .ok();
+
+        Self {
+            layout: *layout,
+            size,
+            strip,
+        }
+    }
+
+    /// Pixels scrolled in one full cycle.
+    pub fn period(&self) -> u32 {
+        self.strip.width()
+    }
+
+    /// The frame shown after scrolling `offset` pixels; offsets wrap around the period.
+    pub fn frame(&self, offset: u64) -> MonoImage {
+        let (mut fb, area) = self.layout.frame(self.size);
+        let period = self.period().max(1) as u64;
+        for x in 0..area.size.width {
+            let sx = ((offset + x as u64) % period) as u32;
+            for y in 0..self.strip.height() {
+                let color = self.strip.pixel(sx, y);
+                let point = area.top_left + Point::new(x as i32, y as i32);
+                if color != self.layout.background && point.x >= 0 && point.y >= 0 {
+                    fb.set_pixel(point.x as u32, point.y as u32, color);
+                }
+            }
+        }
+        fb
+    }
+}
@@ -0,0 +1,250 @@
+//! A to-do list kept in a Markdown file, as GitHub-style task list items:
+//!
+//! ```markdown
+//! # Groceries
+//! - [ ] Milk
+//! - [x] Bread
+//!   - [ ] Sourdough, if there is any
+//! ```
+//!
+//! Items can be toggled and the file written back with only their checkboxes changed;
+//! headings are shown as section titles and every other line is kept but not shown.
+
+use embedded_graphics::{
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::{Line, PrimitiveStyle, Rectangle},
+};
+
+use crate::{
+    buffer::MonoImage,
+    text::{display_width, fit_columns, FontChoice},
+};
+
+/// One task list item.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TodoItem {
+    pub text: String,
+    pub done: bool,
+    /// Nesting level, from the indentation in steps of two spaces.
+    pub depth: usize,
+    /// The line of the file it is on.
+    line: usize,
+    /// Byte offset of the mark between the brackets on that line.
+    mark: usize,
+}
+
+impl TodoItem {
+    /// The zero-based line of the file the item is on.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+}
+
+/// What the list shows, in file order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Entry {
+    Heading(String),
+    /// Index into the items.
+    Item(usize),
+}
+
+/// A parsed to-do file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TodoList {
+    lines: Vec<String>,
+    /// The line ending of the file, kept when writing it back.
+    newline: &'static str,
+    trailing_newline: bool,
+    items: Vec<TodoItem>,
+    entries: Vec<Entry>,
+}
+
+impl TodoList {
+    pub fn parse(text: &str) -> Self {
+        let newline = if text.contains("\r\n") { "\r\n" } else { "\n" };
+        let lines: Vec<String> = text.lines().map(str::to_string).collect();
+        let mut items = Vec::new();
+        let mut entries = Vec::new();
+        for (index, line) in lines.iter().enumerate() {
+            if let Some(heading) = heading(line) {
+                entries.push(Entry::Heading(heading.to_string()));
+            } else if let Some(mut item) = task(line) {
+                item.line = index;
+                entries.push(Entry::Item(items.len()));
+                items.push(item);
+            }
+        }
+        Self {
+            lines,
+            newline,
+            trailing_newline: text.ends_with('\n'),
+            items,
+            entries,
+        }
+    }
+
+    pub fn items(&self) -> &[TodoItem] {
+        &self.items
+    }
+
+    /// The index of the item on line `line` of the file, if there is one.
+    pub fn item_on_line(&self, line: usize) -> Option<usize> {
+        self.items.iter().position(|item| item.line == line)
+    }
+
+    /// Check or uncheck item `index`; returns `false` if there is no such item.
+    pub fn toggle(&mut self, index: usize) -> bool {
+        let Some(item) = self.items.get_mut(index) else {
+            return false;
+        };
+        item.done = !item.done;
+        let mark = if item.done { "x" } else { " " };
+        self.lines[item.line].replace_range(item.mark..item.mark + 1, mark);
+        true
+    }
+
+    /// The file's text, with the checkboxes as they are now.
+    pub fn to_text(&self) -> String {
+        let mut text = self.lines.join(self.newline);
+        if self.trailing_newline {
+            text.push_str(self.newline);
+        }
+        text
+    }
+
+    /// Draw the list onto a canvas of `size`: headings underlined, items with a checkbox
+    /// (ticked and struck through when done), and item `selected`, if any, in inverse video.
+    /// A list longer than the canvas is paged so that the selected item shows.
+    pub fn render(
+        &self,
+        selected: Option<usize>,
+        font: FontChoice,
+        foreground: BinaryColor,
+        background: BinaryColor,
+        size: Size,
+    ) -> MonoImage {
+        let cell = font.char_size();
+        let row = cell.height + 2;
+        let columns = (size.width / cell.width).max(1) as usize;
+        let rows = (size.height / row).max(1) as usize;
+        let mut fb = MonoImage::new(size.width, size.height);
+        fb.clear(background);
+        if self.entries.is_empty() {
+            font.draw_text(&mut fb, "Nothing to do", Point::zero(), foreground)
+                .ok();
+            return fb;
+        }
+
+        let current = selected.and_then(|selected| {
+            self.entries
+                .iter()
+                .position(|entry| *entry == Entry::Item(selected))
+        });
+        let page = current.unwrap_or(0) / rows;
+        let stroke = PrimitiveStyle::with_stroke(foreground, 1);
+        for (slot, entry) in self.entries.iter().skip(page * rows).take(rows).enumerate() {
+            let y = (slot as u32 * row) as i32;
+            match entry {
+                Entry::Heading(text) => {
+                    let text = fit_columns(text, columns, font);
+                    font.draw_text(&mut fb, &text, Point::new(0, y + 1), foreground)
+                        .ok();
+                    let width = display_width(&text) as i32 * cell.width as i32;
+                    let bottom = y + row as i32 - 1;
+                    Line::new(Point::new(0, bottom), Point::new(width - 1, bottom))
+                        .into_styled(stroke)
+                        .draw(&mut fb)
+                        .ok();
+                }
+                Entry::Item(index) => {
+                    let item = &self.items[*index];
+                    let color = if selected == Some(*index) {
+                        Rectangle::new(Point::new(0, y), Size::new(size.width, row))
+                            .into_styled(PrimitiveStyle::with_fill(foreground))
+                            .draw(&mut fb)
+                            .ok();
+                        background
+                    } else {
+                        foreground
+                    };
+                    let indent = (item.depth as u32 * 2 * cell.width) as i32;
+                    // A box as tall as a capital letter, then a space.
+                    let side = (cell.height - 2).min(cell.width * 2);
+                    let checkbox = Rectangle::new(Point::new(indent, y + 2), Size::new(side, side));
+                    checkbox
+                        .into_styled(PrimitiveStyle::with_stroke(color, 1))
+                        .draw(&mut fb)
+                        .ok();
+                    if item.done {
+                        let inner = checkbox.offset(-2);
+                        if let Some(corner) = inner.bottom_right() {
+                            let style = PrimitiveStyle::with_stroke(color, 1);
+                            Line::new(inner.top_left, corner)
+                                .into_styled(style)
+                                .draw(&mut fb)
+                                .ok();
+                            let (left, right) = (inner.top_left.x, corner.x);
+                            Line::new(
+                                Point::new(left, corner.y),
+                                Point::new(right, inner.top_left.y),
+                            )
+                            .into_styled(style)
+                            .draw(&mut fb)
+                            .ok();
+                        }
+                    }
+                    let x = indent + (side + cell.width) as i32;
+                    let room = columns.saturating_sub(x as usize / cell.width as usize);
+                    let text = fit_columns(&item.text, room, font);
+                    font.draw_text(&mut fb, &text, Point::new(x, y + 1), color)
+                        .ok();
+                    if item.done && !text.is_empty() {
+                        let width = display_width(&text) as i32 * cell.width as i32;
+                        let middle = y + 1 + cell.height as i32 / 2;
+                        Line::new(Point::new(x, middle), Point::new(x + width - 1, middle))
+                            .into_styled(PrimitiveStyle::with_stroke(color, 1))
+                            .draw(&mut fb)
+                            .ok();
+                    }
+                }
+            }
+        }
+        fb
+    }
+}
+
+/// The text of a `#` heading.
+fn heading(line: &str) -> Option<&str> {
+    let rest = line.trim_start_matches('#');
+    if rest.len() == line.len() || !rest.starts_with(' ') {
+        return None;
+    }
+    Some(rest.trim())
+}
+
+/// A `- [ ] text` item with `-`, `*` or `+` as the bullet, `x` or `X` for done.
+fn task(line: &str) -> Option<TodoItem> {
+    let content = line.trim_start();
+    let indent = line.len() - content.len();
+    let rest = content
+        .strip_prefix("- ")
+        .or(content.strip_prefix("* "))
+        .or(content.strip_prefix("+ "))?;
+    let done = match rest.get(..3)? {
+        "[ ]" => false,
+        "[x]" | "[X]" => true,
+        _ => return None,
+    };
+    let text = &rest[3..];
+    if !(text.is_empty() || text.starts_with(' ')) {
+        return None;
+    }
+    Some(TodoItem {
+        text: text.trim().to_string(),
+        done,
+        depth: line[..indent].replace('\t', "  ").len() / 2,
+        line: 0,
+        mark: indent + 3,
+    })
+}
@@ -0,0 +1,134 @@
+//! Battery readings from the fuel gauge of a UPS HAT over I2C (the `ups` feature): the
+//! INA219 current/voltage monitor of the Waveshare UPS HATs, or a MAX17040 gauge as on
+//! many LiPo boards.
+
+use std::str::FromStr;
+
+use rppal::i2c::{self, I2c};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum UpsError {
+    #[error("I2C error: {0}")]
+    I2c(#[from] i2c::Error),
+}
+
+/// A supported gauge chip.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Gauge {
+    /// Bus voltage and current through a 0.1 Ω shunt; the charge is estimated from the
+    /// voltage.
+    Ina219,
+    /// Cell voltage and the chip's own state of charge.
+    Max17040,
+}
+
+impl Gauge {
+    /// The address the chip usually has: 0x42 on the Waveshare UPS HATs, 0x36 for the
+    /// MAX17040.
+    pub fn default_address(self) -> u16 {
+        match self {
+            Gauge::Ina219 => 0x42,
+            Gauge::Max17040 => 0x36,
+        }
+    }
+}
+
+impl FromStr for Gauge {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "ina219" => Ok(Gauge::Ina219),
+            "max17040" | "max17043" | "max17048" => Ok(Gauge::Max17040),
+            _ => Err(format!("unknown gauge '{s}' (expected ina219 or max17040)")),
+        }
+    }
+}
+
+/// One reading of the battery.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BatteryReading {
+    /// Charge from 0 to 100.
+    pub percent: f32,
+    pub voltage: f32,
+    /// Current into the battery in mA, negative while discharging; only the INA219 has it.
+    pub current_ma: Option<f32>,
+}
+
+impl BatteryReading {
+    /// Whether current flows into the battery; `false` when the gauge cannot tell.
+    pub fn charging(&self) -> bool {
+        self.current_ma.is_some_and(|current| current > 0.0)
+    }
+}
+
+/// INA219 registers.
+const INA219_SHUNT_VOLTAGE: u8 = 0x01;
+const INA219_BUS_VOLTAGE: u8 = 0x02;
+/// Shunt resistance of the Waveshare UPS HATs, in ohms.
+const INA219_SHUNT_OHMS: f32 = 0.1;
+
+/// MAX17040 registers.
+const MAX17040_VCELL: u8 = 0x02;
+const MAX17040_SOC: u8 = 0x04;
+
+/// Voltage of an empty and a full Li-ion cell, for estimating the charge of an INA219.
+const CELL_EMPTY: f32 = 3.0;
+const CELL_FULL: f32 = 4.2;
+
+/// A gauge on the I2C bus.
+pub struct UpsMonitor {
+    i2c: I2c,
+    gauge: Gauge,
+    /// Cells in series, which the INA219 measures together.
+    cells: u32,
+}
+
+impl UpsMonitor {
+    /// Talk to `gauge` at `address` (its usual one if `None`) on the Pi's I2C bus. `cells`
+    /// is the number of Li-ion cells in series, 2 on the UPS HAT (B) and 1 on the others.
+    pub fn open(gauge: Gauge, address: Option<u16>, cells: u32) -> Result<Self, UpsError> {
+        let mut i2c = I2c::new()?;
+        i2c.set_slave_address(address.unwrap_or(gauge.default_address()))?;
+        Ok(Self {
+            i2c,
+            gauge,
+            cells: cells.max(1),
+        })
+    }
+
+    pub fn read(&mut self) -> Result<BatteryReading, UpsError> {
+        match self.gauge {
+            Gauge::Ina219 => {
+                // Bits 15-3 in 4 mV steps; shunt voltage signed in 10 µV steps.
+                let voltage = (self.register(INA219_BUS_VOLTAGE)? >> 3) as f32 * 0.004;
+                let shunt = self.register(INA219_SHUNT_VOLTAGE)? as i16 as f32 * 0.000_01;
+                let cell = voltage / self.cells as f32;
+                let percent = (cell - CELL_EMPTY) / (CELL_FULL - CELL_EMPTY) * 100.0;
+                Ok(BatteryReading {
+                    percent: percent.clamp(0.0, 100.0),
+                    voltage,
+                    current_ma: Some(shunt / INA219_SHUNT_OHMS * 1000.0),
+                })
+            }
+            Gauge::Max17040 => {
+                // Bits 15-4 in 1.25 mV steps; the state of charge in 1/256 %.
+                let voltage = (self.register(MAX17040_VCELL)? >> 4) as f32 * 0.001_25;
+                let percent = self.register(MAX17040_SOC)? as f32 / 256.0;
+                Ok(BatteryReading {
+                    percent: percent.clamp(0.0, 100.0),
+                    voltage,
+                    current_ma: None,
+                })
+            }
+        }
+    }
+
+    /// A big-endian 16-bit register.
+    fn register(&mut self, register: u8) -> Result<u16, UpsError> {
+        let mut value = [0; 2];
+        self.i2c.write_read(&[register], &mut value)?;
+        Ok(u16::from_be_bytes(value))
+    }
+}
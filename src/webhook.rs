@@ -0,0 +1,201 @@
+//! Short, display-sized summaries of webhook payloads from GitHub, Grafana and other
+//! services.
+
+use serde_json::Value;
+
+/// Where a webhook comes from, which decides how its payload is read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebhookSource {
+    /// GitHub, with the event name from the `X-GitHub-Event` header (`push`, `issues`, ...).
+    GitHub,
+    /// Grafana alerting, both the unified alerting format and the legacy one.
+    Grafana,
+    /// Anything else: the first of the usual `text`, `message`, `title`/`body`, `summary`,
+    /// `content` or `description` fields.
+    Generic,
+}
+
+/// Longest line taken from a free-form field, such as a commit message.
+const MAX_LINE: usize = 80;
+
+/// Alerts listed by name before the rest are only counted.
+const MAX_ALERTS: usize = 5;
+
+/// The text to show for a payload, or `None` if it holds nothing worth showing. `event` is
+/// GitHub's event name and is ignored for other sources.
+pub fn summarize(source: WebhookSource, event: Option<&str>, payload: &Value) -> Option<String> {
+    match source {
+        WebhookSource::GitHub => github(event.unwrap_or_default(), payload),
+        WebhookSource::Grafana => grafana(payload),
+        WebhookSource::Generic => generic(payload),
+    }
+}
+
+fn github(event: &str, payload: &Value) -> Option<String> {
+    let repo = str_at(payload, "/repository/full_name").unwrap_or("GitHub");
+    let action = str_at(payload, "/action").unwrap_or_default();
+    let sender = str_at(payload, "/sender/login").unwrap_or("someone");
+    let summary = match event {
+        "ping" => format!("GitHub webhook connected\n{repo}"),
+        "push" => {
+            let branch = str_at(payload, "/ref")
+                .map(|r| r.trim_start_matches("refs/heads/"))
+                .unwrap_or_default();
+            let commits = payload["commits"].as_array().map_or(0, Vec::len);
+            let pusher = str_at(payload, "/pusher/name").unwrap_or(sender);
+            let mut summary = format!(
+                "{pusher} pushed {commits} commit{} to {repo}:{branch}",
+                plural(commits)
+            );
+            if let Some(message) = str_at(payload, "/head_commit/message") {
+                summary.push('\n');
+                summary.push_str(&first_line(message));
+            }
+            summary
+        }
+        "pull_request" => {
+            let number = payload["number"].as_u64().unwrap_or_default();
+            let title = str_at(payload, "/pull_request/title").unwrap_or_default();
+            let merged = payload["pull_request"]["merged"].as_bool() == Some(true);
+            let action = if action == "closed" && merged {
+                "merged"
+            } else {
+                action
+            };
+            format!(
+                "PR #{number} {action}: {}\n{repo} by {sender}",
+                first_line(title)
+            )
+        }
+        "issues" => {
+            let number = str_or_number(&payload["issue"]["number"]);
+            let title = str_at(payload, "/issue/title").unwrap_or_default();
+            format!(
+                "Issue #{number} {action}: {}\n{repo} by {sender}",
+                first_line(title)
+            )
+        }
+        "issue_comment" => {
+            let number = str_or_number(&payload["issue"]["number"]);
+            let body = str_at(payload, "/comment/body").unwrap_or_default();
+            format!(
+                "{sender} commented on #{number} in {repo}\n{}",
+                first_line(body)
+            )
+        }
+        "release" => {
+            let tag = str_at(payload, "/release/tag_name").unwrap_or_default();
+            format!("Release {tag} {action}\n{repo}")
+        }
+        "workflow_run" => {
+            let run = &payload["workflow_run"];
+            let name = run["name"].as_str().unwrap_or("Workflow");
+            let outcome = run["conclusion"]
+                .as_str()
+                .or(run["status"].as_str())
+                .unwrap_or(action);
+            let branch = run["head_branch"].as_str().unwrap_or_default();
+            format!("{name}: {outcome}\n{repo} {branch}")
+        }
+        "star" | "watch" => format!("{sender} starred {repo}"),
+        "" => return generic(payload),
+        event if action.is_empty() => format!("GitHub {event}\n{repo} by {sender}"),
+        event => format!("GitHub {event} {action}\n{repo} by {sender}"),
+    };
+    Some(summary.trim_end().to_string())
+}
+
+fn grafana(payload: &Value) -> Option<String> {
+    let Some(alerts) = payload["alerts"].as_array() else {
+        // Legacy alerting: one rule per notification.
+        let title = str_at(payload, "/title").or(str_at(payload, "/ruleName"))?;
+        let mut summary = first_line(title);
+        if let Some(message) = str_at(payload, "/message") {
+            summary.push('\n');
+            summary.push_str(&first_line(message));
+        }
+        return Some(summary);
+    };
+    let firing: Vec<&Value> = alerts
+        .iter()
+        .filter(|alert| alert["status"].as_str() != Some("resolved"))
+        .collect();
+    let (heading, listed) = if firing.is_empty() {
+        (
+            format!("RESOLVED: {} alert{}", alerts.len(), plural(alerts.len())),
+            alerts.iter().collect(),
+        )
+    } else {
+        (
+            format!("FIRING: {} alert{}", firing.len(), plural(firing.len())),
+            firing,
+        )
+    };
+    let mut lines = vec![heading];
+    for alert in listed.iter().take(MAX_ALERTS) {
+        let name = str_at(alert, "/labels/alertname").unwrap_or("alert");
+        match str_at(alert, "/annotations/summary") {
+            Some(summary) => lines.push(format!("{name}: {}", first_line(summary))),
+            None => lines.push(name.to_string()),
+        }
+    }
+    if listed.len() > MAX_ALERTS {
+        lines.push(format!("and {} more", listed.len() - MAX_ALERTS));
+    }
+    Some(lines.join("\n"))
+}
+
+fn generic(payload: &Value) -> Option<String> {
+    if let Some(text) = payload.as_str() {
+        return non_empty(text);
+    }
+    for field in ["text", "message", "summary", "content"] {
+        if let Some(text) = payload[field].as_str().and_then(non_empty) {
+            return Some(text);
+        }
+    }
+    let title = payload["title"].as_str().and_then(non_empty);
+    let body = ["body", "description"]
+        .iter()
+        .find_map(|field| payload[*field].as_str().and_then(non_empty));
+    match (title, body) {
+        (Some(title), Some(body)) => Some(format!("{title}\n{body}")),
+        (title, body) => title.or(body),
+    }
+}
+
+/// A string found at a JSON pointer such as `/repository/full_name`.
+fn str_at<'a>(value: &'a Value, pointer: &str) -> Option<&'a str> {
+    value.pointer(pointer)?.as_str()
+}
+
+fn str_or_number(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        other => other.as_u64().unwrap_or_default().to_string(),
+    }
+}
+
+/// The first line of a free-form text, shortened to [`MAX_LINE`] characters.
+fn first_line(text: &str) -> String {
+    let line = text.lines().next().unwrap_or_default().trim();
+    if line.chars().count() > MAX_LINE {
+        let cut: String = line.chars().take(MAX_LINE - 3).collect();
+        format!("{cut}...")
+    } else {
+        line.to_string()
+    }
+}
+
+fn non_empty(text: &str) -> Option<String> {
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+fn plural(count: usize) -> &'static str {
+    if count == 1 {
+        ""
+    } else {
+        "s"
+    }
+}